@@ -0,0 +1,53 @@
+//! Plugin chains described by other tools, see `AudioEngine::import_chain_from_json`.
+//!
+//! A description is a JSON list of plugins in processing order:
+//!
+//! ```json
+//! [
+//!     { "uid_or_name": "565354436F6D707265737369766F6E", "params": { "0": 0.5 } },
+//!     { "uid_or_name": "Reverb", "bypassed": true }
+//! ]
+//! ```
+//!
+//! `uid_or_name` is the plugin's class UID as a hex FUID string, or its name.
+//! `params` maps parameter IDs to normalized values and `bypassed` defaults to false.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::vst::host::PluginId;
+
+/// One plugin of a chain description
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ImportedPlugin {
+    pub uid_or_name: String,
+    #[serde(default)]
+    pub params: BTreeMap<u32, f64>,
+    #[serde(default)]
+    pub bypassed: bool,
+}
+
+/// Parse a chain description
+pub fn parse_chain(json: &str) -> serde_json::Result<Vec<ImportedPlugin>> {
+    serde_json::from_str(json)
+}
+
+/// An entry of the description that wasn't added to the chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnresolvedEntry {
+    /// Position in the description
+    pub index: usize,
+    pub uid_or_name: String,
+    pub reason: String,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone, Default)]
+pub struct ChainImport {
+    /// Plugins added, in the order of the description
+    pub plugins: Vec<PluginId>,
+    pub unresolved: Vec<UnresolvedEntry>,
+    /// Parameters or bypass of added plugins that couldn't be set
+    pub warnings: Vec<String>,
+}
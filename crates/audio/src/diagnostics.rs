@@ -0,0 +1,59 @@
+use serde::Serialize;
+
+/// Snapshot of the engine state for support tickets, see `AudioEngine::diagnostics`
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostics {
+    pub os: String,
+    pub arch: String,
+    pub host: String,
+    pub input: Option<StreamDiagnostics>,
+    pub output: Option<StreamDiagnostics>,
+    pub monitor_output: Option<String>,
+    pub sample_rate: u32,
+    pub buffer_size: u32,
+    pub ring_latency_blocks: usize,
+    pub resampler: ResamplerDiagnostics,
+    /// Loaded plugins in processing order
+    pub plugins: Vec<PluginDiagnostics>,
+    /// Latency of the slowest chain, in samples
+    pub total_latency: u32,
+    pub underrun_count: u64,
+    pub overflow_count: u64,
+    /// Summed plugin CPU, in percent of the block's real-time budget
+    pub dsp_load: f32,
+    pub input_gain: f32,
+    pub denormal_protection: bool,
+}
+
+/// Selected device and stream config on one side of the engine
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamDiagnostics {
+    pub device: Option<String>,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub format: Option<String>,
+    pub supported_formats: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResamplerDiagnostics {
+    pub quality: String,
+    pub adaptive: bool,
+    pub nominal_ratio: Option<f64>,
+    pub effective_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginDiagnostics {
+    pub id: u64,
+    pub name: String,
+    pub path: String,
+    pub vendor: String,
+    pub uid: String,
+    pub chain: u64,
+    pub bypassed: bool,
+    pub setup_failed: bool,
+    /// Last reported latency in samples, if it was polled yet
+    pub latency: Option<u32>,
+    pub cpu: f32,
+}
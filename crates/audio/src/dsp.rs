@@ -0,0 +1,404 @@
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+
+/// Normalized biquad coefficients (a0 = 1)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiquadCoefficients {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl BiquadCoefficients {
+    /// Butterworth high-pass at the given cutoff (RBJ cookbook)
+    pub fn highpass(cutoff: f32, sample_rate: f32) -> Self {
+        let w0 = 2.0 * PI * cutoff / sample_rate;
+        let cos_w0 = w0.cos();
+        let alpha = w0.sin() / (2.0 * FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b0: (1.0 + cos_w0) / 2.0 / a0,
+            b1: -(1.0 + cos_w0) / a0,
+            b2: (1.0 + cos_w0) / 2.0 / a0,
+            a1: -2.0 * cos_w0 / a0,
+            a2: (1.0 - alpha) / a0,
+        }
+    }
+}
+
+/// Per-channel biquad state (transposed direct form II)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl BiquadState {
+    pub fn process(&mut self, coefficients: &BiquadCoefficients, input: f32) -> f32 {
+        let output = coefficients.b0 * input + self.z1;
+        self.z1 = coefficients.b1 * input - coefficients.a1 * output + self.z2;
+        self.z2 = coefficients.b2 * input - coefficients.a2 * output;
+        output
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// One-pole smoothing towards a target gain, so gain changes don't click
+#[derive(Debug, Clone, Copy)]
+pub struct GainSmoother {
+    current: f32,
+    coefficient: f32,
+}
+
+impl GainSmoother {
+    /// Start at `initial`, covering ~63% of any change within `time_constant` seconds
+    pub fn new(initial: f32, time_constant: f32, sample_rate: f32) -> Self {
+        Self {
+            current: initial,
+            coefficient: (-1.0 / (time_constant * sample_rate)).exp(),
+        }
+    }
+
+    /// Gain for the next sample
+    pub fn next(&mut self, target: f32) -> f32 {
+        self.current = target + (self.current - target) * self.coefficient;
+        self.current
+    }
+
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Loudness matching never boosts or cuts by more than this, in dB
+pub const LOUDNESS_MATCH_RANGE_DB: f32 = 24.0;
+
+/// RMS below this counts as silence, which leaves the gain at unity
+const LOUDNESS_MATCH_FLOOR: f32 = 1e-5;
+
+/// Gain that brings a plugin's output RMS to its input RMS, so bypassing it doesn't change
+/// the level. Unity if either side is silent, limited to ±`LOUDNESS_MATCH_RANGE_DB`
+pub fn loudness_match_gain(input_rms: f32, output_rms: f32) -> f32 {
+    if input_rms < LOUDNESS_MATCH_FLOOR || output_rms < LOUDNESS_MATCH_FLOOR {
+        return 1.0;
+    }
+
+    let limit = db_to_linear(LOUDNESS_MATCH_RANGE_DB);
+    (input_rms / output_rms).clamp(1.0 / limit, limit)
+}
+
+/// Share of the ceiling below which `soft_limit` leaves samples untouched
+const SOFT_LIMIT_KNEE: f32 = 0.8;
+
+/// Soft clip a sample so it never exceeds `ceiling`. Linear up to the knee, above it the
+/// remaining headroom is filled with a tanh curve that joins the linear part smoothly.
+pub fn soft_limit(sample: f32, ceiling: f32) -> f32 {
+    let knee = ceiling * SOFT_LIMIT_KNEE;
+    let magnitude = sample.abs();
+    if magnitude <= knee {
+        return sample;
+    }
+
+    let headroom = ceiling - knee;
+    (knee + headroom * ((magnitude - knee) / headroom).tanh()).copysign(sample)
+}
+
+/// Crossfade from `dry` at a mix of 0 to `wet` at a mix of 1
+pub fn dry_wet(dry: f32, wet: f32, mix: f32) -> f32 {
+    dry + (wet - dry) * mix
+}
+
+/// Most channels a `RoutingMatrix` mixes from or to, enough for 7.1
+pub const MAX_ROUTING_CHANNELS: usize = 8;
+
+/// Gain from every source channel to every destination channel, mixed a frame at a time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoutingMatrix {
+    // Indexed by destination, then source
+    gains: [[f32; MAX_ROUTING_CHANNELS]; MAX_ROUTING_CHANNELS],
+}
+
+impl RoutingMatrix {
+    /// Every channel to itself at unity
+    pub fn identity() -> Self {
+        let mut gains = [[0.0; MAX_ROUTING_CHANNELS]; MAX_ROUTING_CHANNELS];
+        for (channel, row) in gains.iter_mut().enumerate() {
+            row[channel] = 1.0;
+        }
+        Self { gains }
+    }
+
+    /// One row of source gains per destination channel, anything missing is silent
+    pub fn from_rows(rows: &[&[f32]]) -> Self {
+        let mut gains = [[0.0; MAX_ROUTING_CHANNELS]; MAX_ROUTING_CHANNELS];
+        for (row, source) in gains.iter_mut().zip(rows) {
+            for (gain, source) in row.iter_mut().zip(source.iter()) {
+                *gain = *source;
+            }
+        }
+        Self { gains }
+    }
+
+    pub fn gain(&self, destination: usize, source: usize) -> f32 {
+        self.gains[destination][source]
+    }
+
+    /// Mix one frame of `input` into `output`
+    pub fn route(&self, input: &[f32], output: &mut [f32]) {
+        for (sample, row) in output.iter_mut().zip(&self.gains) {
+            *sample = row
+                .iter()
+                .zip(input)
+                .map(|(gain, sample)| gain * sample)
+                .sum();
+        }
+    }
+}
+
+impl Default for RoutingMatrix {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Stereo delay of a whole number of frames
+#[derive(Debug, Clone, Default)]
+pub struct DelayLine {
+    frames: Vec<[f32; 2]>,
+    position: usize,
+}
+
+impl DelayLine {
+    pub fn new(delay: usize) -> Self {
+        Self {
+            frames: vec![[0.0; 2]; delay],
+            position: 0,
+        }
+    }
+
+    pub fn delay(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Push a frame in and get the one from `delay` frames ago
+    pub fn process(&mut self, frame: [f32; 2]) -> [f32; 2] {
+        if self.frames.is_empty() {
+            return frame;
+        }
+
+        let delayed = std::mem::replace(&mut self.frames[self.position], frame);
+        self.position = (self.position + 1) % self.frames.len();
+        delayed
+    }
+}
+
+/// Whether `DenormalGuard` flushes denormals in hardware. Covers x86 with SSE and
+/// x86_64 through the MXCSR register, other targets fall back to `flush_denormals`.
+pub const HARDWARE_FTZ: bool = cfg!(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+));
+
+#[cfg(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+))]
+mod mxcsr {
+    /// Flush-to-zero and denormals-are-zero bits
+    pub const FTZ_DAZ: u32 = (1 << 15) | (1 << 6);
+
+    pub fn read() -> u32 {
+        let mut csr = 0u32;
+        unsafe {
+            std::arch::asm!("stmxcsr [{}]", in(reg) &mut csr, options(nostack, preserves_flags));
+        }
+        csr
+    }
+
+    pub fn write(csr: u32) {
+        unsafe {
+            std::arch::asm!("ldmxcsr [{}]", in(reg) &csr, options(nostack, readonly, preserves_flags));
+        }
+    }
+}
+
+/// Sets FTZ/DAZ on the current thread while alive and restores the previous mode
+/// on drop. Does nothing where `HARDWARE_FTZ` is false.
+pub struct DenormalGuard {
+    #[cfg(any(
+        target_arch = "x86_64",
+        all(target_arch = "x86", target_feature = "sse")
+    ))]
+    previous: u32,
+}
+
+impl DenormalGuard {
+    pub fn new() -> Self {
+        #[cfg(any(
+            target_arch = "x86_64",
+            all(target_arch = "x86", target_feature = "sse")
+        ))]
+        {
+            let previous = mxcsr::read();
+            mxcsr::write(previous | mxcsr::FTZ_DAZ);
+            Self { previous }
+        }
+
+        #[cfg(not(any(
+            target_arch = "x86_64",
+            all(target_arch = "x86", target_feature = "sse")
+        )))]
+        Self {}
+    }
+}
+
+impl Default for DenormalGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        #[cfg(any(
+            target_arch = "x86_64",
+            all(target_arch = "x86", target_feature = "sse")
+        ))]
+        mxcsr::write(self.previous);
+    }
+}
+
+/// Replace subnormal samples with zero
+pub fn flush_denormals(samples: &mut [f32]) {
+    for sample in samples.iter_mut().filter(|s| s.is_subnormal()) {
+        *sample = 0.0;
+    }
+}
+
+/// Combined energy below which `CorrelationMeter` reads 0, there's nothing to correlate
+const CORRELATION_FLOOR: f32 = 1e-9;
+
+/// Phase correlation of a stereo signal, +1 for mono, 0 for unrelated channels and -1 for
+/// channels out of phase. The products are averaged with a one-pole over a short window.
+#[derive(Debug, Clone, Copy)]
+pub struct CorrelationMeter {
+    left_right: f32,
+    left_left: f32,
+    right_right: f32,
+    coefficient: f32,
+}
+
+impl CorrelationMeter {
+    /// Average over roughly the last `time_constant` seconds
+    pub fn new(time_constant: f32, sample_rate: f32) -> Self {
+        Self {
+            left_right: 0.0,
+            left_left: 0.0,
+            right_right: 0.0,
+            coefficient: (-1.0 / (time_constant * sample_rate)).exp(),
+        }
+    }
+
+    /// Fold in a block, both channels of the same length
+    pub fn process(&mut self, left: &[f32], right: &[f32]) {
+        for (&l, &r) in left.iter().zip(right) {
+            self.left_right = l * r + (self.left_right - l * r) * self.coefficient;
+            self.left_left = l * l + (self.left_left - l * l) * self.coefficient;
+            self.right_right = r * r + (self.right_right - r * r) * self.coefficient;
+        }
+    }
+
+    pub fn correlation(&self) -> f32 {
+        let energy = (self.left_left * self.right_right).sqrt();
+        if energy < CORRELATION_FLOOR {
+            return 0.0;
+        }
+        (self.left_right / energy).clamp(-1.0, 1.0)
+    }
+}
+
+/// Oscillator and noise source for test signals, keeping phase and noise state between
+/// blocks
+#[derive(Debug, Clone)]
+pub struct SignalGenerator {
+    // In cycles, f64 so a long running tone doesn't drift
+    phase: f64,
+    // xorshift32 state for the noise
+    noise: u32,
+}
+
+impl Default for SignalGenerator {
+    fn default() -> Self {
+        Self {
+            phase: 0.0,
+            noise: 0x2545_F491,
+        }
+    }
+}
+
+impl SignalGenerator {
+    /// Next sample of a sine at `freq` Hz
+    pub fn sine(&mut self, freq: f32, amp: f32, sample_rate: f32) -> f32 {
+        let sample = amp * (2.0 * std::f64::consts::PI * self.phase).sin() as f32;
+        self.phase = (self.phase + freq as f64 / sample_rate as f64).fract();
+        sample
+    }
+
+    /// Next sample of uniform white noise in [-amp, amp)
+    pub fn white_noise(&mut self, amp: f32) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        let uniform = self.noise as f64 / (u32::MAX as f64 + 1.0);
+        amp * (uniform * 2.0 - 1.0) as f32
+    }
+}
+
+/// Converts float samples to the i32 stream format at a device's bit depth. Samples are
+/// rounded to the device's resolution, optionally after adding TPDF dither of ±1 LSB,
+/// and clamped to full scale so overs never wrap to the opposite rail.
+#[derive(Debug, Clone)]
+pub struct Quantizer {
+    bits: u32,
+    // xorshift32 state for the dither noise
+    noise: u32,
+}
+
+impl Quantizer {
+    /// `bits` is the device's sample resolution, 1 to 32
+    pub fn new(bits: u32) -> Self {
+        Self {
+            bits: bits.clamp(1, 32),
+            noise: 0x9E37_79B9,
+        }
+    }
+
+    /// Uniform noise in [0, 1)
+    fn next_uniform(&mut self) -> f64 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise as f64 / (u32::MAX as f64 + 1.0)
+    }
+
+    pub fn quantize(&mut self, sample: f32, dither: bool) -> i32 {
+        // f64 holds every step of a 32 bit device exactly, f32 doesn't near full scale
+        let max = ((1u64 << (self.bits - 1)) - 1) as f64;
+        let mut scaled = sample as f64 * max;
+        if dither {
+            scaled += self.next_uniform() + self.next_uniform() - 1.0;
+        }
+
+        let quantized = scaled.round().clamp(-max - 1.0, max) as i64;
+        (quantized << (32 - self.bits)) as i32
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+/// Internal pipeline state after the last block, see `AudioEngine::debug_snapshot`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DebugSnapshot {
+    /// Samples queued in the ring between the input and output callbacks
+    pub ring_fill: usize,
+    /// Frames in the last block the chain rendered
+    pub block_size: usize,
+    /// Frames the resampler consumed and produced for the last block
+    pub resampler_input_frames: usize,
+    pub resampler_output_frames: usize,
+    /// Range of the samples the chain was given in the last block
+    pub input_range: SampleRange,
+    /// Range of the samples the chain produced in the last block, after the limiter
+    pub output_range: SampleRange,
+    /// Loaded plugins across all chains
+    pub chain_length: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct SampleRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+/// Written by the audio thread after every block, read by `debug_snapshot`
+#[derive(Debug, Default)]
+pub struct PipelineProbe {
+    ring_fill: AtomicUsize,
+    block_size: AtomicUsize,
+    resampler_input_frames: AtomicUsize,
+    resampler_output_frames: AtomicUsize,
+    // Min and max as f32 bits
+    input_range: [AtomicU32; 2],
+    output_range: [AtomicU32; 2],
+}
+
+impl PipelineProbe {
+    /// Forget the last block, called when the streams are rebuilt
+    pub fn reset(&self) {
+        for slot in [
+            &self.ring_fill,
+            &self.block_size,
+            &self.resampler_input_frames,
+            &self.resampler_output_frames,
+        ] {
+            slot.store(0, Ordering::Relaxed);
+        }
+        for slot in self.input_range.iter().chain(&self.output_range) {
+            slot.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Record the chain's input buffer before it renders
+    pub fn record_input<C: AsRef<[f32]>>(&self, buffer: &[C], block_size: usize, channels: usize) {
+        self.block_size.store(block_size, Ordering::Relaxed);
+        store_range(&self.input_range, buffer, block_size, channels);
+    }
+
+    /// Record the chain's output buffer once it's ready for the device
+    pub fn record_output<C: AsRef<[f32]>>(&self, buffer: &[C], block_size: usize, channels: usize) {
+        store_range(&self.output_range, buffer, block_size, channels);
+    }
+
+    pub fn record_resampler(&self, input_frames: usize, output_frames: usize) {
+        self.resampler_input_frames
+            .store(input_frames, Ordering::Relaxed);
+        self.resampler_output_frames
+            .store(output_frames, Ordering::Relaxed);
+    }
+
+    pub fn record_ring_fill(&self, samples: usize) {
+        self.ring_fill.store(samples, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, chain_length: usize) -> DebugSnapshot {
+        let load_range = |range: &[AtomicU32; 2]| SampleRange {
+            min: f32::from_bits(range[0].load(Ordering::Relaxed)),
+            max: f32::from_bits(range[1].load(Ordering::Relaxed)),
+        };
+
+        DebugSnapshot {
+            ring_fill: self.ring_fill.load(Ordering::Relaxed),
+            block_size: self.block_size.load(Ordering::Relaxed),
+            resampler_input_frames: self.resampler_input_frames.load(Ordering::Relaxed),
+            resampler_output_frames: self.resampler_output_frames.load(Ordering::Relaxed),
+            input_range: load_range(&self.input_range),
+            output_range: load_range(&self.output_range),
+            chain_length,
+        }
+    }
+}
+
+fn store_range<C: AsRef<[f32]>>(
+    range: &[AtomicU32; 2],
+    buffer: &[C],
+    block_size: usize,
+    channels: usize,
+) {
+    let (min, max) = buffer
+        .iter()
+        .take(channels)
+        .flat_map(|channel| channel.as_ref().iter().take(block_size))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &s| {
+            (min.min(s), max.max(s))
+        });
+
+    // An empty block leaves both at 0
+    let (min, max) = if min > max { (0.0, 0.0) } else { (min, max) };
+    range[0].store(min.to_bits(), Ordering::Relaxed);
+    range[1].store(max.to_bits(), Ordering::Relaxed);
+}
@@ -1,23 +1,57 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, HostId, SampleFormat, StreamConfig, SupportedStreamConfigRange};
+use cpal::{
+    Device, HostId, SampleFormat, StreamConfig, SupportedStreamConfig, SupportedStreamConfigRange,
+    I24,
+};
 use log::{error, info, trace, warn};
-use ringbuf::traits::{Consumer, Producer, Split};
-use ringbuf::HeapRb;
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
 use rubato::{
-    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+    Resampler, SincFixedIn, SincFixedOut, SincInterpolationParameters, SincInterpolationType,
+    WindowFunction,
 };
 use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 use std::cell::UnsafeCell;
-use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
-use vst::host::{HostParameterChanges, VSTHostContext};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use vst::events::{EventList, HostEventList};
+use vst::host::VSTHostContext;
+use vst::parameters::{EditQueue, HostParameterChanges, ParameterEdit};
 use vst3::base::funknown::IAudioProcessor_Impl;
 use vst3::vst::audio_processor::{
-    AudioBusBuffers, ProcessContext, ProcessData, ProcessMode, SymbolicSampleSize,
+    AudioBusBuffers, ControllerNumbers, ProcessContext, ProcessContextFlags, ProcessData,
+    ProcessMode, SymbolicSampleSize,
 };
 
-use crate::vst::host::PluginId;
+use crate::chain_import::{ChainImport, ImportedPlugin, UnresolvedEntry};
+use crate::diagnostics::{Diagnostics, PluginDiagnostics, ResamplerDiagnostics, StreamDiagnostics};
+use crate::dsp::{
+    db_to_linear, dry_wet, soft_limit, BiquadCoefficients, BiquadState, CorrelationMeter,
+    DelayLine, DenormalGuard, GainSmoother, Quantizer, RoutingMatrix, SignalGenerator,
+    HARDWARE_FTZ, MAX_ROUTING_CHANNELS,
+};
+#[cfg(feature = "debug-introspection")]
+use crate::introspection::{DebugSnapshot, PipelineProbe};
+use crate::midi::{BlockTiming, MidiClockOut, MidiInputPort, MidiMessage};
+use crate::recording::{RecordPoint, RecordWindow, Recording, RecordingTap, BEATS_PER_BAR};
+use crate::vst::host::{
+    BusDescription, ChainId, InsertRamp, LoudnessMatch, PluginFormat, PluginId, PluginMix,
+    PluginParameter, ProcessTimer, SidechainSource,
+};
 
+pub mod chain_import;
+pub mod diagnostics;
+pub mod dsp;
+#[cfg(feature = "debug-introspection")]
+pub mod introspection;
+pub mod midi;
+pub mod recording;
 pub mod vst;
 
 #[repr(C)]
@@ -64,15 +98,34 @@ impl<T: Copy + Clone, const CHANNELS: usize, const BUFFER_SIZE: usize>
     }
 
     pub fn write(&mut self, channel: usize, idx: usize, sample: T) {
+        debug_assert!(
+            channel < CHANNELS,
+            "channel {} out of {}",
+            channel,
+            CHANNELS
+        );
+        debug_assert!(idx < BUFFER_SIZE, "index {} out of {}", idx, BUFFER_SIZE);
+        unsafe {
+            (*self.data.get())[channel][idx] = sample;
+        }
+    }
+
+    /// Write a sample if `channel` and `idx` are in bounds, returns whether it was written
+    pub fn try_write(&mut self, channel: usize, idx: usize, sample: T) -> bool {
+        if channel >= CHANNELS || idx >= BUFFER_SIZE {
+            return false;
+        }
+
         unsafe {
             (*self.data.get())[channel][idx] = sample;
         }
+        true
     }
 
     pub fn as_ref(&self) -> &[&mut [T; BUFFER_SIZE]; CHANNELS] {
         unsafe {
             &*std::mem::transmute::<*const [*mut T; CHANNELS], *mut [&mut [T; BUFFER_SIZE]; CHANNELS]>(
-                Arc::into_raw(self.references.clone()),
+                Arc::as_ptr(&self.references),
             )
         }
     }
@@ -82,7 +135,7 @@ impl<T: Copy + Clone, const CHANNELS: usize, const BUFFER_SIZE: usize>
             &mut *std::mem::transmute::<
                 *const [*mut T; CHANNELS],
                 *mut [&mut [T; BUFFER_SIZE]; CHANNELS],
-            >(Arc::into_raw(self.references.clone()))
+            >(Arc::as_ptr(&self.references))
         }
     }
 }
@@ -93,26 +146,398 @@ impl<T: Copy + Clone, const CHANNELS: usize, const BUFFER_SIZE: usize> Drop
     fn drop(&mut self) {}
 }
 
+/// Runtime-sized counterpart of `Sync2DArray`, for buffers that don't fit its compile-time
+/// channel count or length. Each channel is its own heap buffer, which never moves, so the
+/// pointer table handed to plugins stays valid for the buffer's lifetime.
+pub struct DynChannelBuffer<T: Copy> {
+    data: Vec<Box<[T]>>,
+    references: Vec<*mut T>,
+}
+
+unsafe impl<T: Copy> Send for DynChannelBuffer<T> {}
+
+impl<T: Copy> DynChannelBuffer<T> {
+    pub fn new(default: T, channels: usize, frames: usize) -> Self {
+        let mut data: Vec<Box<[T]>> = (0..channels)
+            .map(|_| vec![default; frames].into_boxed_slice())
+            .collect();
+        let references = data
+            .iter_mut()
+            .map(|channel| channel.as_mut_ptr())
+            .collect();
+        Self { data, references }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn frames(&self) -> usize {
+        self.data.first().map_or(0, |channel| channel.len())
+    }
+
+    pub fn as_ptr(&mut self) -> *const *mut T {
+        self.references.as_ptr()
+    }
+
+    /// Write a sample if `channel` and `idx` are in bounds, returns whether it was written
+    pub fn try_write(&mut self, channel: usize, idx: usize, sample: T) -> bool {
+        match self
+            .data
+            .get_mut(channel)
+            .and_then(|channel| channel.get_mut(idx))
+        {
+            Some(slot) => {
+                *slot = sample;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn as_ref(&self) -> &[Box<[T]>] {
+        &self.data
+    }
+
+    /// The channels must not be replaced, plugins may hold pointers into them
+    pub fn as_mut_ref(&mut self) -> &mut [Box<[T]>] {
+        &mut self.data
+    }
+}
+
+/// Channel buffers a VST bus can point at, fixed or runtime sized
+pub trait ChannelBuffer {
+    fn channel_count(&self) -> usize;
+
+    fn frame_count(&self) -> usize;
+
+    /// One pointer per channel, as `AudioBusBuffers::channel_buffers_32` expects
+    fn channel_pointers(&mut self) -> *const *mut f32;
+}
+
+impl<const CHANNELS: usize, const BUFFER_SIZE: usize> ChannelBuffer
+    for Sync2DArray<f32, CHANNELS, BUFFER_SIZE>
+{
+    fn channel_count(&self) -> usize {
+        CHANNELS
+    }
+
+    fn frame_count(&self) -> usize {
+        BUFFER_SIZE
+    }
+
+    fn channel_pointers(&mut self) -> *const *mut f32 {
+        self.as_ptr()
+    }
+}
+
+impl ChannelBuffer for DynChannelBuffer<f32> {
+    fn channel_count(&self) -> usize {
+        self.channels()
+    }
+
+    fn frame_count(&self) -> usize {
+        self.frames()
+    }
+
+    fn channel_pointers(&mut self) -> *const *mut f32 {
+        self.as_ptr()
+    }
+}
+
+/// Bus buffers covering every channel of `buffer`
+fn bus_buffers(buffer: &mut impl ChannelBuffer) -> AudioBusBuffers {
+    AudioBusBuffers {
+        num_channels: buffer.channel_count() as i32,
+        silence_flags: 0,
+        channel_buffers_32: buffer.channel_pointers() as *mut _,
+    }
+}
+
+/// Interleave the first `frames` frames of `channels` into the ring, returns whether any
+/// sample didn't fit
+fn push_frames<C: AsRef<[f32]>>(
+    producer: &mut HeapProd<f32>,
+    channels: &[C],
+    frames: usize,
+) -> bool {
+    let mut overflowed = false;
+    for i in 0..frames {
+        for channel in channels {
+            let Some(sample) = channel.as_ref().get(i) else {
+                continue;
+            };
+
+            if producer.try_push(*sample).is_err() {
+                overflowed = true;
+            }
+        }
+    }
+    overflowed
+}
+
+/// How `pick_best_format` weighs a device's configurations against the preferences
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FormatStrategy {
+    /// Sample rate first, then buffer size, sample format and channels
+    #[default]
+    BestQuality,
+    /// The smallest supported buffer first, then as `BestQuality`
+    LowestLatency,
+    /// Only a configuration matching every preference, none otherwise
+    ExactOrFail,
+}
+
+impl FormatStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FormatStrategy::BestQuality => "best_quality",
+            FormatStrategy::LowestLatency => "lowest_latency",
+            FormatStrategy::ExactOrFail => "exact_or_fail",
+        }
+    }
+}
+
+impl FromStr for FormatStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "best_quality" => Ok(FormatStrategy::BestQuality),
+            "lowest_latency" => Ok(FormatStrategy::LowestLatency),
+            "exact_or_fail" => Ok(FormatStrategy::ExactOrFail),
+            _ => Err(anyhow!("Unknown format strategy: {}", s)),
+        }
+    }
+}
+
+/// One of the two slots holding a snapshot of the whole chain for A/B comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AbSlot {
+    A,
+    B,
+}
+
+impl AbSlot {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AbSlot::A => "a",
+            AbSlot::B => "b",
+        }
+    }
+}
+
+impl FromStr for AbSlot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "a" => Ok(AbSlot::A),
+            "b" => Ok(AbSlot::B),
+            _ => Err(anyhow!("Unknown A/B slot: {}", s)),
+        }
+    }
+}
+
+/// What the chain is fed, the capture device or a built-in test signal. Amplitudes are
+/// linear, 1.0 being full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputSource {
+    #[default]
+    Device,
+    SineWave {
+        freq: f32,
+        amp: f32,
+    },
+    WhiteNoise {
+        amp: f32,
+    },
+    Silence,
+}
+
+impl InputSource {
+    /// Next sample of the test signal, the same on every channel. 0 for the device, whose
+    /// samples come from the capture stream.
+    pub fn next_sample(&self, generator: &mut SignalGenerator, sample_rate: f32) -> f32 {
+        match *self {
+            InputSource::SineWave { freq, amp } => generator.sine(freq, amp, sample_rate),
+            InputSource::WhiteNoise { amp } => generator.white_noise(amp),
+            InputSource::Device | InputSource::Silence => 0.0,
+        }
+    }
+}
+
+/// Output routing presets for playing stereo material on surround interfaces and back.
+/// Surround channels are in the L, R, C, LFE, Ls, Rs order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Downmix {
+    /// Every channel to the device channel of the same index
+    #[default]
+    Identity,
+    /// Left and right on the front pair, their mid on the centre and each side on its
+    /// surround at -3 dB. The LFE stays silent.
+    StereoTo51,
+    /// ITU-R BS.775 downmix, centre and surrounds at -3 dB into each side. The LFE is
+    /// dropped. Acts on a 5.1 capture, whose channels past the stereo chain pass through
+    /// to the output routing.
+    FiveOneToStereo,
+}
+
+impl Downmix {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Downmix::Identity => "identity",
+            Downmix::StereoTo51 => "stereo_to_5_1",
+            Downmix::FiveOneToStereo => "5_1_to_stereo",
+        }
+    }
+
+    pub fn matrix(&self) -> RoutingMatrix {
+        const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+        match self {
+            Downmix::Identity => RoutingMatrix::identity(),
+            Downmix::StereoTo51 => RoutingMatrix::from_rows(&[
+                &[1.0, 0.0],
+                &[0.0, 1.0],
+                &[0.5, 0.5],
+                &[0.0, 0.0],
+                &[MINUS_3DB, 0.0],
+                &[0.0, MINUS_3DB],
+            ]),
+            Downmix::FiveOneToStereo => RoutingMatrix::from_rows(&[
+                &[1.0, 0.0, MINUS_3DB, 0.0, MINUS_3DB, 0.0],
+                &[0.0, 1.0, MINUS_3DB, 0.0, 0.0, MINUS_3DB],
+            ]),
+        }
+    }
+}
+
+impl FromStr for Downmix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "identity" => Ok(Downmix::Identity),
+            "stereo_to_5_1" => Ok(Downmix::StereoTo51),
+            "5_1_to_stereo" => Ok(Downmix::FiveOneToStereo),
+            _ => Err(anyhow!("Unknown downmix preset: {}", s)),
+        }
+    }
+}
+
+/// Mix a frame of chain output through `routing` into a device frame
+fn write_routed_frame(
+    routing: &RoutingMatrix,
+    source: &[f32],
+    frame: &mut [i32],
+    quantizer: &mut Quantizer,
+    dither: bool,
+) {
+    let mut routed = [0.0; MAX_ROUTING_CHANNELS];
+    routing.route(source, &mut routed);
+    for (j, sample) in frame.iter_mut().enumerate() {
+        *sample = quantizer.quantize(routed.get(j).copied().unwrap_or(0.0), dither);
+    }
+}
+
+/// Fill device frames of `output_channels` from a ring holding frames of `ring_channels`,
+/// mixed through `routing`, or converted sample for sample by the loopback test when it's
+/// `None`. Returns whether the ring ran dry.
+fn pop_frames(
+    consumer: &mut HeapCons<f32>,
+    data: &mut [i32],
+    ring_channels: usize,
+    output_channels: usize,
+    routing: Option<&RoutingMatrix>,
+    quantizer: &mut Quantizer,
+    dither: bool,
+) -> bool {
+    let mut underrun = false;
+
+    // One ring sample per device sample only while the frames match
+    let direct = output_channels == ring_channels
+        && routing.is_none_or(|routing| *routing == RoutingMatrix::identity());
+    if direct {
+        for sample in data {
+            *sample = match consumer.try_pop() {
+                Some(s) if routing.is_none() => loopback_sample(s),
+                Some(s) => quantizer.quantize(s, dither),
+                None => {
+                    underrun = true;
+                    0
+                }
+            };
+        }
+        return underrun;
+    }
+
+    let mut source = [0.0; MAX_ROUTING_CHANNELS];
+    for frame in data.chunks_mut(output_channels) {
+        for j in 0..ring_channels {
+            let sample = consumer.try_pop().unwrap_or_else(|| {
+                underrun = true;
+                0.0
+            });
+            if let Some(slot) = source.get_mut(j) {
+                *slot = sample;
+            }
+        }
+
+        match routing {
+            Some(routing) => write_routed_frame(routing, &source, frame, quantizer, dither),
+            None => {
+                for (j, sample) in frame.iter_mut().enumerate() {
+                    *sample = if j < ring_channels {
+                        loopback_sample(source[j])
+                    } else {
+                        0
+                    };
+                }
+            }
+        }
+    }
+    underrun
+}
+
+/// A plugin as stored in an A/B snapshot
+#[derive(Debug, Clone)]
+struct PluginSnapshot {
+    // None if the plugin couldn't save its state
+    state: Option<Vec<u8>>,
+    bypass: bool,
+}
+
+/// Chain order and every plugin's state and bypass at the time of `store_snapshot`
+#[derive(Debug, Clone, Default)]
+struct ChainSnapshot {
+    order: Vec<PluginId>,
+    plugins: FxHashMap<PluginId, PluginSnapshot>,
+}
+
 /// Selects the best audio format from available configurations
-#[allow(dead_code)]
 fn pick_best_format<I>(
     configs: I,
     preferred_sample_rate: Option<u32>,
     preferred_buffer_size: Option<u32>,
     preferred_sample_format: Option<cpal::SampleFormat>,
     preferred_channels: Option<u16>,
+    strategy: FormatStrategy,
 ) -> Option<cpal::SupportedStreamConfig>
 where
     I: Iterator<Item = cpal::SupportedStreamConfigRange>,
 {
     let mut best_config = None;
-    let mut best_score = (0, 0, 0, 0); // (sample_rate_match, buffer_size_match, format_score, channel_match)
+    // (smallest_buffer, sample_rate_match, buffer_size_match, format_score, channel_match)
+    let mut best_score = None;
 
     for config in configs {
         // Calculate sample format priority score
         let format_score = match config.sample_format() {
-            cpal::SampleFormat::I32 => 7,
-            cpal::SampleFormat::F32 => 6,
+            cpal::SampleFormat::I32 => 8,
+            cpal::SampleFormat::F32 => 7,
+            cpal::SampleFormat::I24 => 6,
             cpal::SampleFormat::U32 => 5,
             cpal::SampleFormat::I16 => 4,
             cpal::SampleFormat::U16 => 3,
@@ -172,7 +597,29 @@ where
             1
         };
 
+        let exact_format =
+            preferred_sample_format.is_none_or(|format| format == config.sample_format());
+        if strategy == FormatStrategy::ExactOrFail
+            && !(sample_rate_match == 1
+                && buffer_size_match == 1
+                && exact_format
+                && channel_match_score == 1)
+        {
+            continue;
+        }
+
+        // Lowest latency ranks by the smallest buffer the range allows, a range that
+        // doesn't report its sizes comes last
+        let smallest_buffer = match (strategy, config.buffer_size()) {
+            (FormatStrategy::LowestLatency, cpal::SupportedBufferSize::Range { min, .. }) => {
+                -(*min as i64)
+            }
+            (FormatStrategy::LowestLatency, cpal::SupportedBufferSize::Unknown) => i64::MIN,
+            _ => 0,
+        };
+
         let current_score = (
+            smallest_buffer,
             sample_rate_match,
             buffer_size_match,
             format_match_score,
@@ -180,8 +627,8 @@ where
         );
 
         // Choose config with better score (sample_rate > buffer_size > format > channels)
-        if current_score > best_score {
-            best_score = current_score;
+        if best_score.is_none_or(|best| current_score > best) {
+            best_score = Some(current_score);
 
             // Create the config with the preferred sample rate if available and supported
             let selected_config = if let Some(preferred_rate) = preferred_sample_rate {
@@ -203,1019 +650,11897 @@ where
     best_config
 }
 
+/// Most frames the plugin chains process in one go, device blocks past this are split
 const MAX_BLOCK_SIZE: usize = 2048;
+/// Largest buffer size accepted for the devices
+const MAX_DEVICE_BLOCK_SIZE: usize = 16384;
+const DEFAULT_RING_LATENCY_BLOCKS: usize = 2;
+/// Upper bound on the ring latency, in seconds of audio
+const MAX_RING_LATENCY_SECS: usize = 2;
+
+/// Sample rates offered to the user when a device reports a continuous range
+const COMMON_SAMPLE_RATES: [u32; 11] = [
+    8000, 11025, 16000, 22050, 32000, 44100, 48000, 88200, 96000, 176400, 192000,
+];
+/// Smallest buffer size accepted and offered to the user, sizes double from here up to
+/// MAX_DEVICE_BLOCK_SIZE
+const MIN_BLOCK_SIZE: usize = 16;
+/// Buffer sizes within this factor of the devices' supported range are clamped into it,
+/// anything further out is rejected as a mistake
+const BUFFER_SIZE_CLAMP_FACTOR: u32 = 2;
+
+/// How long a plugin may take to instantiate before `load_plugin` gives up on it
+pub const DEFAULT_PLUGIN_LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Blocks a plugin inserted into a running chain takes to fade in
+pub const DEFAULT_INSERT_RAMP_BLOCKS: u32 = 8;
+
+/// Time constant of the input gain smoothing, in seconds
+const INPUT_GAIN_SMOOTHING: f32 = 0.01;
+
+/// Time constant of the channel swap and mono sum crossfades, in seconds
+const CHANNEL_UTILITY_SMOOTHING: f32 = 0.005;
+
+/// Time constant of the global A/B crossfade, in seconds
+const GLOBAL_AB_SMOOTHING: f32 = 0.01;
+
+/// Time constant of the monitor dry/wet mix, in seconds
+const MONITOR_MIX_SMOOTHING: f32 = 0.01;
+
+/// Share of dry input below which the global A/B crossfade counts as back on the chain
+const GLOBAL_AB_SETTLED: f32 = 1e-4;
+
+/// Averaging window of the output correlation meter, in seconds
+const OUTPUT_CORRELATION_WINDOW: f32 = 0.3;
+
+/// Output limiter ceiling range in dBFS
+pub const DEFAULT_OUTPUT_CEILING_DB: f32 = -1.0;
+const MIN_OUTPUT_CEILING_DB: f32 = -24.0;
+
+/// Transport tempo range in beats per minute
+const DEFAULT_TEMPO: f64 = 120.0;
+const MIN_TEMPO: f64 = 20.0;
+const MAX_TEMPO: f64 = 400.0;
+
+/// Share of a block's real-time budget the chain may use at the buffer size
+/// `auto_tune_buffer_size` settles on, leaving headroom for the rest of the callback
+const AUTO_TUNE_LOAD_CEILING: f32 = 0.7;
+/// Blocks timed per buffer size when auto tuning, so one slow block doesn't decide it
+const AUTO_TUNE_BLOCKS: usize = 16;
+
+/// Length of the fade in when streams start and the fade out before they're stopped
+const STREAM_FADE_SECS: f32 = 0.005;
+/// Longest `stop_streams` waits for the fade out to reach the device
+const STREAM_FADE_TIMEOUT: Duration = Duration::from_millis(100);
 
 /// Audio configuration for input/output devices
 #[derive(Debug, Clone)]
 pub struct AudioConfig {
+    /// Name of the device on the default host, None for the host's default device
+    pub device: Option<String>,
     pub sample_rate: u32,
     pub buffer_size: u32,
     pub channels: u16,
 }
 
-/// Main audio engine responsible for managing audio hosts, devices, and processing
-#[allow(dead_code)]
-pub struct AudioEngine {
-    // Current audio state
-    host: cpal::Host,
-    input_device: Option<cpal::Device>,
-    output_device: Option<cpal::Device>,
-    input_config: Option<cpal::StreamConfig>,
-    output_config: Option<cpal::StreamConfig>,
+/// Stream config a running stream was built with, and the sample format of its buffers
+#[derive(Debug, Clone)]
+pub struct ActiveStreamConfig {
+    pub config: StreamConfig,
+    pub sample_format: SampleFormat,
+}
 
-    // Audio streams
-    input_stream: Option<cpal::Stream>,
-    output_stream: Option<cpal::Stream>,
+impl ActiveStreamConfig {
+    /// The engine's streams exchange i32 samples with the driver, or 24 bit samples with
+    /// devices running in that format
+    fn new(config: &StreamConfig, device_format: Option<SampleFormat>) -> Self {
+        Self {
+            config: config.clone(),
+            sample_format: stream_sample_format(device_format),
+        }
+    }
 
-    // Audio processing data
-    input_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
-    output_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
-    resampled_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    /// Fixed buffer size the stream asked for, None when the driver picks
+    pub fn buffer_size(&self) -> Option<u32> {
+        match self.config.buffer_size {
+            cpal::BufferSize::Fixed(size) => Some(size),
+            cpal::BufferSize::Default => None,
+        }
+    }
+}
 
-    // VST processing components
-    in_bus: Arc<UnsafeCell<AudioBusBuffers>>,
-    out_bus: Arc<UnsafeCell<AudioBusBuffers>>,
-    input_params: Arc<UnsafeCell<HostParameterChanges>>,
-    process_context: Arc<UnsafeCell<ProcessContext>>,
-    process_data: Arc<ProcessData>,
-    plugin_modules: Arc<RwLock<FxHashMap<PluginId, VSTHostContext>>>,
+/// Resampler presets trading CPU usage for conversion quality
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    Fast,
+    Balanced,
+    #[default]
+    HighQuality,
+}
 
-    // Cached device information for performance
-    cached_hosts: Vec<HostId>,
-    cached_input_devices: FxHashMap<HostId, Vec<String>>,
-    cached_output_devices: FxHashMap<HostId, Vec<String>>,
-    cached_input_configs: FxHashMap<String, Vec<SupportedStreamConfigRange>>,
-    cached_output_configs: FxHashMap<String, Vec<SupportedStreamConfigRange>>,
+impl ResamplerQuality {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResamplerQuality::Fast => "fast",
+            ResamplerQuality::Balanced => "balanced",
+            ResamplerQuality::HighQuality => "high_quality",
+        }
+    }
 
-    // Current audio settings
-    current_sample_rate: u32,
-    current_buffer_size: u32,
+    /// Sinc interpolation parameters for this preset
+    fn sinc_parameters(&self) -> SincInterpolationParameters {
+        match self {
+            ResamplerQuality::Fast => SincInterpolationParameters {
+                sinc_len: 32,
+                f_cutoff: 0.9,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 64,
+                window: WindowFunction::Hann2,
+            },
+            ResamplerQuality::Balanced => SincInterpolationParameters {
+                sinc_len: 128,
+                f_cutoff: 0.925,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 128,
+                window: WindowFunction::Blackman2,
+            },
+            ResamplerQuality::HighQuality => SincInterpolationParameters {
+                sinc_len: 256,
+                f_cutoff: 0.95,
+                interpolation: SincInterpolationType::Linear,
+                oversampling_factor: 256,
+                window: WindowFunction::BlackmanHarris2,
+            },
+        }
+    }
 }
 
-impl Default for AudioEngine {
-    fn default() -> Self {
-        // Initialize caches
-        let mut cached_hosts = Vec::new();
-        let mut cached_input_devices = FxHashMap::default();
-        let mut cached_output_devices = FxHashMap::default();
-        let mut cached_input_configs = FxHashMap::default();
-        let mut cached_output_configs = FxHashMap::default();
-
-        // Cache available hosts and their devices
-        for host_id in cpal::available_hosts() {
-            let Ok(host) = cpal::host_from_id(host_id) else {
-                warn!("Failed to get host from id: {:?}", host_id.name());
-                continue;
-            };
+impl FromStr for ResamplerQuality {
+    type Err = anyhow::Error;
 
-            cached_hosts.push(host_id);
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fast" => Ok(ResamplerQuality::Fast),
+            "balanced" => Ok(ResamplerQuality::Balanced),
+            "high_quality" => Ok(ResamplerQuality::HighQuality),
+            _ => Err(anyhow!("Unknown resampler quality: {}", s)),
+        }
+    }
+}
 
-            // Cache input devices
-            if let Ok(input_devices) = host.input_devices() {
-                let device_names: Vec<String> = input_devices
-                    .filter_map(|device| {
-                        let name = device.name().ok()?;
+/// Most channels of an extra plugin bus the engine provides buffers for
+const MAX_BUS_CHANNELS: usize = 8;
+
+/// Bus layout for a plugin with buses besides its main input and output, or monitoring
+/// an output bus other than its main one. A connected sidechain bus reads `sidechain`,
+/// other extra input buses read silence. Every output bus but the monitored one writes
+/// into a discarded scratch buffer.
+struct BusLayout {
+    // Keep the channels referenced by `inputs` and `outputs` alive
+    _silence: Sync2DArray<f32, MAX_BUS_CHANNELS, MAX_BLOCK_SIZE>,
+    _scratch: Sync2DArray<f32, MAX_BUS_CHANNELS, MAX_BLOCK_SIZE>,
+    // Filled by the renderer before the plugin processes, see `fill_sidechain`
+    sidechain: Sync2DArray<f32, MAX_BUS_CHANNELS, MAX_BLOCK_SIZE>,
+    sidechain_source: Option<SidechainSource>,
+    inputs: Box<[AudioBusBuffers]>,
+    outputs: Box<[AudioBusBuffers]>,
+    process_data: Arc<ProcessData>,
+}
 
-                        // Cache input configs for this device
-                        if let Ok(configs) = device.supported_input_configs() {
-                            cached_input_configs.insert(name.clone(), configs.collect());
-                        }
+// SAFETY: The bus buffers only point into engine owned audio buffers, which are
+// accessed from the audio thread in the same way as the main ProcessData.
+unsafe impl Send for BusLayout {}
+unsafe impl Sync for BusLayout {}
+
+impl BusLayout {
+    /// Whether the plugin needs more than the main ProcessData's single bus per side
+    fn needed(plugin: &VSTHostContext) -> bool {
+        plugin.input_buses.len() > 1
+            || plugin.output_buses.len() > 1
+            || plugin.monitor_bus != 0
+            || plugin.sidechain.is_some()
+    }
 
-                        Some(name)
-                    })
-                    .collect();
-                cached_input_devices.insert(host_id, device_names);
+    fn new(plugin: &VSTHostContext, output_channels: *const *mut f32, main: &ProcessData) -> Self {
+        let mut silence =
+            Sync2DArray::<f32, MAX_BUS_CHANNELS, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
+        let mut scratch =
+            Sync2DArray::<f32, MAX_BUS_CHANNELS, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
+        let mut sidechain =
+            Sync2DArray::<f32, MAX_BUS_CHANNELS, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
+
+        let bus_channels = |buses: &[BusDescription], index: usize| {
+            let channels = buses
+                .get(index)
+                .map_or(2, |bus| bus.channel_count.max(0) as usize);
+            if channels > MAX_BUS_CHANNELS {
+                warn!(
+                    "{} bus {} has {} channels, only {} are connected",
+                    plugin.name, index, channels, MAX_BUS_CHANNELS
+                );
             }
+            channels.min(MAX_BUS_CHANNELS) as i32
+        };
 
-            // Cache output devices
-            if let Ok(output_devices) = host.output_devices() {
-                let device_names: Vec<String> = output_devices
-                    .filter_map(|device| {
-                        let name = device.name().ok()?;
+        let inputs = (0..plugin.input_buses.len().max(1))
+            .map(|i| {
+                if i == 0 {
+                    // SAFETY: The main input bus lives as long as the engine
+                    let main_input = unsafe { &*main.inputs };
+                    return AudioBusBuffers {
+                        num_channels: main_input.num_channels,
+                        silence_flags: 0,
+                        channel_buffers_32: main_input.channel_buffers_32,
+                    };
+                }
 
-                        // Cache output configs for this device
-                        if let Ok(configs) = device.supported_output_configs() {
-                            cached_output_configs.insert(name.clone(), configs.collect());
-                        }
+                let channels = bus_channels(&plugin.input_buses, i);
+                if i == 1 && plugin.sidechain.is_some() {
+                    return AudioBusBuffers {
+                        num_channels: channels,
+                        silence_flags: 0,
+                        channel_buffers_32: sidechain.as_ptr() as *mut _,
+                    };
+                }
+                AudioBusBuffers {
+                    num_channels: channels,
+                    silence_flags: (1u64 << channels) - 1,
+                    channel_buffers_32: silence.as_ptr() as *mut _,
+                }
+            })
+            .collect::<Box<[_]>>();
 
-                        Some(name)
-                    })
-                    .collect();
-                cached_output_devices.insert(host_id, device_names);
+        let outputs = (0..plugin.output_buses.len().max(plugin.monitor_bus + 1))
+            .map(|i| AudioBusBuffers {
+                num_channels: if i == plugin.monitor_bus || i == 0 {
+                    2
+                } else {
+                    bus_channels(&plugin.output_buses, i)
+                },
+                silence_flags: 0,
+                channel_buffers_32: if i == plugin.monitor_bus {
+                    output_channels as *mut _
+                } else {
+                    scratch.as_ptr() as *mut _
+                },
+            })
+            .collect::<Box<[_]>>();
+
+        let mut layout = Self {
+            _silence: silence,
+            _scratch: scratch,
+            sidechain,
+            sidechain_source: plugin.sidechain,
+            inputs,
+            outputs,
+            process_data: Arc::new(ProcessData {
+                process_mode: ProcessMode::Realtime,
+                symbolic_sample_size: SymbolicSampleSize::Sample32,
+                num_samples: 0,
+                num_inputs: 0,
+                num_outputs: 0,
+                inputs: std::ptr::null_mut(),
+                outputs: std::ptr::null_mut(),
+                input_parameter_changes: std::ptr::null_mut(),
+                output_parameter_changes: std::ptr::null_mut(),
+                input_events: std::ptr::null_mut(),
+                output_events: std::ptr::null_mut(),
+                process_context: std::ptr::null_mut(),
+            }),
+        };
+        layout.update_process_data(main);
+        layout
+    }
+
+    /// Copy a block into the sidechain bus. A mono sidechain gets the channels averaged,
+    /// `None` silences it.
+    unsafe fn fill_sidechain(
+        &self,
+        source: Option<&[[f32; MAX_BLOCK_SIZE]; 2]>,
+        block_size: usize,
+        channels: usize,
+    ) {
+        let sidechain = self.sidechain.data.get();
+        let bus_channels = self
+            .inputs
+            .get(1)
+            .map_or(0, |bus| bus.num_channels as usize);
+
+        for i in 0..block_size {
+            match source {
+                Some(source) if bus_channels == 1 => {
+                    let sum = source.iter().take(channels).map(|c| c[i]).sum::<f32>();
+                    (*sidechain)[0][i] = sum / channels.max(1) as f32;
+                }
+                Some(source) => {
+                    for j in 0..bus_channels {
+                        (*sidechain)[j][i] = source[j.min(channels.max(1) - 1)][i];
+                    }
+                }
+                None => {
+                    for j in 0..bus_channels {
+                        (*sidechain)[j][i] = 0.0;
+                    }
+                }
             }
         }
+    }
 
-        // Setup default host and devices
-        let host = cpal::default_host();
-        let input_device = host.default_input_device();
-        let output_device = host.default_output_device();
+    /// Rebuild the ProcessData from the main one, swapping in this bus layout
+    fn update_process_data(&mut self, main: &ProcessData) {
+        self.process_data = Arc::new(ProcessData {
+            process_mode: main.process_mode,
+            symbolic_sample_size: SymbolicSampleSize::Sample32,
+            num_samples: main.num_samples,
+            num_inputs: self.inputs.len() as i32,
+            num_outputs: self.outputs.len() as i32,
+            inputs: self.inputs.as_mut_ptr(),
+            outputs: self.outputs.as_mut_ptr(),
+            input_parameter_changes: main.input_parameter_changes,
+            output_parameter_changes: main.output_parameter_changes,
+            input_events: main.input_events,
+            output_events: main.output_events,
+            process_context: main.process_context,
+        });
+    }
+}
 
-        let (input_config, output_config, current_sample_rate, current_buffer_size) =
-            if let (Some(ref input_dev), Some(ref output_dev)) = (&input_device, &output_device) {
-                let input_cfg = input_dev.default_input_config().ok().map(|c| c.into());
-                let output_cfg = output_dev.default_output_config().ok().map(|c| c.into());
-
-                let sample_rate = input_cfg
-                    .as_ref()
-                    .map(|c: &StreamConfig| c.sample_rate.0)
-                    .unwrap_or(48000);
-                let buffer_size = input_cfg
-                    .as_ref()
-                    .and_then(|c: &StreamConfig| match c.buffer_size {
-                        cpal::BufferSize::Fixed(size) => Some(size),
-                        cpal::BufferSize::Default => Some(512),
-                    })
-                    .unwrap_or(512);
+/// Engine frames downsampled per plugin call when a plugin runs at its own rate
+const PLUGIN_RESAMPLE_CHUNK: usize = 64;
 
-                (input_cfg, output_cfg, sample_rate, buffer_size)
-            } else {
-                (None, None, 48000, 512)
-            };
+/// Sample rate a plugin runs at instead of the engine's, see `set_plugin_sample_rate`
+#[derive(Debug, Clone, Copy)]
+struct PluginRate {
+    sample_rate: u32,
+    /// Latency the resampling adds, in engine samples. 0 while it matches the engine rate
+    resampling_latency: u32,
+}
 
-        info!(
-            "Creating AudioEngine with:\n\tHost: {:?}\n\tInput: {:?}\n\tOutput: {:?}",
-            host.id(),
-            input_device.as_ref().and_then(|d| d.name().ok()),
-            output_device.as_ref().and_then(|d| d.name().ok())
-        );
+/// Channels of each of `count` buses, as a ProcessData lists them
+///
+/// # Safety
+/// `buses` must point to `count` valid bus buffers.
+unsafe fn bus_channels(buses: *const AudioBusBuffers, count: i32) -> Vec<usize> {
+    (0..count.max(0) as usize)
+        .map(|i| (*buses.add(i)).num_channels.max(0) as usize)
+        .collect()
+}
 
-        // Initialize audio processing data
-        let mut input_data = Sync2DArray::<f32, 2, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
-        let mut output_data = Sync2DArray::<f32, 2, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
-        let resampled_data = Sync2DArray::<f32, 2, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
+/// Every channel of `buses`, bus after bus
+///
+/// # Safety
+/// The buses' channel pointers must be valid.
+unsafe fn channel_pointers(buses: &[AudioBusBuffers]) -> impl Iterator<Item = *mut f32> + '_ {
+    buses.iter().flat_map(|bus| {
+        (0..bus.num_channels.max(0) as usize).map(|i| *bus.channel_buffers_32.add(i))
+    })
+}
 
-        // Setup VST processing components
-        let in_bus = Arc::new(UnsafeCell::new(AudioBusBuffers {
-            num_channels: 2,
-            silence_flags: 0,
-            channel_buffers_32: input_data.as_ptr() as *mut _,
-        }));
-
-        let out_bus = Arc::new(UnsafeCell::new(AudioBusBuffers {
-            num_channels: 2,
-            silence_flags: 0,
-            channel_buffers_32: output_data.as_ptr() as *mut _,
-        }));
+/// Wraps a plugin running at its own sample rate. Engine blocks are queued, resampled
+/// to the plugin's rate in fixed chunks, processed and resampled back, so every block
+/// comes out as long as it went in, behind a fixed latency. Every channel of every bus
+/// is resampled, so the plugin sees the same buses, sidechain included, as at the
+/// engine rate.
+struct PluginResampler {
+    engine_rate: u32,
+    plugin_rate: u32,
+    quality: ResamplerQuality,
+    ratio: f64,
+    downsampler: SincFixedIn<f32>,
+    upsampler: SincFixedOut<f32>,
+    // Engine rate input waiting for a full chunk, and processed frames waiting to go out,
+    // one queue per channel of all buses
+    input_queue: Vec<VecDeque<f32>>,
+    queued: usize,
+    output_queue: Vec<VecDeque<f32>>,
+    // Plugin output waiting for the upsampler, primed so it never runs dry
+    plugin_queue: Vec<VecDeque<f32>>,
+    plugin_priming: usize,
+    chunk: Vec<Vec<f32>>,
+    plugin_input: Vec<Vec<f32>>,
+    plugin_output: Vec<Vec<f32>>,
+    pending: Vec<Vec<f32>>,
+    upsampled: Vec<Vec<f32>>,
+    // Plugin rate buses shaped like the engine's, pointing into `plugin_input` and
+    // `plugin_output`. Keep the channels referenced by the buses alive
+    _input_channels: Vec<*mut f32>,
+    _output_channels: Vec<*mut f32>,
+    input_buses: Vec<AudioBusBuffers>,
+    output_buses: Vec<AudioBusBuffers>,
+}
 
-        let input_params = Arc::new(UnsafeCell::new(HostParameterChanges::new()));
-        let process_context = Arc::new(UnsafeCell::new(ProcessContext { padding: [0; 200] }));
+// SAFETY: The bus buffers only point into the resampler's own channels, which are only
+// touched by whoever holds the resampler
+unsafe impl Send for PluginResampler {}
+
+impl PluginResampler {
+    /// A resampler for a plugin whose input and output buses have `inputs` and `outputs`
+    /// channels each
+    fn new(
+        engine_rate: u32,
+        plugin_rate: u32,
+        quality: ResamplerQuality,
+        inputs: &[usize],
+        outputs: &[usize],
+    ) -> Result<Self> {
+        let ratio = plugin_rate as f64 / engine_rate as f64;
+        let input_count = inputs.iter().sum::<usize>();
+        let output_count = outputs.iter().sum::<usize>();
+        let downsampler = SincFixedIn::<f32>::new(
+            ratio,
+            1.0,
+            quality.sinc_parameters(),
+            PLUGIN_RESAMPLE_CHUNK,
+            input_count,
+        )?;
+        let upsampler = SincFixedOut::<f32>::new(
+            1.0 / ratio,
+            1.0,
+            quality.sinc_parameters(),
+            PLUGIN_RESAMPLE_CHUNK,
+            output_count,
+        )?;
 
-        let process_data = Arc::new(ProcessData {
-            process_mode: ProcessMode::Realtime,
-            symbolic_sample_size: SymbolicSampleSize::Sample32,
-            num_samples: current_buffer_size as i32,
-            num_inputs: 1,
-            num_outputs: 1,
-            inputs: in_bus.get(),
-            outputs: out_bus.get(),
-            input_parameter_changes: input_params.get() as *mut _,
-            output_parameter_changes: std::ptr::null_mut(),
-            input_events: std::ptr::null_mut(),
-            output_events: std::ptr::null_mut(),
-            process_context: std::ptr::null_mut(),
-        });
-
-        let plugin_modules = Arc::new(RwLock::new(FxHashMap::default()));
+        let plugin_frames = downsampler.output_frames_max();
+        let plugin_priming = upsampler.input_frames_max();
+        let queues = |count: usize, capacity: usize, primed: usize| {
+            (0..count)
+                .map(|_| {
+                    let mut queue = VecDeque::with_capacity(capacity);
+                    queue.resize(primed, 0.0);
+                    queue
+                })
+                .collect::<Vec<_>>()
+        };
 
-        Self {
-            host,
-            input_device,
-            output_device,
-            input_config,
-            output_config,
-            input_stream: None,
-            output_stream: None,
-            input_data,
-            output_data,
-            resampled_data,
-            in_bus,
-            out_bus,
-            input_params,
-            process_context,
-            process_data,
-            plugin_modules,
-            cached_hosts,
-            cached_input_devices,
-            cached_output_devices,
-            cached_input_configs,
-            cached_output_configs,
-            current_sample_rate,
-            current_buffer_size,
-        }
+        let mut plugin_input = vec![vec![0.0; plugin_frames]; input_count];
+        let mut plugin_output = vec![vec![0.0; plugin_frames]; output_count];
+        let mut input_channels = plugin_input
+            .iter_mut()
+            .map(|channel| channel.as_mut_ptr())
+            .collect::<Vec<_>>();
+        let mut output_channels = plugin_output
+            .iter_mut()
+            .map(|channel| channel.as_mut_ptr())
+            .collect::<Vec<_>>();
+        let buses = |channels: &[usize], pointers: &mut [*mut f32]| {
+            let mut first = 0;
+            channels
+                .iter()
+                .map(|&count| {
+                    let bus = AudioBusBuffers {
+                        num_channels: count as i32,
+                        silence_flags: 0,
+                        channel_buffers_32: pointers[first..].as_mut_ptr(),
+                    };
+                    first += count;
+                    bus
+                })
+                .collect::<Vec<_>>()
+        };
+        let input_buses = buses(inputs, &mut input_channels);
+        let output_buses = buses(outputs, &mut output_channels);
+
+        Ok(Self {
+            engine_rate,
+            plugin_rate,
+            quality,
+            ratio,
+            input_queue: queues(input_count, MAX_BLOCK_SIZE + PLUGIN_RESAMPLE_CHUNK, 0),
+            queued: 0,
+            // Covers the frames held back until the input queue fills a chunk
+            output_queue: queues(
+                output_count,
+                MAX_BLOCK_SIZE + 4 * PLUGIN_RESAMPLE_CHUNK,
+                PLUGIN_RESAMPLE_CHUNK,
+            ),
+            plugin_queue: queues(
+                output_count,
+                plugin_priming * 2 + plugin_frames,
+                plugin_priming,
+            ),
+            plugin_priming,
+            chunk: vec![vec![0.0; PLUGIN_RESAMPLE_CHUNK]; input_count],
+            plugin_input,
+            plugin_output,
+            pending: (0..output_count)
+                .map(|_| Vec::with_capacity(plugin_priming))
+                .collect(),
+            upsampled: vec![vec![0.0; upsampler.output_frames_max()]; output_count],
+            _input_channels: input_channels,
+            _output_channels: output_channels,
+            input_buses,
+            output_buses,
+            downsampler,
+            upsampler,
+        })
     }
-}
-
-// SAFETY: AudioEngine uses UnsafeCell for audio processing, but the design ensures
-// that these are only accessed from the audio thread in a controlled manner.
-// The Arc<UnsafeCell<_>> pattern is safe when properly synchronized.
-unsafe impl Send for AudioEngine {}
-unsafe impl Sync for AudioEngine {}
 
-impl AudioEngine {
-    /// Get all available audio hosts
-    pub fn available_hosts(&self) -> &[HostId] {
-        &self.cached_hosts
+    /// Whether the resampler carries buses of exactly `inputs` and `outputs` channels
+    fn fits(&self, inputs: &[usize], outputs: &[usize]) -> bool {
+        let channels = |buses: &[AudioBusBuffers]| {
+            buses
+                .iter()
+                .map(|bus| bus.num_channels as usize)
+                .collect::<Vec<_>>()
+        };
+        channels(&self.input_buses) == inputs && channels(&self.output_buses) == outputs
     }
 
-    /// Get all available audio host names
-    pub fn available_host_names(&self) -> Vec<String> {
-        self.cached_hosts
-            .iter()
-            .map(|host_id| host_id.name().to_string())
-            .collect()
+    /// Most frames the plugin is handed in one call, for its setupProcessing
+    fn max_plugin_frames(&self) -> usize {
+        self.downsampler.output_frames_max()
     }
 
-    /// Get available input devices for the current host
-    pub fn available_input_devices(&self) -> Result<Vec<Device>> {
-        Ok(self.host.input_devices()?.collect())
+    /// Latency the queues and filters add, in engine samples
+    fn latency(&self) -> u32 {
+        let plugin_side = (self.plugin_priming + self.downsampler.output_delay()) as f64;
+        (PLUGIN_RESAMPLE_CHUNK as f64 + plugin_side / self.ratio).round() as u32
+            + self.upsampler.output_delay() as u32
     }
 
-    /// Get available input device names for the current host
-    pub fn available_input_device_names(&self) -> Result<Vec<String>> {
-        Ok(self
-            .host
-            .input_devices()?
-            .filter_map(|device| device.name().ok())
-            .collect())
-    }
+    /// Run `frames` engine frames of `main`'s buses through the plugin. `process` gets
+    /// the plugin rate ProcessData, with the buses, parameter changes, events and context
+    /// of `main`.
+    ///
+    /// # Safety
+    /// `main` must point to valid buses of at least `frames` frames, shaped like the ones
+    /// the resampler was made for.
+    unsafe fn process(
+        &mut self,
+        main: &ProcessData,
+        frames: usize,
+        mut process: impl FnMut(&mut ProcessData),
+    ) {
+        let inputs = std::slice::from_raw_parts(main.inputs, main.num_inputs.max(0) as usize);
+        let outputs = std::slice::from_raw_parts(main.outputs, main.num_outputs.max(0) as usize);
+        for (queue, channel) in self.input_queue.iter_mut().zip(channel_pointers(inputs)) {
+            queue.extend(std::slice::from_raw_parts(channel, frames));
+        }
+        self.queued += frames;
+        // A connected sidechain is only audible while its bus isn't flagged silent
+        for (bus, main_bus) in self.input_buses.iter_mut().zip(inputs) {
+            bus.silence_flags = main_bus.silence_flags;
+        }
 
-    /// Get available input configurations for a specific device
-    pub fn available_input_configs(
-        &self,
-        device_name: &str,
-    ) -> Option<&[SupportedStreamConfigRange]> {
-        self.cached_input_configs
-            .get(device_name)
-            .map(|v| v.as_slice())
-    }
+        while self.queued >= PLUGIN_RESAMPLE_CHUNK {
+            for (chunk, queue) in self.chunk.iter_mut().zip(&mut self.input_queue) {
+                chunk.clear();
+                chunk.extend(queue.drain(..PLUGIN_RESAMPLE_CHUNK));
+            }
+            self.queued -= PLUGIN_RESAMPLE_CHUNK;
+
+            let plugin_frames = self
+                .downsampler
+                .process_into_buffer(&self.chunk, &mut self.plugin_input, None)
+                .map_or(0, |(_, frames)| frames);
+            let mut data = ProcessData {
+                process_mode: main.process_mode,
+                symbolic_sample_size: SymbolicSampleSize::Sample32,
+                num_samples: plugin_frames as i32,
+                num_inputs: self.input_buses.len() as i32,
+                num_outputs: self.output_buses.len() as i32,
+                inputs: self.input_buses.as_mut_ptr(),
+                outputs: self.output_buses.as_mut_ptr(),
+                input_parameter_changes: main.input_parameter_changes,
+                output_parameter_changes: main.output_parameter_changes,
+                input_events: main.input_events,
+                output_events: main.output_events,
+                process_context: main.process_context,
+            };
+            process(&mut data);
 
-    /// Get available output devices for the current host
-    pub fn available_output_devices(&self) -> Result<Vec<Device>> {
-        Ok(self.host.output_devices()?.collect())
-    }
+            for (queue, channel) in self.plugin_queue.iter_mut().zip(&self.plugin_output) {
+                queue.extend(&channel[..plugin_frames]);
+            }
 
-    /// Get available output device names for the current host
-    pub fn available_output_device_names(&self) -> Result<Vec<String>> {
-        Ok(self
-            .host
-            .output_devices()?
-            .filter_map(|device| device.name().ok())
-            .collect())
-    }
+            while self.plugin_queue.first().map_or(0, VecDeque::len)
+                >= self.upsampler.input_frames_next()
+            {
+                let needed = self.upsampler.input_frames_next();
+                for (pending, queue) in self.pending.iter_mut().zip(&mut self.plugin_queue) {
+                    pending.clear();
+                    pending.extend(queue.drain(..needed));
+                }
 
-    /// Get available output configurations for a specific device
-    pub fn available_output_configs(
-        &self,
-        device_name: &str,
-    ) -> Option<&[SupportedStreamConfigRange]> {
-        self.cached_output_configs
-            .get(device_name)
-            .map(|v| v.as_slice())
-    }
+                let upsampled = self
+                    .upsampler
+                    .process_into_buffer(&self.pending, &mut self.upsampled, None)
+                    .map_or(0, |(_, frames)| frames);
+                for (queue, channel) in self.output_queue.iter_mut().zip(&self.upsampled) {
+                    queue.extend(&channel[..upsampled]);
+                }
+            }
+        }
 
-    /// Get cached input device names for a specific host (more efficient)
-    pub fn cached_input_device_names(&self, host_id: &HostId) -> Option<&[String]> {
-        self.cached_input_devices.get(host_id).map(|v| v.as_slice())
+        for (channel, queue) in channel_pointers(outputs).zip(&mut self.output_queue) {
+            for sample in std::slice::from_raw_parts_mut(channel, frames) {
+                *sample = queue.pop_front().unwrap_or(0.0);
+            }
+        }
     }
+}
 
-    /// Get cached output device names for a specific host (more efficient)
-    pub fn cached_output_device_names(&self, host_id: &HostId) -> Option<&[String]> {
-        self.cached_output_devices
-            .get(host_id)
-            .map(|v| v.as_slice())
-    }
+/// Interleaved output of every plugin that processed, see `render_stems`
+type Stems = FxHashMap<PluginId, Vec<f32>>;
 
-    /// Get cached input device names for the current host
-    pub fn cached_current_input_device_names(&self) -> Option<&[String]> {
-        self.cached_input_devices
-            .get(&self.host.id())
-            .map(|v| v.as_slice())
+/// File name friendly version of a plugin name for stem files
+fn stem_label(name: &str) -> String {
+    let label = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    match label.trim_matches('_') {
+        "" => "plugin".to_string(),
+        label => label.to_string(),
     }
+}
 
-    /// Get cached output device names for the current host
-    pub fn cached_current_output_device_names(&self) -> Option<&[String]> {
-        self.cached_output_devices
-            .get(&self.host.id())
-            .map(|v| v.as_slice())
+/// Write interleaved samples as a WAV file
+fn write_wav(path: &Path, spec: hound::WavSpec, samples: &[f32]) -> Result<()> {
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
     }
+    writer.finalize()?;
+    Ok(())
+}
 
-    /// Get the current host
-    pub fn host(&self) -> &cpal::Host {
-        &self.host
-    }
+/// Plugins that should process the next block, in chain order. Plugins bypassed by the
+/// host and plugins that failed setup are skipped, ones bypassing themselves still run.
+fn processing_chain<'a>(
+    plugins: &'a FxHashMap<PluginId, VSTHostContext>,
+    order: &'a [PluginId],
+) -> impl Iterator<Item = (PluginId, &'a VSTHostContext)> + 'a {
+    order
+        .iter()
+        .filter_map(|id| plugins.get(id).map(|plugin| (*id, plugin)))
+        .filter(|(_, plugin)| {
+            plugin.processor.is_some() && !plugin.host_bypassed() && !plugin.setup_failed
+        })
+}
 
-    /// Get the current host name
-    pub fn host_name(&self) -> &str {
-        self.host.id().name()
-    }
+/// Queue a MIDI input message at `sample_offset` into the next block of every plugin
+/// taking notes. Controllers and pitch bend go to the parameter the plugin maps them to,
+/// if any.
+fn route_midi_message(
+    plugins: &FxHashMap<PluginId, VSTHostContext>,
+    message: MidiMessage,
+    sample_offset: i32,
+) {
+    for (id, plugin) in plugins.iter().filter(|(_, plugin)| plugin.accepts_notes()) {
+        let (channel, controller, value) = match message {
+            MidiMessage::NoteOn {
+                channel,
+                pitch,
+                velocity,
+            } => {
+                if !plugin
+                    .events
+                    .add_note_on(sample_offset, channel as i16, pitch as i16, velocity)
+                {
+                    warn!("Event queue of {:?} is full, dropped {:?}", id, message);
+                }
+                continue;
+            }
+            MidiMessage::NoteOff {
+                channel,
+                pitch,
+                velocity,
+            } => {
+                if !plugin.events.add_note_off(
+                    sample_offset,
+                    channel as i16,
+                    pitch as i16,
+                    velocity,
+                ) {
+                    warn!("Event queue of {:?} is full, dropped {:?}", id, message);
+                }
+                continue;
+            }
+            MidiMessage::ControlChange {
+                channel,
+                controller,
+                value,
+            } => (channel, controller as i16, value),
+            MidiMessage::PitchBend { channel, value } => {
+                (channel, ControllerNumbers::PitchBend, value)
+            }
+        };
 
-    /// Get the current input device
-    pub fn input_device(&self) -> Option<&Device> {
-        self.input_device.as_ref()
+        let Some(param_id) = plugin.midi_controller_param(channel as i16, controller) else {
+            continue;
+        };
+        if !plugin
+            .events
+            .add_param_point(param_id, sample_offset, value as f64)
+        {
+            warn!("Event queue of {:?} is full, dropped {:?}", id, message);
+        }
     }
+}
 
-    /// Get the current input device name
-    pub fn input_device_name(&self) -> Option<String> {
-        self.input_device.as_ref()?.name().ok()
+/// Mean square of the first `frames` samples of the first `channels` channels
+fn mean_square(data: &[[f32; MAX_BLOCK_SIZE]; 2], frames: usize, channels: usize) -> f32 {
+    let sum = data
+        .iter()
+        .take(channels)
+        .flat_map(|channel| &channel[..frames])
+        .map(|sample| sample * sample)
+        .sum::<f32>();
+    sum / (frames * channels).max(1) as f32
+}
+
+/// Absolute peak of the first `frames` samples of `channels` channels
+fn peak(data: &[[f32; MAX_BLOCK_SIZE]; 2], frames: usize, channels: usize) -> f32 {
+    data.iter()
+        .take(channels)
+        .flat_map(|channel| &channel[..frames])
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+}
+
+/// Returned by `load_plugin` when instantiation doesn't finish within the load timeout
+#[derive(Debug, thiserror::Error)]
+#[error("Plugin took longer than {0:?} to load")]
+pub struct PluginLoadTimeout(pub Duration);
+
+/// Last latency every plugin reported, to catch plugins that change it without
+/// calling restartComponent
+#[derive(Debug, Default)]
+struct LatencyTracker {
+    known: FxHashMap<PluginId, u32>,
+}
+
+impl LatencyTracker {
+    /// Record a polled latency, true if it differs from the last known one
+    fn update(&mut self, plugin_id: PluginId, latency: u32) -> bool {
+        self.known.insert(plugin_id, latency) != Some(latency)
     }
 
-    /// Get the current input configuration
-    pub fn input_config(&self) -> Option<&StreamConfig> {
-        self.input_config.as_ref()
+    fn latency(&self, plugin_id: PluginId) -> Option<u32> {
+        self.known.get(&plugin_id).copied()
     }
 
-    /// Get the current output device
-    pub fn output_device(&self) -> Option<&Device> {
-        self.output_device.as_ref()
+    fn remove(&mut self, plugin_id: PluginId) {
+        self.known.remove(&plugin_id);
     }
 
-    /// Get the current output device name
-    pub fn output_device_name(&self) -> Option<String> {
-        self.output_device.as_ref()?.name().ok()
+    fn clear(&mut self) {
+        self.known.clear();
     }
+}
 
-    /// Get the current output configuration
-    pub fn output_config(&self) -> Option<&StreamConfig> {
-        self.output_config.as_ref()
+/// Largest relative change adaptive resampling makes to the nominal ratio
+pub const MAX_RESAMPLE_NUDGE: f64 = 0.001;
+
+/// Keeps the ring between the streams near `target_fill` by nudging the resampling ratio,
+/// absorbing drift between input and output clocks running at nominally equal rates
+struct DriftCorrector {
+    enabled: Arc<AtomicBool>,
+    // Ratio the resampler currently uses, as f64 bits
+    effective_ratio: Arc<AtomicU64>,
+    nominal_ratio: f64,
+    target_fill: usize,
+}
+
+impl DriftCorrector {
+    /// Relative ratio for the current ring fill, above 1 while the ring drains and
+    /// below 1 while it fills up
+    fn relative_ratio(&self, fill: usize) -> f64 {
+        if !self.enabled.load(Ordering::Relaxed) || self.target_fill == 0 {
+            return 1.0;
+        }
+
+        let error = (self.target_fill as f64 - fill as f64) / self.target_fill as f64;
+        1.0 + (error * MAX_RESAMPLE_NUDGE).clamp(-MAX_RESAMPLE_NUDGE, MAX_RESAMPLE_NUDGE)
     }
 
-    /// Get the current sample rate
-    pub fn sample_rate(&self) -> u32 {
-        self.current_sample_rate
+    fn update(&self, resampler: &mut SincFixedIn<f32>, fill: usize) {
+        let relative = self.relative_ratio(fill);
+        if resampler
+            .set_resample_ratio_relative(relative, true)
+            .is_ok()
+        {
+            self.effective_ratio
+                .store((self.nominal_ratio * relative).to_bits(), Ordering::Relaxed);
+        }
     }
+}
 
-    /// Get the current buffer size
-    pub fn buffer_size(&self) -> u32 {
-        self.current_buffer_size
+/// Whether streams on `host` have the device to themselves. ASIO always does, cpal opens
+/// them in shared mode everywhere else, WASAPI included
+fn is_exclusive_host(host: HostId) -> bool {
+    #[cfg(target_os = "windows")]
+    return host == HostId::Asio;
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = host;
+        false
     }
+}
 
-    /// Select a different audio host
-    pub fn select_host(&mut self, host_name: &str) -> Result<()> {
-        // Stop current streams if running
-        self.stop_streams();
+/// Stored in the affinity request when the audio thread isn't pinned
+const NO_AFFINITY: usize = usize::MAX;
 
-        // Find and set the new host
-        let host_id = cpal::available_hosts()
-            .into_iter()
-            .find(|id| id.name() == host_name)
-            .ok_or_else(|| anyhow!("Host '{}' not found", host_name))?;
+/// Pins the audio callback thread it runs on to the requested core, again whenever
+/// the request changes. A thread stays pinned until its stream is rebuilt.
+struct AffinityPinner {
+    requested: Arc<AtomicUsize>,
+    applied: usize,
+}
 
-        self.host = cpal::host_from_id(host_id)?;
+impl AffinityPinner {
+    /// Returns true if the current thread was pinned by this call
+    fn apply(&mut self) -> bool {
+        let requested = self.requested.load(Ordering::Relaxed);
+        if requested == self.applied || requested == NO_AFFINITY {
+            return false;
+        }
 
-        // Reset devices and configs
-        // https://stackoverflow.com/questions/78319116/no-audio-input-via-asio-with-feedback-example-using-cpal
-        // Since ASIO expects input/output to be exclusive, they need to be the same device.
-        self.input_device = self.host.default_input_device();
-        #[cfg(target_os = "windows")]
-        if self.host.id() == cpal::HostId::Asio {
-            self.output_device = self.input_device.clone();
-        } else {
-            self.output_device = self.host.default_output_device();
+        self.applied = requested;
+        let pinned = core_affinity::set_for_current(core_affinity::CoreId { id: requested });
+        if !pinned {
+            warn!("Failed to pin the audio thread to core {}", requested);
         }
+        pinned
+    }
+}
 
-        #[cfg(target_os = "macos")]
-        if self.host.id() == cpal::HostId::CoreAudio {
-            self.input_device = self.output_device.clone();
-            self.input_config = self.output_config.clone();
+/// Sample rate and buffer size newly selected devices lean towards, unless the format
+/// strategy holds them to the engine's current ones
+const PREFERRED_SAMPLE_RATE: u32 = 48000;
+const PREFERRED_BUFFER_SIZE: u32 = 256;
+
+/// Pick the stream config for a newly selected device at the preferred (sample rate,
+/// buffer size), naming the device in any error instead of panicking when it can't report
+/// or has no usable configs
+fn select_device_config<I>(
+    device_name: &str,
+    direction: &str,
+    configs: Result<I, cpal::SupportedStreamConfigsError>,
+    strategy: FormatStrategy,
+    (sample_rate, buffer_size): (u32, u32),
+) -> Result<SupportedStreamConfig>
+where
+    I: Iterator<Item = SupportedStreamConfigRange>,
+{
+    let configs = configs.with_context(|| {
+        format!(
+            "Failed to query {} configurations of device '{}'",
+            direction, device_name
+        )
+    })?;
+
+    pick_best_format(
+        configs,
+        Some(sample_rate),
+        Some(buffer_size),
+        Some(SampleFormat::I32),
+        Some(2),
+        strategy,
+    )
+    .ok_or_else(|| {
+        anyhow!(
+            "No supported {} configurations for device '{}'",
+            direction,
+            device_name
+        )
+    })
+}
+
+/// Sample formats of the config ranges without repeats, in the order they're listed
+fn distinct_sample_formats(configs: &[SupportedStreamConfigRange]) -> Vec<SampleFormat> {
+    let mut formats = Vec::new();
+    for config in configs {
+        if !formats.contains(&config.sample_format()) {
+            formats.push(config.sample_format());
         }
+    }
+    formats
+}
 
-        // Update configs if devices are available
-        if let Some(ref device) = self.input_device {
-            self.input_config = device.default_input_config().ok().map(|c| c.into());
+/// Whether a selected device is no longer listed, or can't even report its name
+fn device_missing(device: Option<&Device>, available: &[String]) -> bool {
+    device.is_some_and(|device| {
+        device
+            .name()
+            .map_or(true, |name| !available.contains(&name))
+    })
+}
+
+/// Devices and MIDI inputs listed right now. Listing can take a while on some drivers,
+/// so it is done without the engine, which only applies the result in `check_devices`,
+/// `check_midi_inputs` and `recover_default_devices`.
+#[derive(Debug, Clone)]
+pub struct DeviceScan {
+    host: HostId,
+    inputs: Option<Vec<String>>,
+    outputs: Option<Vec<String>>,
+    default_input: Option<String>,
+    default_output: Option<String>,
+    midi_inputs: Option<Vec<String>>,
+}
+
+impl DeviceScan {
+    /// List the devices of `host`, see `AudioEngine::shared_host`, and the MIDI inputs
+    pub fn scan(host: &cpal::Host) -> Self {
+        fn names(
+            devices: Result<impl Iterator<Item = Device>, cpal::DevicesError>,
+        ) -> Option<Vec<String>> {
+            Some(devices.ok()?.filter_map(|d| d.name().ok()).collect())
         }
-        if let Some(ref device) = self.output_device {
-            self.output_config = device.default_output_config().ok().map(|c| c.into());
+
+        Self {
+            host: host.id(),
+            inputs: names(host.input_devices()),
+            outputs: names(host.output_devices()),
+            default_input: host.default_input_device().and_then(|d| d.name().ok()),
+            default_output: host.default_output_device().and_then(|d| d.name().ok()),
+            midi_inputs: midi::input_port_names().ok(),
         }
+    }
+}
 
-        // Update current settings
-        self.update_current_settings();
-        self.update_process_data();
+/// Returned by `run` when the streams can't start, a missing input device runs output only
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum EngineError {
+    #[error("No output device selected")]
+    NoOutputDevice,
+    #[error("Buffer of {frames} frames exceeds the maximum block size of {max}")]
+    BlockTooLarge { frames: usize, max: usize },
+    #[error("Buffer of {frames} frames is below the minimum block size of {min}")]
+    BlockTooSmall { frames: usize, min: usize },
+}
 
-        info!("Selected host: {}", host_name);
-        Ok(())
+/// Range of buffer sizes every device accepts, each given by its configs. A device with
+/// any config of unknown limits doesn't restrict it, `None` if no device does.
+fn common_buffer_size_range<'a>(
+    devices: impl Iterator<Item = &'a [SupportedStreamConfigRange]>,
+) -> Option<(u32, u32)> {
+    devices
+        .filter_map(|configs| {
+            configs
+                .iter()
+                .map(|config| match config.buffer_size() {
+                    cpal::SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+                    cpal::SupportedBufferSize::Unknown => None,
+                })
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .reduce(|(min, max), (other_min, other_max)| {
+                    (min.min(other_min), max.max(other_max))
+                })
+        })
+        .reduce(|(min, max), (other_min, other_max)| (min.max(other_min), max.min(other_max)))
+}
+
+/// Clamp a buffer size into the devices' supported range, see `BUFFER_SIZE_CLAMP_FACTOR`
+fn fit_buffer_size(buffer_size: u32, range: Option<(u32, u32)>) -> Result<u32> {
+    let Some((min, max)) = range else {
+        return Ok(buffer_size);
+    };
+    if min > max {
+        return Err(anyhow!(
+            "The input and output devices have no buffer size in common"
+        ));
     }
 
-    /// Select a specific input device
-    pub fn select_input(&mut self, device_name: &str) -> Result<()> {
-        self.stop_streams();
+    if buffer_size < min / BUFFER_SIZE_CLAMP_FACTOR
+        || buffer_size > max.saturating_mul(BUFFER_SIZE_CLAMP_FACTOR)
+    {
+        return Err(anyhow!(
+            "Buffer size {} is out of the devices' range of {}-{}",
+            buffer_size,
+            min,
+            max
+        ));
+    }
 
-        info!("Stopping streams");
+    let fitted = buffer_size.clamp(min, max);
+    if fitted != buffer_size {
+        warn!(
+            "Buffer size {} is out of the devices' range of {}-{}, using {}",
+            buffer_size, min, max, fitted
+        );
+    }
+    Ok(fitted)
+}
 
-        // Reset devices to None first
-        if self.host.id() == HostId::Asio {
-            self.input_device = None;
+/// Check that one of a device's configs covers a sample rate, the error lists the ranges
+/// they do cover
+fn check_rate_supported(
+    configs: &[SupportedStreamConfigRange],
+    sample_rate: u32,
+) -> Result<(), String> {
+    if configs.iter().any(|config| {
+        (config.min_sample_rate().0..=config.max_sample_rate().0).contains(&sample_rate)
+    }) {
+        return Ok(());
+    }
+
+    Err(configs
+        .iter()
+        .map(|config| {
+            format!(
+                "{}-{}",
+                config.min_sample_rate().0,
+                config.max_sample_rate().0
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Buffer size to run at given the DSP load measured at each size, sorted by size. Walks
+/// down from the largest size while the load stays under `ceiling`, then backs off one
+/// step from the smallest size that kept under it. The largest size if none did.
+fn pick_stable_buffer_size(loads: &[(u32, f32)], ceiling: f32) -> Option<u32> {
+    let stable = loads
+        .iter()
+        .rev()
+        .take_while(|(_, load)| *load < ceiling)
+        .count();
+
+    let index = match stable {
+        0 => loads.len().checked_sub(1)?,
+        stable => (loads.len() - stable + 1).min(loads.len() - 1),
+    };
+    Some(loads[index].0)
+}
+
+/// Reject buffer sizes too large to make sense for a device, and ones so small the rings
+/// and resamplers built from them would be degenerate
+fn check_block_size(frames: usize) -> Result<(), EngineError> {
+    if frames < MIN_BLOCK_SIZE {
+        return Err(EngineError::BlockTooSmall {
+            frames,
+            min: MIN_BLOCK_SIZE,
+        });
+    }
+    if frames > MAX_DEVICE_BLOCK_SIZE {
+        return Err(EngineError::BlockTooLarge {
+            frames,
+            max: MAX_DEVICE_BLOCK_SIZE,
+        });
+    }
+    Ok(())
+}
+
+/// Run `load` on a worker thread and wait at most `timeout` for it. On timeout the
+/// worker is abandoned, whatever it eventually returns is dropped on its own thread.
+/// A panic in `load` is resumed on the calling thread.
+fn run_with_timeout<T: Send + 'static>(
+    timeout: Duration,
+    load: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    let (sender, receiver) = mpsc::channel();
+
+    std::thread::Builder::new()
+        .name("plugin-loader".to_string())
+        .spawn(move || {
+            let _ = sender.send(std::panic::catch_unwind(std::panic::AssertUnwindSafe(load)));
+        })?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(Ok(result)) => result,
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(PluginLoadTimeout(timeout).into()),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!("Plugin loader thread exited")),
+    }
+}
+
+/// Inverse of the input callback's conversion, scaling by the same power of two so a
+/// device sample survives the round trip through f32 exactly
+fn loopback_sample(sample: f32) -> i32 {
+    (sample * i32::MAX as f32) as i32
+}
+
+/// Resolution of a sample format in bits, 32 for formats wider than the i32 streams
+fn sample_bits(format: SampleFormat) -> u32 {
+    match format {
+        // Held in 4 bytes, only the low 24 bits count
+        SampleFormat::I24 => 24,
+        _ => (format.sample_size() as u32 * 8).min(32),
+    }
+}
+
+/// Format a stream on a device running in `device_format` is opened with. 24 bit devices
+/// get their own format since drivers don't all widen it, everything else is i32.
+fn stream_sample_format(device_format: Option<SampleFormat>) -> SampleFormat {
+    match device_format {
+        Some(SampleFormat::I24) => SampleFormat::I24,
+        _ => <i32 as cpal::SizedSample>::FORMAT,
+    }
+}
+
+/// Move a 24 bit device sample to the top of the i32 stream format, where the callbacks'
+/// full scale is. The bits above the 24th are shifted out, so samples the driver padded
+/// instead of sign extending convert the same.
+fn i24_to_stream(sample: I24) -> i32 {
+    sample.inner() << 8
+}
+
+/// Inverse of `i24_to_stream`, the arithmetic shift sign extends the 24 bit value
+fn stream_to_i24(sample: i32) -> I24 {
+    I24::new_unchecked(sample >> 8)
+}
+
+/// Build an input stream in the device's format, handing `callback` the samples in the
+/// i32 stream format
+fn build_input_stream<F>(
+    device: &Device,
+    config: &StreamConfig,
+    device_format: Option<SampleFormat>,
+    mut callback: F,
+    error_callback: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    F: FnMut(&[i32]) + Send + 'static,
+{
+    match stream_sample_format(device_format) {
+        SampleFormat::I24 => {
+            // Converted in blocks the engine processes at once, larger device blocks are
+            // handed over in parts so the callback never allocates
+            let chunk = MAX_BLOCK_SIZE * config.channels as usize;
+            let mut converted = vec![0; chunk];
+            device.build_input_stream(
+                config,
+                move |data: &[I24], _: &cpal::InputCallbackInfo| {
+                    for block in data.chunks(chunk) {
+                        let converted = &mut converted[..block.len()];
+                        for (value, &sample) in converted.iter_mut().zip(block) {
+                            *value = i24_to_stream(sample);
+                        }
+                        callback(converted);
+                    }
+                },
+                error_callback,
+                None,
+            )
+        }
+        _ => device.build_input_stream(
+            config,
+            move |data: &[i32], _: &cpal::InputCallbackInfo| callback(data),
+            error_callback,
+            None,
+        ),
+    }
+}
+
+/// Build an output stream in the device's format, `callback` fills the buffer in the i32
+/// stream format
+fn build_output_stream<F>(
+    device: &Device,
+    config: &StreamConfig,
+    device_format: Option<SampleFormat>,
+    mut callback: F,
+    error_callback: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    F: FnMut(&mut [i32]) + Send + 'static,
+{
+    match stream_sample_format(device_format) {
+        SampleFormat::I24 => {
+            // Rendered in parts like the input, see `build_input_stream`
+            let chunk = MAX_BLOCK_SIZE * config.channels as usize;
+            let mut rendered = vec![0; chunk];
+            device.build_output_stream(
+                config,
+                move |data: &mut [I24], _: &cpal::OutputCallbackInfo| {
+                    for block in data.chunks_mut(chunk) {
+                        let rendered = &mut rendered[..block.len()];
+                        callback(rendered);
+                        for (sample, &value) in block.iter_mut().zip(rendered.iter()) {
+                            *sample = stream_to_i24(value);
+                        }
+                    }
+                },
+                error_callback,
+                None,
+            )
+        }
+        _ => device.build_output_stream(
+            config,
+            move |data: &mut [i32], _: &cpal::OutputCallbackInfo| callback(data),
+            error_callback,
+            None,
+        ),
+    }
+}
+
+/// Linear fade applied last in the processing callback. It ramps in when a stream starts
+/// and out once `stop_streams` sets `fading_out`, flagging `silent` when the output is
+/// down to zero, so restarting the streams leaves a short gap instead of a click.
+struct StreamFade {
+    fading_out: Arc<AtomicBool>,
+    silent: Arc<AtomicBool>,
+    gain: f32,
+    step: f32,
+}
+
+impl StreamFade {
+    fn new(fading_out: Arc<AtomicBool>, silent: Arc<AtomicBool>, sample_rate: f32) -> Self {
+        Self {
+            fading_out,
+            silent,
+            gain: 0.0,
+            step: 1.0 / (STREAM_FADE_SECS * sample_rate).max(1.0),
+        }
+    }
+
+    fn apply(&mut self, output: &mut [&mut [f32; MAX_BLOCK_SIZE]; 2], block_size: usize) {
+        let target = if self.fading_out.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            1.0
+        };
+        if self.gain == 1.0 && target == 1.0 {
+            return;
+        }
+
+        for i in 0..block_size {
+            self.gain = if target > self.gain {
+                (self.gain + self.step).min(target)
+            } else {
+                (self.gain - self.step).max(target)
+            };
+            for channel in output.iter_mut() {
+                channel[i] *= self.gain;
+            }
+        }
+
+        if self.gain == 0.0 && target == 0.0 {
+            self.silent.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Ring buffer xrun counters shared with the stream callbacks, see
+/// `AudioEngine::xrun_counters`
+#[derive(Debug, Clone)]
+pub struct XrunCounters {
+    overflow: Arc<AtomicU64>,
+    underrun: Arc<AtomicU64>,
+}
+
+impl XrunCounters {
+    /// Get the total number of xruns (overflows + underruns) since the last `run()`
+    pub fn count(&self) -> u64 {
+        self.overflow.load(Ordering::Relaxed) + self.underrun.load(Ordering::Relaxed)
+    }
+}
+
+/// Stream configs and chain latency as of the engine's last change to them, published
+/// for `EngineMeters`
+#[derive(Debug, Default)]
+struct StreamStatus {
+    input: Option<ActiveStreamConfig>,
+    output: Option<ActiveStreamConfig>,
+    // Real-time budget of one block in microseconds
+    budget_us: f32,
+    latency: u32,
+}
+
+/// Meters and stream status shared with the stream callbacks and the engine, see
+/// `AudioEngine::meters`
+#[derive(Clone)]
+pub struct EngineMeters {
+    plugin_modules: Arc<RwLock<FxHashMap<PluginId, VSTHostContext>>>,
+    output_peaks: Arc<[AtomicU32; 2]>,
+    output_correlation: Arc<AtomicU32>,
+    callback_load: Arc<CallbackLoad>,
+    xruns: XrunCounters,
+    running: Arc<AtomicBool>,
+    status: Arc<RwLock<StreamStatus>>,
+}
+
+impl EngineMeters {
+    /// Whether the output stream is running
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// See `AudioEngine::chain_cpu`
+    pub fn chain_cpu(&self) -> f32 {
+        let budget_us = self.status.read().unwrap().budget_us;
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .values()
+            .map(|plugin| timer_load(&plugin.process_time, budget_us))
+            .sum()
+    }
+
+    /// See `AudioEngine::headroom`
+    pub fn headroom(&self) -> f32 {
+        self.callback_load.headroom()
+    }
+
+    /// See `AudioEngine::is_overloaded`
+    pub fn is_overloaded(&self) -> bool {
+        self.callback_load.is_overloaded()
+    }
+
+    /// See `AudioEngine::output_levels`, both share the same peaks
+    pub fn output_levels(&self) -> [f32; 2] {
+        self.output_peaks
+            .each_ref()
+            .map(|peak| f32::from_bits(peak.swap(0, Ordering::Relaxed)))
+    }
+
+    /// See `AudioEngine::output_correlation`
+    pub fn output_correlation(&self) -> f32 {
+        f32::from_bits(self.output_correlation.load(Ordering::Relaxed))
+    }
+
+    /// See `AudioEngine::xrun_count`
+    pub fn xrun_count(&self) -> u64 {
+        self.xruns.count()
+    }
+
+    /// See `AudioEngine::chain_latency`
+    pub fn chain_latency(&self) -> u32 {
+        self.status.read().unwrap().latency
+    }
+
+    /// Config the running input stream was built with, None while stopped
+    pub fn active_input_config(&self) -> Option<ActiveStreamConfig> {
+        self.status.read().unwrap().input.clone()
+    }
+
+    /// Config the running output stream was built with, None while stopped
+    pub fn active_output_config(&self) -> Option<ActiveStreamConfig> {
+        self.status.read().unwrap().output.clone()
+    }
+}
+
+/// Internal helper to express a process timer's average as a percentage of a block's
+/// real-time budget
+fn timer_load(timer: &ProcessTimer, budget_us: f32) -> f32 {
+    if !budget_us.is_normal() {
+        return 0.0;
+    }
+    timer.average_micros() / budget_us * 100.0
+}
+
+/// Share of the block budget above which a block counts as overloaded
+const OVERLOAD_THRESHOLD: f32 = 0.8;
+
+/// Share of recent blocks that must be overloaded for the overload to count as sustained
+const SUSTAINED_OVERLOAD_SHARE: f32 = 0.25;
+
+/// How long the processing callback takes against its real-time budget, updated after
+/// every block. Catches a chain running close to the budget before it starts to xrun.
+#[derive(Debug, Default)]
+struct CallbackLoad {
+    // Moving averages of the share of the budget used and of blocks over the threshold,
+    // both stored as f32 bits
+    load: AtomicU32,
+    overloaded: AtomicU32,
+}
+
+impl CallbackLoad {
+    /// Weight of the newest block in the moving averages, about the last 50 blocks count
+    const SMOOTHING: f32 = 0.02;
+
+    fn record(&self, elapsed: Duration, budget: Duration) {
+        if budget.is_zero() {
+            return;
+        }
+        let load = elapsed.as_secs_f32() / budget.as_secs_f32();
+        let overloaded = if load > OVERLOAD_THRESHOLD { 1.0 } else { 0.0 };
+
+        for (average, sample) in [(&self.load, load), (&self.overloaded, overloaded)] {
+            let current = f32::from_bits(average.load(Ordering::Relaxed));
+            let next = current + Self::SMOOTHING * (sample - current);
+            average.store(next.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    fn reset(&self) {
+        self.load.store(0, Ordering::Relaxed);
+        self.overloaded.store(0, Ordering::Relaxed);
+    }
+
+    fn load(&self) -> f32 {
+        f32::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    fn overloaded_share(&self) -> f32 {
+        f32::from_bits(self.overloaded.load(Ordering::Relaxed))
+    }
+
+    fn headroom(&self) -> f32 {
+        ((1.0 - self.load()) * 100.0).clamp(0.0, 100.0)
+    }
+
+    fn is_overloaded(&self) -> bool {
+        self.overloaded_share() >= SUSTAINED_OVERLOAD_SHARE
+    }
+}
+
+/// Most worker threads processing parallel chains alongside the audio thread
+const MAX_CHAIN_WORKERS: usize = 7;
+
+/// Spins waiting for a chain worker before yielding the audio thread
+const CHAIN_WORKER_SPINS: u32 = 1000;
+
+/// Raise the calling thread to real-time priority like the audio callback's, so chain
+/// workers aren't preempted by ordinary threads mid-block. False if the OS refused
+#[cfg(windows)]
+fn raise_thread_priority() -> bool {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
+    };
+    unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) != 0 }
+}
+
+#[cfg(unix)]
+fn raise_thread_priority() -> bool {
+    unsafe {
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = libc::sched_get_priority_max(libc::SCHED_FIFO);
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) == 0
+    }
+}
+
+const WORKER_IDLE: u32 = 0;
+const WORKER_QUEUED: u32 = 1;
+const WORKER_DONE: u32 = 2;
+const WORKER_EXIT: u32 = 3;
+
+/// A chain handed to a worker for one block. The worker takes its own read guards, so a
+/// worker the audio thread stopped waiting for never outlives the chain state it reads
+struct ChainJob {
+    chain: ChainId,
+    // The audio thread's context for the block, the lane's plugins read their own copy
+    context: ProcessContext,
+    block_size: usize,
+    channels: usize,
+}
+
+/// Renderer over a worker's own buffers, and the buses its ProcessData points to
+type ChainLane = (ChainRenderer, [Arc<UnsafeCell<AudioBusBuffers>>; 2]);
+
+/// One worker's handoff with the audio thread. The audio thread owns the job and the lane
+/// while the state is idle or done, the worker while it's queued
+struct ChainSlot {
+    state: AtomicU32,
+    job: UnsafeCell<Option<ChainJob>>,
+    // Chain last queued, only touched by the audio thread
+    chain: UnsafeCell<Option<ChainId>>,
+    // Chains this worker has rendered
+    rendered: AtomicU64,
+    // Renderer over the worker's own buffers, sharing the chain state with the engine
+    lane: UnsafeCell<ChainRenderer>,
+    // Never filled, chains with resampled plugins stay on the audio thread
+    resamplers: UnsafeCell<FxHashMap<PluginId, PluginResampler>>,
+    // Keep the buses the lane's ProcessData points to alive
+    _buses: [Arc<UnsafeCell<AudioBusBuffers>>; 2],
+}
+
+// SAFETY: Access to the job and the lane is handed over through `state`, see above
+unsafe impl Send for ChainSlot {}
+unsafe impl Sync for ChainSlot {}
+
+impl ChainSlot {
+    /// Worker side, render the queued chain into the lane's output
+    unsafe fn run(&self) {
+        let Some(job) = (*self.job.get()).take() else {
+            return;
+        };
+
+        let lane = &mut *self.lane.get();
+        *lane.process_context.get() = job.context;
+
+        let plugin_modules = lane.plugin_modules.clone();
+        let plugin_order = lane.plugin_order.clone();
+        let bus_layouts = lane.bus_layouts.clone();
+        let (Ok(plugins), Ok(order), Ok(layouts)) = (
+            plugin_modules.try_read(),
+            plugin_order.try_read(),
+            bus_layouts.try_read(),
+        ) else {
+            lane.copy_input_to_output(job.block_size, job.channels);
+            return;
+        };
+
+        let _ftz = lane
+            .denormal_protection
+            .load(Ordering::Relaxed)
+            .then(DenormalGuard::new);
+        lane.render_chain(
+            &plugins,
+            &order,
+            &layouts,
+            &mut *self.resamplers.get(),
+            job.chain,
+            job.block_size,
+            job.channels,
+        );
+        self.rendered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether the worker is still on a chain, one the audio thread may have given up on
+    fn is_busy(&self) -> bool {
+        self.state.load(Ordering::Acquire) == WORKER_QUEUED
+    }
+}
+
+/// Threads processing independent parallel chains while the audio thread processes the
+/// rest. Workers park between blocks and are woken per block, so handing out a chain
+/// doesn't allocate or lock on the audio thread.
+struct ChainWorkers {
+    slots: Vec<Arc<ChainSlot>>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl ChainWorkers {
+    /// Spawn a worker per lane, each lane rendering into its own buffers
+    fn new(lanes: Vec<ChainLane>) -> Result<Self> {
+        let mut workers = Self {
+            slots: Vec::with_capacity(lanes.len()),
+            threads: Vec::with_capacity(lanes.len()),
+        };
+
+        for (index, (lane, buses)) in lanes.into_iter().enumerate() {
+            let slot = Arc::new(ChainSlot {
+                state: AtomicU32::new(WORKER_IDLE),
+                job: UnsafeCell::new(None),
+                chain: UnsafeCell::new(None),
+                rendered: AtomicU64::new(0),
+                lane: UnsafeCell::new(lane),
+                resamplers: UnsafeCell::new(FxHashMap::default()),
+                _buses: buses,
+            });
+
+            let worker = slot.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("chain-worker-{}", index))
+                .spawn(move || {
+                    if !raise_thread_priority() {
+                        warn!(
+                            "Failed to raise chain worker {} to real-time priority",
+                            index
+                        );
+                    }
+
+                    loop {
+                        match worker.state.load(Ordering::Acquire) {
+                            WORKER_QUEUED => {
+                                let run = std::panic::AssertUnwindSafe(|| unsafe { worker.run() });
+                                if std::panic::catch_unwind(run).is_err() {
+                                    error!("Chain worker {} panicked processing a chain", index);
+                                }
+                                worker.state.store(WORKER_DONE, Ordering::Release);
+                            }
+                            WORKER_EXIT => return,
+                            _ => std::thread::park(),
+                        }
+                    }
+                })?;
+
+            workers.slots.push(slot);
+            workers.threads.push(thread);
+        }
+
+        Ok(workers)
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Chains still rendering on a worker after the audio thread gave up waiting for them.
+    /// Every chain is waited for within its block, so a worker busy between blocks is late
+    unsafe fn late_chains(&self) -> impl Iterator<Item = ChainId> + '_ {
+        self.slots
+            .iter()
+            .filter(|slot| slot.is_busy())
+            .filter_map(|slot| *slot.chain.get())
+    }
+
+    /// Queue a chain on worker `index`, starting from `input`. The worker must be free
+    unsafe fn dispatch(
+        &self,
+        index: usize,
+        job: ChainJob,
+        input: &[[f32; MAX_BLOCK_SIZE]; 2],
+        main: &ProcessData,
+        sample_rate: f32,
+    ) {
+        let slot = &self.slots[index];
+        let lane = &mut *slot.lane.get();
+
+        let lane_input = lane.input_data.data.get();
+        for i in 0..job.block_size {
+            for j in 0..job.channels {
+                (*lane_input)[j][i] = input[j][i];
+            }
+        }
+
+        let data = Arc::as_ptr(&lane.process_data) as *mut ProcessData;
+        (*data).num_samples = main.num_samples;
+        (*data).process_mode = main.process_mode;
+        lane.sample_rate = sample_rate;
+
+        *slot.chain.get() = Some(job.chain);
+        *slot.job.get() = Some(job);
+        slot.state.store(WORKER_QUEUED, Ordering::Release);
+        self.threads[index].thread().unpark();
+    }
+
+    /// Wait for worker `index` to finish its chain and get the lane's output. None once
+    /// `deadline` passes, the worker is then left to finish the chain on its own
+    unsafe fn wait(
+        &self,
+        index: usize,
+        deadline: Instant,
+    ) -> Option<*mut [[f32; MAX_BLOCK_SIZE]; 2]> {
+        let slot = &self.slots[index];
+        let mut spins = 0;
+        while slot.state.load(Ordering::Acquire) != WORKER_DONE {
+            if spins < CHAIN_WORKER_SPINS {
+                spins += 1;
+                std::hint::spin_loop();
+            } else if Instant::now() < deadline {
+                std::thread::yield_now();
+            } else {
+                return None;
+            }
+        }
+        slot.state.store(WORKER_IDLE, Ordering::Relaxed);
+        Some((*slot.lane.get()).output_data.data.get())
+    }
+}
+
+impl Drop for ChainWorkers {
+    fn drop(&mut self) {
+        for (slot, thread) in self.slots.iter().zip(&self.threads) {
+            slot.state.store(WORKER_EXIT, Ordering::Release);
+            thread.thread().unpark();
+        }
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Whether a chain can render on a worker while the others render elsewhere. Its plugins
+/// must only touch their own buffers: no bus layout, resampler, monitor tap, or feeding
+/// another plugin's sidechain
+fn runs_independently(
+    plugins: &FxHashMap<PluginId, VSTHostContext>,
+    order: &[PluginId],
+    layouts: &FxHashMap<PluginId, BusLayout>,
+    resamplers: &FxHashMap<PluginId, PluginResampler>,
+    tap: Option<PluginId>,
+    chain: ChainId,
+) -> bool {
+    processing_chain(plugins, order)
+        .filter(|(_, plugin)| plugin.chain == chain)
+        .all(|(id, _)| {
+            !layouts.contains_key(&id)
+                && !resamplers.contains_key(&id)
+                && tap != Some(id)
+                && !layouts
+                    .values()
+                    .any(|layout| layout.sidechain_source == Some(SidechainSource::Plugin(id)))
+        })
+}
+
+/// Shared state the input callback needs to run one block through the plugin chains
+struct ChainRenderer {
+    plugin_modules: Arc<RwLock<FxHashMap<PluginId, VSTHostContext>>>,
+    plugin_order: Arc<RwLock<Vec<PluginId>>>,
+    bus_layouts: Arc<RwLock<FxHashMap<PluginId, BusLayout>>>,
+    chains: Arc<RwLock<Vec<(ChainId, f32)>>>,
+    chain_delays: Arc<Mutex<FxHashMap<ChainId, DelayLine>>>,
+    // Set while parallel processing is on
+    chain_workers: Arc<Mutex<Option<ChainWorkers>>>,
+    plugin_resamplers: Arc<Mutex<FxHashMap<PluginId, PluginResampler>>>,
+    monitor_mix: Arc<AtomicU32>,
+    monitor_smoother: GainSmoother,
+    monitor_delay: Arc<Mutex<DelayLine>>,
+    // Compare against the bypassed chain, crossfaded when toggled
+    global_ab: Arc<AtomicBool>,
+    global_ab_match: Arc<LoudnessMatch>,
+    ab_smoother: GainSmoother,
+    processing_enabled: Arc<AtomicBool>,
+    denormal_protection: Arc<AtomicBool>,
+    output_limiter: Arc<AtomicBool>,
+    output_ceiling: Arc<AtomicU32>,
+    // Swap left and right, and fold to mono at half level, crossfaded when toggled
+    channel_swap: Arc<AtomicBool>,
+    mono_sum: Arc<AtomicBool>,
+    swap_smoother: GainSmoother,
+    mono_smoother: GainSmoother,
+    // Copy the input straight to the output, skipping the chain and every output stage
+    loopback: Arc<AtomicBool>,
+    fade: StreamFade,
+    // Output peaks per channel as f32 bits since they were last read
+    output_peaks: Arc<[AtomicU32; 2]>,
+    // Phase correlation of the output, published as f32 bits after every block
+    correlation: CorrelationMeter,
+    output_correlation: Arc<AtomicU32>,
+    callback_load: Arc<CallbackLoad>,
+    process_data: Arc<ProcessData>,
+    input_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    output_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    // Untouched input fed to every parallel chain, and the sum of their outputs
+    dry_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    mix_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    // Plugin whose wet output is copied into `tap_data` for the monitor output
+    monitor_tap: Arc<RwLock<Option<PluginId>>>,
+    tap: Option<PluginId>,
+    tap_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    // Only collected by `render_stems`
+    stems: Option<Stems>,
+    sample_rate: f32,
+    // Transport state plugins read through the ProcessData, rewritten before every block
+    tempo: Arc<AtomicU64>,
+    transport_playing: Arc<AtomicBool>,
+    transport_position: Arc<AtomicU64>,
+    // Owned by the renderer, so only the thread running it writes the context. Its main
+    // ProcessData points here, see `set_process_data`
+    process_context: Box<UnsafeCell<ProcessContext>>,
+    // Samples rendered since the renderer was built, playing or not
+    continuous_samples: u64,
+    recording_tap: Arc<Mutex<Option<RecordingTap>>>,
+    midi_timing: Arc<BlockTiming>,
+    #[cfg(feature = "debug-introspection")]
+    probe: Arc<PipelineProbe>,
+}
+
+impl ChainRenderer {
+    /// Render `input_data` into `output_data`, then blend the delayed dry input back in
+    /// when the monitor mix is below fully wet or the global A/B is comparing. Both are
+    /// smoothed, so changing them doesn't click.
+    unsafe fn render(&mut self, block_size: usize, channels: usize) {
+        self.update_process_context(block_size);
+        self.midi_timing
+            .start_block(Instant::now(), block_size, self.sample_rate);
+
+        if self.is_loopback() {
+            self.tap = None;
+            let tap = self.tap_data.data.get();
+            for i in 0..block_size {
+                for j in 0..channels {
+                    (*tap)[j][i] = 0.0;
+                }
+            }
+            self.copy_input_to_output(block_size, channels);
+            return;
+        }
+
+        let wet = f32::from_bits(self.monitor_mix.load(Ordering::Relaxed));
+        let compare = if self.global_ab.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            0.0
+        };
+        // Matching keeps measuring while the chain is heard, so the level is right as
+        // soon as the dry input is switched in
+        let comparing = compare > 0.0
+            || self.ab_smoother.current() > GLOBAL_AB_SETTLED
+            || self.global_ab_match.enabled();
+
+        // `dry_data` keeps the untouched input, the chains only ever read it. It goes
+        // through the delay even while fully wet, so the delay holds the latest input
+        // when the mix moves away from wet.
+        let input = self.input_data.data.get();
+        let dry = self.dry_data.data.get();
+        for i in 0..block_size {
+            for j in 0..channels {
+                (*dry)[j][i] = (*input)[j][i];
+            }
+        }
+
+        self.render_chains(block_size, channels);
+
+        // Line the dry input up with the chain's output. While the delay is being resized
+        // the dry input goes in as is and the mix holds where it was for the block.
+        let aligned = match self.monitor_delay.try_lock() {
+            Ok(mut delay) => {
+                for i in 0..block_size {
+                    let frame = delay.process([(*dry)[0][i], (*dry)[1][i]]);
+                    for (j, sample) in frame.into_iter().enumerate() {
+                        (*dry)[j][i] = sample;
+                    }
+                }
+                true
+            }
+            Err(_) => false,
+        };
+
+        if !self.processing_enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let output = self.output_data.data.get();
+        for i in 0..block_size {
+            let mix = if aligned {
+                self.monitor_smoother.next(wet)
+            } else {
+                self.monitor_smoother.current()
+            };
+            if mix < 1.0 {
+                for j in 0..channels {
+                    (*output)[j][i] = dry_wet((*dry)[j][i], (*output)[j][i], mix);
+                }
+            }
+        }
+
+        if comparing {
+            self.apply_global_ab(compare, block_size, channels);
+        }
+    }
+
+    /// Crossfade the output towards the dry input, lined up with it, while the global A/B
+    /// compares against the bypassed chain. With loudness matching the dry input is
+    /// brought to the level of the output, so the comparison isn't about loudness.
+    unsafe fn apply_global_ab(&mut self, target: f32, block_size: usize, channels: usize) {
+        let dry = &*self.dry_data.data.get();
+        let output = &mut *self.output_data.data.get();
+
+        let (start, end) = if self.global_ab_match.enabled() {
+            let output_ms = mean_square(output, block_size, channels);
+            let dry_ms = mean_square(dry, block_size, channels);
+            self.global_ab_match
+                .next_block(output_ms, dry_ms, block_size, self.sample_rate)
+        } else {
+            (1.0, 1.0)
+        };
+
+        let step = (end - start) / block_size as f32;
+        for i in 0..block_size {
+            let gain = start + step * i as f32;
+            let mix = self.ab_smoother.next(target);
+            for j in 0..channels {
+                output[j][i] = dry_wet(output[j][i], dry[j][i] * gain, mix);
+            }
+        }
+    }
+
+    /// Use `data` for the main buses, pointed at this renderer's process context
+    fn set_process_data(&mut self, data: ProcessData) {
+        self.process_data = Arc::new(ProcessData {
+            process_context: self.process_context.get(),
+            ..data
+        });
+    }
+
+    /// Describe the transport at the start of the block to the plugins, in 4/4 at the
+    /// transport tempo
+    unsafe fn update_process_context(&mut self, block_size: usize) {
+        let tempo = f64::from_bits(self.tempo.load(Ordering::Relaxed));
+        let position = self.transport_position.load(Ordering::Relaxed);
+        let sample_rate = self.sample_rate as f64;
+        let quarter_notes = position as f64 / sample_rate * tempo / 60.0;
+
+        // Plugins only trust the fields whose flag is set
+        let mut state = ProcessContextFlags::TempoValid
+            | ProcessContextFlags::TimeSigValid
+            | ProcessContextFlags::ProjectTimeMusicValid
+            | ProcessContextFlags::BarPositionValid
+            | ProcessContextFlags::ContTimeValid;
+        if self.transport_playing.load(Ordering::Relaxed) {
+            state |= ProcessContextFlags::Playing;
+        }
+
+        *self.process_context.get() = ProcessContext {
+            state,
+            sample_rate,
+            project_time_samples: position as i64,
+            continous_time_samples: self.continuous_samples as i64,
+            project_time_music: quarter_notes,
+            bar_position_music: (quarter_notes / BEATS_PER_BAR).floor() * BEATS_PER_BAR,
+            tempo,
+            time_sig_numerator: BEATS_PER_BAR as i32,
+            time_sig_denominator: 4,
+            ..ProcessContext::default()
+        };
+        self.continuous_samples += block_size as u64;
+    }
+
+    /// Move the transport on by a block and hand the block's final output to the
+    /// recording, if one is running. Called last in the processing callback.
+    fn advance_transport(&mut self, block_size: usize, channels: usize) {
+        let position = self.transport_playing.load(Ordering::Relaxed).then(|| {
+            self.transport_position
+                .fetch_add(block_size as u64, Ordering::Relaxed)
+        });
+
+        // Skipped for the block while a recording is being started or stopped
+        if let Ok(mut tap) = self.recording_tap.try_lock() {
+            if let Some(tap) = tap.as_mut() {
+                tap.capture(self.output_data.as_ref(), channels, position, block_size);
+            }
+        }
+    }
+
+    /// Silence while processing is disabled, otherwise every chain processes the same
+    /// input and their outputs are summed with per-chain gain
+    unsafe fn render_chains(&mut self, block_size: usize, channels: usize) {
+        let output = self.output_data.data.get();
+
+        // The tap stays silent unless the tapped plugin processes this block
+        self.tap = self.monitor_tap.try_read().ok().and_then(|tap| *tap);
+        let tap = self.tap_data.data.get();
+        for i in 0..block_size {
+            for j in 0..channels {
+                (*tap)[j][i] = 0.0;
+            }
+        }
+
+        if !self.processing_enabled.load(Ordering::Relaxed) {
+            for i in 0..block_size {
+                for j in 0..channels {
+                    (*output)[j][i] = 0.0;
+                }
+            }
+            return;
+        }
+
+        let plugin_modules = self.plugin_modules.clone();
+        let plugin_order = self.plugin_order.clone();
+        let bus_layouts = self.bus_layouts.clone();
+        let chains = self.chains.clone();
+        let plugin_resamplers = self.plugin_resamplers.clone();
+
+        let (Ok(plugins), Ok(order), Ok(layouts), Ok(chains), Ok(mut resamplers)) = (
+            plugin_modules.try_read(),
+            plugin_order.try_read(),
+            bus_layouts.try_read(),
+            chains.try_read(),
+            plugin_resamplers.try_lock(),
+        ) else {
+            self.copy_input_to_output(block_size, channels);
+            return;
+        };
+
+        let input = self.input_data.data.get();
+        let dry = self.dry_data.data.get();
+        let mix = self.mix_data.data.get();
+
+        // Sidechains fed from the main input get it now, ones fed from a plugin that won't
+        // process this block are silenced. Plugins copy their output over as they process
+        for layout in layouts.values() {
+            match layout.sidechain_source {
+                Some(SidechainSource::MainInput) => {
+                    layout.fill_sidechain(Some(&*input), block_size, channels);
+                }
+                Some(SidechainSource::Plugin(source))
+                    if !processing_chain(&plugins, &order).any(|(id, _)| id == source) =>
+                {
+                    layout.fill_sidechain(None, block_size, channels);
+                }
+                _ => {}
+            }
+        }
+
+        if chains.len() > 1 {
+            for i in 0..block_size {
+                for j in 0..channels {
+                    (*dry)[j][i] = (*input)[j][i];
+                    (*mix)[j][i] = 0.0;
+                }
+            }
+        }
+
+        // Skipped for the block if the compensation is being rebuilt
+        let chain_delays = self.chain_delays.clone();
+        let mut delays = chain_delays.try_lock().ok();
+
+        // Independent chains after the first go to the workers up front, the rest render
+        // here in order. Outputs are summed in chain order either way, so the mix is the
+        // same with or without the workers
+        let chain_workers = self.chain_workers.clone();
+        let workers = chain_workers.try_lock().ok();
+        let workers = workers
+            .as_ref()
+            .and_then(|workers| workers.as_ref())
+            .filter(|_| chains.len() > 1 && self.stems.is_none());
+        let mut dispatched = [None; MAX_CHAIN_WORKERS];
+        // Chains a worker is still on from an earlier block, left out until it's done
+        let mut late = [None; MAX_CHAIN_WORKERS];
+        // Waiting past the block's duration is an xrun anyway
+        let deadline =
+            Instant::now() + Duration::from_secs_f64(block_size as f64 / self.sample_rate as f64);
+        if let Some(workers) = workers {
+            for (slot, chain) in late.iter_mut().zip(workers.late_chains()) {
+                *slot = Some(chain);
+            }
+
+            let independent = chains
+                .iter()
+                .skip(1)
+                .map(|&(chain, _)| chain)
+                .filter(|&chain| {
+                    !late.contains(&Some(chain))
+                        && runs_independently(
+                            &plugins,
+                            &order,
+                            &layouts,
+                            &resamplers,
+                            self.tap,
+                            chain,
+                        )
+                });
+            let free = (0..workers.len()).filter(|&index| !workers.slots[index].is_busy());
+            for (index, chain) in free.zip(independent) {
+                let job = ChainJob {
+                    chain,
+                    context: *self.process_context.get(),
+                    block_size,
+                    channels,
+                };
+                workers.dispatch(index, job, &*dry, &self.process_data, self.sample_rate);
+                dispatched[index] = Some(chain);
+            }
+        }
+
+        for (index, &(chain, gain)) in chains.iter().enumerate() {
+            let worker = dispatched.iter().position(|&id| id == Some(chain));
+            let rendered = match (worker, workers) {
+                (Some(worker), Some(workers)) => workers.wait(worker, deadline),
+                _ if late.contains(&Some(chain)) => None,
+                _ => {
+                    if index > 0 {
+                        for i in 0..block_size {
+                            for j in 0..channels {
+                                (*input)[j][i] = (*dry)[j][i];
+                            }
+                        }
+                    }
+
+                    self.render_chain(
+                        &plugins,
+                        &order,
+                        &layouts,
+                        &mut resamplers,
+                        chain,
+                        block_size,
+                        channels,
+                    );
+                    Some(output)
+                }
+            };
+
+            // A chain its worker hasn't finished is silent for the block
+            let output = rendered.unwrap_or_else(|| {
+                for i in 0..block_size {
+                    for j in 0..channels {
+                        (*output)[j][i] = 0.0;
+                    }
+                }
+                output
+            });
+
+            if chains.len() == 1 {
+                if gain != 1.0 {
+                    for i in 0..block_size {
+                        for j in 0..channels {
+                            (*output)[j][i] *= gain;
+                        }
+                    }
+                }
+                return;
+            }
+
+            // Line the chain up with the one with the most latency
+            if let Some(delay) = delays.as_mut().and_then(|delays| delays.get_mut(&chain)) {
+                for i in 0..block_size {
+                    let frame = delay.process([(*output)[0][i], (*output)[1][i]]);
+                    for (j, sample) in frame.into_iter().enumerate().take(channels) {
+                        (*output)[j][i] = sample;
+                    }
+                }
+            }
+
+            for i in 0..block_size {
+                for j in 0..channels {
+                    (*mix)[j][i] += (*output)[j][i] * gain;
+                }
+            }
+        }
+
+        for i in 0..block_size {
+            for j in 0..channels {
+                (*output)[j][i] = (*mix)[j][i];
+            }
+        }
+    }
+
+    /// Run a single chain from `input_data` into `output_data`. An empty or fully
+    /// bypassed chain passes the input through dry
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn render_chain(
+        &mut self,
+        plugins: &FxHashMap<PluginId, VSTHostContext>,
+        order: &[PluginId],
+        layouts: &FxHashMap<PluginId, BusLayout>,
+        resamplers: &mut FxHashMap<PluginId, PluginResampler>,
+        chain: ChainId,
+        block_size: usize,
+        channels: usize,
+    ) {
+        let output = self.output_data.data.get();
+        let mut processed = false;
+
+        // Process plugins in chain order - each plugin's output becomes the next plugin's input.
+        // Skipped plugins let the previous output pass through untouched
+        for (plugin_id, plugin) in
+            processing_chain(plugins, order).filter(|(_, plugin)| plugin.chain == chain)
+        {
+            let Some(processor) = plugin.processor.as_ref() else {
+                continue;
+            };
+
+            // Plugins with extra buses or monitoring another output bus get their own bus
+            // layout. The pointer stays valid for the block since the renderer and the
+            // layouts hold the Arcs
+            let data = layouts.get(&plugin_id).map_or_else(
+                || Arc::as_ptr(&self.process_data),
+                |layout| Arc::as_ptr(&layout.process_data),
+            );
+
+            // Hand the plugin the events and parameter changes queued since its last block
+            let block = plugin.events.next_block();
+            plugin.parameters.drain_into(&mut block.parameters);
+            (*(data as *mut ProcessData)).input_parameter_changes =
+                &mut block.parameters as *mut HostParameterChanges as *mut _;
+            (*(data as *mut ProcessData)).input_events =
+                &mut block.events as *mut HostEventList as *mut _;
+            // Bus layouts are shared by every renderer, hand them this one's context. Blocks
+            // can be shorter than the buffer size the ProcessData was built for
+            (*(data as *mut ProcessData)).process_context = self.process_data.process_context;
+            (*(data as *mut ProcessData)).num_samples = block_size as i32;
+
+            // For the first plugin, input comes from the audio input
+            // For subsequent plugins, we need to copy the previous plugin's output to current input
+            if processed {
+                // Copy output_data to input_data for chaining
+                for i in 0..block_size {
+                    for j in 0..channels {
+                        let sample = (*output)[j][i];
+                        self.input_data.write(j, i, sample);
+                    }
+                }
+            }
+
+            let input_peak = peak(&*self.input_data.data.get(), block_size, channels);
+
+            // Level of what goes in, for loudness-matched bypass
+            let input_ms = plugin
+                .loudness_match
+                .enabled()
+                .then(|| mean_square(&*self.input_data.data.get(), block_size, channels));
+
+            // Clear the output buffer before processing
+            for i in 0..block_size {
+                for j in 0..channels {
+                    (*output)[j][i] = 0.0;
+                }
+            }
+
+            // Process the plugin, through its resampler when it runs at its own rate
+            let start = Instant::now();
+            match resamplers.get_mut(&plugin_id) {
+                Some(resampler) => {
+                    let ratio = resampler.ratio;
+                    resampler.process(&*data, block_size, |data| {
+                        // Changes arrive with the engine block, offsets in engine frames
+                        block.parameters.scale_offsets(ratio, data.num_samples);
+                        processor.process(data);
+                        // Events and changes only apply to the first plugin block of the
+                        // engine block
+                        block.clear();
+                    });
+                }
+                None => {
+                    processor.process(data as *mut _);
+                }
+            }
+            plugin.process_time.record(start.elapsed());
+            processed = true;
+
+            self.apply_plugin_mix(&plugin.mix, block_size, channels);
+
+            if let Some(input_ms) = input_ms {
+                let output_ms = mean_square(&*output, block_size, channels);
+                let (start, end) = plugin.loudness_match.next_block(
+                    input_ms,
+                    output_ms,
+                    block_size,
+                    self.sample_rate,
+                );
+                self.apply_gain_ramp(start, end, block_size, channels);
+            }
+
+            if let Some((start, end)) = plugin.insert_ramp.next_block() {
+                self.apply_gain_ramp(start, end, block_size, channels);
+            }
+
+            plugin
+                .io_meter
+                .record(input_peak, peak(&*output, block_size, channels));
+
+            // A sink later in the order gets this block, an earlier one gets it next block
+            for layout in layouts.values().filter(|layout| {
+                layout.sidechain_source == Some(SidechainSource::Plugin(plugin_id))
+            }) {
+                layout.fill_sidechain(Some(&*output), block_size, channels);
+            }
+
+            if self.tap == Some(plugin_id) {
+                let tap = self.tap_data.data.get();
+                for i in 0..block_size {
+                    for j in 0..channels {
+                        (*tap)[j][i] = (*output)[j][i];
+                    }
+                }
+            }
+
+            if let Some(stems) = self.stems.as_mut() {
+                let stem = stems.entry(plugin_id).or_default();
+                for i in 0..block_size {
+                    for j in 0..channels {
+                        stem.push((*output)[j][i]);
+                    }
+                }
+            }
+        }
+
+        if !processed {
+            self.copy_input_to_output(block_size, channels);
+        }
+    }
+
+    /// Scale the output by a gain ramping linearly from `start` to `end` over the block
+    unsafe fn apply_gain_ramp(&mut self, start: f32, end: f32, block_size: usize, channels: usize) {
+        let output = self.output_data.data.get();
+        let step = (end - start) / block_size as f32;
+        for i in 0..block_size {
+            let gain = start + step * i as f32;
+            for j in 0..channels {
+                (*output)[j][i] *= gain;
+            }
+        }
+    }
+
+    /// Blend a plugin's input, delayed by its latency, back into its output
+    unsafe fn apply_plugin_mix(&mut self, mix: &PluginMix, block_size: usize, channels: usize) {
+        let Some((start, end)) = mix.next_block() else {
+            return;
+        };
+        let Some(mut delay) = mix.try_delay() else {
+            return;
+        };
+
+        let input = &*self.input_data.data.get();
+        let output = &mut *self.output_data.data.get();
+        let step = (end - start) / block_size as f32;
+        for i in 0..block_size {
+            let wet = start + step * i as f32;
+            let dry = delay.process([input[0][i], input[1][i]]);
+            for j in 0..channels.min(2) {
+                output[j][i] = dry_wet(dry[j], output[j][i], wet);
+            }
+        }
+    }
+
+    unsafe fn copy_input_to_output(&mut self, block_size: usize, channels: usize) {
+        for i in 0..block_size {
+            for j in 0..channels {
+                (*self.output_data.data.get())[j][i] = (*self.input_data.data.get())[j][i];
+            }
+        }
+    }
+
+    fn is_loopback(&self) -> bool {
+        self.loopback.load(Ordering::Relaxed)
+    }
+
+    /// Swap and mono sum the first two output channels, fading between the plain and
+    /// processed signal for a few milliseconds whenever either is toggled
+    fn apply_channel_utilities(&mut self, block_size: usize, channels: usize) {
+        if self.is_loopback() {
+            return;
+        }
+
+        let swap = if self.channel_swap.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            0.0
+        };
+        let mono = if self.mono_sum.load(Ordering::Relaxed) {
+            1.0
+        } else {
+            0.0
+        };
+        let settled = |smoother: &GainSmoother| smoother.current().abs() < f32::EPSILON;
+        if channels < 2
+            || (swap == 0.0
+                && mono == 0.0
+                && settled(&self.swap_smoother)
+                && settled(&self.mono_smoother))
+        {
+            return;
+        }
+
+        let [left, right, ..] = self.output_data.as_mut_ref();
+        for i in 0..block_size {
+            let swap_mix = self.swap_smoother.next(swap);
+            let mono_mix = self.mono_smoother.next(mono);
+            let (l, r) = (left[i], right[i]);
+            let sum = (l + r) * 0.5;
+            left[i] = dry_wet(dry_wet(l, r, swap_mix), sum, mono_mix);
+            right[i] = dry_wet(dry_wet(r, l, swap_mix), sum, mono_mix);
+        }
+    }
+
+    /// Soft clip the output at the limiter ceiling, if the limiter is on
+    fn limit_output(&mut self, block_size: usize, channels: usize) {
+        if !self.output_limiter.load(Ordering::Relaxed) || self.is_loopback() {
+            return;
+        }
+
+        let ceiling = f32::from_bits(self.output_ceiling.load(Ordering::Relaxed));
+        for channel in self.output_data.as_mut_ref().iter_mut().take(channels) {
+            for sample in &mut channel[..block_size] {
+                *sample = soft_limit(*sample, ceiling);
+            }
+        }
+    }
+
+    /// Fade the stream in or out, see `StreamFade`. Loopback stays bit-exact
+    fn apply_stream_fade(&mut self, block_size: usize) {
+        if self.is_loopback() {
+            return;
+        }
+        self.fade.apply(self.output_data.as_mut_ref(), block_size);
+    }
+
+    /// Fold the block into the output peak and correlation meters
+    fn meter_output(&mut self, block_size: usize, channels: usize) {
+        let output = self.output_data.as_ref();
+        for (channel, peak) in output.iter().zip(self.output_peaks.iter()).take(channels) {
+            let block_peak = channel[..block_size]
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+            peak.fetch_max(block_peak.to_bits(), Ordering::Relaxed);
+        }
+
+        if channels == 0 {
+            return;
+        }
+        // Mono output is fully correlated with itself
+        let left = &output[0][..block_size];
+        let right = &output[channels.min(2) - 1][..block_size];
+        self.correlation.process(left, right);
+        self.output_correlation
+            .store(self.correlation.correlation().to_bits(), Ordering::Relaxed);
+    }
+
+    /// Record how much of the block's real-time budget processing it took since `started`
+    fn record_callback_load(&self, started: Instant, block_size: usize) {
+        let budget = Duration::from_secs_f32(block_size as f32 / self.sample_rate);
+        self.callback_load.record(started.elapsed(), budget);
+    }
+
+    /// Software denormal protection for targets without `HARDWARE_FTZ`
+    fn flush_denormals(&mut self, block_size: usize, channels: usize) {
+        for channel in self.output_data.as_mut_ref().iter_mut().take(channels) {
+            dsp::flush_denormals(&mut channel[..block_size]);
+        }
+    }
+
+    /// Queue the tapped block for the monitor stream as stereo frames, mono is duplicated
+    fn push_tap(&self, producer: &mut HeapProd<f32>, block_size: usize, channels: usize) {
+        let tap = self.tap_data.as_ref();
+        for i in 0..block_size {
+            let left = tap[0][i];
+            let right = if channels > 1 { tap[1][i] } else { left };
+            let _ = producer.try_push(left);
+            let _ = producer.try_push(right);
+        }
+    }
+}
+
+/// Main audio engine responsible for managing audio hosts, devices, and processing
+#[allow(dead_code)]
+pub struct AudioEngine {
+    // Current audio state, shared so devices can be listed without the engine
+    host: Arc<cpal::Host>,
+    input_device: Option<cpal::Device>,
+    output_device: Option<cpal::Device>,
+    input_config: Option<cpal::StreamConfig>,
+    output_config: Option<cpal::StreamConfig>,
+    input_sample_format: Option<SampleFormat>,
+    output_sample_format: Option<SampleFormat>,
+
+    // Audio streams, and the configs they were built with while running
+    input_stream: Option<cpal::Stream>,
+    output_stream: Option<cpal::Stream>,
+    active_input_config: Option<ActiveStreamConfig>,
+    active_output_config: Option<ActiveStreamConfig>,
+
+    // Secondary output playing a single plugin's wet signal
+    monitor_device: Option<cpal::Device>,
+    monitor_stream: Option<cpal::Stream>,
+    monitor_tap: Arc<RwLock<Option<PluginId>>>,
+    tap_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+
+    // Audio processing data
+    input_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    output_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    dry_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+    mix_data: Sync2DArray<f32, 2, MAX_BLOCK_SIZE>,
+
+    // VST processing components
+    in_bus: Arc<UnsafeCell<AudioBusBuffers>>,
+    out_bus: Arc<UnsafeCell<AudioBusBuffers>>,
+    // Empty changes the main ProcessData starts out with. Never written: the renderer
+    // points the ProcessData at the block each plugin drained its `ParameterQueue` into
+    input_params: Arc<UnsafeCell<HostParameterChanges>>,
+    process_data: Arc<ProcessData>,
+    // Realtime while streaming, Offline for the duration of `render_offline`
+    process_mode: ProcessMode,
+    plugin_modules: Arc<RwLock<FxHashMap<PluginId, VSTHostContext>>>,
+    plugin_order: Arc<RwLock<Vec<PluginId>>>,
+    bus_layouts: Arc<RwLock<FxHashMap<PluginId, BusLayout>>>,
+    // Parallel chains in summing order, with their gain into the mix
+    chains: Arc<RwLock<Vec<(ChainId, f32)>>>,
+    // Delays lining parallel chains up with the one with the most latency
+    chain_delays: Arc<Mutex<FxHashMap<ChainId, DelayLine>>>,
+    // Workers processing independent chains alongside the audio thread, see
+    // `set_parallel_processing`
+    chain_workers: Arc<Mutex<Option<ChainWorkers>>>,
+    // Share of processed signal in the output as f32 bits, and the delay lining the dry
+    // input up with the chain
+    monitor_mix: Arc<AtomicU32>,
+    monitor_delay: Arc<Mutex<DelayLine>>,
+    // Momentary comparison of the output against the dry input, see `toggle_global_ab`
+    global_ab: Arc<AtomicBool>,
+    global_ab_match: Arc<LoudnessMatch>,
+    // Plugins running at their own sample rate, and the resamplers wrapping them
+    plugin_rates: FxHashMap<PluginId, PluginRate>,
+    plugin_resamplers: Arc<Mutex<FxHashMap<PluginId, PluginResampler>>>,
+    latency_tracker: LatencyTracker,
+    latency_polling: bool,
+    chain_locked: bool,
+    // Whole chain snapshots to flip between
+    ab_snapshots: FxHashMap<AbSlot, ChainSnapshot>,
+    // When cleared the input callback skips the chain and feeds silence, keeping the streams up
+    processing_enabled: Arc<AtomicBool>,
+    // Flush denormals in the stream callbacks, FTZ/DAZ on x86 and a clamp elsewhere
+    denormal_protection: Arc<AtomicBool>,
+    plugin_load_timeout: Duration,
+    insert_ramp_blocks: u32,
+    // Plugins that failed re-setup since the last `take_setup_failures()`
+    setup_failures: Vec<PluginId>,
+
+    // Cached device information for performance
+    cached_hosts: Vec<HostId>,
+    cached_input_devices: FxHashMap<HostId, Vec<String>>,
+    cached_output_devices: FxHashMap<HostId, Vec<String>>,
+    cached_input_configs: FxHashMap<String, Vec<SupportedStreamConfigRange>>,
+    cached_output_configs: FxHashMap<String, Vec<SupportedStreamConfigRange>>,
+
+    // Current audio settings
+    current_sample_rate: u32,
+    current_buffer_size: u32,
+
+    // Number of device blocks buffered between the input and output streams
+    ring_latency_blocks: usize,
+    resampler_quality: ResamplerQuality,
+    // How device configurations are chosen when a device is selected
+    format_strategy: FormatStrategy,
+    // Nudge the resampling ratio to follow clock drift, and the ratio in use
+    adaptive_resampling: Arc<AtomicBool>,
+    effective_resample_ratio: Arc<AtomicU64>,
+
+    // Linear input trim applied before the chain, as f32 bits
+    input_gain: Arc<AtomicU32>,
+
+    // Soft clipper on the device output, its ceiling linear as f32 bits
+    output_limiter: Arc<AtomicBool>,
+    output_ceiling: Arc<AtomicU32>,
+    output_ceiling_db: f32,
+
+    // Built-in output utilities applied after the chain
+    channel_swap: Arc<AtomicBool>,
+    mono_sum: Arc<AtomicBool>,
+
+    // Bit-exact input to output passthrough for audio path tests
+    loopback_test_mode: Arc<AtomicBool>,
+    downmix: Downmix,
+    output_routing: Arc<Mutex<RoutingMatrix>>,
+    output_peaks: Arc<[AtomicU32; 2]>,
+    output_correlation: Arc<AtomicU32>,
+    // Time the processing callback takes against the block's real-time budget
+    callback_load: Arc<CallbackLoad>,
+    // Published for `EngineMeters` whenever the streams or the chain latency change
+    stream_running: Arc<AtomicBool>,
+    stream_status: Arc<RwLock<StreamStatus>>,
+
+    // Set to fade the running streams out before stopping them, and the callback's
+    // report that the output has gone silent
+    stream_fading_out: Arc<AtomicBool>,
+    stream_silent: Arc<AtomicBool>,
+
+    // TPDF dither before quantizing the output. Follows the output format until set
+    dither: Arc<AtomicBool>,
+    dither_overridden: bool,
+
+    // Exclusive device access requested for WASAPI, and whether the running streams got it
+    exclusive_mode: bool,
+    exclusive_granted: bool,
+
+    // Input high-pass cutoff in Hz, and its coefficients shared with the input callback
+    input_highpass_cutoff: Option<f32>,
+    input_highpass: Arc<RwLock<Option<BiquadCoefficients>>>,
+    // Test signal fed to the chain instead of the capture device
+    input_source: Arc<RwLock<InputSource>>,
+
+    // Ring buffer xrun counters, shared with the stream callbacks
+    overflow_count: Arc<AtomicU64>,
+    underrun_count: Arc<AtomicU64>,
+    // Editor edits of every plugin, shared with their parameter state
+    parameter_edits: Arc<EditQueue>,
+    // Frames queued in the ring after the last input callback, and the delay of the
+    // resampler between the callbacks in output frames, for `roundtrip_latency_ms`
+    ring_fill: Arc<AtomicUsize>,
+    stream_resampler_delay: usize,
+
+    // Pipeline state of the last block, for `debug_snapshot`
+    #[cfg(feature = "debug-introspection")]
+    probe: Arc<PipelineProbe>,
+
+    // Set by the stream error callbacks when the device disappears
+    device_lost: Arc<AtomicBool>,
+
+    // Core the processing callback pins itself to, `NO_AFFINITY` when unpinned
+    audio_thread_affinity: Arc<AtomicUsize>,
+
+    // Transport driving the MIDI clock output, tempo stored as f64 bits. The position
+    // counts the samples played since the transport was rewound.
+    tempo: Arc<AtomicU64>,
+    transport_playing: Arc<AtomicBool>,
+    transport_position: Arc<AtomicU64>,
+    midi_clock: Arc<Mutex<Option<MidiClockOut>>>,
+    // Recording of the output, the tap is taken by the processing callback
+    recording: Option<Recording>,
+    recording_tap: Arc<Mutex<Option<RecordingTap>>>,
+    // Selected MIDI inputs, and the ones of them currently open. A selected port that
+    // went away is reopened when it comes back, see `check_midi_inputs`
+    midi_inputs: Vec<String>,
+    midi_input_ports: Vec<MidiInputPort>,
+    // Block the audio thread is on, to place MIDI input within the next
+    midi_timing: Arc<BlockTiming>,
+}
+
+/// Device names and configs of every available host
+#[derive(Default)]
+struct DeviceCache {
+    hosts: Vec<HostId>,
+    input_devices: FxHashMap<HostId, Vec<String>>,
+    output_devices: FxHashMap<HostId, Vec<String>>,
+    input_configs: FxHashMap<String, Vec<SupportedStreamConfigRange>>,
+    output_configs: FxHashMap<String, Vec<SupportedStreamConfigRange>>,
+}
+
+impl DeviceCache {
+    /// Enumerate every available host. `current` is enumerated as is rather than opening
+    /// a second instance of its host, which some drivers don't allow
+    fn enumerate(current: Option<&cpal::Host>) -> Self {
+        let mut cache = Self::default();
+
+        for host_id in cpal::available_hosts() {
+            let opened;
+            let host = match current {
+                Some(host) if host.id() == host_id => host,
+                _ => {
+                    let Ok(host) = cpal::host_from_id(host_id) else {
+                        warn!("Failed to get host from id: {:?}", host_id.name());
+                        continue;
+                    };
+                    opened = host;
+                    &opened
+                }
+            };
+
+            cache.hosts.push(host_id);
+            cache.add_host(host);
+        }
+
+        cache
+    }
+
+    fn add_host(&mut self, host: &cpal::Host) {
+        // Cache input devices
+        if let Ok(input_devices) = host.input_devices() {
+            let device_names: Vec<String> = input_devices
+                .filter_map(|device| {
+                    let name = device.name().ok()?;
+
+                    // Cache input configs for this device
+                    if let Ok(configs) = device.supported_input_configs() {
+                        self.input_configs.insert(name.clone(), configs.collect());
+                    }
+
+                    Some(name)
+                })
+                .collect();
+            self.input_devices.insert(host.id(), device_names);
+        }
+
+        // Cache output devices
+        if let Ok(output_devices) = host.output_devices() {
+            let device_names: Vec<String> = output_devices
+                .filter_map(|device| {
+                    let name = device.name().ok()?;
+
+                    // Cache output configs for this device
+                    if let Ok(configs) = device.supported_output_configs() {
+                        self.output_configs.insert(name.clone(), configs.collect());
+                    }
+
+                    Some(name)
+                })
+                .collect();
+            self.output_devices.insert(host.id(), device_names);
+        }
+    }
+}
+
+impl Default for AudioEngine {
+    fn default() -> Self {
+        // Setup default host and devices
+        let host = cpal::default_host();
+        let input_device = host.default_input_device();
+        let output_device = host.default_output_device();
+
+        let input_default = input_device
+            .as_ref()
+            .and_then(|d| d.default_input_config().ok());
+        let output_default = output_device
+            .as_ref()
+            .and_then(|d| d.default_output_config().ok());
+
+        Self::from_devices(
+            host,
+            input_device,
+            input_default,
+            output_device,
+            output_default,
+        )
+    }
+}
+
+/// Internal helper to find the device an `AudioConfig` names and the stream format
+/// matching its sample rate, buffer size and channels exactly
+fn configured_device(
+    host: &cpal::Host,
+    config: &AudioConfig,
+    input: bool,
+) -> Result<(Device, SupportedStreamConfig)> {
+    let direction = if input { "Input" } else { "Output" };
+
+    let device = match &config.device {
+        Some(name) => {
+            let devices: Vec<Device> = if input {
+                host.input_devices()?.collect()
+            } else {
+                host.output_devices()?.collect()
+            };
+            devices
+                .into_iter()
+                .find(|d| d.name().is_ok_and(|n| &n == name))
+                .ok_or_else(|| anyhow!("{} device '{}' not found", direction, name))?
+        }
+        None => if input {
+            host.default_input_device()
+        } else {
+            host.default_output_device()
+        }
+        .ok_or_else(|| anyhow!("No default {} device", direction.to_lowercase()))?,
+    };
+    let name = device.name().unwrap_or_default();
+
+    let configs: Vec<SupportedStreamConfigRange> = if input {
+        device.supported_input_configs().map(|c| c.collect())
+    } else {
+        device.supported_output_configs().map(|c| c.collect())
+    }
+    .with_context(|| format!("Failed to query configurations of device '{}'", name))?;
+
+    let format = pick_best_format(
+        configs.into_iter(),
+        Some(config.sample_rate),
+        Some(config.buffer_size),
+        None,
+        Some(config.channels),
+        FormatStrategy::ExactOrFail,
+    )
+    .ok_or_else(|| {
+        anyhow!(
+            "{} device '{}' does not support {} channels at {} Hz with {} frames",
+            direction,
+            name,
+            config.channels,
+            config.sample_rate,
+            config.buffer_size
+        )
+    })?;
+
+    Ok((device, format))
+}
+
+impl AudioEngine {
+    /// Create an engine on the default host with the given devices and formats instead of
+    /// the host's defaults. A direction without a config has no device. Fails if a device
+    /// isn't found or doesn't support its config exactly, the rates and buffer sizes of
+    /// both directions must match.
+    pub fn with_config(input: Option<AudioConfig>, output: Option<AudioConfig>) -> Result<Self> {
+        if let (Some(input), Some(output)) = (&input, &output) {
+            if input.sample_rate != output.sample_rate {
+                return Err(anyhow!(
+                    "Input runs at {} Hz but output at {} Hz",
+                    input.sample_rate,
+                    output.sample_rate
+                ));
+            }
+            if input.buffer_size != output.buffer_size {
+                return Err(anyhow!(
+                    "Input buffer size {} differs from output buffer size {}",
+                    input.buffer_size,
+                    output.buffer_size
+                ));
+            }
+        }
+        for config in input.iter().chain(&output) {
+            check_block_size(config.buffer_size as usize)?;
+        }
+
+        let host = cpal::default_host();
+        let (input_device, input_format) = input
+            .as_ref()
+            .map(|config| configured_device(&host, config, true))
+            .transpose()?
+            .unzip();
+        let (output_device, output_format) = output
+            .as_ref()
+            .map(|config| configured_device(&host, config, false))
+            .transpose()?
+            .unzip();
+
+        let mut engine = Self::from_devices(
+            host,
+            input_device,
+            input_format,
+            output_device,
+            output_format,
+        );
+
+        if let Some(buffer_size) = input.or(output).map(|config| config.buffer_size) {
+            for config in [&mut engine.input_config, &mut engine.output_config]
+                .into_iter()
+                .flatten()
+            {
+                config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
+            }
+            engine.current_buffer_size = buffer_size;
+            engine.update_process_data();
+        }
+
+        Ok(engine)
+    }
+
+    /// Internal helper to build the engine around the picked devices and their formats
+    fn from_devices(
+        host: cpal::Host,
+        input_device: Option<Device>,
+        input_default: Option<SupportedStreamConfig>,
+        output_device: Option<Device>,
+        output_default: Option<SupportedStreamConfig>,
+    ) -> Self {
+        // Cache available hosts and their devices
+        let DeviceCache {
+            hosts: cached_hosts,
+            input_devices: cached_input_devices,
+            output_devices: cached_output_devices,
+            input_configs: cached_input_configs,
+            output_configs: cached_output_configs,
+        } = DeviceCache::enumerate(Some(&host));
+
+        let input_config: Option<StreamConfig> = input_default.as_ref().map(|c| c.config());
+        let output_config: Option<StreamConfig> = output_default.as_ref().map(|c| c.config());
+        let input_sample_format = input_default.as_ref().map(|c| c.sample_format());
+        let output_sample_format = output_default.as_ref().map(|c| c.sample_format());
+
+        let current_sample_rate = input_config
+            .as_ref()
+            .or(output_config.as_ref())
+            .map(|c| c.sample_rate.0)
+            .unwrap_or(48000);
+        let current_buffer_size = input_config
+            .as_ref()
+            .map(|c| match c.buffer_size {
+                cpal::BufferSize::Fixed(size) => size,
+                cpal::BufferSize::Default => 512,
+            })
+            .unwrap_or(512);
+
+        info!(
+            "Creating AudioEngine with:\n\tHost: {:?}\n\tInput: {:?}\n\tOutput: {:?}",
+            host.id(),
+            input_device.as_ref().and_then(|d| d.name().ok()),
+            output_device.as_ref().and_then(|d| d.name().ok())
+        );
+
+        // Initialize audio processing data
+        let mut input_data = Sync2DArray::<f32, 2, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
+        let mut output_data = Sync2DArray::<f32, 2, MAX_BLOCK_SIZE>::new(0.0f32, MAX_BLOCK_SIZE);
+
+        // Setup VST processing components
+        let in_bus = Arc::new(UnsafeCell::new(bus_buffers(&mut input_data)));
+        let out_bus = Arc::new(UnsafeCell::new(bus_buffers(&mut output_data)));
+
+        let input_params = Arc::new(UnsafeCell::new(HostParameterChanges::new()));
+
+        let process_data = Arc::new(ProcessData {
+            process_mode: ProcessMode::Realtime,
+            symbolic_sample_size: SymbolicSampleSize::Sample32,
+            num_samples: current_buffer_size as i32,
+            num_inputs: 1,
+            num_outputs: 1,
+            inputs: in_bus.get(),
+            outputs: out_bus.get(),
+            input_parameter_changes: input_params.get() as *mut _,
+            output_parameter_changes: std::ptr::null_mut(),
+            input_events: std::ptr::null_mut(),
+            output_events: std::ptr::null_mut(),
+            // Every renderer points its ProcessData at its own context
+            process_context: std::ptr::null_mut(),
+        });
+
+        let plugin_modules = Arc::new(RwLock::new(FxHashMap::default()));
+        let plugin_order = Arc::new(RwLock::new(Vec::new()));
+
+        Self {
+            host: Arc::new(host),
+            input_device,
+            output_device,
+            input_config,
+            output_config,
+            input_sample_format,
+            output_sample_format,
+            input_stream: None,
+            output_stream: None,
+            active_input_config: None,
+            active_output_config: None,
+            monitor_device: None,
+            monitor_stream: None,
+            monitor_tap: Arc::new(RwLock::new(None)),
+            tap_data: Sync2DArray::new(0.0f32, MAX_BLOCK_SIZE),
+            input_data,
+            output_data,
+            dry_data: Sync2DArray::new(0.0f32, MAX_BLOCK_SIZE),
+            mix_data: Sync2DArray::new(0.0f32, MAX_BLOCK_SIZE),
+            in_bus,
+            out_bus,
+            input_params,
+            process_data,
+            process_mode: ProcessMode::Realtime,
+            plugin_modules,
+            plugin_order,
+            bus_layouts: Arc::new(RwLock::new(FxHashMap::default())),
+            chains: Arc::new(RwLock::new(vec![(ChainId::MAIN, 1.0)])),
+            chain_delays: Arc::new(Mutex::new(FxHashMap::default())),
+            chain_workers: Arc::new(Mutex::new(None)),
+            monitor_mix: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            monitor_delay: Arc::new(Mutex::new(DelayLine::new(0))),
+            global_ab: Arc::new(AtomicBool::new(false)),
+            global_ab_match: Arc::new(LoudnessMatch::default()),
+            plugin_rates: FxHashMap::default(),
+            plugin_resamplers: Arc::new(Mutex::new(FxHashMap::default())),
+            latency_tracker: LatencyTracker::default(),
+            latency_polling: false,
+            chain_locked: false,
+            ab_snapshots: FxHashMap::default(),
+            processing_enabled: Arc::new(AtomicBool::new(true)),
+            denormal_protection: Arc::new(AtomicBool::new(true)),
+            plugin_load_timeout: DEFAULT_PLUGIN_LOAD_TIMEOUT,
+            insert_ramp_blocks: DEFAULT_INSERT_RAMP_BLOCKS,
+            setup_failures: Vec::new(),
+            cached_hosts,
+            cached_input_devices,
+            cached_output_devices,
+            cached_input_configs,
+            cached_output_configs,
+            current_sample_rate,
+            current_buffer_size,
+            ring_latency_blocks: DEFAULT_RING_LATENCY_BLOCKS,
+            resampler_quality: ResamplerQuality::default(),
+            format_strategy: FormatStrategy::default(),
+            adaptive_resampling: Arc::new(AtomicBool::new(false)),
+            effective_resample_ratio: Arc::new(AtomicU64::new(1.0f64.to_bits())),
+            input_gain: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            output_limiter: Arc::new(AtomicBool::new(false)),
+            output_ceiling: Arc::new(AtomicU32::new(
+                db_to_linear(DEFAULT_OUTPUT_CEILING_DB).to_bits(),
+            )),
+            output_ceiling_db: DEFAULT_OUTPUT_CEILING_DB,
+            channel_swap: Arc::new(AtomicBool::new(false)),
+            mono_sum: Arc::new(AtomicBool::new(false)),
+            loopback_test_mode: Arc::new(AtomicBool::new(false)),
+            downmix: Downmix::Identity,
+            output_routing: Arc::new(Mutex::new(RoutingMatrix::identity())),
+            output_peaks: Arc::new([AtomicU32::new(0), AtomicU32::new(0)]),
+            output_correlation: Arc::new(AtomicU32::new(0)),
+            callback_load: Arc::new(CallbackLoad::default()),
+            stream_running: Arc::new(AtomicBool::new(false)),
+            stream_status: Arc::new(RwLock::new(StreamStatus::default())),
+            stream_fading_out: Arc::new(AtomicBool::new(false)),
+            stream_silent: Arc::new(AtomicBool::new(false)),
+            dither: Arc::new(AtomicBool::new(false)),
+            dither_overridden: false,
+            exclusive_mode: false,
+            exclusive_granted: false,
+            input_highpass_cutoff: None,
+            input_highpass: Arc::new(RwLock::new(None)),
+            input_source: Arc::new(RwLock::new(InputSource::Device)),
+            overflow_count: Arc::new(AtomicU64::new(0)),
+            underrun_count: Arc::new(AtomicU64::new(0)),
+            parameter_edits: Arc::new(EditQueue::default()),
+            ring_fill: Arc::new(AtomicUsize::new(0)),
+            stream_resampler_delay: 0,
+            #[cfg(feature = "debug-introspection")]
+            probe: Arc::new(PipelineProbe::default()),
+            device_lost: Arc::new(AtomicBool::new(false)),
+            audio_thread_affinity: Arc::new(AtomicUsize::new(NO_AFFINITY)),
+            tempo: Arc::new(AtomicU64::new(DEFAULT_TEMPO.to_bits())),
+            transport_playing: Arc::new(AtomicBool::new(false)),
+            transport_position: Arc::new(AtomicU64::new(0)),
+            midi_clock: Arc::new(Mutex::new(None)),
+            recording: None,
+            recording_tap: Arc::new(Mutex::new(None)),
+            midi_inputs: Vec::new(),
+            midi_input_ports: Vec::new(),
+            midi_timing: Arc::new(BlockTiming::default()),
+        }
+    }
+}
+
+// SAFETY: AudioEngine uses UnsafeCell for audio processing, but the design ensures
+// that these are only accessed from the audio thread in a controlled manner.
+// The Arc<UnsafeCell<_>> pattern is safe when properly synchronized.
+unsafe impl Send for AudioEngine {}
+unsafe impl Sync for AudioEngine {}
+
+impl AudioEngine {
+    /// Get all available audio hosts
+    pub fn available_hosts(&self) -> &[HostId] {
+        &self.cached_hosts
+    }
+
+    /// Get all available audio host names
+    pub fn available_host_names(&self) -> Vec<String> {
+        self.cached_hosts
+            .iter()
+            .map(|host_id| host_id.name().to_string())
+            .collect()
+    }
+
+    /// Look up one of the available hosts by name
+    pub fn available_host_by_name(&self, host_name: &str) -> Result<HostId> {
+        self.cached_hosts
+            .iter()
+            .copied()
+            .find(|id| id.name() == host_name)
+            .ok_or_else(|| anyhow!("Host '{}' not found", host_name))
+    }
+
+    /// Get available input devices for the current host
+    pub fn available_input_devices(&self) -> Result<Vec<Device>> {
+        Ok(self.host.input_devices()?.collect())
+    }
+
+    /// Get available input device names for the current host
+    pub fn available_input_device_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .host
+            .input_devices()?
+            .filter_map(|device| device.name().ok())
+            .collect())
+    }
+
+    /// Get available input configurations for a specific device
+    pub fn available_input_configs(
+        &self,
+        device_name: &str,
+    ) -> Option<&[SupportedStreamConfigRange]> {
+        self.cached_input_configs
+            .get(device_name)
+            .map(|v| v.as_slice())
+    }
+
+    /// Internal helper to collect every cached config of a device, input or output
+    fn device_configs(&self, device_name: &str) -> Vec<&SupportedStreamConfigRange> {
+        self.cached_input_configs
+            .get(device_name)
+            .into_iter()
+            .chain(self.cached_output_configs.get(device_name))
+            .flatten()
+            .collect()
+    }
+
+    /// Common sample rates the device supports, plus the exact rates of fixed-rate configs
+    pub fn supported_sample_rates(&self, device_name: &str) -> Vec<u32> {
+        let configs = self.device_configs(device_name);
+
+        let mut rates: Vec<u32> = COMMON_SAMPLE_RATES
+            .iter()
+            .copied()
+            .filter(|rate| {
+                configs.iter().any(|config| {
+                    (config.min_sample_rate().0..=config.max_sample_rate().0).contains(rate)
+                })
+            })
+            .chain(
+                configs
+                    .iter()
+                    .filter(|config| config.min_sample_rate() == config.max_sample_rate())
+                    .map(|config| config.min_sample_rate().0),
+            )
+            .collect();
+
+        rates.sort_unstable();
+        rates.dedup();
+        rates
+    }
+
+    /// Power of two buffer sizes the device supports, up to the engine's maximum buffer size
+    pub fn supported_buffer_sizes(&self, device_name: &str) -> Vec<u32> {
+        let configs = self.device_configs(device_name);
+
+        std::iter::successors(Some(MIN_BLOCK_SIZE as u32), |size| Some(size * 2))
+            .take_while(|size| *size as usize <= MAX_DEVICE_BLOCK_SIZE)
+            .filter(|size| {
+                configs.iter().any(|config| match config.buffer_size() {
+                    cpal::SupportedBufferSize::Range { min, max } => (*min..=*max).contains(size),
+                    cpal::SupportedBufferSize::Unknown => true,
+                })
+            })
+            .collect()
+    }
+
+    /// Get available output devices for the current host
+    pub fn available_output_devices(&self) -> Result<Vec<Device>> {
+        Ok(self.host.output_devices()?.collect())
+    }
+
+    /// Get available output device names for the current host
+    pub fn available_output_device_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .host
+            .output_devices()?
+            .filter_map(|device| device.name().ok())
+            .collect())
+    }
+
+    /// Get available output configurations for a specific device
+    pub fn available_output_configs(
+        &self,
+        device_name: &str,
+    ) -> Option<&[SupportedStreamConfigRange]> {
+        self.cached_output_configs
+            .get(device_name)
+            .map(|v| v.as_slice())
+    }
+
+    /// Get cached input device names for a specific host (more efficient)
+    pub fn cached_input_device_names(&self, host_id: &HostId) -> Option<&[String]> {
+        self.cached_input_devices.get(host_id).map(|v| v.as_slice())
+    }
+
+    /// Get cached output device names for a specific host (more efficient)
+    pub fn cached_output_device_names(&self, host_id: &HostId) -> Option<&[String]> {
+        self.cached_output_devices
+            .get(host_id)
+            .map(|v| v.as_slice())
+    }
+
+    /// Get cached input device names for the current host
+    pub fn cached_current_input_device_names(&self) -> Option<&[String]> {
+        self.cached_input_devices
+            .get(&self.host.id())
+            .map(|v| v.as_slice())
+    }
+
+    /// Get cached output device names for the current host
+    pub fn cached_current_output_device_names(&self) -> Option<&[String]> {
+        self.cached_output_devices
+            .get(&self.host.id())
+            .map(|v| v.as_slice())
+    }
+
+    /// Get the current host
+    pub fn host(&self) -> &cpal::Host {
+        &self.host
+    }
+
+    /// Get the current host to list its devices without holding on to the engine, see
+    /// `DeviceScan`. Selecting another host replaces it.
+    pub fn shared_host(&self) -> Arc<cpal::Host> {
+        self.host.clone()
+    }
+
+    /// Get the current host name
+    pub fn host_name(&self) -> &str {
+        self.host.id().name()
+    }
+
+    /// Get the current input device
+    pub fn input_device(&self) -> Option<&Device> {
+        self.input_device.as_ref()
+    }
+
+    /// Get the current input device name
+    pub fn input_device_name(&self) -> Option<String> {
+        self.input_device.as_ref()?.name().ok()
+    }
+
+    /// Get the current input configuration
+    pub fn input_config(&self) -> Option<&StreamConfig> {
+        self.input_config.as_ref()
+    }
+
+    /// Get the current output device
+    pub fn output_device(&self) -> Option<&Device> {
+        self.output_device.as_ref()
+    }
+
+    /// Get the current output device name
+    pub fn output_device_name(&self) -> Option<String> {
+        self.output_device.as_ref()?.name().ok()
+    }
+
+    /// Get the current output configuration
+    pub fn output_config(&self) -> Option<&StreamConfig> {
+        self.output_config.as_ref()
+    }
+
+    /// Config the running input stream was built with, None while stopped or output only
+    pub fn active_input_config(&self) -> Option<&ActiveStreamConfig> {
+        self.active_input_config.as_ref()
+    }
+
+    /// Config the running output stream was built with, None while stopped
+    pub fn active_output_config(&self) -> Option<&ActiveStreamConfig> {
+        self.active_output_config.as_ref()
+    }
+
+    /// Get the (input, output) sample formats of the selected stream configs
+    pub fn stream_formats(&self) -> Option<(SampleFormat, SampleFormat)> {
+        self.input_sample_format.zip(self.output_sample_format)
+    }
+
+    /// Sample formats the selected input device offers, from its cached configs
+    pub fn supported_input_formats(&self) -> Vec<SampleFormat> {
+        self.input_device_name()
+            .and_then(|name| self.cached_input_configs.get(&name))
+            .map_or_else(Vec::new, |configs| distinct_sample_formats(configs))
+    }
+
+    /// Sample formats the selected output device offers, from its cached configs
+    pub fn supported_output_formats(&self) -> Vec<SampleFormat> {
+        self.output_device_name()
+            .and_then(|name| self.cached_output_configs.get(&name))
+            .map_or_else(Vec::new, |configs| distinct_sample_formats(configs))
+    }
+
+    /// Get the current sample rate
+    pub fn sample_rate(&self) -> u32 {
+        self.current_sample_rate
+    }
+
+    /// Get the current buffer size
+    pub fn buffer_size(&self) -> u32 {
+        self.current_buffer_size
+    }
+
+    /// Get the number of device blocks buffered between input and output
+    pub fn ring_latency_blocks(&self) -> usize {
+        self.ring_latency_blocks
+    }
+
+    /// Set the number of device blocks to buffer between input and output (min 1).
+    /// Takes effect on the next `run()`
+    pub fn set_ring_latency_blocks(&mut self, blocks: usize) {
+        self.ring_latency_blocks = blocks.max(1);
+    }
+
+    /// Get the resampler quality preset
+    pub fn resampler_quality(&self) -> ResamplerQuality {
+        self.resampler_quality
+    }
+
+    /// Set the resampler quality preset. Takes effect on the next `run()`
+    pub fn set_resampler_quality(&mut self, quality: ResamplerQuality) {
+        self.resampler_quality = quality;
+        info!("Set resampler quality to: {}", quality.as_str());
+    }
+
+    pub fn format_strategy(&self) -> FormatStrategy {
+        self.format_strategy
+    }
+
+    /// Set how device configurations are chosen. Takes effect the next time a device
+    /// is selected
+    pub fn set_format_strategy(&mut self, strategy: FormatStrategy) {
+        self.format_strategy = strategy;
+        info!("Set format strategy to: {}", strategy.as_str());
+    }
+
+    pub fn exclusive_mode(&self) -> bool {
+        self.exclusive_mode
+    }
+
+    /// Ask for exclusive access to the device. cpal opens streams in shared mode on every
+    /// host but ASIO, which is always exclusive, so a request fails anywhere else. Nothing
+    /// needs restarting either way.
+    pub fn set_exclusive_mode(&mut self, enabled: bool) -> Result<()> {
+        let host = self.host.id();
+        if enabled && !is_exclusive_host(host) {
+            return Err(anyhow!(
+                "Exclusive mode isn't supported by cpal on the {} host",
+                host.name()
+            ));
+        }
+
+        self.exclusive_mode = enabled;
+        info!("Set exclusive mode to: {}", enabled);
+        Ok(())
+    }
+
+    /// Whether the running streams have the device to themselves
+    pub fn exclusive_mode_granted(&self) -> bool {
+        self.exclusive_granted
+    }
+
+    pub fn adaptive_resampling(&self) -> bool {
+        self.adaptive_resampling.load(Ordering::Relaxed)
+    }
+
+    /// Let the resampler follow drift between the input and output clocks, by up to
+    /// `MAX_RESAMPLE_NUDGE` around the nominal ratio
+    pub fn set_adaptive_resampling(&mut self, enabled: bool) {
+        self.adaptive_resampling.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Output over input sample rate of the selected configs
+    pub fn nominal_resample_ratio(&self) -> Option<f64> {
+        let input = self.input_config.as_ref()?;
+        let output = self.output_config.as_ref()?;
+        Some(output.sample_rate.0 as f64 / input.sample_rate.0 as f64)
+    }
+
+    /// Ratio the running resampler is using, including any adaptive nudge.
+    /// Nominal while the streams are stopped.
+    pub fn effective_resample_ratio(&self) -> Option<f64> {
+        if self.input_stream.is_none() {
+            return self.nominal_resample_ratio();
+        }
+        Some(f64::from_bits(
+            self.effective_resample_ratio.load(Ordering::Relaxed),
+        ))
+    }
+
+    fn drift_corrector(&self, nominal_ratio: f64, target_fill: usize) -> DriftCorrector {
+        self.effective_resample_ratio
+            .store(nominal_ratio.to_bits(), Ordering::Relaxed);
+
+        DriftCorrector {
+            enabled: self.adaptive_resampling.clone(),
+            effective_ratio: self.effective_resample_ratio.clone(),
+            nominal_ratio,
+            target_fill,
+        }
+    }
+
+    /// Linear gain applied to the device input before the plugin chain
+    pub fn input_gain(&self) -> f32 {
+        f32::from_bits(self.input_gain.load(Ordering::Relaxed))
+    }
+
+    /// Boost or cut the device input before it reaches the plugins. Changes are smoothed
+    /// in the callback, so this can be called while the streams are running.
+    pub fn set_input_gain(&mut self, linear: f32) -> Result<()> {
+        if !(linear.is_finite() && linear >= 0.0) {
+            return Err(anyhow!("Input gain must be a finite, non-negative value"));
+        }
+
+        self.input_gain.store(linear.to_bits(), Ordering::Relaxed);
+        info!("Set input gain to: {}", linear);
+        Ok(())
+    }
+
+    /// Share of processed signal in the output, 0 for dry input only and 1 for the
+    /// chain only
+    pub fn monitor_mix(&self) -> f32 {
+        f32::from_bits(self.monitor_mix.load(Ordering::Relaxed))
+    }
+
+    /// Blend the chain's output with the dry input, delayed by the chain latency so the
+    /// two stay aligned. Takes effect on the next block.
+    pub fn set_monitor_mix(&mut self, wet: f32) -> Result<()> {
+        if !(0.0..=1.0).contains(&wet) {
+            return Err(anyhow!("Monitor mix must be between 0 and 1, got {}", wet));
+        }
+
+        self.monitor_mix.store(wet.to_bits(), Ordering::Relaxed);
+        info!("Set monitor mix to: {}", wet);
+        Ok(())
+    }
+
+    /// Whether the global A/B is comparing against the bypassed chain
+    pub fn global_ab(&self) -> bool {
+        self.global_ab.load(Ordering::Relaxed)
+    }
+
+    /// Switch between the processed output and the dry input lined up with it, for a
+    /// quick check of what the whole chain does. Unlike bypass the chain keeps
+    /// processing, and the switch is a short crossfade. Returns whether the dry input
+    /// is heard now.
+    pub fn toggle_global_ab(&mut self) -> bool {
+        let dry = !self.global_ab.fetch_xor(true, Ordering::Relaxed);
+        info!(
+            "Global A/B on the {}",
+            if dry {
+                "bypassed chain"
+            } else {
+                "processed chain"
+            }
+        );
+        dry
+    }
+
+    /// Bring the dry input to the level of the processed output while comparing, so the
+    /// global A/B compares the sound rather than a change in loudness
+    pub fn set_global_ab_loudness_match(&mut self, enabled: bool) {
+        self.global_ab_match.set_enabled(enabled);
+        info!("Set global A/B loudness match to {}", enabled);
+    }
+
+    pub fn global_ab_loudness_match(&self) -> bool {
+        self.global_ab_match.enabled()
+    }
+
+    /// Whether left and right are swapped after the chain
+    pub fn channel_swap(&self) -> bool {
+        self.channel_swap.load(Ordering::Relaxed)
+    }
+
+    /// Swap left and right after the chain, for interfaces wired the wrong way round.
+    /// Crossfaded in the callback, so this can be called while the streams are running.
+    pub fn set_channel_swap(&mut self, enabled: bool) {
+        self.channel_swap.store(enabled, Ordering::Relaxed);
+        info!("Set channel swap to: {}", enabled);
+    }
+
+    /// Whether the output is summed to mono after the chain
+    pub fn mono_sum(&self) -> bool {
+        self.mono_sum.load(Ordering::Relaxed)
+    }
+
+    /// Sum left and right at half level into both channels to check mono compatibility.
+    /// Crossfaded in the callback, so this can be called while the streams are running.
+    pub fn set_mono_sum(&mut self, enabled: bool) {
+        self.mono_sum.store(enabled, Ordering::Relaxed);
+        info!("Set mono sum to: {}", enabled);
+    }
+
+    /// Whether the engine passes the input through bit-exact
+    pub fn loopback_test_mode(&self) -> bool {
+        self.loopback_test_mode.load(Ordering::Relaxed)
+    }
+
+    /// Pass the input to the output untouched for audio path tests. The plugins, input
+    /// gain, high-pass, monitor mix, channel utilities, limiter, denormal flushing and
+    /// dither are all skipped, samples are converted to and from float by a power of two,
+    /// and the resampler is bypassed when the input and output rates match. Device samples
+    /// of up to 24 bits then come out sample-for-sample identical. Takes effect on the
+    /// next block.
+    pub fn set_loopback_test_mode(&mut self, enabled: bool) {
+        self.loopback_test_mode.store(enabled, Ordering::Relaxed);
+        info!(
+            "Loopback test mode {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+
+    pub fn downmix_preset(&self) -> Downmix {
+        self.downmix
+    }
+
+    /// Route the chain output to the device channels through a preset matrix, see
+    /// `Downmix`. Takes effect on the next block.
+    pub fn set_downmix_preset(&mut self, preset: Downmix) {
+        *self.output_routing.lock().unwrap() = preset.matrix();
+        self.downmix = preset;
+        info!("Set downmix preset to: {}", preset.as_str());
+    }
+
+    /// Whether the output limiter is on, and its ceiling in dBFS
+    pub fn output_limiter(&self) -> (bool, f32) {
+        (
+            self.output_limiter.load(Ordering::Relaxed),
+            self.output_ceiling_db,
+        )
+    }
+
+    /// Soft clip the device output so it never exceeds `ceiling_db` dBFS, protecting
+    /// ears and speakers from a misbehaving plugin. Takes effect on the next block.
+    pub fn set_output_limiter(&mut self, enabled: bool, ceiling_db: f32) -> Result<()> {
+        if !(MIN_OUTPUT_CEILING_DB..=0.0).contains(&ceiling_db) {
+            return Err(anyhow!(
+                "Limiter ceiling must be between {} and 0 dBFS, got {}",
+                MIN_OUTPUT_CEILING_DB,
+                ceiling_db
+            ));
+        }
+
+        self.output_ceiling
+            .store(db_to_linear(ceiling_db).to_bits(), Ordering::Relaxed);
+        self.output_ceiling_db = ceiling_db;
+        self.output_limiter.store(enabled, Ordering::Relaxed);
+        info!(
+            "Output limiter {} at {} dBFS",
+            if enabled { "enabled" } else { "disabled" },
+            ceiling_db
+        );
+        Ok(())
+    }
+
+    /// Get the input high-pass cutoff in Hz, if enabled
+    pub fn input_highpass(&self) -> Option<f32> {
+        self.input_highpass_cutoff
+    }
+
+    /// Enable a high-pass on the input at the given cutoff in Hz, or disable it with `None`.
+    /// Removes DC offset and low rumble before the plugin chain.
+    pub fn set_input_highpass(&mut self, cutoff: Option<f32>) -> Result<()> {
+        if let Some(cutoff) = cutoff {
+            let nyquist = self.current_sample_rate as f32 / 2.0;
+            if !(cutoff > 0.0 && cutoff < nyquist) {
+                return Err(anyhow!(
+                    "High-pass cutoff {} Hz must be between 0 and {} Hz",
+                    cutoff,
+                    nyquist
+                ));
+            }
+        }
+
+        self.input_highpass_cutoff = cutoff;
+        self.update_input_highpass();
+
+        info!("Set input high-pass to: {:?}", cutoff);
+        Ok(())
+    }
+
+    pub fn input_source(&self) -> InputSource {
+        *self.input_source.read().unwrap()
+    }
+
+    /// Feed the chain a test signal instead of the capture device. While it's not the
+    /// device the streams run without capturing, so they also run with no input device.
+    /// Running streams are restarted when the capture stream has to be added or removed.
+    pub fn set_input_source(&mut self, source: InputSource) -> Result<()> {
+        let nyquist = self.current_sample_rate as f32 / 2.0;
+        match source {
+            InputSource::SineWave { freq, .. } if !(freq > 0.0 && freq < nyquist) => {
+                return Err(anyhow!(
+                    "Sine frequency {} Hz must be between 0 and {} Hz",
+                    freq,
+                    nyquist
+                ));
+            }
+            InputSource::SineWave { amp, .. } | InputSource::WhiteNoise { amp }
+                if !(0.0..=1.0).contains(&amp) =>
+            {
+                return Err(anyhow!("Amplitude {} must be between 0 and 1", amp));
+            }
+            _ => {}
+        }
+
+        *self.input_source.write().unwrap() = source;
+        info!("Set input source to: {:?}", source);
+
+        let capturing = self.active_input_config.is_some();
+        let capture = source == InputSource::Device
+            && self.input_device.is_some()
+            && self.input_config.is_some();
+        if self.is_running() && capturing != capture {
+            self.restart_streams()?;
+        }
+        Ok(())
+    }
+
+    /// Internal helper to recompute the input high-pass coefficients for the current sample rate
+    fn update_input_highpass(&mut self) {
+        // A cutoff above the new Nyquist frequency would make the filter unstable
+        let nyquist = self.current_sample_rate as f32 / 2.0;
+        let coefficients = self
+            .input_highpass_cutoff
+            .filter(|cutoff| *cutoff < nyquist)
+            .map(|cutoff| BiquadCoefficients::highpass(cutoff, self.current_sample_rate as f32));
+
+        *self.input_highpass.write().unwrap() = coefficients;
+    }
+
+    /// Compute the ring buffer capacity in samples for the current settings
+    fn ring_capacity(&self, channels: usize) -> Result<usize> {
+        let buffer_size = self.current_buffer_size as usize;
+        if buffer_size == 0 {
+            return Err(anyhow!("Cannot size ring buffer for a zero buffer size"));
+        }
+
+        let frames = buffer_size
+            .checked_mul(self.ring_latency_blocks)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Ring latency of {} blocks overflows",
+                    self.ring_latency_blocks
+                )
+            })?;
+
+        let max_frames = self.current_sample_rate as usize * MAX_RING_LATENCY_SECS;
+        if frames > max_frames {
+            return Err(anyhow!(
+                "Ring latency of {} blocks x {} samples exceeds {}s at {} Hz",
+                self.ring_latency_blocks,
+                buffer_size,
+                MAX_RING_LATENCY_SECS,
+                self.current_sample_rate
+            ));
+        }
+
+        Ok(frames * channels)
+    }
+
+    /// Time from a sample reaching the input device to it leaving the output device, in
+    /// milliseconds: both device buffers, the ring between the callbacks, the resampler's
+    /// delay and the chain's plugin latency. While the streams are stopped the ring counts
+    /// at the fill the drift correction aims for. Without a capture stream only the output
+    /// buffer and the plugins count.
+    pub fn roundtrip_latency_ms(&self) -> f32 {
+        let ms =
+            |frames: f64, sample_rate: u32| (frames * 1000.0 / sample_rate.max(1) as f64) as f32;
+        let buffer_frames = |config: &StreamConfig| match config.buffer_size {
+            cpal::BufferSize::Fixed(size) => size as f64,
+            cpal::BufferSize::Default => self.current_buffer_size as f64,
+        };
+
+        let mut latency = ms(self.chain_latency() as f64, self.current_sample_rate);
+        let Some(output) = &self.output_config else {
+            return latency;
+        };
+        latency += ms(buffer_frames(output), output.sample_rate.0);
+
+        let Some(input) = &self.input_config else {
+            return latency;
+        };
+        if self.input_source() != InputSource::Device {
+            return latency;
+        }
+        latency += ms(buffer_frames(input), input.sample_rate.0);
+
+        let (ring, resampler) = if self.input_stream.is_some() {
+            (
+                self.ring_fill.load(Ordering::Relaxed) as f64,
+                self.stream_resampler_delay as f64,
+            )
+        } else {
+            let ratio = output.sample_rate.0 as f64 / input.sample_rate.0 as f64;
+            let sinc_len = self.resampler_quality.sinc_parameters().sinc_len;
+            (
+                (self.ring_capacity(1).unwrap_or(0) / 2) as f64,
+                (sinc_len / 2) as f64 * ratio,
+            )
+        };
+        latency + ms(ring + resampler, output.sample_rate.0)
+    }
+
+    /// Get the number of input callbacks that dropped samples because the ring was full
+    pub fn overflow_count(&self) -> u64 {
+        self.overflow_count.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of output callbacks that zero-filled because the ring was empty
+    pub fn underrun_count(&self) -> u64 {
+        self.underrun_count.load(Ordering::Relaxed)
+    }
+
+    /// Get the total number of xruns (overflows + underruns) since the last `run()`
+    pub fn xrun_count(&self) -> u64 {
+        self.overflow_count() + self.underrun_count()
+    }
+
+    /// Get the xrun counters to poll without holding on to the engine. They stay the
+    /// same counters across `run()`, which resets them to zero.
+    pub fn xrun_counters(&self) -> XrunCounters {
+        XrunCounters {
+            overflow: self.overflow_count.clone(),
+            underrun: self.underrun_count.clone(),
+        }
+    }
+
+    /// Get the meters and stream status to poll without holding on to the engine
+    pub fn meters(&self) -> EngineMeters {
+        EngineMeters {
+            plugin_modules: self.plugin_modules.clone(),
+            output_peaks: self.output_peaks.clone(),
+            output_correlation: self.output_correlation.clone(),
+            callback_load: self.callback_load.clone(),
+            xruns: self.xrun_counters(),
+            running: self.stream_running.clone(),
+            status: self.stream_status.clone(),
+        }
+    }
+
+    /// Internal helper to publish the active configs, block budget and chain latency to
+    /// `EngineMeters`
+    fn publish_stream_status(&self) {
+        self.stream_running
+            .store(self.is_running(), Ordering::Relaxed);
+        let latency = self.chain_latency();
+        *self.stream_status.write().unwrap() = StreamStatus {
+            input: self.active_input_config.clone(),
+            output: self.active_output_config.clone(),
+            budget_us: self.block_budget_us(),
+            latency,
+        };
+    }
+
+    /// Whether the output stream is running
+    pub fn is_running(&self) -> bool {
+        self.output_stream.is_some()
+    }
+
+    /// Peak of each output channel since the last call
+    pub fn output_levels(&self) -> [f32; 2] {
+        self.output_peaks
+            .each_ref()
+            .map(|peak| f32::from_bits(peak.swap(0, Ordering::Relaxed)))
+    }
+
+    /// Phase correlation of the output over the last few hundred milliseconds, +1 for mono,
+    /// -1 for channels out of phase
+    pub fn output_correlation(&self) -> f32 {
+        f32::from_bits(self.output_correlation.load(Ordering::Relaxed))
+    }
+
+    /// Select a different audio host
+    pub fn select_host(&mut self, host_name: &str) -> Result<()> {
+        // Stop current streams if running
+        self.stop_streams();
+
+        // Find and set the new host
+        let host_id = cpal::available_hosts()
+            .into_iter()
+            .find(|id| id.name() == host_name)
+            .ok_or_else(|| anyhow!("Host '{}' not found", host_name))?;
+
+        self.host = Arc::new(cpal::host_from_id(host_id)?);
+        self.exclusive_mode &= is_exclusive_host(host_id);
+
+        // Reset devices and configs
+        // https://stackoverflow.com/questions/78319116/no-audio-input-via-asio-with-feedback-example-using-cpal
+        // Since ASIO expects input/output to be exclusive, they need to be the same device.
+        self.input_device = self.host.default_input_device();
+        #[cfg(target_os = "windows")]
+        if self.host.id() == cpal::HostId::Asio {
+            self.output_device = self.input_device.clone();
+        } else {
+            self.output_device = self.host.default_output_device();
+        }
+
+        #[cfg(target_os = "macos")]
+        if self.host.id() == cpal::HostId::CoreAudio {
+            self.input_device = self.output_device.clone();
+            self.input_config = self.output_config.clone();
+        }
+
+        // Update configs if devices are available
+        self.input_config = None;
+        self.output_config = None;
+        self.input_sample_format = None;
+        self.output_sample_format = None;
+        if let Some(config) = self
+            .input_device
+            .as_ref()
+            .and_then(|device| device.default_input_config().ok())
+        {
+            self.apply_input_config(config);
+        }
+        if let Some(config) = self
+            .output_device
+            .as_ref()
+            .and_then(|device| device.default_output_config().ok())
+        {
+            self.apply_output_config(config);
+        }
+
+        // Update current settings
+        self.update_current_settings();
+        self.update_process_data();
+
+        info!("Selected host: {}", host_name);
+        Ok(())
+    }
+
+    /// Select a specific input device
+    pub fn select_input(&mut self, device_name: &str) -> Result<()> {
+        self.stop_streams();
+
+        info!("Stopping streams");
+
+        // Reset devices to None first
+        if self.host.id() == HostId::Asio {
+            self.input_device = None;
+            self.output_device = None;
+            self.input_config = None;
+            self.output_config = None;
+            self.input_sample_format = None;
+            self.output_sample_format = None;
+
+            // Store current host ID to recreate it
+            let current_host_id = self.host.id();
+
+            // Recreate the host to refresh its internal state
+            self.host = Arc::new(cpal::host_from_id(current_host_id)?);
+        }
+
+        trace!(
+            "Reset host and devices, now selecting input device: {}",
+            device_name
+        );
+
+        let device = self
+            .host
+            .input_devices()?
+            .find(|d| d.name().map_or(false, |name| name == device_name))
+            .ok_or_else(|| {
+                info!("Input device '{}' not found", device_name);
+                anyhow!("Input device '{}' not found", device_name)
+            })?;
+
+        trace!(
+            "Supported input configs: {:?}",
+            device
+                .supported_input_configs()
+                .map(|m| m.collect::<Vec<_>>())
+                .unwrap_or_default()
+        );
+
+        let config = select_device_config(
+            device_name,
+            "input",
+            device.supported_input_configs(),
+            self.format_strategy,
+            self.preferred_stream_settings(),
+        )?;
+        self.apply_input_config(config);
+        //device.default_input_config().ok().map(|c| c.into());
+        self.input_device = Some(device);
+
+        // Handle ASIO/CoreAudio device exclusivity
+        #[cfg(target_os = "windows")]
+        if self.host.id() == cpal::HostId::Asio {
+            self.output_device = self.input_device.clone();
+            self.output_config = self.input_config.clone();
+            self.output_sample_format = self.input_sample_format;
+        }
+
+        #[cfg(target_os = "macos")]
+        if self.host.id() == cpal::HostId::CoreAudio {
+            self.output_device = self.input_device.clone();
+            self.output_config = self.input_config.clone();
+            self.output_sample_format = self.input_sample_format;
+        }
+
+        self.update_current_settings();
+        self.update_process_data();
+        info!("Selected input device: {}", device_name);
+        Ok(())
+    }
+
+    /// Select a specific output device
+    pub fn select_output(&mut self, device_name: &str) -> Result<()> {
+        self.stop_streams();
+
+        // Reset devices to None first
+        if self.host.id() == HostId::Asio {
+            self.input_device = None;
             self.output_device = None;
             self.input_config = None;
             self.output_config = None;
+            self.input_sample_format = None;
+            self.output_sample_format = None;
+
+            // Store current host ID to recreate it
+            let current_host_id = self.host.id();
+
+            // Recreate the host to refresh its internal state
+            self.host = Arc::new(cpal::host_from_id(current_host_id)?);
+        }
+
+        trace!(
+            "Reset host and devices, now selecting output device: {}",
+            device_name
+        );
+
+        let device = self
+            .host
+            .output_devices()?
+            .find(|d| d.name().map_or(false, |name| name == device_name))
+            .ok_or_else(|| anyhow!("Output device '{}' not found", device_name))?;
+
+        trace!(
+            "Supported output configs: {:?}",
+            device
+                .supported_output_configs()
+                .map(|m| m.collect::<Vec<_>>())
+                .unwrap_or_default()
+        );
+
+        let config = select_device_config(
+            device_name,
+            "output",
+            device.supported_output_configs(),
+            self.format_strategy,
+            self.preferred_stream_settings(),
+        )?;
+        self.apply_output_config(config);
+        //device.default_output_config().ok().map(|c| c.into());
+        self.output_device = Some(device);
+
+        // Handle ASIO/CoreAudio device exclusivity
+        #[cfg(target_os = "windows")]
+        if self.host.id() == cpal::HostId::Asio {
+            self.input_device = self.output_device.clone();
+            self.input_config = self.output_config.clone();
+            self.input_sample_format = self.output_sample_format;
+        }
+
+        #[cfg(target_os = "macos")]
+        if self.host.id() == cpal::HostId::CoreAudio {
+            self.input_device = self.output_device.clone();
+            self.input_config = self.output_config.clone();
+            self.input_sample_format = self.output_sample_format;
+        }
+
+        self.update_current_settings();
+        self.update_process_data();
+        info!("Selected output device: {}", device_name);
+        Ok(())
+    }
+
+    /// Internal helper for the (sample rate, buffer size) a newly selected device is asked
+    /// for. `ExactOrFail` holds it to the engine's current ones rather than the defaults.
+    fn preferred_stream_settings(&self) -> (u32, u32) {
+        match self.format_strategy {
+            FormatStrategy::ExactOrFail => (self.current_sample_rate, self.current_buffer_size),
+            _ => (PREFERRED_SAMPLE_RATE, PREFERRED_BUFFER_SIZE),
+        }
+    }
+
+    /// Internal helper for the stream config of a picked device config. Lowest latency
+    /// asks for the smallest buffer the device allows and an exact match for the one it
+    /// was matched against, otherwise the driver picks.
+    fn stream_config(&self, config: &SupportedStreamConfig) -> StreamConfig {
+        let buffer_size = match (self.format_strategy, config.buffer_size()) {
+            (FormatStrategy::LowestLatency, cpal::SupportedBufferSize::Range { min, max }) => {
+                cpal::BufferSize::Fixed((*min).max(MIN_BLOCK_SIZE as u32).min(*max))
+            }
+            (FormatStrategy::ExactOrFail, _) => {
+                cpal::BufferSize::Fixed(self.preferred_stream_settings().1)
+            }
+            _ => cpal::BufferSize::Default,
+        };
+
+        StreamConfig {
+            buffer_size,
+            ..config.config()
+        }
+    }
+
+    /// Internal helper to adopt a picked input config
+    fn apply_input_config(&mut self, config: SupportedStreamConfig) {
+        self.input_sample_format = Some(config.sample_format());
+        self.input_config = Some(self.stream_config(&config));
+    }
+
+    /// Internal helper to adopt a picked output config
+    fn apply_output_config(&mut self, config: SupportedStreamConfig) {
+        self.output_sample_format = Some(config.sample_format());
+        self.output_config = Some(self.stream_config(&config));
+    }
+
+    /// Set the sample rate, rejected if a selected device doesn't support it. Like the
+    /// buffer size it takes a stream rebuild on the next `run()`, with a short faded gap
+    /// in the output.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
+        self.check_sample_rate(sample_rate)?;
+        self.current_sample_rate = sample_rate;
+
+        // Update configs if devices are available
+        if let Some(ref mut config) = self.input_config {
+            config.sample_rate = cpal::SampleRate(sample_rate);
+        }
+        if let Some(ref mut config) = self.output_config {
+            config.sample_rate = cpal::SampleRate(sample_rate);
+        }
+
+        // Update ProcessData to reflect any changes
+        self.update_process_data();
+        self.update_input_highpass();
+        self.reconfigure_plugins();
+
+        info!("Set sample rate to: {}", sample_rate);
+        Ok(())
+    }
+
+    /// Check that both selected devices support a sample rate
+    pub fn check_sample_rate(&self, sample_rate: u32) -> Result<()> {
+        let devices = [
+            (
+                "Input",
+                self.input_device_name(),
+                &self.cached_input_configs,
+            ),
+            (
+                "Output",
+                self.output_device_name(),
+                &self.cached_output_configs,
+            ),
+        ];
+
+        for (direction, name, cached_configs) in devices {
+            let Some(name) = name else {
+                continue;
+            };
+            let Some(configs) = cached_configs.get(&name) else {
+                continue;
+            };
+
+            if let Err(ranges) = check_rate_supported(configs, sample_rate) {
+                return Err(anyhow!(
+                    "{} device '{}' does not support {} Hz (supported: {} Hz)",
+                    direction,
+                    name,
+                    sample_rate,
+                    ranges
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Range of buffer sizes both selected devices accept, going by their cached configs.
+    /// `None` when neither reports a range.
+    pub fn buffer_size_range(&self) -> Option<(u32, u32)> {
+        let devices = [
+            (self.input_device_name(), &self.cached_input_configs),
+            (self.output_device_name(), &self.cached_output_configs),
+        ];
+
+        common_buffer_size_range(
+            devices
+                .into_iter()
+                .filter_map(|(name, cached_configs)| cached_configs.get(&name?))
+                .map(|configs| configs.as_slice()),
+        )
+    }
+
+    /// Set the buffer size, clamped into the range the devices support. Devices can't
+    /// change it on a running stream, the next `run()` rebuilds the streams, leaving a
+    /// short faded gap in the output.
+    pub fn set_buffer_size(&mut self, buffer_size: u32) -> Result<()> {
+        check_block_size(buffer_size as usize)?;
+        let buffer_size = fit_buffer_size(buffer_size, self.buffer_size_range())?;
+        check_block_size(buffer_size as usize)?;
+        self.current_buffer_size = buffer_size;
+
+        // Update configs if devices are available
+        if let Some(ref mut config) = self.input_config {
+            config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
+        }
+        if let Some(ref mut config) = self.output_config {
+            config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
+        }
+
+        // Update ProcessData to reflect the new buffer size
+        self.update_process_data();
+
+        info!("Set buffer size to: {}", buffer_size);
+        Ok(())
+    }
+
+    /// Find the smallest buffer size the current chain runs at reliably. The chain is timed
+    /// offline on silence at each supported size, from the largest down, until its load
+    /// nears the real-time budget, see `pick_stable_buffer_size`. Stops the streams while
+    /// measuring and restarts them at the previous buffer size after, whether or not a
+    /// size was found. The returned size isn't applied.
+    pub fn auto_tune_buffer_size(&mut self) -> Result<u32> {
+        let previous_size = self.current_buffer_size;
+        let was_running = self.is_running();
+        self.stop_streams();
+
+        let tuned = self.measure_stable_buffer_size();
+
+        self.current_buffer_size = previous_size;
+        if was_running {
+            self.run()
+                .context("Failed to restart the streams after auto tuning")?;
+        }
+        tuned
+    }
+
+    /// Internal helper for `auto_tune_buffer_size`, timing the chain with the streams
+    /// stopped
+    fn measure_stable_buffer_size(&mut self) -> Result<u32> {
+        let mut candidates: Vec<u32> = match self.output_device_name() {
+            Some(name) => self.supported_buffer_sizes(&name),
+            None => std::iter::successors(Some(MIN_BLOCK_SIZE as u32), |size| Some(size * 2))
+                .take_while(|size| *size as usize <= MAX_DEVICE_BLOCK_SIZE)
+                .collect(),
+        };
+        if let Some((min, max)) = self.buffer_size_range() {
+            candidates.retain(|size| (min..=max).contains(size));
+        }
+
+        let silence = vec![0.0f32; MAX_DEVICE_BLOCK_SIZE];
+        let mut loads = Vec::with_capacity(candidates.len());
+        for size in candidates.into_iter().rev() {
+            let block = &silence[..size as usize];
+            let start = Instant::now();
+            for _ in 0..AUTO_TUNE_BLOCKS {
+                self.process_block(&[block, block])?;
+            }
+
+            let budget = size as f32 / self.current_sample_rate as f32 * AUTO_TUNE_BLOCKS as f32;
+            let load = start.elapsed().as_secs_f32() / budget;
+            trace!("Chain load at a buffer size of {}: {:.2}", size, load);
+
+            loads.push((size, load));
+            if load >= AUTO_TUNE_LOAD_CEILING {
+                break;
+            }
+        }
+        loads.reverse();
+
+        let size = pick_stable_buffer_size(&loads, AUTO_TUNE_LOAD_CEILING)
+            .ok_or_else(|| anyhow!("The devices support no buffer size to tune"))?;
+        info!("Auto tuned buffer size: {}", size);
+        Ok(size)
+    }
+
+    /// Internal helper to stop audio streams. Running streams are faded out first and
+    /// dropped before returning, which frees their callbacks and buffers, so there is a
+    /// gap of a few milliseconds plus the ring latency until the next `run()`.
+    fn stop_streams(&mut self) {
+        if self.output_stream.is_some() {
+            self.fade_out_streams();
+        }
+
+        self.active_input_config = None;
+        self.active_output_config = None;
+        if let Some(stream) = self.input_stream.take() {
+            let _ = stream.pause();
+        }
+        if let Some(stream) = self.output_stream.take() {
+            let _ = stream.pause();
+        }
+        if let Some(stream) = self.monitor_stream.take() {
+            let _ = stream.pause();
+        }
+        self.stream_fading_out.store(false, Ordering::Relaxed);
+        self.exclusive_granted = false;
+        self.publish_stream_status();
+    }
+
+    /// Internal helper asking the callback to fade out, then waiting for the silent
+    /// blocks to pass the ring buffer. Gives up after `STREAM_FADE_TIMEOUT` in case the
+    /// callback stalled.
+    fn fade_out_streams(&self) {
+        self.stream_silent.store(false, Ordering::Relaxed);
+        self.stream_fading_out.store(true, Ordering::Relaxed);
+
+        let start = Instant::now();
+        while !self.stream_silent.load(Ordering::Relaxed) {
+            if start.elapsed() >= STREAM_FADE_TIMEOUT {
+                warn!("Streams didn't fade out in time, stopping them anyway");
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let queued = (self.ring_latency_blocks + 1) as f64 * self.current_buffer_size as f64
+            / self.current_sample_rate.max(1) as f64;
+        std::thread::sleep(Duration::from_secs_f64(queued).min(STREAM_FADE_TIMEOUT));
+    }
+
+    /// Internal helper to update current settings from configs
+    fn update_current_settings(&mut self) {
+        if let Some(ref config) = self.input_config {
+            self.current_sample_rate = config.sample_rate.0;
+            if let cpal::BufferSize::Fixed(size) = config.buffer_size {
+                self.current_buffer_size = size;
+            }
+        } else if let Some(ref config) = self.output_config {
+            self.current_sample_rate = config.sample_rate.0;
+            if let cpal::BufferSize::Fixed(size) = config.buffer_size {
+                self.current_buffer_size = size;
+            }
+        }
+
+        self.update_input_highpass();
+        self.reconfigure_plugins();
+    }
+
+    /// Internal helper to re-setup every plugin for the current sample rate.
+    /// Plugins that reject it are flagged and skipped by the chain.
+    fn reconfigure_plugins(&mut self) {
+        let ids = self
+            .plugin_modules
+            .read()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+
+        for id in ids {
+            if let Err(err) = self.setup_plugin(id) {
+                warn!("Plugin {:?} failed setup, skipping it: {}", id, err);
+                self.setup_failures.push(id);
+            }
+        }
+        self.rebuild_latency_compensation();
+    }
+
+    /// Internal helper to set a plugin up for the engine rate, or for its own rate behind
+    /// a resampler when it has one that differs
+    fn setup_plugin(&mut self, plugin_id: PluginId) -> Result<()> {
+        let engine_rate = self.current_sample_rate;
+        let resampler = match self.plugin_rates.get(&plugin_id) {
+            Some(rate) if rate.sample_rate != engine_rate => {
+                let (inputs, outputs) = self.plugin_bus_channels(plugin_id);
+                Some(PluginResampler::new(
+                    engine_rate,
+                    rate.sample_rate,
+                    self.resampler_quality,
+                    &inputs,
+                    &outputs,
+                )?)
+            }
+            _ => None,
+        };
+
+        let (sample_rate, max_samples_per_block) =
+            match (&resampler, self.plugin_rates.get(&plugin_id)) {
+                (Some(resampler), Some(rate)) => (rate.sample_rate, resampler.max_plugin_frames()),
+                _ => (engine_rate, MAX_BLOCK_SIZE),
+            };
+        if let Some(rate) = self.plugin_rates.get_mut(&plugin_id) {
+            rate.resampling_latency = resampler.as_ref().map_or(0, |r| r.latency());
+        }
+
+        let mut resamplers = self.plugin_resamplers.lock().unwrap();
+        match resampler {
+            Some(resampler) => resamplers.insert(plugin_id, resampler),
+            None => resamplers.remove(&plugin_id),
+        };
+        drop(resamplers);
+
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .reconfigure(
+                sample_rate as f64,
+                max_samples_per_block as i32,
+                self.process_mode,
+            )
+    }
+
+    /// Run a plugin at its own sample rate. Its input is resampled to `sample_rate` before
+    /// `process` and back to the engine rate after, with the added latency compensated
+    /// like the plugin's own. `None` runs it at the engine rate again.
+    pub fn set_plugin_sample_rate(
+        &mut self,
+        plugin_id: PluginId,
+        sample_rate: Option<u32>,
+    ) -> Result<()> {
+        if !self.is_plugin_loaded(plugin_id) {
+            return Err(anyhow!("Plugin with ID {:?} not found", plugin_id));
+        }
+
+        let supported = COMMON_SAMPLE_RATES[0]..=COMMON_SAMPLE_RATES[COMMON_SAMPLE_RATES.len() - 1];
+        match sample_rate {
+            Some(rate) if !supported.contains(&rate) => {
+                return Err(anyhow!(
+                    "Plugin sample rate {} Hz is outside {}-{} Hz",
+                    rate,
+                    supported.start(),
+                    supported.end()
+                ));
+            }
+            Some(rate) => {
+                self.plugin_rates.insert(
+                    plugin_id,
+                    PluginRate {
+                        sample_rate: rate,
+                        resampling_latency: 0,
+                    },
+                );
+            }
+            None => {
+                self.plugin_rates.remove(&plugin_id);
+            }
+        }
+
+        let result = self.setup_plugin(plugin_id);
+        self.rebuild_latency_compensation();
+        info!(
+            "Set sample rate of plugin {:?} to {:?}",
+            plugin_id, sample_rate
+        );
+        result
+    }
+
+    /// Sample rate a plugin processes at, the engine rate unless it has its own
+    pub fn plugin_sample_rate(&self, plugin_id: PluginId) -> u32 {
+        self.plugin_rates
+            .get(&plugin_id)
+            .map_or(self.current_sample_rate, |rate| rate.sample_rate)
+    }
+
+    /// Take the plugins that failed re-setup since the last call
+    pub fn take_setup_failures(&mut self) -> Vec<PluginId> {
+        std::mem::take(&mut self.setup_failures)
+    }
+
+    /// Internal helper to build ProcessData over the main buses for blocks of `num_samples`
+    fn new_process_data(&self, num_samples: i32) -> ProcessData {
+        ProcessData {
+            process_mode: self.process_mode,
+            symbolic_sample_size: SymbolicSampleSize::Sample32,
+            num_samples,
+            num_inputs: 1,
+            num_outputs: 1,
+            inputs: self.in_bus.get(),
+            outputs: self.out_bus.get(),
+            input_parameter_changes: self.input_params.get() as *mut _,
+            output_parameter_changes: std::ptr::null_mut(),
+            input_events: std::ptr::null_mut(),
+            output_events: std::ptr::null_mut(),
+            process_context: std::ptr::null_mut(),
+        }
+    }
+
+    /// Internal helper to update ProcessData with current audio settings
+    fn update_process_data(&mut self) {
+        // Since ProcessData is Arc<ProcessData>, we can't modify it directly.
+        // We need to create a new ProcessData and replace the Arc.
+        self.process_data = Arc::new(self.new_process_data(self.current_buffer_size as i32));
+
+        for layout in self.bus_layouts.write().unwrap().values_mut() {
+            layout.update_process_data(&self.process_data);
+        }
+    }
+
+    /// Start audio processing. Streams already running are faded out and released before
+    /// the new ones are built, so calling this again to apply new settings doesn't leave
+    /// the old streams processing alongside the new ones. Settings that can't run are
+    /// rejected first, leaving the old streams running.
+    pub fn run(&mut self) -> Result<()> {
+        self.check_runnable()?;
+        self.stop_streams();
+        #[cfg(feature = "debug-introspection")]
+        self.probe.reset();
+        self.callback_load.reset();
+        self.exclusive_granted = is_exclusive_host(self.host.id());
+
+        let (Some(ref output_device), Some(ref output_config)) =
+            (&self.output_device, &self.output_config)
+        else {
+            return Err(EngineError::NoOutputDevice.into());
+        };
+        // A test signal doesn't need the capture stream
+        if self.input_source() != InputSource::Device {
+            return self.run_output_only();
+        }
+        let (Some(ref input_device), Some(ref input_config)) =
+            (&self.input_device, &self.input_config)
+        else {
+            return self.run_output_only();
+        };
+
+        let channels = input_config.channels as usize;
+        // The chains are stereo, capture channels past the first two pass through the ring
+        // to the output routing untouched
+        let chain_channels = channels.min(2);
+        let mut renderer = self.chain_renderer();
+        let input_highpass = self.input_highpass.clone();
+        let mut device_frames = DynChannelBuffer::new(0.0f32, channels, MAX_BLOCK_SIZE);
+
+        let capacity = self.ring_capacity(channels)?;
+        let ring = HeapRb::<f32>::new(capacity);
+        let (mut producer, mut consumer) = ring.split();
+
+        self.overflow_count.store(0, Ordering::Relaxed);
+        self.underrun_count.store(0, Ordering::Relaxed);
+        let overflow_count = self.overflow_count.clone();
+        let underrun_count = self.underrun_count.clone();
+        self.ring_fill.store(0, Ordering::Relaxed);
+        let ring_fill = self.ring_fill.clone();
+
+        let params = self.resampler_quality.sinc_parameters();
+
+        let nominal_ratio = output_config.sample_rate.0 as f64 / input_config.sample_rate.0 as f64;
+        // Sized for the chain's largest block, the chunk follows every block's size
+        let mut resampler =
+            SincFixedIn::<f32>::new(nominal_ratio, 2.0, params, MAX_BLOCK_SIZE, channels)?;
+        let drift = self.drift_corrector(nominal_ratio, capacity / 2);
+        let resampler_delay = resampler.output_delay();
+        let matched_rates = input_config.sample_rate == output_config.sample_rate;
+        if self.loopback_test_mode() && !matched_rates {
+            warn!(
+                "Loopback test mode can't be bit-exact, input runs at {} Hz and output at {} Hz",
+                input_config.sample_rate.0, output_config.sample_rate.0
+            );
+        }
+
+        // Upsampling can produce more frames than a fixed block holds
+        let mut resampled_data =
+            DynChannelBuffer::new(0.0f32, channels, resampler.output_frames_max());
+
+        info!("Creating input stream with config: {:?}", input_config);
+
+        let input_format = input_device.default_input_config().unwrap();
+        info!("Input format: {:?}", input_format);
+
+        let mut highpass_state = [BiquadState::default(); 2];
+        let input_gain = self.input_gain.clone();
+        let mut input_smoother = GainSmoother::new(
+            self.input_gain(),
+            INPUT_GAIN_SMOOTHING,
+            input_config.sample_rate.0 as f32,
+        );
+
+        let tempo = self.tempo.clone();
+        let transport_playing = self.transport_playing.clone();
+        let midi_clock = self.midi_clock.clone();
+        let input_sample_rate = input_config.sample_rate.0 as f64;
+        let mut affinity = self.affinity_pinner();
+        let (monitor_stream, mut tap_producer) =
+            self.build_monitor_stream(input_config.sample_rate)?.unzip();
+
+        let input_stream = build_input_stream(
+            input_device,
+            input_config,
+            self.input_sample_format,
+            move |data: &[i32]| {
+                affinity.apply();
+                let protect = renderer.denormal_protection.load(Ordering::Relaxed);
+                let _ftz = protect.then(DenormalGuard::new);
+
+                // Device blocks larger than the chain's buffers are processed in parts
+                for block in data.chunks(channels * MAX_BLOCK_SIZE) {
+                    let started = Instant::now();
+                    let block_size = block.len() / channels;
+
+                    let loopback = renderer.is_loopback();
+                    let highpass = input_highpass.try_read().ok().and_then(|h| *h);
+                    let target_gain = f32::from_bits(input_gain.load(Ordering::Relaxed));
+
+                    // Copy input audio data to the input buffer
+                    for (i, frame) in block.chunks_exact(channels).enumerate() {
+                        let gain = input_smoother.next(target_gain);
+                        for (j, &sample) in frame.iter().enumerate() {
+                            let mut sample = sample as f32 / i32::MAX as f32;
+                            if j >= chain_channels {
+                                device_frames.try_write(j, i, sample);
+                                continue;
+                            }
+                            if !loopback {
+                                sample *= gain;
+                                if let Some(ref coefficients) = highpass {
+                                    sample = highpass_state[j].process(coefficients, sample);
+                                }
+                            }
+                            renderer.input_data.write(j, i, sample);
+                        }
+                    }
+
+                    #[cfg(feature = "debug-introspection")]
+                    renderer.probe.record_input(
+                        renderer.input_data.as_ref(),
+                        block_size,
+                        chain_channels,
+                    );
+
+                    unsafe {
+                        renderer.render(block_size, chain_channels);
+                    }
+                    renderer.apply_channel_utilities(block_size, chain_channels);
+
+                    if protect && !HARDWARE_FTZ && !loopback {
+                        renderer.flush_denormals(block_size, chain_channels);
+                    }
+                    renderer.limit_output(block_size, chain_channels);
+                    renderer.apply_stream_fade(block_size);
+                    renderer.meter_output(block_size, chain_channels);
+                    renderer.record_callback_load(started, block_size);
+
+                    #[cfg(feature = "debug-introspection")]
+                    renderer.probe.record_output(
+                        renderer.output_data.as_ref(),
+                        block_size,
+                        chain_channels,
+                    );
+                    renderer.advance_transport(block_size, chain_channels);
+
+                    if let Some(ref mut producer) = tap_producer {
+                        renderer.push_tap(producer, block_size, chain_channels);
+                    }
+
+                    if let Ok(mut clock) = midi_clock.try_lock() {
+                        if let Some(clock) = clock.as_mut() {
+                            clock.process(
+                                f64::from_bits(tempo.load(Ordering::Relaxed)),
+                                transport_playing.load(Ordering::Relaxed),
+                                input_sample_rate,
+                                block_size,
+                            );
+                        }
+                    }
+
+                    // The chain's output joins the passed through channels
+                    let rendered = renderer.output_data.as_ref();
+                    for (j, channel) in device_frames
+                        .as_mut_ref()
+                        .iter_mut()
+                        .take(chain_channels)
+                        .enumerate()
+                    {
+                        channel[..block_size].copy_from_slice(&rendered[j][..block_size]);
+                    }
+
+                    // Loopback at matched rates skips the resampler so no sample is
+                    // interpolated
+                    let output = device_frames.as_ref();
+                    let overflowed = if loopback && matched_rates {
+                        #[cfg(feature = "debug-introspection")]
+                        renderer.probe.record_resampler(0, 0);
+                        push_frames(&mut producer, output, block_size)
+                    } else {
+                        drift.update(&mut resampler, producer.occupied_len());
+                        let (_consumed, frames) = resampler
+                            .set_chunk_size(block_size)
+                            .and_then(|_| {
+                                resampler.process_into_buffer(
+                                    output,
+                                    resampled_data.as_mut_ref(),
+                                    None,
+                                )
+                            })
+                            .unwrap_or((0, 0));
+                        #[cfg(feature = "debug-introspection")]
+                        renderer.probe.record_resampler(_consumed, frames);
+                        push_frames(&mut producer, resampled_data.as_ref(), frames)
+                    };
+
+                    #[cfg(feature = "debug-introspection")]
+                    renderer.probe.record_ring_fill(producer.occupied_len());
+                    ring_fill.store(producer.occupied_len() / channels, Ordering::Relaxed);
+
+                    if overflowed {
+                        overflow_count.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            },
+            self.stream_error_callback("Input"),
+        )?;
+
+        let (mut quantizer, dither) = self.output_quantizer();
+        let loopback = self.loopback_test_mode.clone();
+        let output_routing = self.output_routing.clone();
+        let mut routing = *output_routing.lock().unwrap();
+        let output_channels = output_config.channels as usize;
+
+        let output_stream = build_output_stream(
+            output_device,
+            output_config,
+            self.output_sample_format,
+            move |data: &mut [i32]| {
+                let dither = dither.load(Ordering::Relaxed);
+                let loopback = loopback.load(Ordering::Relaxed);
+                // Keep the last matrix while it's being replaced
+                if let Ok(matrix) = output_routing.try_lock() {
+                    routing = *matrix;
+                }
+
+                let underrun = pop_frames(
+                    &mut consumer,
+                    data,
+                    channels,
+                    output_channels,
+                    (!loopback).then_some(&routing),
+                    &mut quantizer,
+                    dither,
+                );
+                if underrun {
+                    underrun_count.fetch_add(1, Ordering::Relaxed);
+                }
+            },
+            self.stream_error_callback("Output"),
+        )?;
+
+        input_stream.play()?;
+        output_stream.play()?;
+
+        self.active_input_config = Some(ActiveStreamConfig::new(
+            input_config,
+            self.input_sample_format,
+        ));
+        self.active_output_config = Some(ActiveStreamConfig::new(
+            output_config,
+            self.output_sample_format,
+        ));
+        self.input_stream = Some(input_stream);
+        self.output_stream = Some(output_stream);
+        self.monitor_stream = monitor_stream;
+        self.stream_resampler_delay = resampler_delay;
+        self.publish_stream_status();
+
+        info!("Audio streams started successfully");
+        Ok(())
+    }
+
+    /// Without a capture device, or while a test signal is the input source, the chain
+    /// runs in the output callback on the test signal or silence, so generators and
+    /// instruments still play.
+    /// Internal helper checking what `run()` needs before it stops the running streams
+    fn check_runnable(&self) -> Result<()> {
+        // The rings and resamplers are sized from the buffer size
+        check_block_size(self.current_buffer_size as usize)?;
+        if self.output_device.is_none() || self.output_config.is_none() {
+            return Err(EngineError::NoOutputDevice.into());
+        }
+        if let (Some(_), Some(ref config)) = (&self.input_device, &self.input_config) {
+            if self.input_source() == InputSource::Device {
+                self.ring_capacity(config.channels as usize)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run_output_only(&mut self) -> Result<()> {
+        let (Some(ref output_device), Some(ref output_config)) =
+            (&self.output_device, &self.output_config)
+        else {
+            return Err(EngineError::NoOutputDevice.into());
+        };
+
+        let channels = output_config.channels as usize;
+        let chain_channels = channels.min(2);
+        let mut renderer = self.chain_renderer();
+
+        let tempo = self.tempo.clone();
+        let transport_playing = self.transport_playing.clone();
+        let midi_clock = self.midi_clock.clone();
+        let output_sample_rate = output_config.sample_rate.0 as f64;
+        let input_source = self.input_source.clone();
+        let mut source = self.input_source();
+        let mut generator = SignalGenerator::default();
+        let mut affinity = self.affinity_pinner();
+        let (monitor_stream, mut tap_producer) = self
+            .build_monitor_stream(output_config.sample_rate)?
+            .unzip();
+
+        let (mut quantizer, dither) = self.output_quantizer();
+        let output_routing = self.output_routing.clone();
+        let mut routing = *output_routing.lock().unwrap();
+
+        info!(
+            "Creating output only stream with config: {:?}, input: {:?}",
+            output_config, source
+        );
+
+        let output_stream = build_output_stream(
+            output_device,
+            output_config,
+            self.output_sample_format,
+            move |data: &mut [i32]| {
+                affinity.apply();
+                let protect = renderer.denormal_protection.load(Ordering::Relaxed);
+                let _ftz = protect.then(DenormalGuard::new);
+
+                // Keep the last source while it's being replaced
+                if let Ok(current) = input_source.try_read() {
+                    source = *current;
+                }
+
+                for block in data.chunks_mut(channels * MAX_BLOCK_SIZE) {
+                    let started = Instant::now();
+                    let block_size = block.len() / channels;
+
+                    for i in 0..block_size {
+                        let sample = source.next_sample(&mut generator, output_sample_rate as f32);
+                        for j in 0..chain_channels {
+                            renderer.input_data.write(j, i, sample);
+                        }
+                    }
+
+                    #[cfg(feature = "debug-introspection")]
+                    renderer.probe.record_input(
+                        renderer.input_data.as_ref(),
+                        block_size,
+                        chain_channels,
+                    );
+
+                    unsafe {
+                        renderer.render(block_size, chain_channels);
+                    }
+                    renderer.apply_channel_utilities(block_size, chain_channels);
+
+                    let loopback = renderer.is_loopback();
+                    if protect && !HARDWARE_FTZ && !loopback {
+                        renderer.flush_denormals(block_size, chain_channels);
+                    }
+                    renderer.limit_output(block_size, chain_channels);
+                    renderer.apply_stream_fade(block_size);
+                    renderer.meter_output(block_size, chain_channels);
+                    renderer.record_callback_load(started, block_size);
+
+                    #[cfg(feature = "debug-introspection")]
+                    renderer.probe.record_output(
+                        renderer.output_data.as_ref(),
+                        block_size,
+                        chain_channels,
+                    );
+                    renderer.advance_transport(block_size, chain_channels);
+
+                    if let Some(ref mut producer) = tap_producer {
+                        renderer.push_tap(producer, block_size, chain_channels);
+                    }
+
+                    if let Ok(mut clock) = midi_clock.try_lock() {
+                        if let Some(clock) = clock.as_mut() {
+                            clock.process(
+                                f64::from_bits(tempo.load(Ordering::Relaxed)),
+                                transport_playing.load(Ordering::Relaxed),
+                                output_sample_rate,
+                                block_size,
+                            );
+                        }
+                    }
+
+                    let dither = dither.load(Ordering::Relaxed);
+                    if let Ok(matrix) = output_routing.try_lock() {
+                        routing = *matrix;
+                    }
+
+                    let output = renderer.output_data.as_ref();
+                    for (i, frame) in block.chunks_mut(channels).enumerate() {
+                        if !loopback {
+                            let source = [output[0][i], output[1][i]];
+                            write_routed_frame(
+                                &routing,
+                                &source[..chain_channels],
+                                frame,
+                                &mut quantizer,
+                                dither,
+                            );
+                            continue;
+                        }
+
+                        for (j, sample) in frame.iter_mut().enumerate() {
+                            let s = if j < chain_channels {
+                                output[j][i]
+                            } else {
+                                0.0
+                            };
+                            *sample = loopback_sample(s);
+                        }
+                    }
+                }
+            },
+            self.stream_error_callback("Output"),
+        )?;
+
+        output_stream.play()?;
+
+        self.active_input_config = None;
+        self.active_output_config = Some(ActiveStreamConfig::new(
+            output_config,
+            self.output_sample_format,
+        ));
+        self.input_stream = None;
+        self.output_stream = Some(output_stream);
+        self.monitor_stream = monitor_stream;
+        self.publish_stream_status();
+
+        info!("Output only stream started successfully");
+        Ok(())
+    }
+
+    /// Error callback for the main streams, flags the device as lost when it goes away
+    fn stream_error_callback(
+        &self,
+        stream: &'static str,
+    ) -> impl FnMut(cpal::StreamError) + Send + 'static {
+        let device_lost = self.device_lost.clone();
+
+        move |err| {
+            error!("{} stream error: {:?}", stream, err);
+            if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                device_lost.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn affinity_pinner(&self) -> AffinityPinner {
+        AffinityPinner {
+            requested: self.audio_thread_affinity.clone(),
+            applied: NO_AFFINITY,
+        }
+    }
+
+    /// Pin the thread running the plugin chain to a CPU core, picked up on the next
+    /// callback. Unpinning takes effect once the streams are restarted.
+    pub fn set_audio_thread_affinity(&mut self, core: Option<usize>) -> Result<()> {
+        if let Some(core) = core {
+            let cores = core_affinity::get_core_ids()
+                .ok_or_else(|| anyhow!("Thread affinity isn't supported on this platform"))?;
+            if !cores.iter().any(|c| c.id == core) {
+                return Err(anyhow!("No CPU core {}, available: {}", core, cores.len()));
+            }
+        }
+
+        self.audio_thread_affinity
+            .store(core.unwrap_or(NO_AFFINITY), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn audio_thread_affinity(&self) -> Option<usize> {
+        match self.audio_thread_affinity.load(Ordering::Relaxed) {
+            NO_AFFINITY => None,
+            core => Some(core),
+        }
+    }
+
+    /// Stop and rebuild the streams from the current devices and configs
+    pub fn restart_streams(&mut self) -> Result<()> {
+        self.stop_streams();
+        self.run()
+    }
+
+    /// Check whether the selected input or output device went away, either reported by a
+    /// stream error or missing from `scan`. Refreshes the cached device lists. A scan of a
+    /// host that has since been replaced only reports stream errors.
+    pub fn check_devices(&mut self, scan: &DeviceScan) -> bool {
+        let mut lost = self.device_lost.swap(false, Ordering::Relaxed);
+        if scan.host != self.host.id() {
+            return lost;
+        }
+
+        if let Some(ref names) = scan.inputs {
+            lost |= device_missing(self.input_device.as_ref(), names);
+            self.cached_input_devices.insert(scan.host, names.clone());
+        }
+        if let Some(ref names) = scan.outputs {
+            lost |= device_missing(self.output_device.as_ref(), names);
+            self.cached_output_devices.insert(scan.host, names.clone());
+        }
+
+        lost
+    }
+
+    /// Enumerate hosts, devices and their configs again, so devices plugged in since
+    /// startup show up. The streams and selected devices are left alone. Configs of
+    /// devices still listed but busy right now, e.g. held exclusively, are kept from
+    /// before.
+    pub fn refresh_devices(&mut self) {
+        let mut cache = DeviceCache::enumerate(Some(self.host.as_ref()));
+
+        let listed = |devices: &FxHashMap<HostId, Vec<String>>, name: &str| {
+            devices.values().flatten().any(|listed| listed == name)
+        };
+        for (name, configs) in self.cached_input_configs.drain() {
+            if listed(&cache.input_devices, &name) {
+                cache.input_configs.entry(name).or_insert(configs);
+            }
+        }
+        for (name, configs) in self.cached_output_configs.drain() {
+            if listed(&cache.output_devices, &name) {
+                cache.output_configs.entry(name).or_insert(configs);
+            }
+        }
+
+        info!(
+            "Refreshed devices: {} hosts, {} inputs, {} outputs",
+            cache.hosts.len(),
+            cache.input_devices.values().map(Vec::len).sum::<usize>(),
+            cache.output_devices.values().map(Vec::len).sum::<usize>()
+        );
+
+        self.cached_hosts = cache.hosts;
+        self.cached_input_devices = cache.input_devices;
+        self.cached_output_devices = cache.output_devices;
+        self.cached_input_configs = cache.input_configs;
+        self.cached_output_configs = cache.output_configs;
+    }
+
+    /// Fall back to the host's default devices listed in `scan` and restart the streams,
+    /// after the selected ones went away
+    pub fn recover_default_devices(&mut self, scan: &DeviceScan) -> Result<()> {
+        if scan.host != self.host.id() {
+            return Err(anyhow!("Devices were listed for another host"));
+        }
+        self.stop_streams();
+
+        match scan.default_output {
+            Some(ref name) => self.select_output(name)?,
+            None => {
+                self.output_device = None;
+                self.output_config = None;
+            }
+        }
+
+        match scan.default_input {
+            Some(ref name) => self.select_input(name)?,
+            None => {
+                self.input_device = None;
+                self.input_config = None;
+            }
+        }
+
+        self.restart_streams()
+    }
+
+    pub fn has_input_device(&self) -> bool {
+        self.input_device.is_some()
+    }
+
+    pub fn has_output_device(&self) -> bool {
+        self.output_device.is_some()
+    }
+
+    /// Quantizer for the main output's format, and the shared dither switch set to the
+    /// format's default unless dither was set explicitly
+    fn output_quantizer(&self) -> (Quantizer, Arc<AtomicBool>) {
+        let bits = self.output_sample_format.map_or(32, sample_bits);
+        if !self.dither_overridden {
+            self.dither.store(bits <= 16, Ordering::Relaxed);
+        }
+        (Quantizer::new(bits), self.dither.clone())
+    }
+
+    /// Start the monitor output at the chain's sample rate, if a monitor device is selected.
+    /// Returns the stream with the producer the chain callback feeds the tapped signal into.
+    fn build_monitor_stream(
+        &self,
+        sample_rate: cpal::SampleRate,
+    ) -> Result<Option<(cpal::Stream, HeapProd<f32>)>> {
+        let Some(ref device) = self.monitor_device else {
+            return Ok(None);
+        };
+
+        let range = device
+            .supported_output_configs()?
+            .filter(|c| c.min_sample_rate() <= sample_rate && sample_rate <= c.max_sample_rate())
+            .max_by_key(|c| (c.sample_format() == SampleFormat::I32, c.channels().min(2)))
+            .ok_or_else(|| anyhow!("Monitor device doesn't support {} Hz", sample_rate.0))?;
+        let config: StreamConfig = range.with_sample_rate(sample_rate).config();
+        let channels = config.channels as usize;
+
+        let ring = HeapRb::<f32>::new(self.ring_capacity(2)?);
+        let (producer, mut consumer) = ring.split();
+        let mut quantizer = Quantizer::new(sample_bits(range.sample_format()));
+
+        info!("Creating monitor stream with config: {:?}", config);
+
+        let stream = build_output_stream(
+            device,
+            &config,
+            Some(range.sample_format()),
+            move |data: &mut [i32]| {
+                for frame in data.chunks_mut(channels) {
+                    let left = consumer.try_pop().unwrap_or(0.0);
+                    let right = consumer.try_pop().unwrap_or(0.0);
+
+                    for (j, sample) in frame.iter_mut().enumerate() {
+                        let s = match j {
+                            0 => left,
+                            1 => right,
+                            _ => 0.0,
+                        };
+                        *sample = quantizer.quantize(s, false);
+                    }
+                }
+            },
+            |err| {
+                error!("Monitor stream error: {:?}", err);
+            },
+        )?;
+
+        stream.play()?;
+
+        Ok(Some((stream, producer)))
+    }
+
+    /// Select the output device for the monitor tap, `None` turns the monitor output off.
+    /// Takes effect on the next `run()`.
+    pub fn select_monitor_output(&mut self, device_name: Option<&str>) -> Result<()> {
+        self.stop_streams();
+
+        self.monitor_device = match device_name {
+            Some(device_name) => Some(
+                self.host
+                    .output_devices()?
+                    .find(|d| d.name().is_ok_and(|name| name == device_name))
+                    .ok_or_else(|| anyhow!("Output device '{}' not found", device_name))?,
+            ),
+            None => None,
+        };
+
+        Ok(())
+    }
+
+    /// Name of the monitor output device
+    pub fn monitor_output(&self) -> Option<String> {
+        self.monitor_device.as_ref().and_then(|d| d.name().ok())
+    }
+
+    /// Route a plugin's wet output to the monitor output, `None` silences the monitor
+    pub fn set_monitor_tap(&mut self, plugin_id: Option<PluginId>) -> Result<()> {
+        if let Some(plugin_id) = plugin_id {
+            if !self.plugin_modules.read().unwrap().contains_key(&plugin_id) {
+                return Err(anyhow!("Plugin with ID {:?} not found", plugin_id));
+            }
+        }
+
+        *self.monitor_tap.write().unwrap() = plugin_id;
+        Ok(())
+    }
+
+    pub fn monitor_tap(&self) -> Option<PluginId> {
+        *self.monitor_tap.read().unwrap()
+    }
+
+    /// Internal helper to hand the chain state to a stream callback
+    fn chain_renderer(&self) -> ChainRenderer {
+        let mut renderer = ChainRenderer {
+            plugin_modules: self.plugin_modules.clone(),
+            plugin_order: self.plugin_order.clone(),
+            bus_layouts: self.bus_layouts.clone(),
+            chains: self.chains.clone(),
+            chain_delays: self.chain_delays.clone(),
+            chain_workers: self.chain_workers.clone(),
+            plugin_resamplers: self.plugin_resamplers.clone(),
+            monitor_mix: self.monitor_mix.clone(),
+            monitor_smoother: GainSmoother::new(
+                self.monitor_mix(),
+                MONITOR_MIX_SMOOTHING,
+                self.current_sample_rate as f32,
+            ),
+            monitor_delay: self.monitor_delay.clone(),
+            global_ab: self.global_ab.clone(),
+            global_ab_match: self.global_ab_match.clone(),
+            ab_smoother: GainSmoother::new(
+                if self.global_ab() { 1.0 } else { 0.0 },
+                GLOBAL_AB_SMOOTHING,
+                self.current_sample_rate as f32,
+            ),
+            processing_enabled: self.processing_enabled.clone(),
+            denormal_protection: self.denormal_protection.clone(),
+            output_limiter: self.output_limiter.clone(),
+            output_ceiling: self.output_ceiling.clone(),
+            channel_swap: self.channel_swap.clone(),
+            mono_sum: self.mono_sum.clone(),
+            swap_smoother: GainSmoother::new(
+                if self.channel_swap() { 1.0 } else { 0.0 },
+                CHANNEL_UTILITY_SMOOTHING,
+                self.current_sample_rate as f32,
+            ),
+            mono_smoother: GainSmoother::new(
+                if self.mono_sum() { 1.0 } else { 0.0 },
+                CHANNEL_UTILITY_SMOOTHING,
+                self.current_sample_rate as f32,
+            ),
+            loopback: self.loopback_test_mode.clone(),
+            output_peaks: self.output_peaks.clone(),
+            correlation: CorrelationMeter::new(
+                OUTPUT_CORRELATION_WINDOW,
+                self.current_sample_rate as f32,
+            ),
+            output_correlation: self.output_correlation.clone(),
+            callback_load: self.callback_load.clone(),
+            fade: StreamFade::new(
+                self.stream_fading_out.clone(),
+                self.stream_silent.clone(),
+                self.current_sample_rate as f32,
+            ),
+            process_data: self.process_data.clone(),
+            input_data: self.input_data.clone(),
+            output_data: self.output_data.clone(),
+            dry_data: self.dry_data.clone(),
+            mix_data: self.mix_data.clone(),
+            monitor_tap: self.monitor_tap.clone(),
+            tap: None,
+            tap_data: self.tap_data.clone(),
+            stems: None,
+            sample_rate: self.current_sample_rate as f32,
+            tempo: self.tempo.clone(),
+            transport_playing: self.transport_playing.clone(),
+            transport_position: self.transport_position.clone(),
+            process_context: Box::new(UnsafeCell::new(ProcessContext::default())),
+            continuous_samples: 0,
+            recording_tap: self.recording_tap.clone(),
+            midi_timing: self.midi_timing.clone(),
+            #[cfg(feature = "debug-introspection")]
+            probe: self.probe.clone(),
+        };
+        renderer.set_process_data(self.new_process_data(self.current_buffer_size as i32));
+        renderer
+    }
+
+    /// Internal helper to build a renderer over its own buffers for a chain worker. It
+    /// shares the chain state but never hands chains on itself
+    fn chain_lane(&self) -> ChainLane {
+        let mut lane = self.chain_renderer();
+        lane.chain_workers = Arc::new(Mutex::new(None));
+        lane.input_data = Sync2DArray::new(0.0f32, MAX_BLOCK_SIZE);
+        lane.output_data = Sync2DArray::new(0.0f32, MAX_BLOCK_SIZE);
+
+        let in_bus = Arc::new(UnsafeCell::new(bus_buffers(&mut lane.input_data)));
+        let out_bus = Arc::new(UnsafeCell::new(bus_buffers(&mut lane.output_data)));
+        lane.set_process_data(ProcessData {
+            inputs: in_bus.get(),
+            outputs: out_bus.get(),
+            ..self.new_process_data(self.current_buffer_size as i32)
+        });
+
+        (lane, [in_bus, out_bus])
+    }
+
+    /// Process independent parallel chains on worker threads, one chain per worker, joined
+    /// before the chains are summed. Chains keep their plugins in order. Ones with
+    /// plugins sharing buffers across chains, through sidechains, extra buses, resampling
+    /// or the monitor tap, stay on the audio thread.
+    pub fn set_parallel_processing(&mut self, enabled: bool) -> Result<()> {
+        let mut chain_workers = self.chain_workers.lock().unwrap();
+        if enabled == chain_workers.is_some() {
+            return Ok(());
+        }
+
+        if !enabled {
+            // Joins the workers, the audio thread skips them while the lock is held
+            *chain_workers = None;
+            info!("Disabled parallel chain processing");
+            return Ok(());
+        }
+
+        let count = std::thread::available_parallelism()
+            .map_or(1, |cores| cores.get().saturating_sub(1))
+            .clamp(1, MAX_CHAIN_WORKERS);
+        let lanes = (0..count).map(|_| self.chain_lane()).collect();
+        *chain_workers = Some(ChainWorkers::new(lanes)?);
+        info!("Enabled parallel chain processing on {} workers", count);
+        Ok(())
+    }
+
+    pub fn parallel_processing(&self) -> bool {
+        self.chain_workers.lock().unwrap().is_some()
+    }
+
+    /// Get the transport tempo in beats per minute
+    pub fn tempo(&self) -> f64 {
+        f64::from_bits(self.tempo.load(Ordering::Relaxed))
+    }
+
+    /// Set the transport tempo, picked up by the MIDI clock and the plugins' process
+    /// context on the next block
+    pub fn set_tempo(&mut self, bpm: f64) -> Result<()> {
+        if !(MIN_TEMPO..=MAX_TEMPO).contains(&bpm) {
+            return Err(anyhow!(
+                "Tempo must be between {} and {} BPM, got {}",
+                MIN_TEMPO,
+                MAX_TEMPO,
+                bpm
+            ));
+        }
+
+        self.tempo.store(bpm.to_bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn is_transport_playing(&self) -> bool {
+        self.transport_playing.load(Ordering::Relaxed)
+    }
+
+    /// Start or stop the transport. The MIDI clock sends start, or continue if the
+    /// transport wasn't rewound since it last stopped.
+    pub fn set_transport_playing(&mut self, playing: bool) {
+        self.transport_playing.store(playing, Ordering::Relaxed);
+    }
+
+    /// Move the transport back to the start
+    pub fn rewind_transport(&mut self) {
+        self.transport_position.store(0, Ordering::Relaxed);
+        if let Some(clock) = self.midi_clock.lock().unwrap().as_mut() {
+            clock.rewind();
+        }
+    }
+
+    /// Samples the transport played since it was last rewound
+    pub fn transport_position(&self) -> u64 {
+        self.transport_position.load(Ordering::Relaxed)
+    }
+
+    /// Record the output to a 32 bit float WAV file at the chain's sample rate. With a
+    /// start point writing begins at exactly that transport sample, and with a stop point
+    /// it ends there, bars being converted at the current tempo. Either makes the
+    /// recording follow the transport, nothing is written while it's stopped. A start
+    /// point already passed starts right away.
+    pub fn start_recording(
+        &mut self,
+        path: impl AsRef<Path>,
+        start_at: Option<RecordPoint>,
+        stop_at: Option<RecordPoint>,
+    ) -> Result<()> {
+        // A recording that reached its stop point is complete, it only needs collecting
+        if let Some(recording) = self.recording.take_if(|r| r.reached_stop()) {
+            self.recording_tap.lock().unwrap().take();
+            recording.finish()?;
+        }
+        if self.recording.is_some() {
+            return Err(anyhow!("Already recording"));
+        }
+
+        let tempo = self.tempo();
+        let sample_rate = self.current_sample_rate;
+        let window = RecordWindow {
+            start: start_at.map(|point| point.to_sample(tempo, sample_rate)),
+            stop: stop_at.map(|point| point.to_sample(tempo, sample_rate)),
+        };
+        if let (Some(start), Some(stop)) = (window.start, window.stop) {
+            if stop <= start {
+                return Err(anyhow!(
+                    "Recording stop at sample {} must come after its start at {}",
+                    stop,
+                    start
+                ));
+            }
+        }
+
+        let (recording, tap) = Recording::start(path.as_ref(), sample_rate, window)?;
+        *self.recording_tap.lock().unwrap() = Some(tap);
+        info!(
+            "Recording to {} from {:?} to {:?}",
+            recording.path().display(),
+            window.start,
+            window.stop
+        );
+        self.recording = Some(recording);
+        Ok(())
+    }
+
+    /// Finish the recording and return the path of the completed file
+    pub fn stop_recording(&mut self) -> Result<PathBuf> {
+        let recording = self
+            .recording
+            .take()
+            .ok_or_else(|| anyhow!("Not recording"))?;
+        self.recording_tap.lock().unwrap().take();
+
+        let path = recording.finish()?;
+        info!("Finished recording {}", path.display());
+        Ok(path)
+    }
+
+    /// Whether a recording is running and hasn't reached its stop point
+    pub fn is_recording(&self) -> bool {
+        self.recording.as_ref().is_some_and(|r| !r.reached_stop())
+    }
+
+    /// Send 24 PPQN MIDI clock following the transport to the named output port
+    pub fn enable_midi_clock_out(&mut self, port: &str) -> Result<()> {
+        let clock = MidiClockOut::connect(port)?;
+        *self.midi_clock.lock().unwrap() = Some(clock);
+        Ok(())
+    }
+
+    /// Stop sending MIDI clock, sending stop first if the transport is playing
+    pub fn disable_midi_clock_out(&mut self) {
+        self.midi_clock.lock().unwrap().take();
+    }
+
+    pub fn is_midi_clock_out_enabled(&self) -> bool {
+        self.midi_clock.lock().unwrap().is_some()
+    }
+
+    /// Output port MIDI clock is sent to, if enabled
+    pub fn midi_clock_out_port(&self) -> Option<String> {
+        self.midi_clock
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|clock| clock.port_name().to_string())
+    }
+
+    /// Names of the available MIDI input ports
+    pub fn list_midi_inputs(&self) -> Vec<String> {
+        midi::input_port_names().unwrap_or_else(|err| {
+            warn!("Failed to list MIDI inputs: {}", err);
+            Vec::new()
+        })
+    }
+
+    /// Listen to a single MIDI input port, closing any others. Notes, controllers and
+    /// pitch bend go to every plugin taking notes, see `route_midi_message`
+    pub fn select_midi_input(&mut self, port: &str) -> Result<()> {
+        let input = self.open_midi_input(port)?;
+        self.midi_input_ports = vec![input];
+        self.midi_inputs = vec![port.to_string()];
+        Ok(())
+    }
+
+    /// Select the MIDI input ports to listen to. Ports that fail to open stay selected
+    /// and are opened once they show up
+    pub fn set_midi_inputs(&mut self, ports: Vec<String>) {
+        info!("MIDI inputs: {:?}", ports);
+        self.midi_input_ports = ports
+            .iter()
+            .filter_map(|port| {
+                self.open_midi_input(port)
+                    .inspect_err(|err| warn!("{}", err))
+                    .ok()
+            })
+            .collect();
+        self.midi_inputs = ports;
+    }
+
+    pub fn midi_inputs(&self) -> &[String] {
+        &self.midi_inputs
+    }
+
+    /// Close selected MIDI inputs that went away and reopen ones that came back.
+    /// Returns the ports lost and the ports reopened since the last check.
+    pub fn check_midi_inputs(&mut self, scan: &DeviceScan) -> (Vec<String>, Vec<String>) {
+        let Some(ref available) = scan.midi_inputs else {
+            return (Vec::new(), Vec::new());
+        };
+
+        let mut lost = Vec::new();
+        self.midi_input_ports.retain(|input| {
+            let present = available.iter().any(|port| port == input.port_name());
+            if !present {
+                warn!("MIDI input {} went away", input.port_name());
+                lost.push(input.port_name().to_string());
+            }
+            present
+        });
+
+        let mut restored = Vec::new();
+        for port in &self.midi_inputs {
+            let open = self
+                .midi_input_ports
+                .iter()
+                .any(|input| input.port_name() == port);
+            if open || !available.contains(port) {
+                continue;
+            }
+
+            match self.open_midi_input(port) {
+                Ok(input) => {
+                    self.midi_input_ports.push(input);
+                    restored.push(port.clone());
+                }
+                Err(err) => trace!("MIDI input {} not reopened yet: {}", port, err),
+            }
+        }
+
+        (lost, restored)
+    }
+
+    /// Internal helper to open a MIDI input port routed into the plugins
+    fn open_midi_input(&self, port: &str) -> Result<MidiInputPort> {
+        let plugin_modules = self.plugin_modules.clone();
+        let timing = self.midi_timing.clone();
+        MidiInputPort::connect(port, move |message, at| {
+            route_midi_message(
+                &plugin_modules.read().unwrap(),
+                message,
+                timing.sample_offset(at),
+            );
+        })
+    }
+
+    /// Pause or resume processing without touching the streams. While paused the
+    /// chain is skipped and silence is sent to the output, unlike bypass which
+    /// passes the input through dry.
+    pub fn set_processing_enabled(&mut self, enabled: bool) {
+        self.processing_enabled.store(enabled, Ordering::Relaxed);
+        info!(
+            "Processing {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+
+    /// Check if the input callback is running the chain
+    pub fn is_processing_enabled(&self) -> bool {
+        self.processing_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Add TPDF dither before quantizing the output. Defaults to on for 16 bit and smaller
+    /// output formats and off otherwise, takes effect on the next block.
+    pub fn set_dither(&mut self, enabled: bool) {
+        self.dither.store(enabled, Ordering::Relaxed);
+        self.dither_overridden = true;
+    }
+
+    pub fn dither(&self) -> bool {
+        if self.dither_overridden {
+            self.dither.load(Ordering::Relaxed)
+        } else {
+            self.output_sample_format.map_or(32, sample_bits) <= 16
+        }
+    }
+
+    /// Keep denormals out of the chain buffers, on by default. On x86/x86_64 the callbacks
+    /// set the MXCSR flush-to-zero bits, elsewhere subnormal output samples are zeroed.
+    pub fn set_denormal_protection(&mut self, enabled: bool) {
+        self.denormal_protection.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn denormal_protection(&self) -> bool {
+        self.denormal_protection.load(Ordering::Relaxed)
+    }
+
+    /// Run one block through the current chains synchronously, without any streams.
+    /// `input` holds one slice per channel, all the same length, and the output is
+    /// returned the same way. The chains process the first two channels, any further
+    /// ones pass through untouched. Fails while the streams are running since they share
+    /// the processing buffers.
+    pub fn process_block(&mut self, input: &[&[f32]]) -> Result<Vec<Vec<f32>>> {
+        if self.input_stream.is_some() || self.output_stream.is_some() {
+            return Err(anyhow!(
+                "Can't process a block while the streams are running"
+            ));
+        }
+
+        let channels = input.len();
+        if channels == 0 {
+            return Err(anyhow!("Expected at least 1 channel"));
+        }
+        let chain_channels = channels.min(2);
+
+        let frames = input[0].len();
+        if input.iter().any(|channel| channel.len() != frames) {
+            return Err(anyhow!("All channels must have the same length"));
+        }
+
+        let mut output: Vec<Vec<f32>> = input.iter().map(|channel| channel.to_vec()).collect();
+        let mut renderer = self.chain_renderer();
+
+        // Blocks larger than the chain's buffers are processed in parts
+        for start in (0..frames).step_by(MAX_BLOCK_SIZE) {
+            let block_size = (frames - start).min(MAX_BLOCK_SIZE);
+            renderer.set_process_data(self.new_process_data(block_size as i32));
+
+            for (j, channel) in input.iter().take(chain_channels).enumerate() {
+                for (i, sample) in channel[start..start + block_size].iter().enumerate() {
+                    renderer.input_data.write(j, i, *sample);
+                }
+            }
+
+            unsafe {
+                renderer.render(block_size, chain_channels);
+                renderer.apply_channel_utilities(block_size, chain_channels);
+                renderer.limit_output(block_size, chain_channels);
+                renderer.meter_output(block_size, chain_channels);
+
+                let rendered = &*renderer.output_data.data.get();
+                for (j, channel) in output.iter_mut().take(chain_channels).enumerate() {
+                    channel[start..start + block_size].copy_from_slice(&rendered[j][..block_size]);
+                }
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Render interleaved `input` with `channels` channels through the chains faster than
+    /// real time and return the interleaved output. Plugins are set up for offline
+    /// processing for the duration and get blocks of the current buffer size. The chains
+    /// process the first two channels, any further ones pass through untouched. Fails
+    /// while the streams are running since they share the processing buffers.
+    pub fn render_offline(&mut self, input: &[f32], channels: u16) -> Result<Vec<f32>> {
+        self.render_offline_with_stems(input, channels, false)
+            .map(|(output, _)| output)
+    }
+
+    /// Internal helper running the offline render, optionally collecting every plugin's
+    /// interleaved output alongside the mix
+    fn render_offline_with_stems(
+        &mut self,
+        input: &[f32],
+        channels: u16,
+        collect_stems: bool,
+    ) -> Result<(Vec<f32>, Stems)> {
+        if self.input_stream.is_some() || self.output_stream.is_some() {
+            return Err(anyhow!(
+                "Can't render offline while the streams are running"
+            ));
+        }
+
+        let channels = channels as usize;
+        if channels == 0 {
+            return Err(anyhow!("Expected at least 1 channel"));
+        }
+        let chain_channels = channels.min(2);
+        if input.len() % channels != 0 {
+            return Err(anyhow!(
+                "Input of {} samples is not a whole number of {} channel frames",
+                input.len(),
+                channels
+            ));
+        }
+
+        let block_size = (self.current_buffer_size as usize).clamp(1, MAX_BLOCK_SIZE);
+        self.set_process_mode(ProcessMode::Offline);
+
+        let mut renderer = self.chain_renderer();
+        if collect_stems {
+            renderer.stems = Some(FxHashMap::default());
+        }
+
+        let mut output = Vec::with_capacity(input.len());
+        for block in input.chunks(block_size * channels) {
+            let frames = block.len() / channels;
+            renderer.set_process_data(self.new_process_data(frames as i32));
+
+            for (i, frame) in block.chunks_exact(channels).enumerate() {
+                for (j, sample) in frame.iter().take(chain_channels).enumerate() {
+                    renderer.input_data.write(j, i, *sample);
+                }
+            }
+
+            unsafe {
+                renderer.render(frames, chain_channels);
+
+                let rendered = &*renderer.output_data.data.get();
+                for (i, frame) in block.chunks_exact(channels).enumerate() {
+                    output.extend(rendered[..chain_channels].iter().map(|channel| channel[i]));
+                    output.extend_from_slice(&frame[chain_channels..]);
+                }
+            }
+        }
+
+        self.set_process_mode(ProcessMode::Realtime);
+        info!("Rendered {} frames offline", input.len() / channels);
+        Ok((output, renderer.stems.unwrap_or_default()))
+    }
+
+    /// Render a WAV file through the chains offline and write the output of every
+    /// processing plugin, taken right after it, plus the final mix as separate WAVs in
+    /// `output_dir`. Stems are named by chain position and plugin name. The file must be
+    /// mono or stereo at the engine sample rate. Returns the written paths, mix last.
+    pub fn render_stems(
+        &mut self,
+        input_wav: impl AsRef<Path>,
+        output_dir: impl AsRef<Path>,
+    ) -> Result<Vec<PathBuf>> {
+        let input_wav = input_wav.as_ref();
+        let output_dir = output_dir.as_ref();
+
+        let mut reader = hound::WavReader::open(input_wav)
+            .with_context(|| format!("Failed to open {}", input_wav.display()))?;
+        let spec = reader.spec();
+        // Stems hold what the stereo chains produced
+        if spec.channels > 2 {
+            return Err(anyhow!(
+                "{} has {} channels, stems are rendered from mono or stereo files",
+                input_wav.display(),
+                spec.channels
+            ));
+        }
+        if spec.sample_rate != self.current_sample_rate {
+            return Err(anyhow!(
+                "{} is {} Hz, the engine runs at {} Hz",
+                input_wav.display(),
+                spec.sample_rate,
+                self.current_sample_rate
+            ));
+        }
+
+        let input = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|s| s as f32 * scale))
+                    .collect::<Result<Vec<_>, _>>()?
+            }
+        };
+
+        let (mix, mut stems) = self.render_offline_with_stems(&input, spec.channels, true)?;
+
+        std::fs::create_dir_all(output_dir)
+            .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+        let output_spec = hound::WavSpec {
+            channels: spec.channels,
+            sample_rate: spec.sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+
+        let mut written = Vec::new();
+        let order = self.plugin_order();
+        for (index, id) in order.iter().enumerate() {
+            let Some(stem) = stems.remove(id) else {
+                continue;
+            };
+            let name = self
+                .plugin_modules
+                .read()
+                .unwrap()
+                .get(id)
+                .map_or_else(String::new, |plugin| plugin.name.clone());
+            let path = output_dir.join(format!("{:02}_{}.wav", index + 1, stem_label(&name)));
+            write_wav(&path, output_spec, &stem)?;
+            written.push(path);
+        }
+
+        let path = output_dir.join("mix.wav");
+        write_wav(&path, output_spec, &mix)?;
+        written.push(path);
+
+        info!(
+            "Rendered {} stems of {} into {}",
+            written.len(),
+            input_wav.display(),
+            output_dir.display()
+        );
+        Ok(written)
+    }
+
+    /// Internal helper to switch the process mode and set every plugin up for it
+    fn set_process_mode(&mut self, process_mode: ProcessMode) {
+        self.process_mode = process_mode;
+        self.update_process_data();
+        self.reconfigure_plugins();
+    }
+
+    /// Lock or unlock the plugin chain. While locked, structural edits (loading,
+    /// removing, reordering or clearing plugins) are rejected, but bypass and
+    /// parameter changes are still allowed.
+    pub fn set_chain_locked(&mut self, locked: bool) {
+        self.chain_locked = locked;
+        info!("Chain {}", if locked { "locked" } else { "unlocked" });
+    }
+
+    /// Check if the plugin chain is locked against structural edits
+    pub fn is_chain_locked(&self) -> bool {
+        self.chain_locked
+    }
+
+    /// Internal helper to reject structural edits while the chain is locked
+    fn ensure_chain_unlocked(&self) -> Result<()> {
+        if self.chain_locked {
+            return Err(anyhow!("Plugin chain is locked"));
+        }
+        Ok(())
+    }
+
+    /// Add a VST plugin to the processing chain
+    pub fn load_plugin(&mut self, path: &str) -> Result<PluginId> {
+        self.load_plugin_with_class(path, None)
+    }
+
+    /// Add one class of a plugin whose factory ships several, by its UID as
+    /// `vst::host::list_classes` reports it
+    pub fn load_plugin_class(&mut self, path: &str, class_uid: &str) -> Result<PluginId> {
+        self.load_plugin_with_class(path, Some(class_uid.to_string()))
+    }
+
+    /// Internal helper to load the given class of a plugin, or its first one
+    fn load_plugin_with_class(
+        &mut self,
+        path: &str,
+        class_uid: Option<String>,
+    ) -> Result<PluginId> {
+        self.ensure_chain_unlocked()?;
+
+        info!("Loading plugin: {:?}", path);
+
+        let plugin = self.instantiate_plugin(path, class_uid)?;
+        let id = self.insert_plugin(plugin)?;
+        info!("Successfully loaded plugin: {} with ID: {:?}", path, id);
+        Ok(id)
+    }
+
+    /// Internal helper to instantiate the given class of a plugin, or its first one. Only
+    /// loading the module is bounded by the load timeout, the instances are created on the
+    /// calling thread since VST3 wants the edit controller and its view created on the UI
+    /// thread.
+    fn instantiate_plugin(&self, path: &str, class_uid: Option<String>) -> Result<VSTHostContext> {
+        // A plugin hanging in its initialization must not hang the caller with it
+        let owned_path = path.to_string();
+        let mut plugin = run_with_timeout(self.plugin_load_timeout, move || {
+            VSTHostContext::open(&owned_path, class_uid.as_deref())
+        })?;
+        plugin.create_instances()?;
+
+        unsafe {
+            plugin.processor.as_mut().unwrap().set_processing(true);
+        }
+        Ok(plugin)
+    }
+
+    /// Append the plugins of a chain description exported by another tool, see
+    /// `chain_import` for the format. `resolve` maps each entry's UID or name to a plugin
+    /// path and the class to load from it, usually through the scanned plugins. Entries
+    /// that don't resolve or fail to load are skipped and reported, the rest are added in
+    /// order with their parameters and bypass set. Settings that fail are reported as
+    /// warnings.
+    pub fn import_chain_from_json(
+        &mut self,
+        json: &str,
+        resolve: impl Fn(&str) -> Option<(String, Option<String>)>,
+    ) -> Result<ChainImport> {
+        let entries = chain_import::parse_chain(json).context("Invalid chain description")?;
+        self.import_chain(
+            entries,
+            resolve,
+            |engine, path, class_uid| match class_uid {
+                Some(class_uid) => engine.load_plugin_class(path, class_uid),
+                None => engine.load_plugin(path),
+            },
+        )
+    }
+
+    /// Internal helper for `import_chain_from_json`, loading plugins through `load`
+    fn import_chain(
+        &mut self,
+        entries: Vec<ImportedPlugin>,
+        resolve: impl Fn(&str) -> Option<(String, Option<String>)>,
+        mut load: impl FnMut(&mut Self, &str, Option<&str>) -> Result<PluginId>,
+    ) -> Result<ChainImport> {
+        self.ensure_chain_unlocked()?;
+
+        let mut import = ChainImport::default();
+        for (index, entry) in entries.into_iter().enumerate() {
+            let loaded = resolve(&entry.uid_or_name)
+                .ok_or_else(|| anyhow!("No scanned plugin matches"))
+                .and_then(|(path, class_uid)| load(self, &path, class_uid.as_deref()));
+            let plugin_id = match loaded {
+                Ok(plugin_id) => plugin_id,
+                Err(err) => {
+                    warn!("Skipping chain entry {}: {:#}", entry.uid_or_name, err);
+                    import.unresolved.push(UnresolvedEntry {
+                        index,
+                        uid_or_name: entry.uid_or_name,
+                        reason: format!("{:#}", err),
+                    });
+                    continue;
+                }
+            };
+
+            // Settings that don't apply are reported, the plugin stays in the chain
+            for (&id, &value) in &entry.params {
+                if let Err(err) = self.set_parameter(plugin_id, id, value) {
+                    let warning = format!("{} parameter {}: {:#}", entry.uid_or_name, id, err);
+                    warn!("{}", warning);
+                    import.warnings.push(warning);
+                }
+            }
+            if entry.bypassed {
+                if let Err(err) = self.set_bypassed(plugin_id, true) {
+                    let warning = format!("{} bypass: {:#}", entry.uid_or_name, err);
+                    warn!("{}", warning);
+                    import.warnings.push(warning);
+                }
+            }
+            import.plugins.push(plugin_id);
+        }
+
+        info!(
+            "Imported chain: {} plugins added, {} unresolved, {} warnings",
+            import.plugins.len(),
+            import.unresolved.len(),
+            import.warnings.len()
+        );
+        Ok(import)
+    }
+
+    /// Add a VST plugin hosted in its own process. A crash in the plugin only stops its
+    /// processing, the chain passes audio through it from then on.
+    #[cfg(feature = "sandbox")]
+    pub fn load_plugin_sandboxed(&mut self, path: &str) -> Result<PluginId> {
+        self.ensure_chain_unlocked()?;
+
+        info!("Loading sandboxed plugin: {:?}", path);
+
+        let owned_path = path.to_string();
+        let mut plugin = run_with_timeout(self.plugin_load_timeout, move || {
+            VSTHostContext::sandboxed(&owned_path)
+        })?;
+
+        // The host starts at its own defaults, bring it to the engine's rate
+        let sample_rate = self.current_sample_rate as f64;
+        if let Err(err) = plugin.reconfigure(sample_rate, MAX_BLOCK_SIZE as i32, self.process_mode)
+        {
+            warn!("Sandboxed plugin {} rejected setup: {}", path, err);
+        }
+
+        let id = self.insert_plugin(plugin)?;
+        info!(
+            "Successfully loaded sandboxed plugin: {} with ID: {:?}",
+            path, id
+        );
+        Ok(id)
+    }
+
+    pub fn plugin_load_timeout(&self) -> Duration {
+        self.plugin_load_timeout
+    }
+
+    /// Set how long `load_plugin` waits for a plugin's module to load
+    pub fn set_plugin_load_timeout(&mut self, timeout: Duration) {
+        self.plugin_load_timeout = timeout;
+    }
+
+    pub fn insert_ramp_blocks(&self) -> u32 {
+        self.insert_ramp_blocks
+    }
+
+    /// Set how many blocks a plugin loaded into the running chain fades in over, 0 disables it
+    pub fn set_insert_ramp_blocks(&mut self, blocks: u32) {
+        self.insert_ramp_blocks = blocks;
+    }
+
+    /// Internal helper to append an instantiated plugin to the end of the chain.
+    /// Fades the plugin in when the streams are already running.
+    fn insert_plugin(&mut self, mut plugin: VSTHostContext) -> Result<PluginId> {
+        self.ensure_chain_unlocked()?;
+
+        let id = plugin.id;
+        if self.input_stream.is_some() || self.output_stream.is_some() {
+            plugin.insert_ramp = InsertRamp::new(self.insert_ramp_blocks);
+        }
+        plugin
+            .parameters
+            .report_edits_to(id, self.parameter_edits.clone());
+
+        self.plugin_modules.write().unwrap().insert(id, plugin);
+        self.plugin_order.write().unwrap().push(id);
+        self.update_bus_layout(id);
+        self.refresh_latencies();
+        Ok(id)
+    }
+
+    /// Remove a plugin from the processing chain, and thus invalidates its context
+    pub fn remove_plugin(&mut self, plugin_id: PluginId) -> Result<()> {
+        self.ensure_chain_unlocked()?;
+
+        let removed = self.plugin_modules.write().unwrap().remove(&plugin_id);
+        match removed {
+            Some(_) => {
+                self.forget_plugin(plugin_id);
+                info!("Removed plugin with ID: {:?}", plugin_id);
+                Ok(())
+            }
+            None => Err(anyhow!("Plugin with ID {:?} not found", plugin_id)),
+        }
+    }
+
+    /// Internal helper to drop everything the engine tracks for a removed plugin
+    fn forget_plugin(&mut self, plugin_id: PluginId) {
+        self.plugin_order
+            .write()
+            .unwrap()
+            .retain(|id| *id != plugin_id);
+        self.bus_layouts.write().unwrap().remove(&plugin_id);
+        let mut tap = self.monitor_tap.write().unwrap();
+        if *tap == Some(plugin_id) {
+            *tap = None;
+        }
+        drop(tap);
+        self.plugin_rates.remove(&plugin_id);
+        self.plugin_resamplers.lock().unwrap().remove(&plugin_id);
+        self.latency_tracker.remove(plugin_id);
+        self.rebuild_latency_compensation();
+
+        let sinks = self
+            .plugin_modules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, plugin)| plugin.sidechain == Some(SidechainSource::Plugin(plugin_id)))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for sink in sinks {
+            if let Err(err) = self.set_sidechain_source(sink, None) {
+                warn!("Failed to disconnect sidechain of {:?}: {}", sink, err);
+            }
+        }
+    }
+
+    /// Reload a plugin from disk, e.g. after rebuilding it. The instance's state is saved,
+    /// its module released and the new instance takes over its ID, chain position, chain
+    /// and bypass. Returns whether the saved state was restored, a version that rejects
+    /// it starts from its defaults instead. If loading fails the plugin is removed.
+    pub fn reload_plugin(&mut self, plugin_id: PluginId) -> Result<bool> {
+        #[cfg(feature = "sandbox")]
+        let sandboxed = self
+            .plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .is_some_and(|plugin| plugin.sandbox.is_some());
+
+        // The same class comes back from a factory that ships several
+        let class_uid = self
+            .plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .filter(|plugin| plugin.format == PluginFormat::Vst3 && !plugin.uid.is_empty())
+            .map(|plugin| plugin.uid.clone());
+
+        self.reload_plugin_with(plugin_id, move |engine, path| {
+            #[cfg(feature = "sandbox")]
+            if sandboxed {
+                return run_with_timeout(engine.plugin_load_timeout, move || {
+                    VSTHostContext::sandboxed(&path)
+                });
+            }
+
+            engine.instantiate_plugin(&path, class_uid)
+        })
+    }
+
+    /// Internal helper for `reload_plugin` with the instantiation swappable for tests
+    fn reload_plugin_with(
+        &mut self,
+        plugin_id: PluginId,
+        load: impl FnOnce(&Self, String) -> Result<VSTHostContext>,
+    ) -> Result<bool> {
+        self.ensure_chain_unlocked()?;
+
+        let (path, state, chain, bypass, monitor_bus, sidechain, loudness_match) = {
+            let plugins = self.plugin_modules.read().unwrap();
+            let plugin = plugins
+                .get(&plugin_id)
+                .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?;
+            let state = plugin
+                .save_state()
+                .inspect_err(|err| warn!("Reloading {} without its state: {}", plugin.name, err))
+                .ok();
+            (
+                plugin.path.clone(),
+                state,
+                plugin.chain,
+                plugin.bypass,
+                plugin.monitor_bus,
+                plugin.sidechain,
+                plugin.loudness_match.enabled(),
+            )
+        };
+
+        info!("Reloading plugin {:?} from {}", plugin_id, path);
+
+        // The old instance has to go first so its module is unloaded before loading it again
+        self.bus_layouts.write().unwrap().remove(&plugin_id);
+        drop(self.plugin_modules.write().unwrap().remove(&plugin_id));
+
+        let mut plugin = match load(self, path) {
+            Ok(plugin) => plugin,
+            Err(err) => {
+                self.forget_plugin(plugin_id);
+                return Err(err.context(format!(
+                    "Failed to reload plugin {:?}, it was removed",
+                    plugin_id
+                )));
+            }
+        };
+
+        plugin.id = plugin_id;
+        plugin.chain = chain;
+        plugin.set_bypassed(bypass);
+        plugin.loudness_match.set_enabled(loudness_match);
+        if let Err(err) = plugin.set_sidechain(sidechain) {
+            warn!("Plugin {:?} lost its sidechain: {}", plugin_id, err);
+        }
+        let restored = match state.map(|state| plugin.load_state(&state)) {
+            Some(Ok(())) => true,
+            Some(Err(err)) => {
+                warn!(
+                    "{} rejected its saved state, keeping its defaults: {}",
+                    plugin.name, err
+                );
+                false
+            }
+            None => false,
+        };
+        if self.input_stream.is_some() || self.output_stream.is_some() {
+            plugin.insert_ramp = InsertRamp::new(self.insert_ramp_blocks);
+        }
+        plugin
+            .parameters
+            .report_edits_to(plugin_id, self.parameter_edits.clone());
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .insert(plugin_id, plugin);
+        self.update_bus_layout(plugin_id);
+
+        if let Err(err) = self.setup_plugin(plugin_id) {
+            warn!("Plugin {:?} failed setup, skipping it: {}", plugin_id, err);
+            self.setup_failures.push(plugin_id);
+        }
+        if monitor_bus != 0 {
+            if let Err(err) = self.set_monitor_bus(plugin_id, monitor_bus) {
+                warn!("Plugin {:?} lost its monitored bus: {}", plugin_id, err);
+            }
+        }
+        self.refresh_latencies();
+        self.rebuild_latency_compensation();
+
+        info!("Reloaded plugin {:?}", plugin_id);
+        Ok(restored)
+    }
+
+    /// Reorder the processing chain. `order` must contain every loaded plugin exactly once.
+    pub fn reorder_plugins(&mut self, order: &[PluginId]) -> Result<()> {
+        self.ensure_chain_unlocked()?;
+
+        let mut current = self.plugin_order.write().unwrap();
+
+        let mut sorted_new = order.to_vec();
+        let mut sorted_current = current.clone();
+        sorted_new.sort_by_key(|id| id.0);
+        sorted_current.sort_by_key(|id| id.0);
+
+        if sorted_new != sorted_current {
+            return Err(anyhow!(
+                "New order must contain every loaded plugin exactly once"
+            ));
+        }
+
+        *current = order.to_vec();
+        info!("Reordered plugin chain: {:?}", order);
+        Ok(())
+    }
+
+    /// Move a plugin to the start of the chain, keeping the others in order
+    pub fn move_plugin_to_front(&mut self, plugin_id: PluginId) -> Result<()> {
+        self.move_plugin(plugin_id, |order, id| order.insert(0, id))
+    }
+
+    /// Move a plugin to the end of the chain, keeping the others in order
+    pub fn move_plugin_to_back(&mut self, plugin_id: PluginId) -> Result<()> {
+        self.move_plugin(plugin_id, |order, id| order.push(id))
+    }
+
+    /// Internal helper taking a plugin out of the chain order and putting it back with `place`
+    fn move_plugin(
+        &mut self,
+        plugin_id: PluginId,
+        place: impl FnOnce(&mut Vec<PluginId>, PluginId),
+    ) -> Result<()> {
+        self.ensure_chain_unlocked()?;
+
+        let mut order = self.plugin_order.write().unwrap();
+        let index = order
+            .iter()
+            .position(|id| *id == plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?;
+
+        order.remove(index);
+        place(&mut order, plugin_id);
+        info!("Moved plugin {:?}, chain is now: {:?}", plugin_id, order);
+        Ok(())
+    }
+
+    /// Store the chain order and every plugin's state and bypass in an A/B slot,
+    /// replacing what it held. Plugins that fail to save their state keep only their
+    /// bypass in the snapshot.
+    pub fn store_snapshot(&mut self, slot: AbSlot) {
+        let order = self.plugin_order();
+        let plugins = self
+            .plugin_modules
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, plugin)| {
+                let state = plugin
+                    .save_state()
+                    .inspect_err(|err| {
+                        warn!(
+                            "Storing {} in snapshot {} without its state: {}",
+                            plugin.name,
+                            slot.as_str(),
+                            err
+                        )
+                    })
+                    .ok();
+                let snapshot = PluginSnapshot {
+                    state,
+                    bypass: plugin.bypass,
+                };
+                (*id, snapshot)
+            })
+            .collect();
+
+        self.ab_snapshots
+            .insert(slot, ChainSnapshot { order, plugins });
+        info!("Stored chain snapshot {}", slot.as_str());
+    }
+
+    /// Restore the chain order, plugin states and bypasses stored in an A/B slot. Plugins
+    /// removed since are skipped and ones loaded since keep their state and move to the
+    /// end of the chain. Everything is applied between two blocks, the block that would
+    /// render during the recall passes the input through instead. A recall that changes
+    /// the order fails while the chain is locked.
+    pub fn recall_snapshot(&mut self, slot: AbSlot) -> Result<()> {
+        let snapshot = self
+            .ab_snapshots
+            .get(&slot)
+            .ok_or_else(|| anyhow!("Snapshot {} is empty", slot.as_str()))?;
+
+        let current = self.plugin_order();
+        let mut order: Vec<PluginId> = snapshot
+            .order
+            .iter()
+            .copied()
+            .filter(|id| current.contains(id))
+            .collect();
+        order.extend(current.iter().filter(|id| !snapshot.order.contains(id)));
+        if order != current {
+            self.ensure_chain_unlocked()?;
+        }
+
+        {
+            let mut plugins = self.plugin_modules.write().unwrap();
+            for (id, stored) in &snapshot.plugins {
+                let Some(plugin) = plugins.get_mut(id) else {
+                    continue;
+                };
+                plugin.set_bypassed(stored.bypass);
+                if let Some(Err(err)) = stored.state.as_ref().map(|state| plugin.load_state(state))
+                {
+                    warn!(
+                        "{} rejected its state from snapshot {}: {}",
+                        plugin.name,
+                        slot.as_str(),
+                        err
+                    );
+                }
+            }
+            *self.plugin_order.write().unwrap() = order;
+        }
+
+        self.refresh_latencies();
+        self.rebuild_latency_compensation();
+        info!("Recalled chain snapshot {}", slot.as_str());
+        Ok(())
+    }
+
+    /// Whether an A/B slot holds a snapshot
+    pub fn has_snapshot(&self, slot: AbSlot) -> bool {
+        self.ab_snapshots.contains_key(&slot)
+    }
+
+    /// Add an empty parallel chain. It processes the same input as every other chain
+    /// and is summed into the output at unity gain.
+    pub fn add_chain(&mut self) -> Result<ChainId> {
+        self.ensure_chain_unlocked()?;
+
+        let id = ChainId::new();
+        self.chains.write().unwrap().push((id, 1.0));
+        info!("Added parallel chain {:?}", id);
+        Ok(id)
+    }
+
+    /// Remove a parallel chain, moving its plugins back to the main chain
+    pub fn remove_chain(&mut self, chain_id: ChainId) -> Result<()> {
+        self.ensure_chain_unlocked()?;
+
+        if chain_id == ChainId::MAIN {
+            return Err(anyhow!("The main chain can't be removed"));
+        }
+
+        let mut chains = self.chains.write().unwrap();
+        let len = chains.len();
+        chains.retain(|(id, _)| *id != chain_id);
+        if chains.len() == len {
+            return Err(anyhow!("Chain with ID {:?} not found", chain_id));
+        }
+
+        drop(chains);
+
+        for plugin in self.plugin_modules.write().unwrap().values_mut() {
+            if plugin.chain == chain_id {
+                plugin.chain = ChainId::MAIN;
+            }
+        }
+        self.rebuild_latency_compensation();
+
+        info!("Removed parallel chain {:?}", chain_id);
+        Ok(())
+    }
+
+    /// Get the chains in summing order with their gains
+    pub fn chains(&self) -> Vec<(ChainId, f32)> {
+        self.chains.read().unwrap().clone()
+    }
+
+    /// Set the linear gain a chain is summed into the output with
+    pub fn set_chain_gain(&mut self, chain_id: ChainId, gain: f32) -> Result<()> {
+        if !gain.is_finite() || gain < 0.0 {
+            return Err(anyhow!("Invalid chain gain: {}", gain));
+        }
+
+        let mut chains = self.chains.write().unwrap();
+        let chain = chains
+            .iter_mut()
+            .find(|(id, _)| *id == chain_id)
+            .ok_or_else(|| anyhow!("Chain with ID {:?} not found", chain_id))?;
+
+        chain.1 = gain;
+        Ok(())
+    }
+
+    /// Move a plugin to another chain. It keeps its position in the overall plugin order.
+    pub fn set_plugin_chain(&mut self, plugin_id: PluginId, chain_id: ChainId) -> Result<()> {
+        self.ensure_chain_unlocked()?;
+
+        if !self
+            .chains
+            .read()
+            .unwrap()
+            .iter()
+            .any(|(id, _)| *id == chain_id)
+        {
+            return Err(anyhow!("Chain with ID {:?} not found", chain_id));
+        }
+
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .chain = chain_id;
+        self.rebuild_latency_compensation();
+
+        info!("Moved plugin {:?} to chain {:?}", plugin_id, chain_id);
+        Ok(())
+    }
+
+    /// Route an output bus of a plugin to the engine output, 0 being the main bus
+    pub fn set_monitor_bus(&mut self, plugin_id: PluginId, bus_index: usize) -> Result<()> {
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .set_monitor_bus(bus_index)?;
+
+        self.update_bus_layout(plugin_id);
+
+        info!(
+            "Monitoring output bus {} of plugin {:?}",
+            bus_index, plugin_id
+        );
+        Ok(())
+    }
+
+    /// Feed a plugin's sidechain input bus from the main input or another plugin's output,
+    /// `None` disconnects it. A source processing after the plugin arrives a block late.
+    pub fn set_sidechain_source(
+        &mut self,
+        plugin_id: PluginId,
+        source: Option<SidechainSource>,
+    ) -> Result<()> {
+        if let Some(SidechainSource::Plugin(source_id)) = source {
+            if source_id == plugin_id {
+                return Err(anyhow!("A plugin can't sidechain itself"));
+            }
+            if !self.is_plugin_loaded(source_id) {
+                return Err(anyhow!("Plugin with ID {:?} not found", source_id));
+            }
+        }
+
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .set_sidechain(source)?;
+        self.update_bus_layout(plugin_id);
+
+        info!("Set sidechain of plugin {:?} to {:?}", plugin_id, source);
+        Ok(())
+    }
+
+    pub fn sidechain_source(&self, plugin_id: PluginId) -> Option<SidechainSource> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .and_then(|plugin| plugin.sidechain)
+    }
+
+    /// Internal helper to give a plugin its own bus layout if it needs one
+    fn update_bus_layout(&mut self, plugin_id: PluginId) {
+        let output_channels = self.output_data.as_ptr();
+        let plugins = self.plugin_modules.read().unwrap();
+        let mut bus_layouts = self.bus_layouts.write().unwrap();
+
+        match plugins
+            .get(&plugin_id)
+            .filter(|plugin| BusLayout::needed(plugin))
+        {
+            Some(plugin) => {
+                let layout = BusLayout::new(plugin, output_channels, &self.process_data);
+                bus_layouts.insert(plugin_id, layout);
+            }
+            None => {
+                bus_layouts.remove(&plugin_id);
+            }
+        }
+        drop(bus_layouts);
+        drop(plugins);
+
+        self.refit_resampler(plugin_id);
+    }
+
+    /// Internal helper for the channels of each bus a plugin processes, through its bus
+    /// layout if it has one
+    fn plugin_bus_channels(&self, plugin_id: PluginId) -> (Vec<usize>, Vec<usize>) {
+        let layouts = self.bus_layouts.read().unwrap();
+        let data = layouts
+            .get(&plugin_id)
+            .map_or(&*self.process_data, |layout| &*layout.process_data);
+        unsafe {
+            (
+                bus_channels(data.inputs, data.num_inputs),
+                bus_channels(data.outputs, data.num_outputs),
+            )
+        }
+    }
+
+    /// Internal helper to rebuild a plugin's resampler for a new bus layout, it carries
+    /// every channel of every bus
+    fn refit_resampler(&mut self, plugin_id: PluginId) {
+        let (inputs, outputs) = self.plugin_bus_channels(plugin_id);
+        let mut resamplers = self.plugin_resamplers.lock().unwrap();
+        let Some(resampler) = resamplers.get_mut(&plugin_id) else {
+            return;
+        };
+        if resampler.fits(&inputs, &outputs) {
+            return;
+        }
+
+        match PluginResampler::new(
+            resampler.engine_rate,
+            resampler.plugin_rate,
+            resampler.quality,
+            &inputs,
+            &outputs,
+        ) {
+            Ok(refitted) => *resampler = refitted,
+            Err(err) => warn!("Failed to resize the resampler of {:?}: {}", plugin_id, err),
+        }
+    }
+
+    /// Get the output bus currently monitored for a plugin
+    pub fn monitor_bus(&self, plugin_id: PluginId) -> Option<usize> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.monitor_bus)
+    }
+
+    /// Remove every plugin from the processing chain
+    pub fn clear_plugins(&mut self) -> Result<()> {
+        self.ensure_chain_unlocked()?;
+
+        self.plugin_order.write().unwrap().clear();
+        self.bus_layouts.write().unwrap().clear();
+        *self.monitor_tap.write().unwrap() = None;
+        self.plugin_modules.write().unwrap().clear();
+        self.plugin_rates.clear();
+        self.plugin_resamplers.lock().unwrap().clear();
+        self.latency_tracker.clear();
+        self.rebuild_latency_compensation();
+        info!("Cleared plugin chain");
+        Ok(())
+    }
+
+    /// Bypass or re-enable a plugin, through its bypass parameter if it has one. Allowed
+    /// even while the chain is locked.
+    pub fn set_bypassed(&mut self, plugin_id: PluginId, bypassed: bool) -> Result<()> {
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .set_bypassed(bypassed);
+        self.rebuild_latency_compensation();
+        info!("Set bypass of plugin {:?} to {}", plugin_id, bypassed);
+        Ok(())
+    }
+
+    /// Blend a plugin's output with its own input, from 0 (dry) to 1 (wet), for a
+    /// parallel-style blend inside a serial chain. The dry signal is delayed by the
+    /// plugin's latency. Takes effect on the next block.
+    pub fn set_plugin_mix(&mut self, plugin_id: PluginId, wet: f32) -> Result<()> {
+        if !(0.0..=1.0).contains(&wet) {
+            return Err(anyhow!("Plugin mix must be between 0 and 1, got {}", wet));
+        }
+
+        let plugins = self.plugin_modules.read().unwrap();
+        let plugin = plugins
+            .get(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?;
+        plugin
+            .mix
+            .set_latency(self.processing_latency(plugin_id).unwrap_or(0) as usize);
+        plugin.mix.set_wet(wet);
+        info!("Set mix of plugin {:?} to {}", plugin_id, wet);
+        Ok(())
+    }
+
+    pub fn plugin_mix(&self, plugin_id: PluginId) -> Option<f32> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.mix.wet())
+    }
+
+    /// Scale a plugin's output to the level of its input, so bypassing it compares the
+    /// sound rather than a change in loudness
+    pub fn set_bypass_loudness_match(&mut self, plugin_id: PluginId, enabled: bool) -> Result<()> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .loudness_match
+            .set_enabled(enabled);
+        info!(
+            "Set loudness-matched bypass of plugin {:?} to {}",
+            plugin_id, enabled
+        );
+        Ok(())
+    }
+
+    pub fn bypass_loudness_match(&self, plugin_id: PluginId) -> Option<bool> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.loudness_match.enabled())
+    }
+
+    /// Whether the app should periodically call `refresh_latencies`, off by default
+    pub fn latency_polling(&self) -> bool {
+        self.latency_polling
+    }
+
+    pub fn set_latency_polling(&mut self, enabled: bool) {
+        self.latency_polling = enabled;
+    }
+
+    /// Last latency a plugin reported, in samples
+    pub fn plugin_latency(&self, plugin_id: PluginId) -> Option<u32> {
+        self.latency_tracker.latency(plugin_id)
+    }
+
+    /// Latency of the slowest chain, in samples. Bypassed plugins don't count.
+    pub fn chain_latency(&self) -> u32 {
+        self.chain_latencies()
+            .into_iter()
+            .map(|(_, latency)| latency)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Poll every plugin's getLatencySamples and rebuild the latency compensation if any
+    /// of them changed. Catches plugins that change their latency without restartComponent.
+    /// Returns whether anything changed.
+    pub fn refresh_latencies(&mut self) -> bool {
+        let polled = self
+            .plugin_modules
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(id, plugin)| {
+                let processor = plugin.processor.as_ref()?;
+                Some((*id, unsafe { processor.get_latency_samples() }))
+            })
+            .collect::<Vec<_>>();
+
+        let mut changed = false;
+        for (id, latency) in polled {
+            if self.latency_tracker.update(id, latency) {
+                info!("Plugin {:?} reports {} samples of latency", id, latency);
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.rebuild_latency_compensation();
+        }
+        changed
+    }
+
+    /// Internal helper to sum the known latency of the processing plugins in every chain
+    fn chain_latencies(&self) -> Vec<(ChainId, u32)> {
+        let plugins = self.plugin_modules.read().unwrap();
+        let order = self.plugin_order.read().unwrap();
+
+        self.chains
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(chain, _)| {
+                let latency = processing_chain(&plugins, &order)
+                    .filter(|(_, plugin)| plugin.chain == *chain)
+                    .filter_map(|(id, _)| self.processing_latency(id))
+                    .sum();
+                (*chain, latency)
+            })
+            .collect()
+    }
+
+    /// Internal helper for the latency a plugin adds in engine samples. A plugin running at
+    /// its own rate reports in its own samples, and its resampling adds to it.
+    fn processing_latency(&self, plugin_id: PluginId) -> Option<u32> {
+        let latency = self.latency_tracker.latency(plugin_id);
+        match self.plugin_rates.get(&plugin_id) {
+            Some(rate) if rate.resampling_latency > 0 => {
+                let scale = self.current_sample_rate as f64 / rate.sample_rate as f64;
+                let plugin_latency = (latency.unwrap_or(0) as f64 * scale).round() as u32;
+                Some(plugin_latency + rate.resampling_latency)
+            }
+            _ => latency,
+        }
+    }
+
+    /// Internal helper to delay every parallel chain to the latency of the slowest one.
+    /// Delays that didn't change are kept so their contents carry over.
+    fn rebuild_latency_compensation(&mut self) {
+        for (id, plugin) in self.plugin_modules.read().unwrap().iter() {
+            let latency = self.processing_latency(*id).unwrap_or(0);
+            plugin.mix.set_latency(latency as usize);
+        }
+
+        let latencies = self.chain_latencies();
+        let max_latency = latencies
+            .iter()
+            .map(|(_, latency)| *latency)
+            .max()
+            .unwrap_or(0);
+
+        let mut delays = self.chain_delays.lock().unwrap();
+        let mut rebuilt = FxHashMap::default();
+        for (chain, latency) in latencies {
+            let delay = (max_latency - latency) as usize;
+            if delay == 0 {
+                continue;
+            }
+
+            let line = match delays.remove(&chain) {
+                Some(line) if line.delay() == delay => line,
+                _ => DelayLine::new(delay),
+            };
+            rebuilt.insert(chain, line);
+        }
+        *delays = rebuilt;
+
+        let mut monitor_delay = self.monitor_delay.lock().unwrap();
+        if monitor_delay.delay() != max_latency as usize {
+            *monitor_delay = DelayLine::new(max_latency as usize);
+        }
+        drop(monitor_delay);
+        self.stream_status.write().unwrap().latency = max_latency;
+    }
+
+    /// Gather the host, devices, stream configs, plugins and xrun counters into one
+    /// report that can be attached to a support ticket
+    pub fn diagnostics(&self) -> Diagnostics {
+        let (input_format, output_format) = self.stream_formats().unzip();
+        let format_names =
+            |formats: Vec<SampleFormat>| formats.iter().map(|f| f.to_string()).collect();
+
+        let input = self.input_config.as_ref().map(|config| StreamDiagnostics {
+            device: self.input_device_name(),
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+            format: input_format.map(|f| f.to_string()),
+            supported_formats: format_names(self.supported_input_formats()),
+        });
+        let output = self.output_config.as_ref().map(|config| StreamDiagnostics {
+            device: self.output_device_name(),
+            channels: config.channels,
+            sample_rate: config.sample_rate.0,
+            format: output_format.map(|f| f.to_string()),
+            supported_formats: format_names(self.supported_output_formats()),
+        });
+
+        let plugins = {
+            let modules = self.plugin_modules.read().unwrap();
+            self.plugin_order
+                .read()
+                .unwrap()
+                .iter()
+                .filter_map(|id| {
+                    let plugin = modules.get(id)?;
+                    Some(PluginDiagnostics {
+                        id: id.0,
+                        name: plugin.name.clone(),
+                        path: plugin.path.clone(),
+                        vendor: plugin.vendor.clone(),
+                        uid: plugin.uid.clone(),
+                        chain: plugin.chain.0,
+                        bypassed: plugin.bypass,
+                        setup_failed: plugin.setup_failed,
+                        latency: self.latency_tracker.latency(*id),
+                        cpu: self.block_load(&plugin.process_time),
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        Diagnostics {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            host: self.host_name().to_string(),
+            input,
+            output,
+            monitor_output: self.monitor_output(),
+            sample_rate: self.current_sample_rate,
+            buffer_size: self.current_buffer_size,
+            ring_latency_blocks: self.ring_latency_blocks,
+            resampler: ResamplerDiagnostics {
+                quality: self.resampler_quality.as_str().to_string(),
+                adaptive: self.adaptive_resampling(),
+                nominal_ratio: self.nominal_resample_ratio(),
+                effective_ratio: self.effective_resample_ratio(),
+            },
+            dsp_load: plugins.iter().map(|plugin| plugin.cpu).sum(),
+            plugins,
+            total_latency: self.chain_latency(),
+            underrun_count: self.underrun_count(),
+            overflow_count: self.overflow_count(),
+            input_gain: self.input_gain(),
+            denormal_protection: self.denormal_protection(),
+        }
+    }
+
+    /// Internal buffer and pipeline state after the last block the callback ran, for
+    /// tracking down glitches. Without running streams it reads all zeroes.
+    #[cfg(feature = "debug-introspection")]
+    pub fn debug_snapshot(&self) -> DebugSnapshot {
+        self.probe.snapshot(self.plugin_order.read().unwrap().len())
+    }
+
+    /// Event queue of a plugin, notes and automation added to it reach the plugin's
+    /// processor at the start of its next block
+    pub fn plugin_events(&self, plugin_id: PluginId) -> Option<Arc<EventList>> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.events.clone())
+    }
+
+    /// Parameters a plugin's edit controller describes
+    pub fn plugin_parameters(&self, plugin_id: PluginId) -> Result<Vec<PluginParameter>> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.parameter_list.clone())
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))
+    }
+
+    /// Queue automation for a plugin parameter into its next block, see
+    /// `VSTHostContext::automate_parameter`
+    pub fn automate_parameter(
+        &self,
+        plugin_id: PluginId,
+        id: u32,
+        sample_offset: i32,
+        value: f64,
+    ) -> Result<()> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .automate_parameter(id, sample_offset, value)
+    }
+
+    /// A plugin's (input, output) peaks since they were last read
+    pub fn plugin_io_levels(&self, plugin_id: PluginId) -> Option<(f32, f32)> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.io_meter.take())
+    }
+
+    /// Every loaded plugin's (input, output) peaks since they were last read, in chain
+    /// order, so a whole chain of meters updates from one call
+    pub fn all_plugin_io_levels(&self) -> Vec<(PluginId, f32, f32)> {
+        let plugins = self.plugin_modules.read().unwrap();
+        self.plugin_order
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|id| plugins.get(id))
+            .map(|plugin| {
+                let (input, output) = plugin.io_meter.take();
+                (plugin.id, input, output)
+            })
+            .collect()
+    }
+
+    /// Average share of the block's real-time budget a plugin spends processing, in percent
+    pub fn plugin_cpu(&self, plugin_id: PluginId) -> f32 {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map_or(0.0, |plugin| self.block_load(&plugin.process_time))
+    }
+
+    /// Share of the block's real-time budget the whole chain spends processing, in percent
+    pub fn chain_cpu(&self) -> f32 {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .values()
+            .map(|plugin| self.block_load(&plugin.process_time))
+            .sum()
+    }
+
+    /// Share of the block's real-time budget left after processing, in percent. Averaged
+    /// over the last blocks, 0 once processing takes the whole budget.
+    pub fn headroom(&self) -> f32 {
+        self.callback_load.headroom()
+    }
+
+    /// Whether enough of the last blocks took over 80% of their budget that the streams
+    /// are likely to xrun soon, raising the buffer size gives them more room
+    pub fn is_overloaded(&self) -> bool {
+        self.callback_load.is_overloaded()
+    }
+
+    /// Internal helper to express a process timer's average as a percentage of the block
+    fn block_load(&self, timer: &ProcessTimer) -> f32 {
+        timer_load(timer, self.block_budget_us())
+    }
+
+    /// Internal helper for the real-time budget of one block in microseconds
+    fn block_budget_us(&self) -> f32 {
+        self.current_buffer_size as f32 / self.current_sample_rate as f32 * 1_000_000.0
+    }
+
+    /// Set a normalized parameter value on a plugin's controller and processor
+    pub fn set_parameter(&mut self, plugin_id: PluginId, id: u32, value: f64) -> Result<()> {
+        self.plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&plugin_id)
+            .ok_or_else(|| anyhow!("Plugin with ID {:?} not found", plugin_id))?
+            .set_parameter(id, value)
+    }
+
+    /// Last normalized value set by the app or reported by the plugin's editor
+    pub fn parameter_value(&self, plugin_id: PluginId, id: u32) -> Option<f64> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .and_then(|plugin| plugin.parameters.value(id))
+    }
+
+    /// Take the parameter edits plugin editors made since the last call
+    pub fn take_parameter_edits(&self) -> Vec<ParameterEdit> {
+        self.parameter_edits.take()
+    }
+
+    /// Get the queue of parameter edits to poll without holding on to the engine. It
+    /// stays the same queue for the engine's lifetime.
+    pub fn parameter_edit_queue(&self) -> Arc<EditQueue> {
+        self.parameter_edits.clone()
+    }
+
+    /// Check if a plugin is bypassed
+    pub fn is_bypassed(&self, plugin_id: PluginId) -> Option<bool> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .get(&plugin_id)
+            .map(|plugin| plugin.bypass)
+    }
+
+    /// Get reference to loaded plugin modules
+    pub fn plugin_modules(&self) -> RwLockReadGuard<'_, FxHashMap<PluginId, VSTHostContext>> {
+        self.plugin_modules.read().unwrap()
+    }
+
+    pub fn plugin_modules_mut(
+        &mut self,
+    ) -> RwLockWriteGuard<'_, FxHashMap<PluginId, VSTHostContext>> {
+        self.plugin_modules.write().unwrap()
+    }
+
+    /// Get the loaded plugin IDs in processing order
+    pub fn plugin_order(&self) -> Vec<PluginId> {
+        self.plugin_order.read().unwrap().clone()
+    }
+
+    /// Check if a plugin is loaded
+    pub fn is_plugin_loaded(&self, plugin_id: PluginId) -> bool {
+        self.plugin_modules.read().unwrap().contains_key(&plugin_id)
+    }
+
+    /// Remove a plugin by ID
+    pub fn unload_plugin(&mut self, plugin_id: PluginId) -> Result<()> {
+        self.remove_plugin(plugin_id)
+    }
+
+    /// Get list of all loaded plugin IDs
+    pub fn get_loaded_plugin_ids(&self) -> Vec<PluginId> {
+        self.plugin_modules
+            .read()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+    use vst::host::HostComponentHandler;
+    use vst::parameters::MAX_PARAMETER_CHANGES;
+    use vst3::base::funknown::{IEditController_Impl, TResult};
+    use vst3::vst::audio_processor::ParameterInfo;
+
+    fn make_range(fmt: SampleFormat) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            2,
+            SampleRate(44100),
+            SampleRate(44100),
+            SupportedBufferSize::Unknown,
+            fmt,
+        )
+    }
+
+    fn make_range_with_config(
+        fmt: SampleFormat,
+        min_rate: u32,
+        max_rate: u32,
+        buffer_size: SupportedBufferSize,
+    ) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(
+            2,
+            SampleRate(min_rate),
+            SampleRate(max_rate),
+            buffer_size,
+            fmt,
+        )
+    }
+
+    #[test]
+    fn test_available_host_by_name() {
+        let engine = AudioEngine::default();
+        for host_id in engine.available_hosts() {
+            assert_eq!(
+                engine.available_host_by_name(host_id.name()).unwrap(),
+                *host_id
+            );
+        }
+        assert!(engine.available_host_by_name("Not a host").is_err());
+    }
+
+    #[test]
+    fn test_refresh_devices_drops_unplugged_devices() {
+        let mut engine = AudioEngine::default();
+        let host_id = engine.host().id();
+        let input = engine.input_device_name();
+        let output = engine.output_device_name();
+
+        engine
+            .cached_input_devices
+            .entry(host_id)
+            .or_default()
+            .push("Unplugged".to_string());
+        engine
+            .cached_input_configs
+            .insert("Unplugged".to_string(), Vec::new());
+
+        engine.refresh_devices();
+
+        assert!(!engine
+            .cached_current_input_device_names()
+            .unwrap_or_default()
+            .contains(&"Unplugged".to_string()));
+        assert!(!engine.cached_input_configs.contains_key("Unplugged"));
+        assert_eq!(engine.input_device_name(), input);
+        assert_eq!(engine.output_device_name(), output);
+    }
+
+    #[test]
+    fn test_input_source_test_signals() {
+        let mut generator = SignalGenerator::default();
+        let sine = InputSource::SineWave {
+            freq: 1000.0,
+            amp: 0.5,
+        };
+        let samples: Vec<f32> = (0..48)
+            .map(|_| sine.next_sample(&mut generator, 48000.0))
+            .collect();
+        assert_eq!(samples[0], 0.0);
+        assert!((samples[12] - 0.5).abs() < 1e-6);
+        assert!((samples[36] + 0.5).abs() < 1e-6);
+        // One full cycle later it starts over
+        assert!(sine.next_sample(&mut generator, 48000.0).abs() < 1e-6);
+
+        let noise = InputSource::WhiteNoise { amp: 0.25 };
+        let samples: Vec<f32> = (0..4800)
+            .map(|_| noise.next_sample(&mut generator, 48000.0))
+            .collect();
+        assert!(samples.iter().all(|s| s.abs() <= 0.25));
+        assert!(samples.iter().any(|&s| s > 0.2) && samples.iter().any(|&s| s < -0.2));
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 0.02);
+
+        for source in [InputSource::Silence, InputSource::Device] {
+            assert_eq!(source.next_sample(&mut generator, 48000.0), 0.0);
+        }
+
+        let mut engine = AudioEngine::default();
+        assert_eq!(engine.input_source(), InputSource::Device);
+        engine.set_input_source(sine).unwrap();
+        assert_eq!(engine.input_source(), sine);
+
+        // Out of range settings keep the current source
+        let nyquist = engine.current_sample_rate as f32 / 2.0;
+        for invalid in [
+            InputSource::SineWave {
+                freq: 0.0,
+                amp: 0.5,
+            },
+            InputSource::SineWave {
+                freq: nyquist,
+                amp: 0.5,
+            },
+            InputSource::SineWave {
+                freq: 440.0,
+                amp: 1.5,
+            },
+            InputSource::WhiteNoise { amp: -0.1 },
+        ] {
+            assert!(engine.set_input_source(invalid).is_err());
+        }
+        assert_eq!(engine.input_source(), sine);
+
+        let json: InputSource =
+            serde_json::from_str(r#"{ "type": "white_noise", "amp": 0.1 }"#).unwrap();
+        assert_eq!(json, InputSource::WhiteNoise { amp: 0.1 });
+    }
+
+    #[test]
+    fn test_supported_rates_and_buffer_sizes() {
+        let mut engine = AudioEngine::default();
+        engine.cached_input_configs.insert(
+            "Test Device".to_string(),
+            vec![
+                make_range_with_config(
+                    SampleFormat::F32,
+                    44100,
+                    96000,
+                    SupportedBufferSize::Range { min: 64, max: 512 },
+                ),
+                make_range_with_config(
+                    SampleFormat::I16,
+                    22222,
+                    22222,
+                    SupportedBufferSize::Range { min: 100, max: 200 },
+                ),
+            ],
+        );
+
+        assert_eq!(
+            engine.supported_sample_rates("Test Device"),
+            vec![22222, 44100, 48000, 88200, 96000]
+        );
+        assert_eq!(
+            engine.supported_buffer_sizes("Test Device"),
+            vec![64, 128, 256, 512]
+        );
+        assert!(engine.supported_sample_rates("Missing").is_empty());
+
+        engine.cached_output_configs.insert(
+            "Test Device".to_string(),
+            vec![make_range(SampleFormat::F32)],
+        );
+        assert_eq!(
+            engine.supported_buffer_sizes("Test Device"),
+            vec![16, 32, 64, 128, 256, 512, 1024, 2048, 4096, 8192, 16384]
+        );
+    }
+
+    #[test]
+    fn test_buffer_size_and_sample_rate_follow_device_limits() {
+        let input = vec![
+            make_range_with_config(
+                SampleFormat::F32,
+                44100,
+                48000,
+                SupportedBufferSize::Range { min: 64, max: 512 },
+            ),
+            make_range_with_config(
+                SampleFormat::I16,
+                96000,
+                96000,
+                SupportedBufferSize::Range {
+                    min: 128,
+                    max: 1024,
+                },
+            ),
+        ];
+        let output = vec![make_range_with_config(
+            SampleFormat::F32,
+            44100,
+            48000,
+            SupportedBufferSize::Range { min: 32, max: 256 },
+        )];
+        let unknown = vec![make_range(SampleFormat::F32)];
+
+        let range = common_buffer_size_range([input.as_slice(), output.as_slice()].into_iter());
+        assert_eq!(range, Some((64, 256)));
+        assert_eq!(
+            common_buffer_size_range([input.as_slice(), unknown.as_slice()].into_iter()),
+            Some((64, 1024))
+        );
+        assert_eq!(
+            common_buffer_size_range([unknown.as_slice()].into_iter()),
+            None
+        );
+
+        assert_eq!(fit_buffer_size(128, range).unwrap(), 128);
+        assert_eq!(fit_buffer_size(48, range).unwrap(), 64);
+        assert_eq!(fit_buffer_size(500, range).unwrap(), 256);
+        let err = fit_buffer_size(4096, range).unwrap_err();
+        assert!(err.to_string().contains("64-256"));
+        assert!(fit_buffer_size(16, range).is_err());
+        assert_eq!(fit_buffer_size(4096, None).unwrap(), 4096);
+        assert!(fit_buffer_size(128, Some((512, 256))).is_err());
+
+        assert!(check_rate_supported(&input, 48000).is_ok());
+        assert!(check_rate_supported(&input, 96000).is_ok());
+        assert_eq!(
+            check_rate_supported(&input, 192000),
+            Err("44100-48000, 96000-96000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pick_best_format_prefers_f32() {
+        let configs = vec![
+            make_range(SampleFormat::I16),
+            make_range(SampleFormat::F32),
+            make_range(SampleFormat::U16),
+        ];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn test_pick_best_format_prefers_i32_over_u32() {
+        let configs = vec![make_range(SampleFormat::U32), make_range(SampleFormat::I32)];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::I32);
+    }
+
+    #[test]
+    fn test_pick_best_format_scores_i24() {
+        let configs = vec![
+            make_range(SampleFormat::I16),
+            make_range(SampleFormat::I24),
+            make_range(SampleFormat::U32),
+        ];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::I24);
+
+        let configs = vec![make_range(SampleFormat::I24), make_range(SampleFormat::F32)];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::F32);
+    }
+
+    #[test]
+    fn test_pick_best_format_returns_none_for_empty() {
+        let configs: Vec<SupportedStreamConfigRange> = vec![];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_pick_best_format_picks_first_in_priority() {
+        let configs = vec![
+            make_range(SampleFormat::U8),
+            make_range(SampleFormat::I8),
+            make_range(SampleFormat::I16),
+        ];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::I16);
+    }
+
+    #[test]
+    fn test_pick_best_format_prioritizes_sample_rate() {
+        let configs = vec![
+            make_range_with_config(
+                SampleFormat::F32,
+                22050,
+                22050,
+                SupportedBufferSize::Unknown,
+            ),
+            make_range_with_config(
+                SampleFormat::I16,
+                44100,
+                44100,
+                SupportedBufferSize::Unknown,
+            ),
+        ];
+        let result = pick_best_format(
+            configs.into_iter(),
+            Some(44100),
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        let config = result.unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+        assert_eq!(config.sample_rate().0, 44100);
+    }
+
+    #[test]
+    fn test_pick_best_format_prioritizes_buffer_size() {
+        use cpal::SupportedBufferSize;
+        let configs = vec![
+            make_range_with_config(
+                SampleFormat::F32,
+                44100,
+                44100,
+                SupportedBufferSize::Range { min: 128, max: 256 },
+            ),
+            make_range_with_config(
+                SampleFormat::I16,
+                44100,
+                44100,
+                SupportedBufferSize::Range {
+                    min: 512,
+                    max: 1024,
+                },
+            ),
+        ];
+        let result = pick_best_format(
+            configs.into_iter(),
+            Some(44100),
+            Some(512),
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::I16);
+    }
+
+    #[test]
+    fn test_pick_best_format_exact_format_match() {
+        let configs = vec![make_range(SampleFormat::I16), make_range(SampleFormat::F32)];
+        let result = pick_best_format(
+            configs.into_iter(),
+            None,
+            None,
+            Some(SampleFormat::I16),
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        assert_eq!(result.unwrap().sample_format(), SampleFormat::I16);
+    }
+
+    #[test]
+    fn test_pick_best_format_all_preferences_match() {
+        use cpal::SupportedBufferSize;
+        let configs = vec![
+            make_range_with_config(
+                SampleFormat::I16,
+                22050,
+                22050,
+                SupportedBufferSize::Range { min: 128, max: 256 },
+            ),
+            make_range_with_config(
+                SampleFormat::F32,
+                44100,
+                48000,
+                SupportedBufferSize::Range {
+                    min: 512,
+                    max: 1024,
+                },
+            ),
+        ];
+        let result = pick_best_format(
+            configs.into_iter(),
+            Some(44100),
+            Some(512),
+            Some(SampleFormat::F32),
+            None,
+            FormatStrategy::BestQuality,
+        );
+        assert!(result.is_some());
+        let config = result.unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::F32);
+        assert_eq!(config.sample_rate().0, 44100);
+    }
+
+    /// Ranges a strategy picks from: a high-rate f32 range with large buffers, a 48 kHz
+    /// i16 range with small buffers and a 48 kHz i32 range with medium buffers
+    fn strategy_ranges() -> Vec<SupportedStreamConfigRange> {
+        use cpal::SupportedBufferSize;
+        vec![
+            make_range_with_config(
+                SampleFormat::F32,
+                96000,
+                96000,
+                SupportedBufferSize::Range {
+                    min: 512,
+                    max: 2048,
+                },
+            ),
+            make_range_with_config(
+                SampleFormat::I16,
+                48000,
+                48000,
+                SupportedBufferSize::Range { min: 32, max: 256 },
+            ),
+            make_range_with_config(
+                SampleFormat::I32,
+                48000,
+                48000,
+                SupportedBufferSize::Range {
+                    min: 128,
+                    max: 1024,
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_format_strategy_best_quality() {
+        let config = pick_best_format(
+            strategy_ranges().into_iter(),
+            Some(48000),
+            Some(512),
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        )
+        .unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I32);
+        assert_eq!(config.sample_rate().0, 48000);
+    }
+
+    #[test]
+    fn test_format_strategy_lowest_latency() {
+        // The smallest buffer wins over the buffer size preference and format
+        let config = pick_best_format(
+            strategy_ranges().into_iter(),
+            Some(48000),
+            Some(512),
+            None,
+            None,
+            FormatStrategy::LowestLatency,
+        )
+        .unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+    }
+
+    #[test]
+    fn test_format_strategy_exact_or_fail() {
+        let exact = pick_best_format(
+            strategy_ranges().into_iter(),
+            Some(96000),
+            Some(1024),
+            Some(SampleFormat::F32),
+            Some(2),
+            FormatStrategy::ExactOrFail,
+        )
+        .unwrap();
+        assert_eq!(exact.sample_format(), SampleFormat::F32);
+        assert_eq!(exact.sample_rate().0, 96000);
+
+        // 48 kHz f32 isn't offered by any range, best quality settles for another
+        let preferences = (Some(48000), Some(256), Some(SampleFormat::F32), Some(2));
+        assert!(pick_best_format(
+            strategy_ranges().into_iter(),
+            preferences.0,
+            preferences.1,
+            preferences.2,
+            preferences.3,
+            FormatStrategy::ExactOrFail,
+        )
+        .is_none());
+        assert!(pick_best_format(
+            strategy_ranges().into_iter(),
+            preferences.0,
+            preferences.1,
+            preferences.2,
+            preferences.3,
+            FormatStrategy::BestQuality,
+        )
+        .is_some());
+
+        assert_eq!(
+            "lowest_latency".parse::<FormatStrategy>().unwrap(),
+            FormatStrategy::LowestLatency
+        );
+        assert!("fastest".parse::<FormatStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_format_strategy_sets_the_requested_buffer() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(512).unwrap();
+        let range = make_range_with_config(
+            SampleFormat::I32,
+            44100,
+            96000,
+            SupportedBufferSize::Range { min: 8, max: 4096 },
+        );
+        let pick = |engine: &AudioEngine| {
+            select_device_config(
+                "Interface",
+                "output",
+                Ok(vec![range].into_iter()),
+                engine.format_strategy(),
+                engine.preferred_stream_settings(),
+            )
+            .map(|config| engine.stream_config(&config))
+        };
+
+        let config = pick(&engine).unwrap();
+        assert_eq!(config.buffer_size, cpal::BufferSize::Default);
+        assert_eq!(config.sample_rate.0, PREFERRED_SAMPLE_RATE);
+
+        // The smallest buffer the device allows, but no smaller than a block
+        engine.set_format_strategy(FormatStrategy::LowestLatency);
+        let config = pick(&engine).unwrap();
+        assert_eq!(
+            config.buffer_size,
+            cpal::BufferSize::Fixed(MIN_BLOCK_SIZE as u32)
+        );
+
+        // Exactly the engine's settings, not the defaults, or nothing
+        engine.set_format_strategy(FormatStrategy::ExactOrFail);
+        let config = pick(&engine).unwrap();
+        assert_eq!(config.buffer_size, cpal::BufferSize::Fixed(512));
+        assert_eq!(config.sample_rate.0, engine.sample_rate());
+        engine.set_buffer_size(8192).unwrap();
+        assert!(pick(&engine).is_err());
+    }
+
+    /// Minimal in-process stand-ins for a plugin's component and audio processor
+    mod mock {
+        use std::ffi::c_void;
+
+        use vst3::base::funknown::{
+            FUnknown, FUnknown_Vtbl, IAudioProcessor, IAudioProcessor_Vtbl, IBStream,
+            IBStream_Impl, IComponent, IComponent_Vtbl, IPluginBase, IPluginBase_Vtbl, TResult,
+            FUID,
+        };
+        use vst3::vst::audio_processor::speaker_arr::SpeakerArrangement;
+        use vst3::vst::audio_processor::{
+            BusDirection, BusInfo, IParamValueQueue_Impl, IParameterChanges,
+            IParameterChanges_Impl, IoMode, MediaType, ProcessContext, ProcessData, ProcessSetup,
+            RoutingInfo, SymbolicSampleSize,
+        };
+        use vst3::VSTPtr;
+
+        // FUID is passed by value to match the vtable signature
+        #[allow(improper_ctypes_definitions)]
+        unsafe extern "C" fn query_interface(
+            _this: *mut FUnknown,
+            _iid: FUID,
+            _obj: *mut *mut c_void,
+        ) -> TResult {
+            TResult::NoInterface
+        }
+
+        unsafe extern "C" fn add_ref(_this: *mut FUnknown) -> u32 {
+            1
+        }
+
+        unsafe extern "C" fn release(_this: *mut FUnknown) -> u32 {
+            1
+        }
+
+        thread_local! {
+            /// Lifecycle calls that shut a mock down, in the order they were made
+            pub static SHUTDOWN_CALLS: std::cell::RefCell<Vec<&'static str>> =
+                const { std::cell::RefCell::new(Vec::new()) };
+        }
+
+        thread_local! {
+            /// Process context the last block was processed with, if it had one
+            pub static LAST_CONTEXT: std::cell::Cell<Option<ProcessContext>> =
+                const { std::cell::Cell::new(None) };
+        }
+
+        fn record_shutdown(call: &'static str) {
+            SHUTDOWN_CALLS.with(|calls| calls.borrow_mut().push(call));
+        }
+
+        pub(super) const UNKNOWN_VTBL: FUnknown_Vtbl = FUnknown_Vtbl {
+            query_interface,
+            add_ref,
+            release,
+        };
+
+        /// Processor that writes its input scaled by `gain` to its output
+        #[repr(C)]
+        pub struct MockProcessor {
+            base: IAudioProcessor,
+            gain: f32,
+        }
+
+        unsafe extern "C" fn set_bus_arrangements(
+            _this: *mut IAudioProcessor,
+            _inputs: *mut SpeakerArrangement,
+            _num_inputs: i32,
+            _outputs: *mut SpeakerArrangement,
+            _num_outputs: i32,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_bus_arrangements(
+            _this: *mut IAudioProcessor,
+            _dir: BusDirection,
+            _index: i32,
+            _arr: *mut SpeakerArrangement,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn can_process_sample_size(
+            _this: *mut IAudioProcessor,
+            _symbolic_sample_size: SymbolicSampleSize,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_latency_samples(_this: *mut IAudioProcessor) -> u32 {
+            0
+        }
+
+        unsafe extern "C" fn setup_processing(
+            _this: *mut IAudioProcessor,
+            _setup: *mut ProcessSetup,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn set_processing(_this: *mut IAudioProcessor, state: bool) -> TResult {
+            if !state {
+                record_shutdown("setProcessing(false)");
+            }
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn process(
+            this: *mut IAudioProcessor,
+            data: *mut ProcessData,
+        ) -> TResult {
+            let data = &*data;
+            if !data.process_context.is_null() {
+                LAST_CONTEXT.with(|context| context.set(Some(*data.process_context)));
+            }
+
+            // Parameter 0 sets the gain
+            let changes = data.input_parameter_changes as *mut IParameterChanges;
+            if !changes.is_null() {
+                for index in 0..(*changes).get_parameter_count() {
+                    let queue = &*(*changes).get_parameter_data(index);
+                    let points = queue.get_point_count();
+                    if queue.get_parameter_id() != 0 || points == 0 {
+                        continue;
+                    }
+
+                    let mut offset = 0;
+                    let mut value = 0.0;
+                    queue.get_point(points - 1, &mut offset, &mut value);
+                    (*(this as *mut MockProcessor)).gain = value as f32;
+                }
+            }
+
+            let gain = (*(this as *mut MockProcessor)).gain;
+            let input = &*data.inputs;
+            let output = &*data.outputs;
+
+            for channel in 0..output.num_channels as usize {
+                let src = *input.channel_buffers_32.add(channel);
+                let dst = *output.channel_buffers_32.add(channel);
+                for i in 0..data.num_samples as usize {
+                    *dst.add(i) = *src.add(i) * gain;
+                }
+            }
+
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_tail_samples(_this: *mut IAudioProcessor) -> u32 {
+            0
+        }
+
+        static PROCESSOR_VTBL: IAudioProcessor_Vtbl = IAudioProcessor_Vtbl {
+            base: UNKNOWN_VTBL,
+            set_bus_arrangements,
+            get_bus_arrangements,
+            can_process_sample_size,
+            get_latency_samples,
+            setup_processing,
+            set_processing,
+            process,
+            get_tail_samples,
+        };
+
+        pub fn processor(gain: f32) -> VSTPtr<IAudioProcessor> {
+            let processor = Box::new(MockProcessor {
+                base: IAudioProcessor {
+                    vtable: &PROCESSOR_VTBL,
+                },
+                gain,
+            });
+            VSTPtr::new(Box::into_raw(processor) as *mut IAudioProcessor)
+        }
+
+        /// Component whose `setActive(true)` fails when `fail_activation` is set. Its
+        /// state is whatever bytes were last restored.
+        #[repr(C)]
+        pub struct MockComponent {
+            base: IComponent,
+            fail_activation: bool,
+            state: Vec<u8>,
+        }
+
+        unsafe extern "C" fn initialize(
+            _this: *mut IPluginBase,
+            _context: *mut FUnknown,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn terminate(_this: *mut IPluginBase) -> TResult {
+            record_shutdown("terminate");
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_controller_class_id(
+            _this: *mut IComponent,
+            _class_id: *mut FUID,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn set_io_mode(_this: *mut IComponent, _mode: IoMode) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_bus_count(
+            _this: *mut IComponent,
+            _media_type: MediaType,
+            _dir: BusDirection,
+        ) -> i32 {
+            1
+        }
+
+        unsafe extern "C" fn get_bus_info(
+            _this: *mut IComponent,
+            _media_type: MediaType,
+            _dir: BusDirection,
+            _index: i32,
+            _bus: *mut BusInfo,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_routing_info(
+            _this: *mut IComponent,
+            _in_info: *mut RoutingInfo,
+            _out_info: *mut RoutingInfo,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn activate_bus(
+            _this: *mut IComponent,
+            _media_type: MediaType,
+            _dir: BusDirection,
+            _index: i32,
+            _state: bool,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn set_active(this: *mut IComponent, state: bool) -> TResult {
+            if !state {
+                record_shutdown("setActive(false)");
+            }
+            if state && (*(this as *mut MockComponent)).fail_activation {
+                TResult::InternalError
+            } else {
+                TResult::ResultOk
+            }
+        }
+
+        unsafe extern "C" fn set_state(this: *mut IComponent, state: *mut c_void) -> TResult {
+            let stream = &*(state as *mut IBStream);
+            let mut data = Vec::new();
+            let mut chunk = [0u8; 64];
+            loop {
+                let mut read = 0;
+                stream.read(chunk.as_mut_ptr() as *mut c_void, 64, &mut read);
+                if read <= 0 {
+                    break;
+                }
+                data.extend_from_slice(&chunk[..read as usize]);
+            }
+            (*(this as *mut MockComponent)).state = data;
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_state(this: *mut IComponent, state: *mut c_void) -> TResult {
+            let data = &mut (*(this as *mut MockComponent)).state;
+            let stream = &*(state as *mut IBStream);
+            let mut written = 0;
+            stream.write(
+                data.as_mut_ptr() as *mut c_void,
+                data.len() as i32,
+                &mut written,
+            )
+        }
+
+        static COMPONENT_VTBL: IComponent_Vtbl = IComponent_Vtbl {
+            base: IPluginBase_Vtbl {
+                base: UNKNOWN_VTBL,
+                initialize,
+                terminate,
+            },
+            get_controller_class_id,
+            set_io_mode,
+            get_bus_count,
+            get_bus_info,
+            get_routing_info,
+            activate_bus,
+            set_active,
+            set_state,
+            get_state,
+        };
+
+        pub fn component(fail_activation: bool) -> VSTPtr<IComponent> {
+            let component = Box::new(MockComponent {
+                base: IComponent {
+                    vtable: &COMPONENT_VTBL,
+                },
+                fail_activation,
+                state: Vec::new(),
+            });
+            VSTPtr::new(Box::into_raw(component) as *mut IComponent)
+        }
+    }
+
+    /// Unit info with just the root unit. With `programs` it has program list
+    /// `PROGRAM_LIST_ID` holding `PROGRAM_NAMES`, otherwise no program list.
+    mod mock_units {
+        use std::ffi::{c_char, c_void};
+
+        use vst3::base::funknown::TResult;
+        use vst3::vst::audio_processor::{
+            BusDirection, IUnitInfo, IUnitInfo_Vtbl, MediaType, ProgramListInfo, UnitInfo,
+            NO_PROGRAM_LIST_ID, ROOT_UNIT_ID,
+        };
+        use vst3::vst::host_application::String128;
+        use vst3::VSTPtr;
+
+        pub const PROGRAM_LIST_ID: i32 = 100;
+        pub const PROGRAM_NAMES: [&str; 4] = ["Init", "Bass", "Lead", "Pad"];
+
+        #[repr(C)]
+        struct MockUnitInfo {
+            base: IUnitInfo,
+            programs: bool,
+        }
+
+        fn write_name(name: &str, target: *mut String128) {
+            let target = unsafe { &mut *target };
+            for (i, unit) in name.encode_utf16().enumerate() {
+                target[i] = unit;
+            }
+        }
+
+        unsafe extern "C" fn get_unit_count(_this: *mut IUnitInfo) -> i32 {
+            1
+        }
+
+        unsafe extern "C" fn get_unit_info(
+            this: *mut IUnitInfo,
+            unit_index: i32,
+            info: *mut UnitInfo,
+        ) -> TResult {
+            if unit_index != 0 {
+                return TResult::InvalidArgument;
+            }
+            let programs = (*(this as *mut MockUnitInfo)).programs;
+            *info = UnitInfo {
+                id: ROOT_UNIT_ID,
+                program_list_id: if programs {
+                    PROGRAM_LIST_ID
+                } else {
+                    NO_PROGRAM_LIST_ID
+                },
+                ..UnitInfo::default()
+            };
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_program_list_count(this: *mut IUnitInfo) -> i32 {
+            (*(this as *mut MockUnitInfo)).programs as i32
+        }
+
+        unsafe extern "C" fn get_program_list_info(
+            _this: *mut IUnitInfo,
+            list_index: i32,
+            info: *mut ProgramListInfo,
+        ) -> TResult {
+            if list_index != 0 {
+                return TResult::InvalidArgument;
+            }
+            *info = ProgramListInfo {
+                id: PROGRAM_LIST_ID,
+                program_count: PROGRAM_NAMES.len() as i32,
+                ..ProgramListInfo::default()
+            };
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_program_name(
+            _this: *mut IUnitInfo,
+            list_id: i32,
+            program_index: i32,
+            name: *mut String128,
+        ) -> TResult {
+            match PROGRAM_NAMES.get(program_index as usize) {
+                Some(program) if list_id == PROGRAM_LIST_ID => {
+                    write_name(program, name);
+                    TResult::ResultOk
+                }
+                _ => TResult::InvalidArgument,
+            }
+        }
+
+        unsafe extern "C" fn get_program_info(
+            _this: *mut IUnitInfo,
+            _list_id: i32,
+            _program_index: i32,
+            _attribute_id: *const c_char,
+            _attribute_value: *mut String128,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn has_program_pitch_names(
+            _this: *mut IUnitInfo,
+            _list_id: i32,
+            _program_index: i32,
+        ) -> TResult {
+            TResult::ResultFalse
+        }
+
+        unsafe extern "C" fn get_program_pitch_name(
+            _this: *mut IUnitInfo,
+            _list_id: i32,
+            _program_index: i32,
+            _midi_pitch: i16,
+            _name: *mut String128,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn get_selected_unit(_this: *mut IUnitInfo) -> i32 {
+            ROOT_UNIT_ID
+        }
+
+        unsafe extern "C" fn select_unit(_this: *mut IUnitInfo, _unit_id: i32) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_unit_by_bus(
+            _this: *mut IUnitInfo,
+            _media_type: MediaType,
+            _dir: BusDirection,
+            _bus_index: i32,
+            _channel: i32,
+            _unit_id: *mut i32,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn set_unit_program_data(
+            _this: *mut IUnitInfo,
+            _list_or_unit_id: i32,
+            _program_index: i32,
+            _data: *mut c_void,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        static UNIT_INFO_VTBL: IUnitInfo_Vtbl = IUnitInfo_Vtbl {
+            base: super::mock::UNKNOWN_VTBL,
+            get_unit_count,
+            get_unit_info,
+            get_program_list_count,
+            get_program_list_info,
+            get_program_name,
+            get_program_info,
+            has_program_pitch_names,
+            get_program_pitch_name,
+            get_selected_unit,
+            select_unit,
+            get_unit_by_bus,
+            set_unit_program_data,
+        };
+
+        pub fn unit_info(programs: bool) -> VSTPtr<IUnitInfo> {
+            let unit_info = Box::new(MockUnitInfo {
+                base: IUnitInfo {
+                    vtable: &UNIT_INFO_VTBL,
+                },
+                programs,
+            });
+            VSTPtr::new(Box::into_raw(unit_info) as *mut IUnitInfo)
+        }
+    }
+
+    /// Edit controller reporting `ACTIVE_VOICES` through read-only parameter
+    /// `STATUS_PARAMETER_ID`, titled "Voices". Every other call is a no-op.
+    mod mock_controller {
+        use std::ffi::{c_char, c_void};
+
+        use vst3::base::funknown::{
+            FUnknown, IEditController, IEditController_Vtbl, IPlugView, IPluginBase,
+            IPluginBase_Vtbl, TResult,
+        };
+        use vst3::vst::audio_processor::{ParameterFlags, ParameterInfo};
+        use vst3::vst::host_application::String128;
+        use vst3::VSTPtr;
+
+        pub const STATUS_PARAMETER_ID: u32 = 9;
+        pub const ACTIVE_VOICES: u32 = 12;
+        pub const GAIN_PARAMETER_ID: u32 = 0;
+        pub const BYPASS_PARAMETER_ID: u32 = 7;
+
+        fn write_text(text: &str, target: &mut String128) {
+            for (i, unit) in text.encode_utf16().enumerate() {
+                target[i] = unit;
+            }
+        }
+
+        unsafe extern "C" fn initialize(
+            _this: *mut IPluginBase,
+            _context: *mut FUnknown,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn terminate(_this: *mut IPluginBase) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn set_component_state(
+            _this: *mut IEditController,
+            _state: *mut c_void,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn set_state(
+            _this: *mut IEditController,
+            _state: *mut c_void,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_state(
+            _this: *mut IEditController,
+            _state: *mut c_void,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn get_parameter_count(_this: *mut IEditController) -> i32 {
+            3
+        }
+
+        unsafe extern "C" fn get_parameter_info(
+            _this: *mut IEditController,
+            param_index: i32,
+            info: *mut ParameterInfo,
+        ) -> TResult {
+            let info = &mut *info;
+            let (id, flags, title) = match param_index {
+                0 => (STATUS_PARAMETER_ID, ParameterFlags::IsReadOnly, "Voices"),
+                1 => (GAIN_PARAMETER_ID, ParameterFlags::CanAutomate, "Gain"),
+                2 => (
+                    BYPASS_PARAMETER_ID,
+                    ParameterFlags::CanAutomate | ParameterFlags::IsBypass,
+                    "Bypass",
+                ),
+                _ => return TResult::InvalidArgument,
+            };
+            *info = ParameterInfo {
+                id,
+                flags,
+                ..ParameterInfo::default()
+            };
+            write_text(title, &mut info.title);
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_param_string_by_value(
+            _this: *mut IEditController,
+            id: u32,
+            _value_normalized: f64,
+            string: *mut c_void,
+        ) -> TResult {
+            if id != STATUS_PARAMETER_ID {
+                return TResult::InvalidArgument;
+            }
+            write_text(&ACTIVE_VOICES.to_string(), &mut *(string as *mut String128));
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn get_param_value_by_string(
+            _this: *mut IEditController,
+            _id: u32,
+            _string: *mut c_void,
+            _value_normalized: *mut c_void,
+        ) -> TResult {
+            TResult::NotImplemented
+        }
+
+        unsafe extern "C" fn normalized_param_to_plain(
+            _this: *mut IEditController,
+            _id: u32,
+            value_normalized: f64,
+        ) -> f64 {
+            value_normalized
+        }
+
+        unsafe extern "C" fn plain_param_to_normalized(
+            _this: *mut IEditController,
+            _id: u32,
+            plain_value: f64,
+        ) -> f64 {
+            plain_value
+        }
+
+        unsafe extern "C" fn get_param_normalized(_this: *mut IEditController, _id: u32) -> f64 {
+            0.0
+        }
+
+        unsafe extern "C" fn set_param_normalized(
+            _this: *mut IEditController,
+            _id: u32,
+            _value: f64,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn set_component_handler(
+            _this: *mut IEditController,
+            _handler: *mut c_void,
+        ) -> TResult {
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn create_view(
+            _this: *mut IEditController,
+            _name: *const c_char,
+        ) -> *mut IPlugView {
+            std::ptr::null_mut()
+        }
+
+        static CONTROLLER_VTBL: IEditController_Vtbl = IEditController_Vtbl {
+            base: IPluginBase_Vtbl {
+                base: super::mock::UNKNOWN_VTBL,
+                initialize,
+                terminate,
+            },
+            set_component_state,
+            set_state,
+            get_state,
+            get_parameter_count,
+            get_parameter_info,
+            get_param_string_by_value,
+            get_param_value_by_string,
+            normalized_param_to_plain,
+            plain_param_to_normalized,
+            get_param_normalized,
+            set_param_normalized,
+            set_component_handler,
+            create_view,
+        };
+
+        pub fn controller() -> VSTPtr<IEditController> {
+            let controller = Box::new(IEditController {
+                vtable: &CONTROLLER_VTBL,
+            });
+            VSTPtr::new(Box::into_raw(controller))
+        }
+    }
+
+    /// Plugin factory declaring a mix of audio and other classes, without instances
+    mod mock_factory {
+        use std::cell::Cell;
+        use std::ffi::{c_char, c_void};
+
+        use vst3::ascii_to_uid;
+        use vst3::base::funknown::{
+            IPluginFactory, IPluginFactory_Vtbl, PClassInfo, PFactoryInfo, TResult,
+        };
+        use vst3::VSTPtr;
+
+        pub const VENDOR: &str = "Mock Audio";
+
+        /// UID, category and name of every class, in factory order
+        pub const CLASSES: [(&str, &str, &str); 3] = [
+            (
+                "5D1E5A3F-0000-4000-8000-000000000001",
+                "Audio Module Class",
+                "Mono",
+            ),
+            (
+                "5D1E5A3F-0000-4000-8000-000000000002",
+                "Component Controller Class",
+                "Editor",
+            ),
+            (
+                "5D1E5A3F-0000-4000-8000-000000000003",
+                "Audio Module Class",
+                "Stereo",
+            ),
+        ];
+
+        thread_local! {
+            /// Class the last `createInstance` asked for
+            pub static CREATED: Cell<Option<[c_char; 16]>> = const { Cell::new(None) };
+        }
+
+        fn write_text(text: &str, target: &mut [c_char]) {
+            for (i, byte) in text.bytes().enumerate() {
+                target[i] = byte as c_char;
+            }
+        }
+
+        unsafe extern "C" fn get_factory_info(
+            _this: *mut IPluginFactory,
+            info: *mut PFactoryInfo,
+        ) -> TResult {
+            write_text(VENDOR, &mut (*info).vendor);
+            TResult::ResultOk
+        }
+
+        unsafe extern "C" fn count_classes(_this: *mut IPluginFactory) -> i32 {
+            CLASSES.len() as i32
+        }
+
+        unsafe extern "C" fn get_class_info(
+            _this: *mut IPluginFactory,
+            index: i32,
+            info: *mut PClassInfo,
+        ) -> TResult {
+            let Some(&(uid, category, name)) = CLASSES.get(index as usize) else {
+                return TResult::InvalidArgument;
+            };
+            (*info).cid = ascii_to_uid(uid).unwrap();
+            write_text(category, &mut (*info).category);
+            write_text(name, &mut (*info).name);
+            TResult::ResultOk
+        }
+
+        // UIDs are passed by value to match the vtable signature
+        #[allow(improper_ctypes_definitions)]
+        unsafe extern "C" fn create_instance(
+            _this: *mut IPluginFactory,
+            cid: [c_char; 16],
+            _iid: [c_char; 16],
+            _obj: *mut *mut c_void,
+        ) -> TResult {
+            CREATED.with(|created| created.set(Some(cid)));
+            TResult::NoInterface
+        }
+
+        static FACTORY_VTBL: IPluginFactory_Vtbl = IPluginFactory_Vtbl {
+            base: super::mock::UNKNOWN_VTBL,
+            get_factory_info,
+            count_classes,
+            get_class_info,
+            create_instance,
+        };
+
+        pub fn factory() -> VSTPtr<IPluginFactory> {
+            let factory = Box::new(IPluginFactory {
+                vtable: &FACTORY_VTBL,
+            });
+            VSTPtr::new(Box::into_raw(factory))
+        }
+    }
+
+    /// In-process stand-in for a VST2 plugin's `AEffect`, loaded through its entry point
+    /// like a plugin library would be
+    mod mock_vst2 {
+        use std::cell::{Cell, RefCell};
+        use std::ffi::c_void;
+
+        use crate::vst::vst2::{
+            flags, host_opcode, opcode, AEffect, ERect, HostCallback, EFFECT_MAGIC,
+        };
+
+        /// Normalized values its parameters start at, parameter 0 is the gain
+        pub const INITIAL_PARAMETERS: [f32; 2] = [0.5, 0.25];
+
+        /// Size of the editor reported by `effEditGetRect`
+        pub const EDITOR_WIDTH: i32 = 400;
+        pub const EDITOR_HEIGHT: i32 = 300;
+
+        static EDITOR_RECT: ERect = ERect {
+            top: 10,
+            left: 20,
+            bottom: 10 + EDITOR_HEIGHT as i16,
+            right: 20 + EDITOR_WIDTH as i16,
+        };
+
+        thread_local! {
+            /// Dispatcher calls as `(opcode, value, opt)`, in the order they were made
+            static DISPATCHED: RefCell<Vec<(i32, isize, f32)>> = const { RefCell::new(Vec::new()) };
+            /// The host callback and effect of the last created plugin
+            static HOST: Cell<Option<(HostCallback, *mut AEffect)>> = const { Cell::new(None) };
+        }
+
+        #[repr(C)]
+        struct MockEffect {
+            effect: AEffect,
+            parameters: [f32; 2],
+        }
+
+        /// Dispatcher calls since the last take. String queries aren't recorded, logging
+        /// decides whether they're made.
+        pub fn take_dispatched() -> Vec<(i32, isize, f32)> {
+            DISPATCHED.with(|calls| calls.take())
+        }
+
+        /// Report an edit of parameter `index` to the host, like the plugin's editor would
+        pub fn automate(index: i32, value: f32) {
+            let (host, effect) = HOST.with(|host| host.get()).unwrap();
+            unsafe {
+                host(
+                    effect,
+                    host_opcode::AUTOMATE,
+                    index,
+                    0,
+                    std::ptr::null_mut(),
+                    value,
+                )
+            };
+        }
+
+        unsafe extern "C" fn dispatcher(
+            effect: *mut AEffect,
+            op: i32,
+            _index: i32,
+            value: isize,
+            ptr: *mut c_void,
+            opt: f32,
+        ) -> isize {
+            if op != opcode::GET_PARAM_NAME {
+                DISPATCHED.with(|calls| calls.borrow_mut().push((op, value, opt)));
+            }
+
+            match op {
+                opcode::EDIT_GET_RECT => {
+                    *(ptr as *mut *const ERect) = &EDITOR_RECT;
+                    1
+                }
+                opcode::CLOSE => {
+                    drop(Box::from_raw(effect as *mut MockEffect));
+                    HOST.with(|host| host.set(None));
+                    1
+                }
+                _ => 0,
+            }
+        }
+
+        unsafe extern "C" fn set_parameter(effect: *mut AEffect, index: i32, value: f32) {
+            (*(effect as *mut MockEffect)).parameters[index as usize] = value;
+        }
+
+        unsafe extern "C" fn get_parameter(effect: *mut AEffect, index: i32) -> f32 {
+            (*(effect as *mut MockEffect)).parameters[index as usize]
+        }
+
+        /// Writes its input scaled by parameter 0 to its output
+        unsafe extern "C" fn process_replacing(
+            effect: *mut AEffect,
+            inputs: *mut *mut f32,
+            outputs: *mut *mut f32,
+            frames: i32,
+        ) {
+            let gain = (*(effect as *mut MockEffect)).parameters[0];
+            for channel in 0..(*effect).num_outputs as usize {
+                let src = *inputs.add(channel);
+                let dst = *outputs.add(channel);
+                for i in 0..frames as usize {
+                    *dst.add(i) = *src.add(i) * gain;
+                }
+            }
+        }
+
+        pub unsafe extern "C" fn entry(host: HostCallback) -> *mut AEffect {
+            let effect = Box::into_raw(Box::new(MockEffect {
+                effect: AEffect {
+                    magic: EFFECT_MAGIC,
+                    dispatcher,
+                    process: None,
+                    set_parameter,
+                    get_parameter,
+                    num_programs: 0,
+                    num_params: INITIAL_PARAMETERS.len() as i32,
+                    num_inputs: 2,
+                    num_outputs: 2,
+                    flags: flags::HAS_EDITOR | flags::CAN_REPLACING,
+                    reserved1: 0,
+                    reserved2: 0,
+                    initial_delay: 0,
+                    real_qualities: 0,
+                    off_qualities: 0,
+                    io_ratio: 1.0,
+                    object: std::ptr::null_mut(),
+                    user: std::ptr::null_mut(),
+                    unique_id: i32::from_be_bytes(*b"Mock"),
+                    version: 1,
+                    process_replacing: Some(process_replacing),
+                    process_double_replacing: None,
+                    future: [0; 56],
+                },
+                parameters: INITIAL_PARAMETERS,
+            })) as *mut AEffect;
+            HOST.with(|current| current.set(Some((host, effect))));
+            effect
+        }
+    }
+
+    fn mock_plugin() -> VSTHostContext {
+        let mut plugin = VSTHostContext::default();
+        plugin.id = PluginId::new();
+        plugin.name = "Mock".to_string();
+        plugin
+    }
+
+    /// A mock plugin with a working processor that applies `gain`
+    fn mock_gain_plugin(gain: f32, fail_activation: bool) -> VSTHostContext {
+        let mut plugin = mock_plugin();
+        plugin.component = Some(mock::component(fail_activation));
+        plugin.processor = Some(mock::processor(gain));
+        plugin
+    }
+
+    #[test]
+    fn test_removed_plugin_stops_processing_before_terminating() {
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+
+        mock::SHUTDOWN_CALLS.with(|calls| calls.borrow_mut().clear());
+        engine.remove_plugin(id).unwrap();
+
+        let calls = mock::SHUTDOWN_CALLS.with(|calls| calls.take());
+        assert_eq!(
+            calls,
+            vec!["setProcessing(false)", "setActive(false)", "terminate"]
+        );
+    }
+
+    #[test]
+    fn test_current_program_name_from_unit_info() {
+        let mut plugin = mock_plugin();
+        assert_eq!(plugin.current_program_name(), None);
+
+        // Program 2 of 4 is selected by any value in [0.5, 0.75)
+        plugin.program_parameter = Some((7, 0));
+        plugin.parameters.set(7, 0.6);
+        plugin.unit_info = Some(mock_units::unit_info(true));
+        assert_eq!(
+            plugin.current_program_name().as_deref(),
+            Some(mock_units::PROGRAM_NAMES[2])
+        );
+
+        plugin.parameters.set(7, 1.0);
+        assert_eq!(plugin.current_program_name().as_deref(), Some("Pad"));
+
+        // A unit without a program list has no program name
+        plugin.unit_info = Some(mock_units::unit_info(false));
+        assert_eq!(plugin.current_program_name(), None);
+    }
+
+    #[test]
+    fn test_status_string_from_status_parameter() {
+        let mut plugin = mock_plugin();
+        assert_eq!(plugin.status_string(), None);
+
+        let editor = mock_controller::controller();
+        let mut info = ParameterInfo::default();
+        unsafe { editor.get_parameter_info(0, &mut info) };
+        assert!(vst::host::is_status_parameter(&info));
+        info.flags = 0;
+        assert!(!vst::host::is_status_parameter(&info));
+
+        plugin.editor = Some(editor);
+        assert_eq!(plugin.status_string(), None);
+
+        plugin.status_parameter = Some((mock_controller::STATUS_PARAMETER_ID, "Voices".into()));
+        assert_eq!(
+            plugin.status_string(),
+            Some(format!("Voices: {}", mock_controller::ACTIVE_VOICES))
+        );
+    }
+
+    /// Parameters of `mock_controller` as the host reads them when loading a plugin
+    fn mock_parameter_list() -> Vec<PluginParameter> {
+        let editor = mock_controller::controller();
+        (0..unsafe { editor.get_parameter_count() })
+            .map(|i| {
+                let mut info = ParameterInfo::default();
+                unsafe { editor.get_parameter_info(i, &mut info) };
+                PluginParameter::from(&info)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_non_automatable_parameter_rejects_automation() {
+        let mut plugin = mock_plugin();
+        plugin.parameter_list = mock_parameter_list();
+
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(plugin).unwrap();
+        let parameters = engine.plugin_parameters(id).unwrap();
+        assert_eq!(parameters.len(), 3);
+        assert!(!parameters[0].automatable);
+        assert!(parameters[1].automatable);
+        assert_eq!(parameters[1].title, "Gain");
+
+        let voices = mock_controller::STATUS_PARAMETER_ID;
+        let gain = mock_controller::GAIN_PARAMETER_ID;
+        let err = engine.automate_parameter(id, voices, 0, 0.5).unwrap_err();
+        assert!(err.to_string().contains("Voices"));
+        assert!(engine.automate_parameter(id, 42, 0, 0.5).is_err());
+        engine.automate_parameter(id, gain, 16, 0.25).unwrap();
+
+        let events = engine.plugin_events(id).unwrap();
+        let block = unsafe { events.next_block() };
+        assert_eq!(block.parameters.queues().len(), 1);
+        assert_eq!(block.parameters.queues()[0].points(), &[(16, 0.25)]);
+    }
+
+    #[test]
+    fn test_bypass_parameter_drives_bypass() {
+        let mut engine = AudioEngine::default();
+        let mut plugin = mock_gain_plugin(0.5, false);
+        plugin.parameter_list = mock_parameter_list();
+        assert_eq!(
+            plugin.bypass_param_id(),
+            Some(mock_controller::BYPASS_PARAMETER_ID)
+        );
+        let soft = engine.insert_plugin(plugin).unwrap();
+        let hard = engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        assert_eq!(engine.plugin_modules()[&hard].bypass_param_id(), None);
+
+        engine.set_bypassed(soft, true).unwrap();
+        engine.set_bypassed(hard, true).unwrap();
+        {
+            let plugins = engine.plugin_modules();
+            let bypass = mock_controller::BYPASS_PARAMETER_ID;
+            assert_eq!(plugins[&soft].parameters.value(bypass), Some(1.0));
+            assert!(!plugins[&soft].host_bypassed());
+            assert!(plugins[&hard].host_bypassed());
+        }
+        assert_eq!(engine.is_bypassed(soft), Some(true));
+
+        // The mock ignores its bypass parameter, so only the host-bypassed plugin is
+        // skipped
+        let output = engine.process_block(&[&[1.0; 16], &[1.0; 16]]).unwrap();
+        assert_eq!(output, vec![vec![0.5; 16], vec![0.5; 16]]);
+
+        engine.set_bypassed(soft, false).unwrap();
+        let value = engine.plugin_modules()[&soft]
+            .parameters
+            .value(mock_controller::BYPASS_PARAMETER_ID);
+        assert_eq!(value, Some(0.0));
+    }
+
+    #[test]
+    fn test_failed_setup_is_skipped_in_chain() {
+        let mut engine = AudioEngine::default();
+        let good = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        let bad = engine.insert_plugin(mock_gain_plugin(1.0, true)).unwrap();
+
+        engine.set_sample_rate(44100).unwrap();
+        assert_eq!(engine.take_setup_failures(), vec![bad]);
+        assert!(engine.take_setup_failures().is_empty());
+
+        let plugins = engine.plugin_modules();
+        assert!(plugins[&bad].setup_failed);
+        assert!(!plugins[&good].setup_failed);
+
+        let order = engine.plugin_order();
+        let chain: Vec<PluginId> = processing_chain(&plugins, &order)
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(chain, vec![good]);
+    }
+
+    #[test]
+    fn test_process_block_through_empty_and_gain_chains() {
+        let mut engine = AudioEngine::default();
+        let left: Vec<f32> = (0..128).map(|i| i as f32 / 128.0).collect();
+        let right: Vec<f32> = left.iter().map(|s| -s).collect();
+
+        let output = engine.process_block(&[&left, &right]).unwrap();
+        assert_eq!(output, vec![left.clone(), right.clone()]);
+
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        let output = engine.process_block(&[&left, &right]).unwrap();
+        let expected: Vec<Vec<f32>> = [&left, &right]
+            .iter()
+            .map(|channel| channel.iter().map(|s| s * 0.5).collect())
+            .collect();
+        assert_eq!(output, expected);
+
+        assert!(engine.process_block(&[]).is_err());
+        assert!(engine.process_block(&[&left, &right[..64]]).is_err());
+    }
+
+    #[test]
+    fn test_process_block_beyond_chain_limits() {
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+
+        // Split into chain sized parts, channels past the first two pass through
+        let frames = MAX_BLOCK_SIZE * 2 + 5;
+        let channels: Vec<Vec<f32>> = (1..=4)
+            .map(|j| {
+                (0..frames)
+                    .map(|i| j as f32 * 0.1 + i as f32 * 1e-5)
+                    .collect()
+            })
+            .collect();
+        let input: Vec<&[f32]> = channels.iter().map(|channel| channel.as_slice()).collect();
+
+        let output = engine.process_block(&input).unwrap();
+        assert_eq!(output.len(), 4);
+        for (j, (out, inp)) in output.iter().zip(&channels).enumerate() {
+            let gain = if j < 2 { 0.5 } else { 1.0 };
+            assert_eq!(out.len(), frames);
+            assert!(out
+                .iter()
+                .zip(inp)
+                .all(|(o, i)| (o - i * gain).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_render_does_not_leak_shared_buffers() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(64).unwrap();
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+
+        let mut renderer = engine.chain_renderer();
+        let process_data = Arc::strong_count(&engine.process_data);
+        let references = Arc::strong_count(&engine.output_data.references);
+
+        for _ in 0..100 {
+            unsafe { renderer.render(64, 2) };
+            let _ = renderer.output_data.as_ref();
+            let _ = renderer.input_data.as_mut_ref();
+        }
+
+        assert_eq!(Arc::strong_count(&engine.process_data), process_data);
+        assert_eq!(
+            Arc::strong_count(&engine.output_data.references),
+            references
+        );
+    }
+
+    #[test]
+    fn test_disabled_processing_renders_silence() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(64).unwrap();
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+
+        // The renderer outlives the toggles, just like the running input callback
+        let mut renderer = engine.chain_renderer();
+        for i in 0..64 {
+            for j in 0..2 {
+                renderer.input_data.write(j, i, 1.0);
+            }
+        }
+
+        unsafe { renderer.render(64, 2) };
+        assert!(renderer.output_data.as_ref()[0][..64]
+            .iter()
+            .all(|&s| s == 0.5));
+
+        engine.set_processing_enabled(false);
+        assert!(!engine.is_processing_enabled());
+        unsafe { renderer.render(64, 2) };
+        assert!(renderer
+            .output_data
+            .as_ref()
+            .iter()
+            .all(|channel| channel[..64].iter().all(|&s| s == 0.0)));
+
+        engine.set_processing_enabled(true);
+        unsafe { renderer.render(64, 2) };
+        assert!(renderer.output_data.as_ref()[1][..64]
+            .iter()
+            .all(|&s| s == 0.5));
+    }
+
+    #[test]
+    fn test_global_ab_crossfades_to_dry_input() {
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        let mut renderer = engine.chain_renderer();
+        let render = |renderer: &mut ChainRenderer| {
+            for i in 0..64 {
+                for j in 0..2 {
+                    renderer.input_data.write(j, i, 0.25);
+                }
+            }
+            unsafe { renderer.render(64, 2) };
+            renderer.output_data.as_ref()[0][..64].to_vec()
+        };
+
+        assert!(render(&mut renderer).iter().all(|&s| s == 0.5));
+
+        // Fades from the processed 0.5 to the dry 0.25 without a jump
+        assert!(engine.toggle_global_ab());
+        assert!(engine.global_ab());
+        let block = render(&mut renderer);
+        assert!((block[0] - 0.5).abs() < 0.01);
+        assert!(block.windows(2).all(|w| w[1] < w[0] && w[0] - w[1] < 0.01));
+
+        // The crossfade carries on while the delay is locked for resizing
+        let delay = engine.monitor_delay.lock().unwrap();
+        let locked = render(&mut renderer);
+        drop(delay);
+        assert!(locked[0] < block[63]);
+        assert!(locked.windows(2).all(|w| w[1] < w[0]));
+        for _ in 0..100 {
+            render(&mut renderer);
+        }
+        assert!(render(&mut renderer)
+            .iter()
+            .all(|&s| (s - 0.25).abs() < 1e-3));
+
+        // And back
+        assert!(!engine.toggle_global_ab());
+        let block = render(&mut renderer);
+        assert!((block[0] - 0.25).abs() < 0.01);
+        assert!(block.windows(2).all(|w| w[1] > w[0] && w[1] - w[0] < 0.01));
+        for _ in 0..100 {
+            render(&mut renderer);
+        }
+        assert!(render(&mut renderer)
+            .iter()
+            .all(|&s| (s - 0.5).abs() < 1e-3));
+
+        // Loudness matched, the dry input comes in at the processed level
+        engine.set_global_ab_loudness_match(true);
+        assert!(engine.global_ab_loudness_match());
+        render(&mut renderer);
+        assert!(engine.toggle_global_ab());
+        for _ in 0..20 {
+            assert!(render(&mut renderer)
+                .iter()
+                .all(|&s| (s - 0.5).abs() < 1e-3));
+        }
+    }
+
+    #[cfg(feature = "debug-introspection")]
+    #[test]
+    fn test_debug_snapshot_reflects_last_block() {
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+
+        let snapshot = engine.debug_snapshot();
+        assert_eq!(snapshot.block_size, 0);
+        assert_eq!(snapshot.chain_length, 2);
+
+        // One block as the input callback runs it
+        let mut renderer = engine.chain_renderer();
+        for i in 0..48 {
+            let sample = i as f32 / 100.0 - 0.2;
+            renderer.input_data.write(0, i, sample);
+            renderer.input_data.write(1, i, -sample);
+        }
+        renderer
+            .probe
+            .record_input(renderer.input_data.as_ref(), 48, 2);
+        unsafe { renderer.render(48, 2) };
+        renderer
+            .probe
+            .record_output(renderer.output_data.as_ref(), 48, 2);
+        renderer.probe.record_resampler(48, 52);
+        renderer.probe.record_ring_fill(104);
+
+        let snapshot = engine.debug_snapshot();
+        assert_eq!(snapshot.block_size, 48);
+        assert_eq!(snapshot.resampler_input_frames, 48);
+        assert_eq!(snapshot.resampler_output_frames, 52);
+        assert_eq!(snapshot.ring_fill, 104);
+        assert_eq!(snapshot.chain_length, 2);
+        assert!((snapshot.input_range.min + 0.27).abs() < 1e-6);
+        assert!((snapshot.input_range.max - 0.27).abs() < 1e-6);
+        assert!((snapshot.output_range.min + 0.27).abs() < 1e-6);
+        assert!((snapshot.output_range.max - 0.27).abs() < 1e-6);
+
+        // Restarting the streams forgets the last block
+        engine.probe.reset();
+        assert_eq!(engine.debug_snapshot().block_size, 0);
+    }
+
+    #[test]
+    fn test_parallel_processing_matches_serial() {
+        let build = || {
+            let mut engine = AudioEngine::default();
+            engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+            for (index, gains) in [[2.0, 3.0], [0.25, 1.5], [-1.0, 0.75]].iter().enumerate() {
+                let chain = engine.add_chain().unwrap();
+                engine.set_chain_gain(chain, 0.3 + index as f32).unwrap();
+                for &gain in gains {
+                    let id = engine.insert_plugin(mock_gain_plugin(gain, false)).unwrap();
+                    engine.set_plugin_chain(id, chain).unwrap();
+                    engine.set_plugin_mix(id, 0.6).unwrap();
+                }
+            }
+            engine
+        };
+
+        let mut serial = build();
+        let mut parallel = build();
+        parallel.set_parallel_processing(true).unwrap();
+        assert!(parallel.parallel_processing());
+        assert!(!serial.parallel_processing());
+
+        let left = (0..64).map(|i| (i as f32 * 0.37).sin()).collect::<Vec<_>>();
+        let right = (0..64).map(|i| (i as f32 * 0.11).cos()).collect::<Vec<_>>();
+        for _ in 0..4 {
+            let expected = serial.process_block(&[&left, &right]).unwrap();
+            let output = parallel.process_block(&[&left, &right]).unwrap();
+            assert_eq!(output, expected);
+        }
+
+        // Every chain after the main one ran on a worker each block, up to one per worker
+        let rendered = |engine: &AudioEngine| {
+            let workers = engine.chain_workers.lock().unwrap();
+            let slots = workers.as_ref().map_or(&[][..], |workers| &workers.slots);
+            slots
+                .iter()
+                .map(|slot| slot.rendered.load(Ordering::Relaxed))
+                .sum::<u64>()
+        };
+        let workers = parallel
+            .chain_workers
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .len();
+        assert_eq!(rendered(&parallel), 4 * workers.min(3) as u64);
+
+        parallel.set_parallel_processing(false).unwrap();
+        assert!(!parallel.parallel_processing());
+        assert_eq!(
+            parallel.process_block(&[&left, &right]).unwrap(),
+            serial.process_block(&[&left, &right]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parallel_chains_are_summed_with_gain() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(64).unwrap();
+
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        let parallel = engine.add_chain().unwrap();
+        let first = engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        let second = engine.insert_plugin(mock_gain_plugin(3.0, false)).unwrap();
+        engine.set_plugin_chain(first, parallel).unwrap();
+        engine.set_plugin_chain(second, parallel).unwrap();
+        engine.set_chain_gain(parallel, 0.25).unwrap();
+
+        let mut renderer = engine.chain_renderer();
+        let fill_input = |renderer: &mut ChainRenderer| {
+            for i in 0..64 {
+                for j in 0..2 {
+                    renderer.input_data.write(j, i, 1.0);
+                }
+            }
+        };
+
+        // Main: 1.0 * 0.5, parallel: 1.0 * 2.0 * 3.0 * 0.25
+        fill_input(&mut renderer);
+        unsafe { renderer.render(64, 2) };
+        for channel in renderer.output_data.as_ref().iter() {
+            assert!(channel[..64].iter().all(|&s| (s - 2.0).abs() < 1e-6));
+        }
+
+        // Removing the chain moves its plugins into the main chain, in series
+        engine.remove_chain(parallel).unwrap();
+        assert!(engine.remove_chain(ChainId::MAIN).is_err());
+        assert_eq!(engine.chains(), vec![(ChainId::MAIN, 1.0)]);
+        fill_input(&mut renderer);
+        unsafe { renderer.render(64, 2) };
+        assert!(renderer.output_data.as_ref()[0][..64]
+            .iter()
+            .all(|&s| (s - 3.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_monitor_tap_copies_plugin_output() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(64).unwrap();
+        let first = engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        engine.insert_plugin(mock_gain_plugin(3.0, false)).unwrap();
+
+        assert!(engine.set_monitor_tap(Some(PluginId(0))).is_err());
+        engine.set_monitor_tap(Some(first)).unwrap();
+        assert_eq!(engine.monitor_tap(), Some(first));
+
+        let mut renderer = engine.chain_renderer();
+        let fill_input = |renderer: &mut ChainRenderer| {
+            for i in 0..64 {
+                for j in 0..2 {
+                    renderer.input_data.write(j, i, 1.0);
+                }
+            }
+        };
+
+        // The tap holds the first plugin's output, the main path still runs the full chain
+        fill_input(&mut renderer);
+        unsafe { renderer.render(64, 2) };
+        for (tap, output) in renderer
+            .tap_data
+            .as_ref()
+            .iter()
+            .zip(renderer.output_data.as_ref().iter())
+        {
+            assert!(tap[..64].iter().all(|&s| s == 0.5));
+            assert!(output[..64].iter().all(|&s| s == 1.5));
+        }
+
+        let ring = HeapRb::<f32>::new(256);
+        let (mut producer, mut consumer) = ring.split();
+        renderer.push_tap(&mut producer, 64, 2);
+        assert_eq!(consumer.try_pop(), Some(0.5));
+        assert_eq!(consumer.try_pop(), Some(0.5));
+
+        // Removing the tapped plugin clears the tap
+        engine.remove_plugin(first).unwrap();
+        assert_eq!(engine.monitor_tap(), None);
+        fill_input(&mut renderer);
+        unsafe { renderer.render(64, 2) };
+        assert!(renderer.tap_data.as_ref()[0][..64]
+            .iter()
+            .all(|&s| s == 0.0));
+        assert!(renderer.output_data.as_ref()[0][..64]
+            .iter()
+            .all(|&s| s == 3.0));
+    }
+
+    #[test]
+    fn test_adaptive_resampling_nudges_effective_ratio() {
+        let mut engine = AudioEngine::default();
+        let drift = engine.drift_corrector(1.0, 1000);
+        let mut resampler =
+            SincFixedIn::<f32>::new(1.0, 2.0, ResamplerQuality::Fast.sinc_parameters(), 64, 2)
+                .unwrap();
+        let ratio = engine.effective_resample_ratio.clone();
+        let effective = move || f64::from_bits(ratio.load(Ordering::Relaxed));
+
+        // Disabled, the ratio stays nominal whatever the fill
+        drift.update(&mut resampler, 0);
+        assert_eq!(effective(), 1.0);
+
+        engine.set_adaptive_resampling(true);
+        assert!(engine.adaptive_resampling());
+
+        // Half the target fill speeds up by half the maximum nudge
+        drift.update(&mut resampler, 500);
+        assert!((effective() - (1.0 + MAX_RESAMPLE_NUDGE / 2.0)).abs() < 1e-12);
+
+        // Overfull slows down, clamped to the maximum nudge
+        drift.update(&mut resampler, 5000);
+        assert!((effective() - (1.0 - MAX_RESAMPLE_NUDGE)).abs() < 1e-12);
+
+        drift.update(&mut resampler, 1000);
+        assert_eq!(effective(), 1.0);
+    }
+
+    #[test]
+    fn test_insert_ramp_fades_in_then_holds() {
+        let ramp = InsertRamp::new(4);
+        assert_eq!(ramp.next_block(), Some((0.0, 0.25)));
+        assert_eq!(ramp.next_block(), Some((0.25, 0.5)));
+        assert_eq!(ramp.next_block(), Some((0.5, 0.75)));
+        assert_eq!(ramp.next_block(), Some((0.75, 1.0)));
+        assert_eq!(ramp.next_block(), None);
+        assert_eq!(ramp.next_block(), None);
+        assert_eq!(InsertRamp::gain(10, 4), 1.0);
+        assert_eq!(InsertRamp::default().next_block(), None);
+
+        // The renderer scales the fresh plugin's output along the ramp
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(64).unwrap();
+        let id = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        engine
+            .plugin_modules
+            .write()
+            .unwrap()
+            .get_mut(&id)
+            .unwrap()
+            .insert_ramp = InsertRamp::new(2);
+
+        let mut renderer = engine.chain_renderer();
+        let mut render = || {
+            for i in 0..64 {
+                for j in 0..2 {
+                    renderer.input_data.write(j, i, 1.0);
+                }
+            }
+            unsafe { renderer.render(64, 2) };
+            let output = renderer.output_data.as_ref();
+            (output[0][0], output[1][32], output[0][63])
+        };
+
+        let (first, middle, _) = render();
+        assert_eq!(first, 0.0);
+        assert!((middle - 0.25).abs() < 1e-6);
+        let (first, _, last) = render();
+        assert!((first - 0.5).abs() < 1e-6);
+        assert!(last < 1.0);
+        assert_eq!(render(), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_plugin_cpu_is_share_of_block_budget() {
+        let mut engine = AudioEngine::default();
+        engine.set_sample_rate(48000).unwrap();
+        engine.set_buffer_size(480).unwrap();
+        let id = engine.insert_plugin(mock_plugin()).unwrap();
+        assert_eq!(engine.plugin_cpu(id), 0.0);
+
+        // 480 samples at 48 kHz is a 10 ms budget
+        {
+            let plugins = engine.plugin_modules();
+            let timer = &plugins[&id].process_time;
+            timer.record(Duration::from_millis(2));
+            assert_eq!(timer.average_micros(), 2000.0);
+
+            timer.record(Duration::from_millis(4));
+            assert!((timer.average_micros() - 2100.0).abs() < 1e-3);
+        }
+        assert!((engine.plugin_cpu(id) - 21.0).abs() < 1e-3);
+        assert_eq!(engine.plugin_cpu(PluginId(0)), 0.0);
+    }
+
+    #[test]
+    fn test_diagnostics_report_engine_state() {
+        let mut engine = AudioEngine::default();
+        engine.set_sample_rate(48000).unwrap();
+        engine.set_buffer_size(480).unwrap();
+        engine.set_resampler_quality(ResamplerQuality::Fast);
+
+        let first = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        let second = engine.insert_plugin(mock_plugin()).unwrap();
+        engine.set_bypassed(second, true).unwrap();
+        {
+            let mut plugins = engine.plugin_modules_mut();
+            let plugin = plugins.get_mut(&first).unwrap();
+            plugin.name = "Mock Gain".to_string();
+            plugin.path = "/plugins/mock.vst3".to_string();
+            plugin.vendor = "Sona".to_string();
+            plugin.uid = "0123456789ABCDEF".to_string();
+            plugin.process_time.record(Duration::from_millis(2));
+        }
+
+        engine.refresh_latencies();
+
+        let diagnostics = engine.diagnostics();
+        assert_eq!(diagnostics.os, std::env::consts::OS);
+        assert_eq!(diagnostics.sample_rate, 48000);
+        assert_eq!(diagnostics.buffer_size, 480);
+        assert_eq!(diagnostics.resampler.quality, "fast");
+        assert_eq!(diagnostics.total_latency, 0);
+        assert_eq!(diagnostics.underrun_count, 0);
+        assert_eq!(diagnostics.overflow_count, 0);
+
+        // Plugins are listed in processing order, 2 ms of a 10 ms block is 20%
+        let ids = diagnostics.plugins.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![first.0, second.0]);
+        assert_eq!(diagnostics.plugins[0].latency, Some(0));
+        assert_eq!(diagnostics.plugins[1].latency, None);
+        assert!(diagnostics.plugins[1].bypassed);
+        assert!((diagnostics.dsp_load - 20.0).abs() < 1e-3);
+
+        let json = serde_json::to_value(&diagnostics).unwrap();
+        assert_eq!(json["buffer_size"], 480);
+        assert_eq!(
+            json["resampler"]["adaptive"],
+            diagnostics.resampler.adaptive
+        );
+        assert_eq!(json["plugins"][0]["name"], "Mock Gain");
+        assert_eq!(json["plugins"][0]["path"], "/plugins/mock.vst3");
+        assert_eq!(json["plugins"][0]["uid"], "0123456789ABCDEF");
+        assert_eq!(json["plugins"][1]["bypassed"], true);
+    }
+
+    /// ProcessData over the given buses, without parameter changes, events or context
+    fn bus_process_data(
+        inputs: &mut [AudioBusBuffers],
+        outputs: &mut [AudioBusBuffers],
+        frames: usize,
+    ) -> ProcessData {
+        ProcessData {
+            process_mode: ProcessMode::Realtime,
+            symbolic_sample_size: SymbolicSampleSize::Sample32,
+            num_samples: frames as i32,
+            num_inputs: inputs.len() as i32,
+            num_outputs: outputs.len() as i32,
+            inputs: inputs.as_mut_ptr(),
+            outputs: outputs.as_mut_ptr(),
+            input_parameter_changes: std::ptr::null_mut(),
+            output_parameter_changes: std::ptr::null_mut(),
+            input_events: std::ptr::null_mut(),
+            output_events: std::ptr::null_mut(),
+            process_context: std::ptr::null_mut(),
+        }
+    }
+
+    #[test]
+    fn test_plugin_resampler_at_half_rate_keeps_block_length() {
+        let mut resampler =
+            PluginResampler::new(48000, 24000, ResamplerQuality::Fast, &[2], &[2]).unwrap();
+        let latency = resampler.latency() as usize;
+        assert!(resampler.max_plugin_frames() >= PLUGIN_RESAMPLE_CHUNK / 2);
+
+        let mut input = DynChannelBuffer::new(0.5f32, 2, MAX_BLOCK_SIZE);
+        let mut output = DynChannelBuffer::new(1.0f32, 2, MAX_BLOCK_SIZE);
+        let mut in_bus = [bus_buffers(&mut input)];
+        let mut out_bus = [bus_buffers(&mut output)];
+        let mut engine_frames = 0;
+        let mut plugin_frames = 0;
+        let mut rendered = Vec::new();
+
+        // Odd block sizes don't line up with the resampling chunk
+        for frames in [480, 100, 37, 1024, 480, 255, 480, 480] {
+            let main = bus_process_data(&mut in_bus, &mut out_bus, frames);
+            unsafe {
+                resampler.process(&main, frames, |data| {
+                    let frames = data.num_samples as usize;
+                    plugin_frames += frames;
+                    let input = channel_pointers(std::slice::from_raw_parts(data.inputs, 1));
+                    let output = channel_pointers(std::slice::from_raw_parts(data.outputs, 1));
+                    for (src, dst) in input.zip(output) {
+                        std::ptr::copy_nonoverlapping(src, dst, frames);
+                    }
+                });
+            }
+            engine_frames += frames;
+            rendered.extend_from_slice(&output.as_ref()[0][..frames]);
+        }
+
+        // Every block comes back as long as it went in, the plugin sees half the frames
+        assert_eq!(rendered.len(), engine_frames);
+        let expected = engine_frames / 2;
+        assert!(plugin_frames.abs_diff(expected) <= PLUGIN_RESAMPLE_CHUNK);
+
+        // Past the latency the signal comes through without gaps
+        assert!(rendered[..latency / 2].iter().all(|&s| s == 0.0));
+        let settled = &rendered[latency + PLUGIN_RESAMPLE_CHUNK..];
+        assert!(settled.iter().all(|&s| (s - 0.5).abs() < 0.01));
+    }
+
+    #[test]
+    fn test_plugin_resampler_keeps_buses_and_parameter_offsets() {
+        let mut resampler =
+            PluginResampler::new(48000, 24000, ResamplerQuality::Fast, &[2, 1], &[2, 2]).unwrap();
+        assert!(resampler.fits(&[2, 1], &[2, 2]));
+        assert!(!resampler.fits(&[2], &[2]));
+        let latency = resampler.latency() as usize;
+
+        let frames = 256;
+        let mut input = DynChannelBuffer::new(0.5f32, 2, frames);
+        let mut sidechain = DynChannelBuffer::new(0.25f32, 1, frames);
+        let mut output = DynChannelBuffer::new(0.0f32, 2, frames);
+        let mut scratch = DynChannelBuffer::new(0.0f32, 2, frames);
+        let mut in_buses = [bus_buffers(&mut input), bus_buffers(&mut sidechain)];
+        in_buses[1].silence_flags = 1;
+        let mut out_buses = [bus_buffers(&mut output), bus_buffers(&mut scratch)];
+        let mut changes = HostParameterChanges::new();
+
+        // The plugin gets the main input on its first output bus and the sidechain on its
+        // second
+        let mut rendered = Vec::new();
+        let mut extra = Vec::new();
+        let mut offsets = Vec::new();
+        for _ in 0..8 {
+            changes.clear();
+            changes.add_change(0, 0, 0.5);
+            changes.add_change(0, 1, 0.75);
+            changes.add_change(0, frames as i32 - 1, 1.0);
+            let mut main = bus_process_data(&mut in_buses, &mut out_buses, frames);
+            main.input_parameter_changes = &mut changes as *mut HostParameterChanges as *mut _;
+            unsafe {
+                resampler.process(&main, frames, |data| {
+                    let inputs = std::slice::from_raw_parts(data.inputs, 2);
+                    let outputs = std::slice::from_raw_parts(data.outputs, 2);
+                    assert_eq!((data.num_inputs, data.num_outputs), (2, 2));
+                    assert_eq!(inputs[1].num_channels, 1);
+                    assert_eq!(inputs[1].silence_flags, 1);
+
+                    let frames = data.num_samples as usize;
+                    let sidechain = *inputs[1].channel_buffers_32;
+                    for (j, bus) in outputs.iter().enumerate() {
+                        for i in 0..2 {
+                            let src = if j == 0 {
+                                *inputs[0].channel_buffers_32.add(i)
+                            } else {
+                                sidechain
+                            };
+                            let dst = *bus.channel_buffers_32.add(i);
+                            std::ptr::copy_nonoverlapping(src, dst, frames);
+                        }
+                    }
+
+                    let changes = &mut *(data.input_parameter_changes as *mut HostParameterChanges);
+                    changes.scale_offsets(0.5, data.num_samples);
+                    for queue in changes.queues() {
+                        offsets.push((queue.points().to_vec(), frames as i32));
+                    }
+                    changes.clear();
+                });
+            }
+            rendered.extend_from_slice(&output.as_ref()[1]);
+            extra.extend_from_slice(&scratch.as_ref()[0]);
+        }
+
+        let settled = latency + PLUGIN_RESAMPLE_CHUNK;
+        assert!(rendered[settled..].iter().all(|&s| (s - 0.5).abs() < 0.01));
+        assert!(extra[settled..].iter().all(|&s| (s - 0.25).abs() < 0.01));
+
+        // Offsets are in plugin frames, ones that meet keep the later value
+        for (points, frames) in offsets {
+            assert_eq!(points, vec![(0, 0.75), (frames - 1, 1.0)]);
+        }
+
+        // The engine's resampler follows the plugin's bus layout
+        let bus = |name: &str, channel_count| BusDescription {
+            name: name.to_string(),
+            channel_count,
+            aux: false,
+        };
+        let mut engine = AudioEngine::default();
+        // Without a component every output bus can be monitored
+        let mut plugin = mock_plugin();
+        plugin.processor = Some(mock::processor(1.0));
+        plugin.input_buses = vec![bus("Input", 2), bus("Sidechain", 1)];
+        plugin.output_buses = vec![bus("Output", 2), bus("Aux", 1)];
+        let id = engine.insert_plugin(plugin).unwrap();
+        engine.set_plugin_sample_rate(id, Some(24000)).unwrap();
+        let fits = |engine: &AudioEngine, inputs: &[usize], outputs: &[usize]| {
+            engine.plugin_resamplers.lock().unwrap()[&id].fits(inputs, outputs)
+        };
+        assert!(fits(&engine, &[2, 1], &[2, 1]));
+
+        engine
+            .set_sidechain_source(id, Some(SidechainSource::MainInput))
+            .unwrap();
+        engine.set_monitor_bus(id, 1).unwrap();
+        assert!(fits(&engine, &[2, 1], &[2, 2]));
+        engine.set_monitor_bus(id, 0).unwrap();
+        assert!(fits(&engine, &[2, 1], &[2, 1]));
+    }
+
+    #[test]
+    fn test_plugin_sample_rate_override() {
+        let mut engine = AudioEngine::default();
+        engine.set_sample_rate(48000).unwrap();
+        engine.set_buffer_size(256).unwrap();
+        let id = engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        assert_eq!(engine.plugin_sample_rate(id), 48000);
+        assert_eq!(engine.chain_latency(), 0);
+
+        assert!(engine.set_plugin_sample_rate(id, Some(1000)).is_err());
+        assert!(engine
+            .set_plugin_sample_rate(PluginId(0), Some(24000))
+            .is_err());
+
+        // The resampling latency is compensated like the plugin's own
+        engine.set_plugin_sample_rate(id, Some(24000)).unwrap();
+        assert_eq!(engine.plugin_sample_rate(id), 24000);
+        let latency = engine.chain_latency() as usize;
+        assert!(latency > 0);
+
+        let input = vec![0.25f32; 256];
+        let mut rendered = Vec::new();
+        for _ in 0..8 {
+            let output = engine.process_block(&[&input, &input]).unwrap();
+            assert_eq!(output[0].len(), 256);
+            rendered.extend_from_slice(&output[0]);
+        }
+        let settled = &rendered[latency + PLUGIN_RESAMPLE_CHUNK..];
+        assert!(settled.iter().all(|&s| (s - 0.5).abs() < 0.01));
+
+        // Matching the engine rate drops the resampler again
+        engine.set_plugin_sample_rate(id, Some(48000)).unwrap();
+        assert_eq!(engine.chain_latency(), 0);
+        assert!(engine.plugin_resamplers.lock().unwrap().is_empty());
+
+        engine.set_plugin_sample_rate(id, Some(96000)).unwrap();
+        engine.remove_plugin(id).unwrap();
+        assert!(engine.plugin_resamplers.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_render_offline_processes_whole_input() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(64).unwrap();
+        engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+
+        // 150 stereo frames, not a multiple of the block size
+        let input = (0..300).map(|i| i as f32 / 300.0).collect::<Vec<_>>();
+        let output = engine.render_offline(&input, 2).unwrap();
+        assert_eq!(output.len(), input.len());
+        assert!(output
+            .iter()
+            .zip(&input)
+            .all(|(out, inp)| (out - inp * 2.0).abs() < 1e-6));
+        assert_eq!(engine.process_mode, ProcessMode::Realtime);
+        assert_eq!(engine.process_data.process_mode, ProcessMode::Realtime);
+
+        let mono = engine.render_offline(&[0.25; 100], 1).unwrap();
+        assert_eq!(mono, vec![0.5; 100]);
+
+        assert!(engine.render_offline(&[0.0; 3], 2).is_err());
+        assert!(engine.render_offline(&[0.0; 4], 0).is_err());
+        assert_eq!(
+            engine
+                .render_offline(&[0.25, 0.25, 0.25, 0.125, 0.125, 0.125], 3)
+                .unwrap(),
+            vec![0.5, 0.5, 0.25, 0.25, 0.25, 0.125]
+        );
+        assert!(engine.render_offline(&[], 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_record_window_starts_within_block() {
+        use crate::recording::{RecordPoint, RecordWindow};
+
+        let window = RecordWindow {
+            start: Some(100),
+            stop: Some(150),
+        };
+        assert!(window.frames_in_block(Some(0), 64).is_empty());
+        assert_eq!(window.frames_in_block(Some(64), 64), 36..64);
+        assert_eq!(window.frames_in_block(Some(128), 64), 0..22);
+        assert_eq!(window.frames_in_block(Some(192), 64), 0..0);
+        assert!(!window.is_finished(Some(64), 64));
+        assert!(window.is_finished(Some(128), 64));
+        // Nothing is recorded while the transport is stopped
+        assert_eq!(window.frames_in_block(None, 64), 0..0);
+        assert_eq!(RecordWindow::default().frames_in_block(None, 64), 0..64);
+
+        // Four beats a bar, half a second each at 120 BPM
+        assert_eq!(RecordPoint::Bar(2).to_sample(120.0, 48000), 192000);
+        assert_eq!(RecordPoint::Sample(7).to_sample(120.0, 48000), 7);
+    }
+
+    #[test]
+    fn test_recording_starts_at_target_sample() {
+        let dir = std::env::temp_dir().join(format!("sona-recording-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("take.wav");
+
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        engine.set_transport_playing(true);
+        assert!(engine
+            .start_recording(
+                &path,
+                Some(RecordPoint::Sample(200)),
+                Some(RecordPoint::Sample(100))
+            )
+            .is_err());
+        engine
+            .start_recording(
+                &path,
+                Some(RecordPoint::Sample(100)),
+                Some(RecordPoint::Sample(228)),
+            )
+            .unwrap();
+        assert!(engine.is_recording());
+
+        // Every sample carries its transport position
+        let mut renderer = engine.chain_renderer();
+        for block in 0..5 {
+            for i in 0..64 {
+                let position = (block * 64 + i) as f32 / 1000.0;
+                renderer.input_data.write(0, i, position);
+                renderer.input_data.write(1, i, -position);
+            }
+            unsafe { renderer.render(64, 2) };
+            renderer.advance_transport(64, 2);
+        }
+        assert_eq!(engine.transport_position(), 320);
+        assert!(!engine.is_recording());
+
+        let path = engine.stop_recording().unwrap();
+        let samples = hound::WavReader::open(&path)
+            .unwrap()
+            .samples::<f32>()
+            .map(|s| s.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(samples.len(), 128 * 2);
+        assert_eq!(samples[0], 0.1);
+        assert_eq!(samples[1], -0.1);
+        assert_eq!(samples[254], 0.227);
+        assert!(engine.stop_recording().is_err());
+
+        engine.rewind_transport();
+        assert_eq!(engine.transport_position(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_renderers_own_their_process_context() {
+        // The stream callbacks move a renderer onto the audio thread
+        fn assert_send<T: Send>() {}
+        assert_send::<ChainRenderer>();
+
+        let engine = AudioEngine::default();
+        let first = engine.chain_renderer();
+        let second = engine.chain_renderer();
+        assert_eq!(
+            first.process_data.process_context,
+            first.process_context.get()
+        );
+        assert_ne!(
+            first.process_data.process_context,
+            second.process_data.process_context
+        );
+    }
+
+    #[test]
+    fn test_process_context_follows_transport() {
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        engine.set_tempo(90.0).unwrap();
+
+        let mut renderer = engine.chain_renderer();
+        let sample_rate = renderer.sample_rate as f64;
+        unsafe { renderer.render(64, 2) };
+        let context = mock::LAST_CONTEXT.with(|context| context.take()).unwrap();
+        assert_eq!(context.tempo, 90.0);
+        assert_eq!(context.sample_rate, sample_rate);
+        assert_ne!(context.state & ProcessContextFlags::TempoValid, 0);
+        assert_eq!(context.state & ProcessContextFlags::Playing, 0);
+        assert_eq!(
+            (context.time_sig_numerator, context.time_sig_denominator),
+            (4, 4)
+        );
+
+        // A tempo-synced delay's quarter note follows the new tempo on the next block
+        engine.set_tempo(120.0).unwrap();
+        engine.set_transport_playing(true);
+        unsafe { renderer.render(64, 2) };
+        renderer.advance_transport(64, 2);
+        unsafe { renderer.render(64, 2) };
+        let context = mock::LAST_CONTEXT.with(|context| context.take()).unwrap();
+        let quarter_note = 60.0 / context.tempo * context.sample_rate;
+        assert_eq!(quarter_note, sample_rate / 2.0);
+        assert_ne!(context.state & ProcessContextFlags::Playing, 0);
+        assert_eq!(context.project_time_samples, 64);
+        assert_eq!(context.continous_time_samples, 128);
+        assert!((context.project_time_music - 64.0 / quarter_note).abs() < 1e-9);
+        assert_eq!(context.bar_position_music, 0.0);
+
+        // A parallel chain on a worker sees the same transport through its lane's context
+        let chain = engine.add_chain().unwrap();
+        let id = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        engine.set_plugin_chain(id, chain).unwrap();
+        engine.set_parallel_processing(true).unwrap();
+        renderer.advance_transport(64, 2);
+        unsafe { renderer.render(64, 2) };
+
+        let workers = engine.chain_workers.lock().unwrap();
+        let slot = &workers.as_ref().unwrap().slots[0];
+        assert_eq!(slot.rendered.load(Ordering::Relaxed), 1);
+        let lane = unsafe { &*slot.lane.get() };
+        assert_eq!(
+            lane.process_data.process_context,
+            lane.process_context.get()
+        );
+        let (expected, context) =
+            unsafe { (*renderer.process_context.get(), *lane.process_context.get()) };
+        assert_eq!(context.tempo, 120.0);
+        assert_ne!(context.state & ProcessContextFlags::Playing, 0);
+        assert_eq!(context.project_time_samples, 128);
+        assert_eq!(
+            context.continous_time_samples,
+            expected.continous_time_samples
+        );
+        assert_eq!(context.project_time_music, expected.project_time_music);
+    }
+
+    #[test]
+    fn test_render_stems_match_chain_stages() {
+        let dir = std::env::temp_dir().join(format!("sona-stems-{}", std::process::id()));
+        let input_path = dir.join("input.wav");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut engine = AudioEngine::default();
+        engine.set_sample_rate(48000).unwrap();
+        engine.set_buffer_size(64).unwrap();
+        let first = engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        let second = engine.insert_plugin(mock_gain_plugin(3.0, false)).unwrap();
+        engine.plugin_modules_mut().get_mut(&second).unwrap().name = "Gain / x3".to_string();
+
+        let left = (0..200).map(|i| i as f32 / 400.0).collect::<Vec<_>>();
+        let right = left.iter().map(|s| -s).collect::<Vec<_>>();
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48000,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let interleaved = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(l, r)| [*l, *r])
+            .collect::<Vec<_>>();
+        write_wav(&input_path, spec, &interleaved).unwrap();
+
+        let stems = engine.render_stems(&input_path, dir.join("stems")).unwrap();
+        let names = stems
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["01_Mock.wav", "02_Gain___x3.wav", "mix.wav"]);
+
+        let read = |path: &Path| {
+            hound::WavReader::open(path)
+                .unwrap()
+                .samples::<f32>()
+                .map(|s| s.unwrap())
+                .collect::<Vec<_>>()
+        };
+        let stage = |engine: &mut AudioEngine| {
+            let output = engine.process_block(&[&left, &right]).unwrap();
+            output[0]
+                .iter()
+                .zip(&output[1])
+                .flat_map(|(l, r)| [*l, *r])
+                .collect::<Vec<_>>()
+        };
+
+        // Each stem is the chain output up to and including its plugin
+        engine.set_bypassed(second, true).unwrap();
+        assert_eq!(read(&stems[0]), stage(&mut engine));
+        engine.set_bypassed(second, false).unwrap();
+        assert_eq!(read(&stems[1]), stage(&mut engine));
+        assert_eq!(read(&stems[2]), stage(&mut engine));
+        assert_ne!(read(&stems[0]), read(&stems[1]));
+
+        // Bypassed plugins don't process, so they get no stem
+        engine.set_bypassed(first, true).unwrap();
+        let stems = engine
+            .render_stems(&input_path, dir.join("bypassed"))
+            .unwrap();
+        assert_eq!(stems.len(), 2);
+
+        engine.set_sample_rate(44100).unwrap();
+        assert!(engine
+            .render_stems(&input_path, dir.join("wrong-rate"))
+            .is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parameter_changes_queue_points() {
+        use vst3::vst::audio_processor::{
+            IParamValueQueue_Impl, IParameterChanges, IParameterChanges_Impl,
+        };
+
+        let mut changes = HostParameterChanges::new();
+        assert!(changes.add_change(7, 0, 0.25));
+        assert!(changes.add_change(3, 16, 0.5));
+        assert!(changes.add_change(7, 32, 0.75));
+        // Same offset replaces the earlier point
+        assert!(changes.add_change(7, 32, 1.0));
+
+        unsafe {
+            let ptr = &mut changes as *mut HostParameterChanges as *mut IParameterChanges;
+            assert_eq!((*ptr).get_parameter_count(), 2);
+            assert!((*ptr).get_parameter_data(2).is_null());
+
+            let queue = &*(*ptr).get_parameter_data(0);
+            assert_eq!(queue.get_parameter_id(), 7);
+            assert_eq!(queue.get_point_count(), 2);
+            let (mut offset, mut value) = (0, 0.0);
+            assert_eq!(
+                queue.get_point(1, &mut offset, &mut value),
+                TResult::ResultOk
+            );
+            assert_eq!((offset, value), (32, 1.0));
+            assert_ne!(
+                queue.get_point(2, &mut offset, &mut value),
+                TResult::ResultOk
+            );
+
+            // Plugins may add parameters themselves, existing ids reuse their queue
+            let mut index = -1;
+            let id = 3;
+            assert!(!(*ptr).add_parameter_data(&id, &mut index).is_null());
+            assert_eq!(index, 1);
+        }
+
+        changes.clear();
+        assert!(changes.queues().is_empty());
+        for id in 0..MAX_PARAMETER_CHANGES as u32 {
+            assert!(changes.add_change(id, 0, 0.0));
+        }
+        assert!(!changes.add_change(u32::MAX, 0, 0.0));
+    }
+
+    #[test]
+    fn test_embedded_view_size_follows_constraints() {
+        use crate::vst::host::embedded_view_rect;
+        use vst3::gui::plug_view::ViewRect;
+
+        let size = |rect: ViewRect| (rect.left, rect.top, rect.right, rect.bottom);
+        let preferred = ViewRect {
+            left: 10,
+            top: 20,
+            right: 410,
+            bottom: 320,
+        };
+        // Keeps the view between 200x100 and 600x500
+        let constrain = |rect: &mut ViewRect| {
+            rect.right = rect.right.clamp(200, 600);
+            rect.bottom = rect.bottom.clamp(100, 500);
+        };
+
+        // A resizable view fills the region within its constraints
+        assert_eq!(
+            size(embedded_view_rect(500, 400, preferred, true, constrain)),
+            (0, 0, 500, 400)
+        );
+        assert_eq!(
+            size(embedded_view_rect(800, 700, preferred, true, constrain)),
+            (0, 0, 600, 500)
+        );
+        // Below its minimum it stays larger than the region and gets clipped
+        assert_eq!(
+            size(embedded_view_rect(150, 50, preferred, true, constrain)),
+            (0, 0, 200, 100)
+        );
+        // A fixed view keeps its preferred size and is never asked
+        assert_eq!(
+            size(embedded_view_rect(
+                800,
+                700,
+                preferred,
+                false,
+                |_| unreachable!()
+            )),
+            (0, 0, 400, 300)
+        );
+        assert_eq!(
+            size(embedded_view_rect(-5, 300, preferred, true, |_| {})),
+            (0, 0, 0, 300)
+        );
+    }
 
-            // Store current host ID to recreate it
-            let current_host_id = self.host.id();
+    #[test]
+    fn test_midi_input_routes_to_note_plugins() {
+        use vst3::vst::audio_processor::EventTypes;
+
+        assert_eq!(
+            MidiMessage::parse(&[0x91, 60, 127]),
+            Some(MidiMessage::NoteOn {
+                channel: 1,
+                pitch: 60,
+                velocity: 1.0
+            })
+        );
+        // Zero velocity note on is a note off
+        assert_eq!(
+            MidiMessage::parse(&[0x90, 60, 0]),
+            Some(MidiMessage::NoteOff {
+                channel: 0,
+                pitch: 60,
+                velocity: 0.0
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xB2, 74, 127]),
+            Some(MidiMessage::ControlChange {
+                channel: 2,
+                controller: 74,
+                value: 1.0
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0xE0, 0x7F, 0x7F]),
+            Some(MidiMessage::PitchBend {
+                channel: 0,
+                value: 1.0
+            })
+        );
+        assert_eq!(MidiMessage::parse(&[0xC0, 5]), None);
+        assert_eq!(MidiMessage::parse(&[0xF8]), None);
+        assert_eq!(MidiMessage::parse(&[0x90, 0x80, 64]), None);
+
+        let mut instrument = mock_plugin();
+        instrument.event_inputs = 1;
+        let effect = mock_plugin();
+        let mut plugins = FxHashMap::default();
+        plugins.insert(PluginId(1), instrument);
+        plugins.insert(PluginId(2), effect);
+
+        route_midi_message(&plugins, MidiMessage::parse(&[0x90, 64, 100]).unwrap(), 3);
+        route_midi_message(&plugins, MidiMessage::parse(&[0x80, 64, 0]).unwrap(), 40);
+        // Without a MIDI mapping controllers have nowhere to go
+        route_midi_message(&plugins, MidiMessage::parse(&[0xB0, 1, 64]).unwrap(), 0);
 
-            // Recreate the host to refresh its internal state
-            self.host = cpal::host_from_id(current_host_id)?;
+        unsafe {
+            let block = plugins[&PluginId(1)].events.next_block();
+            let events = block.events.events();
+            assert_eq!(events.len(), 2);
+            assert_eq!(events[0].ty, EventTypes::NoteOnEvent);
+            assert_eq!(events[0].data.note_on.pitch, 64);
+            assert_eq!(events[0].sample_offset, 3);
+            assert_eq!(events[1].ty, EventTypes::NoteOffEvent);
+            assert_eq!(events[1].sample_offset, 40);
+            assert!(block.parameters.queues().is_empty());
+
+            let block = plugins[&PluginId(2)].events.next_block();
+            assert!(block.events.events().is_empty());
         }
 
-        trace!(
-            "Reset host and devices, now selecting input device: {}",
-            device_name
+        // Mapped controllers come from the map built at load, on their own channel only
+        let instrument = plugins.get_mut(&PluginId(1)).unwrap();
+        instrument.midi_controllers.insert((2, 74), 7);
+        instrument
+            .midi_controllers
+            .insert((0, ControllerNumbers::PitchBend), 8);
+        route_midi_message(&plugins, MidiMessage::parse(&[0xB2, 74, 127]).unwrap(), 5);
+        route_midi_message(&plugins, MidiMessage::parse(&[0xB0, 74, 127]).unwrap(), 6);
+        route_midi_message(&plugins, MidiMessage::parse(&[0xE0, 0, 0x40]).unwrap(), 9);
+        unsafe {
+            let block = plugins[&PluginId(1)].events.next_block();
+            let mut changes = block
+                .parameters
+                .queues()
+                .iter()
+                .map(|queue| (queue.id(), queue.points().to_vec()))
+                .collect::<Vec<_>>();
+            changes.sort_by_key(|(id, _)| *id);
+            assert_eq!(changes.len(), 2);
+            assert_eq!(changes[0].0, 7);
+            assert_eq!(changes[0].1, vec![(5, 1.0)]);
+            assert_eq!(changes[1].0, 8);
+            assert_eq!(changes[1].1[0].0, 9);
+        }
+    }
+
+    #[test]
+    fn test_midi_input_lands_at_its_offset_in_the_block() {
+        use crate::midi::StampClock;
+
+        let start = Instant::now();
+        let timing = BlockTiming::default();
+        assert_eq!(timing.sample_offset(start), 0);
+
+        // 1 ms into a 64 sample block at 48 kHz, clamped to the block
+        timing.start_block(start, 64, 48000.0);
+        assert_eq!(timing.sample_offset(start), 0);
+        assert_eq!(timing.sample_offset(start + Duration::from_millis(1)), 48);
+        assert_eq!(timing.sample_offset(start + Duration::from_millis(10)), 63);
+
+        // Stamps keep their spacing from the first message's delivery
+        let mut clock = StampClock::default();
+        assert_eq!(clock.instant(1_000, start), start);
+        let later = start + Duration::from_millis(5);
+        assert_eq!(
+            clock.instant(1_500, later),
+            start + Duration::from_micros(500)
         );
+        // A message delivered faster than the anchor moves it up
+        assert_eq!(clock.instant(10_000, later), later);
+        assert_eq!(
+            clock.instant(10_200, later + Duration::from_millis(1)),
+            later + Duration::from_micros(200)
+        );
+    }
 
-        let device = self
-            .host
-            .input_devices()?
-            .find(|d| d.name().map_or(false, |name| name == device_name))
-            .ok_or_else(|| {
-                info!("Input device '{}' not found", device_name);
-                anyhow!("Input device '{}' not found", device_name)
-            })?;
+    #[test]
+    fn test_parameter_queue_hands_changes_over_whole() {
+        use vst::parameters::ParameterQueue;
+
+        let queue = Arc::new(ParameterQueue::new(64));
+        let writers = (0..2u32)
+            .map(|writer| {
+                let queue = queue.clone();
+                std::thread::spawn(move || {
+                    let mut sent = 0;
+                    while sent < 200 {
+                        // Value mirrors the id, so a torn change would show up as a mismatch
+                        let id = writer * 1000 + sent;
+                        if queue.push(id, id as f64) {
+                            sent += 1;
+                        } else {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut received = Vec::new();
+        let mut changes = HostParameterChanges::new();
+        while received.len() < 400 {
+            changes.clear();
+            unsafe { queue.drain_into(&mut changes) };
+            for change in changes.queues() {
+                for &(offset, value) in change.points() {
+                    assert_eq!(offset, 0);
+                    assert_eq!(value, change.id() as f64);
+                    received.push(change.id());
+                }
+            }
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
 
-        trace!(
-            "Supported input configs: {:?}",
-            device
-                .supported_input_configs()
-                .map(|m| m.collect::<Vec<_>>())
-                .unwrap_or_default()
+        // Each writer's changes arrive in the order they were pushed
+        for writer in 0..2 {
+            let ids = received
+                .iter()
+                .filter(|&&id| id / 1000 == writer)
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(ids, (0..200).map(|i| writer * 1000 + i).collect::<Vec<_>>());
+        }
+
+        // What doesn't fit a block waits for the next one, and a full queue turns away more
+        let queue = ParameterQueue::new(MAX_PARAMETER_CHANGES + 3);
+        for id in 0..MAX_PARAMETER_CHANGES as u32 + 3 {
+            assert!(queue.push(id, 0.5));
+        }
+        assert!(!queue.push(0, 0.5));
+        changes.clear();
+        unsafe { queue.drain_into(&mut changes) };
+        assert_eq!(changes.queues().len(), MAX_PARAMETER_CHANGES);
+        changes.clear();
+        unsafe { queue.drain_into(&mut changes) };
+        assert_eq!(changes.queues().len(), 3);
+    }
+
+    #[test]
+    fn test_event_list_swaps_queued_block_in() {
+        use vst3::vst::audio_processor::{Event, EventTypes, IEventList, IEventList_Impl};
+
+        let events = EventList::new();
+        assert!(events.add_note_on(32, 0, 60, 0.8));
+        assert!(events.add_note_off(8, 0, 62, 0.0));
+        assert!(events.add_param_point(5, 16, 0.5));
+
+        unsafe {
+            let block = events.next_block();
+            assert_eq!(block.parameters.queues().len(), 1);
+            assert_eq!(block.parameters.queues()[0].points(), &[(16, 0.5)]);
+
+            // The processor sees the events sorted by sample offset
+            let list = &mut block.events as *mut HostEventList as *mut IEventList;
+            assert_eq!((*list).get_event_count(), 2);
+            let mut event = Event::default();
+            assert_eq!((*list).get_event(0, &mut event), TResult::ResultOk);
+            assert_eq!(
+                (event.sample_offset, event.ty),
+                (8, EventTypes::NoteOffEvent)
+            );
+            assert_eq!((*list).get_event(1, &mut event), TResult::ResultOk);
+            assert_eq!(event.ty, EventTypes::NoteOnEvent);
+            assert_eq!(
+                (event.data.note_on.pitch, event.data.note_on.velocity),
+                (60, 0.8)
+            );
+            assert_ne!((*list).get_event(2, &mut event), TResult::ResultOk);
+
+            // Events added while a block is out go to the next one
+            assert!(events.add_note_on(0, 0, 64, 1.0));
+            let block = events.next_block();
+            assert_eq!(block.events.events().len(), 1);
+            assert!(block.parameters.queues().is_empty());
+
+            let block = events.next_block();
+            assert!(block.events.events().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_editor_edits_reach_processor_and_app() {
+        use vst3::vst::host_application::{IComponentHandler, IComponentHandler_Impl};
+
+        let mut engine = AudioEngine::default();
+        let plugin = mock_gain_plugin(1.0, false);
+        let handler = HostComponentHandler::new(plugin.parameters.clone());
+        let plugin_id = engine.insert_plugin(plugin).unwrap();
+        let queue = engine.parameter_edit_queue();
+
+        let ptr = &handler as *const HostComponentHandler as *mut IComponentHandler;
+        assert_eq!(unsafe { (*ptr).perform_edit(0, 0.25) }, TResult::ResultOk);
+
+        assert_eq!(engine.parameter_value(plugin_id, 0), Some(0.25));
+        assert_eq!(
+            queue.take(),
+            vec![ParameterEdit {
+                plugin_id,
+                id: 0,
+                value: 0.25
+            }]
+        );
+        assert!(engine.take_parameter_edits().is_empty());
+
+        // The queue is shared, edits land in it without going through the engine
+        assert_eq!(unsafe { (*ptr).perform_edit(0, 0.3) }, TResult::ResultOk);
+        assert_eq!(engine.take_parameter_edits().len(), 1);
+        assert!(queue.take().is_empty());
+        assert_eq!(unsafe { (*ptr).perform_edit(0, 0.25) }, TResult::ResultOk);
+        assert_eq!(queue.take().len(), 1);
+
+        // The processor picks the change up on its next block and keeps it
+        let input = vec![1.0; 64];
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[0], vec![0.25; 64]);
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[1], vec![0.25; 64]);
+
+        // Without an edit controller there is nothing to set the parameter on
+        assert!(engine.set_parameter(plugin_id, 0, 0.5).is_err());
+        assert_eq!(engine.parameter_value(plugin_id, 0), Some(0.25));
+    }
+
+    #[test]
+    fn test_import_chain_from_description() {
+        let json = r#"[
+            { "uid_or_name": "Compressor", "params": { "0": 0.25, "3": 0.75 } },
+            { "uid_or_name": "Missing" },
+            { "uid_or_name": "ABCDEF0123456789ABCDEF0123456789", "bypassed": true }
+        ]"#;
+        let entries = chain_import::parse_chain(json).unwrap();
+        assert_eq!(entries[0].params.len(), 2);
+        assert!(!entries[0].bypassed);
+        assert!(entries[2].bypassed);
+
+        let mut engine = AudioEngine::default();
+        let existing = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+
+        // The reverb is one class of a factory shipping several
+        let reverb_class = "ABCDEF01-2345-6789-ABCD-EF0123456789";
+        let resolve = |uid_or_name: &str| match uid_or_name {
+            "Compressor" => Some(("/plugins/Compressor.vst3".to_string(), None)),
+            "ABCDEF0123456789ABCDEF0123456789" => Some((
+                "/plugins/Reverb.vst3".to_string(),
+                Some(reverb_class.to_string()),
+            )),
+            _ => None,
+        };
+        let import = engine
+            .import_chain(entries, resolve, |engine, path, class_uid| {
+                let mut plugin = mock_gain_plugin(1.0, false);
+                plugin.path = path.to_string();
+                plugin.uid = class_uid.unwrap_or_default().to_string();
+                plugin.editor = Some(mock_controller::controller());
+                engine.insert_plugin(plugin)
+            })
+            .unwrap();
+
+        // Appended after the plugins already loaded, in the order described
+        assert_eq!(import.plugins.len(), 2);
+        assert_eq!(
+            engine.plugin_order(),
+            vec![existing, import.plugins[0], import.plugins[1]]
+        );
+        let paths: Vec<String> = import
+            .plugins
+            .iter()
+            .map(|id| engine.plugin_modules()[id].path.clone())
+            .collect();
+        assert_eq!(paths, ["/plugins/Compressor.vst3", "/plugins/Reverb.vst3"]);
+        assert_eq!(
+            engine.plugin_modules()[&import.plugins[1]].uid,
+            reverb_class
         );
 
-        self.input_config = Some(
-            pick_best_format(
-                device.supported_input_configs().unwrap(),
-                Some(48000),             // No preferred sample rate
-                Some(256),               // No preferred buffer size
-                Some(SampleFormat::I32), // No preferred sample format
-                Some(2),
-            )
-            .ok_or_else(|| {
-                anyhow!(
-                    "No supported input configurations for device '{}'",
-                    device_name
+        assert_eq!(engine.parameter_value(import.plugins[0], 0), Some(0.25));
+        assert_eq!(engine.parameter_value(import.plugins[0], 3), Some(0.75));
+        assert_eq!(engine.is_bypassed(import.plugins[0]), Some(false));
+        assert_eq!(engine.is_bypassed(import.plugins[1]), Some(true));
+
+        assert_eq!(import.unresolved.len(), 1);
+        assert_eq!(import.unresolved[0].index, 1);
+        assert_eq!(import.unresolved[0].uid_or_name, "Missing");
+
+        assert!(engine
+            .import_chain_from_json("{ \"not\": \"a list\" }", resolve)
+            .is_err());
+    }
+
+    #[test]
+    fn test_import_chain_continues_past_failed_settings() {
+        let json = r#"[
+            { "uid_or_name": "Gone", "params": { "0": 0.5 }, "bypassed": true },
+            { "uid_or_name": "Compressor" }
+        ]"#;
+        let entries = chain_import::parse_chain(json).unwrap();
+
+        let mut engine = AudioEngine::default();
+        let resolve = |uid_or_name: &str| Some((format!("/plugins/{}.vst3", uid_or_name), None));
+        let import = engine
+            .import_chain(entries, resolve, |engine, path, _| {
+                // Gone again by the time its settings are applied
+                if path.contains("Gone") {
+                    return Ok(PluginId(999));
+                }
+                engine.insert_plugin(mock_gain_plugin(1.0, false))
+            })
+            .unwrap();
+
+        // The rest of the chain is still imported, the failed settings are reported
+        assert_eq!(import.plugins.len(), 2);
+        assert_eq!(engine.plugin_order(), vec![import.plugins[1]]);
+        assert!(import.unresolved.is_empty());
+        assert_eq!(import.warnings.len(), 2);
+        assert!(import.warnings[0].starts_with("Gone parameter 0"));
+        assert!(import.warnings[1].starts_with("Gone bypass"));
+    }
+
+    #[test]
+    fn test_factory_classes_list_every_class_and_probe_audio_ones() {
+        use mock_factory::{CLASSES, VENDOR};
+        use vst::host::{audio_classes, factory_classes};
+
+        let factory = mock_factory::factory();
+        let classes = unsafe { factory_classes(&factory) }.unwrap();
+        let listed = classes
+            .iter()
+            .map(|class| {
+                (
+                    class.uid.as_str(),
+                    class.category.as_str(),
+                    class.name.as_str(),
                 )
             })
-            .unwrap()
-            .into(),
+            .collect::<Vec<_>>();
+        assert_eq!(listed, CLASSES);
+
+        // Only the audio classes are probed, each for an entry of its own
+        let probed = unsafe { audio_classes(&factory, "/plugins/Multi.vst3") }.unwrap();
+        assert_eq!(probed.len(), 2);
+        for (metadata, (uid, category, name)) in probed.iter().zip([CLASSES[0], CLASSES[2]]) {
+            assert_eq!(
+                (metadata.uid.as_str(), metadata.category.as_str()),
+                (uid, category)
+            );
+            assert_eq!(metadata.name, name);
+            assert_eq!(metadata.vendor, VENDOR);
+            assert_eq!(metadata.format, PluginFormat::Vst3);
+        }
+    }
+
+    #[test]
+    fn test_new_with_class_instantiates_the_requested_class() {
+        use mock_factory::{CLASSES, VENDOR};
+
+        let open = |class_uid| unsafe {
+            VSTHostContext::from_factory(mock_factory::factory(), "/plugins/Multi.vst3", class_uid)
+        };
+
+        // The first audio class unless one is asked for
+        let plugin = open(None).unwrap();
+        assert_eq!(
+            (plugin.uid.as_str(), plugin.name.as_str()),
+            (CLASSES[0].0, "Mono")
         );
-        //device.default_input_config().ok().map(|c| c.into());
-        self.input_device = Some(device);
+        assert_eq!(plugin.vendor, VENDOR);
 
-        // Handle ASIO/CoreAudio device exclusivity
-        #[cfg(target_os = "windows")]
-        if self.host.id() == cpal::HostId::Asio {
-            self.output_device = self.input_device.clone();
-            self.output_config = self.input_config.clone();
+        let mut plugin = open(Some(CLASSES[2].0)).unwrap();
+        assert_eq!(
+            (plugin.uid.as_str(), plugin.name.as_str()),
+            (CLASSES[2].0, "Stereo")
+        );
+
+        // The mock has no component to give, but it was asked for the picked class
+        mock_factory::CREATED.with(|created| created.set(None));
+        assert!(plugin.create_instances().is_err());
+        assert_eq!(
+            mock_factory::CREATED.with(|created| created.get()),
+            Some(vst3::ascii_to_uid(CLASSES[2].0).unwrap())
+        );
+
+        // Classes that aren't there or don't process audio can't be picked
+        assert!(open(Some(CLASSES[1].0)).is_err());
+        assert!(open(Some("5D1E5A3F-0000-4000-8000-0000000000FF")).is_err());
+        assert!(open(Some("not a uid")).is_err());
+    }
+
+    #[test]
+    fn test_reload_plugin_keeps_position_and_state() {
+        let mut engine = AudioEngine::default();
+        let first = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        let reloaded = engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        let last = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+
+        // Component state "preset" without controller state
+        let mut state = 6u32.to_le_bytes().to_vec();
+        state.extend_from_slice(b"preset");
+        {
+            let mut plugins = engine.plugin_modules_mut();
+            let plugin = plugins.get_mut(&reloaded).unwrap();
+            plugin.load_state(&state).unwrap();
+            plugin.bypass = true;
+        }
+
+        let restored = engine
+            .reload_plugin_with(reloaded, |_, _| Ok(mock_gain_plugin(0.25, false)))
+            .unwrap();
+        assert!(restored);
+        assert_eq!(engine.plugin_order(), vec![first, reloaded, last]);
+        let plugins = engine.plugin_modules();
+        let plugin = &plugins[&reloaded];
+        assert_eq!(plugin.id, reloaded);
+        assert!(plugin.bypass);
+        assert_eq!(plugin.save_state().unwrap(), state);
+        drop(plugins);
+
+        // A plugin that fails to load again is gone from the chain
+        assert!(engine
+            .reload_plugin_with(reloaded, |_, path| Err(anyhow!("{} is missing", path)))
+            .is_err());
+        assert_eq!(engine.plugin_order(), vec![first, last]);
+        assert!(!engine.is_plugin_loaded(reloaded));
+        assert!(engine.reload_plugin(reloaded).is_err());
+    }
+
+    #[test]
+    fn test_reload_plugin_keeps_sidechain_and_loudness_match() {
+        let sidechained = || {
+            let mut plugin = mock_gain_plugin(1.0, false);
+            plugin.input_buses = ["Input", "Sidechain"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| BusDescription {
+                    name: name.to_string(),
+                    channel_count: 2,
+                    aux: i > 0,
+                })
+                .collect();
+            plugin
+        };
+
+        let mut engine = AudioEngine::default();
+        let source = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        let id = engine.insert_plugin(sidechained()).unwrap();
+        let sidechain = Some(SidechainSource::Plugin(source));
+        engine.set_sidechain_source(id, sidechain).unwrap();
+        engine.set_bypass_loudness_match(id, true).unwrap();
+
+        engine
+            .reload_plugin_with(id, |_, _| Ok(sidechained()))
+            .unwrap();
+        assert_eq!(engine.sidechain_source(id), sidechain);
+        assert_eq!(engine.bypass_loudness_match(id), Some(true));
+        assert!(engine.bus_layouts.read().unwrap().contains_key(&id));
+    }
+
+    #[test]
+    fn test_ab_snapshots_restore_order_state_and_bypass() {
+        let mut engine = AudioEngine::default();
+        let first = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        let second = engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        assert!(engine.recall_snapshot(AbSlot::A).is_err());
+
+        // Component state `name` without controller state
+        let state = |name: &[u8]| {
+            let mut state = (name.len() as u32).to_le_bytes().to_vec();
+            state.extend_from_slice(name);
+            state
+        };
+        let set_state = |engine: &mut AudioEngine, id: PluginId, name: &[u8]| {
+            engine
+                .plugin_modules_mut()
+                .get_mut(&id)
+                .unwrap()
+                .load_state(&state(name))
+                .unwrap();
+        };
+
+        set_state(&mut engine, first, b"a");
+        engine.store_snapshot(AbSlot::A);
+
+        set_state(&mut engine, first, b"b");
+        engine.set_bypassed(second, true).unwrap();
+        engine.reorder_plugins(&[second, first]).unwrap();
+        engine.store_snapshot(AbSlot::B);
+
+        engine.recall_snapshot(AbSlot::A).unwrap();
+        assert_eq!(engine.plugin_order(), vec![first, second]);
+        assert_eq!(engine.is_bypassed(second), Some(false));
+        assert_eq!(
+            engine.plugin_modules()[&first].save_state().unwrap(),
+            state(b"a")
+        );
+
+        // A recall that reorders is a structural edit
+        engine.set_chain_locked(true);
+        assert!(engine.recall_snapshot(AbSlot::B).is_err());
+        assert_eq!(engine.plugin_order(), vec![first, second]);
+        engine.set_chain_locked(false);
+
+        // Removed plugins are skipped, new ones go last
+        engine.remove_plugin(second).unwrap();
+        let added = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        engine.recall_snapshot(AbSlot::B).unwrap();
+        assert_eq!(engine.plugin_order(), vec![first, added]);
+        assert_eq!(
+            engine.plugin_modules()[&first].save_state().unwrap(),
+            state(b"b")
+        );
+        assert!(engine.has_snapshot(AbSlot::A));
+    }
+
+    #[test]
+    fn test_chain_lock_blocks_structural_edits() {
+        let mut engine = AudioEngine::default();
+        let mut plugin = mock_plugin();
+        plugin.editor = Some(mock_controller::controller());
+        let first = engine.insert_plugin(plugin).unwrap();
+        let second = engine.insert_plugin(mock_plugin()).unwrap();
+
+        engine.set_chain_locked(true);
+        assert!(engine.is_chain_locked());
+
+        let err = engine.load_plugin("missing.vst3").unwrap_err();
+        assert!(err.to_string().contains("locked"));
+        assert!(engine.insert_plugin(mock_plugin()).is_err());
+        assert!(engine.remove_plugin(first).is_err());
+        assert!(engine.unload_plugin(first).is_err());
+        assert!(engine.reorder_plugins(&[second, first]).is_err());
+        assert!(engine.clear_plugins().is_err());
+        assert_eq!(engine.plugin_order(), vec![first, second]);
+
+        // Parameters and bypass are no structural edits and must keep working
+        let gain = mock_controller::GAIN_PARAMETER_ID;
+        assert!(engine.set_parameter(first, gain, 0.5).is_ok());
+        assert_eq!(engine.parameter_value(first, gain), Some(0.5));
+        assert!(engine.set_bypassed(first, true).is_ok());
+        assert_eq!(engine.is_bypassed(first), Some(true));
+        assert!(engine.set_bypassed(first, false).is_ok());
+        assert_eq!(engine.is_bypassed(first), Some(false));
+
+        engine.set_chain_locked(false);
+        assert!(engine.reorder_plugins(&[second, first]).is_ok());
+        assert_eq!(engine.plugin_order(), vec![second, first]);
+        assert!(engine.clear_plugins().is_ok());
+        assert!(engine.plugin_order().is_empty());
+    }
+
+    #[test]
+    fn test_reorder_plugins_rejects_mismatched_order() {
+        let mut engine = AudioEngine::default();
+        let first = engine.insert_plugin(mock_plugin()).unwrap();
+        let second = engine.insert_plugin(mock_plugin()).unwrap();
+
+        assert!(engine.reorder_plugins(&[first]).is_err());
+        assert!(engine.reorder_plugins(&[first, first]).is_err());
+        assert!(engine
+            .reorder_plugins(&[first, second, PluginId(0)])
+            .is_err());
+        assert_eq!(engine.plugin_order(), vec![first, second]);
+    }
+
+    #[test]
+    fn test_move_plugin_to_front_and_back() {
+        let mut engine = AudioEngine::default();
+        let ids: Vec<PluginId> = (0..4)
+            .map(|_| engine.insert_plugin(mock_plugin()).unwrap())
+            .collect();
+
+        engine.move_plugin_to_front(ids[2]).unwrap();
+        assert_eq!(engine.plugin_order(), vec![ids[2], ids[0], ids[1], ids[3]]);
+
+        engine.move_plugin_to_back(ids[0]).unwrap();
+        assert_eq!(engine.plugin_order(), vec![ids[2], ids[1], ids[3], ids[0]]);
+
+        // Already in place is a no-op
+        engine.move_plugin_to_front(ids[2]).unwrap();
+        engine.move_plugin_to_back(ids[0]).unwrap();
+        assert_eq!(engine.plugin_order(), vec![ids[2], ids[1], ids[3], ids[0]]);
+
+        assert!(engine.move_plugin_to_front(PluginId::new()).is_err());
+        engine.set_chain_locked(true);
+        assert!(engine.move_plugin_to_back(ids[2]).is_err());
+        assert_eq!(engine.plugin_order(), vec![ids[2], ids[1], ids[3], ids[0]]);
+    }
+
+    #[test]
+    fn test_ring_capacity_respects_latency_blocks() {
+        let mut engine = AudioEngine::default();
+        engine.set_sample_rate(48000).unwrap();
+        engine.set_buffer_size(512).unwrap();
+
+        assert_eq!(engine.ring_latency_blocks(), 2);
+        assert_eq!(engine.ring_capacity(2).unwrap(), 512 * 2 * 2);
+
+        engine.set_ring_latency_blocks(0);
+        assert_eq!(engine.ring_latency_blocks(), 1);
+        assert_eq!(engine.ring_capacity(2).unwrap(), 512 * 2);
+
+        engine.set_ring_latency_blocks(1000);
+        assert!(engine.ring_capacity(2).is_err());
+
+        engine.set_ring_latency_blocks(2);
+        engine.current_buffer_size = 0;
+        assert!(engine.ring_capacity(2).is_err());
+    }
+
+    #[test]
+    fn test_monitor_bus_routes_selected_slot_to_output() {
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(mock_plugin()).unwrap();
+
+        assert!(engine.set_monitor_bus(PluginId(0), 1).is_err());
+        engine.set_monitor_bus(id, 1).unwrap();
+        assert_eq!(engine.monitor_bus(id), Some(1));
+
+        {
+            let monitors = engine.bus_layouts.read().unwrap();
+            let data = &monitors[&id].process_data;
+            assert_eq!(data.num_outputs, 2);
+
+            unsafe {
+                let outputs = std::slice::from_raw_parts(data.outputs, 2);
+                let output_channels = engine.output_data.as_ptr();
+                assert_eq!(outputs[1].channel_buffers_32 as *const _, output_channels);
+                assert_ne!(outputs[0].channel_buffers_32 as *const _, output_channels);
+
+                // Whatever the plugin writes to bus 1 lands in the engine output
+                *(*outputs[1].channel_buffers_32).add(3) = 0.5;
+                *(*outputs[0].channel_buffers_32).add(4) = 0.25;
+            }
+        }
+
+        assert_eq!(engine.output_data.read()[3], 0.5);
+        assert_eq!(engine.output_data.read()[4], 0.0);
+
+        engine.set_monitor_bus(id, 0).unwrap();
+        assert_eq!(engine.monitor_bus(id), Some(0));
+        assert!(engine.bus_layouts.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_extra_buses_get_silence_and_scratch() {
+        let bus = |name: &str, channel_count, aux| BusDescription {
+            name: name.to_string(),
+            channel_count,
+            aux,
+        };
+
+        let mut engine = AudioEngine::default();
+        let mut plugin = mock_gain_plugin(0.5, false);
+        plugin.input_buses = vec![bus("Input", 2, false), bus("Sidechain", 1, true)];
+        plugin.output_buses = vec![
+            bus("Output", 2, false),
+            bus("Low", 2, true),
+            bus("High", 2, true),
+        ];
+        let id = engine.insert_plugin(plugin).unwrap();
+        let plain = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+
+        {
+            let layouts = engine.bus_layouts.read().unwrap();
+            assert!(!layouts.contains_key(&plain));
+            let data = &layouts[&id].process_data;
+            assert_eq!((data.num_inputs, data.num_outputs), (2, 3));
+
+            unsafe {
+                let inputs = std::slice::from_raw_parts(data.inputs, 2);
+                assert_eq!(
+                    inputs[0].channel_buffers_32,
+                    (*engine.in_bus.get()).channel_buffers_32
+                );
+                assert_eq!(inputs[1].num_channels, 1);
+                assert_eq!(inputs[1].silence_flags, 1);
+                let sidechain = std::slice::from_raw_parts(*inputs[1].channel_buffers_32, 64);
+                assert!(sidechain.iter().all(|s| *s == 0.0));
+
+                let outputs = std::slice::from_raw_parts(data.outputs, 3);
+                let output_channels = engine.output_data.as_ptr();
+                assert_eq!(outputs[0].channel_buffers_32 as *const _, output_channels);
+                assert_ne!(outputs[1].channel_buffers_32 as *const _, output_channels);
+                assert_ne!(outputs[2].channel_buffers_32 as *const _, output_channels);
+            }
+        }
+
+        // The main bus still carries the audio
+        let input = vec![1.0; 64];
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[0], vec![0.5; 64]);
+
+        engine.remove_plugin(id).unwrap();
+        assert!(engine.bus_layouts.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sidechain_reads_main_input_or_plugin_output() {
+        let bus = |name: &str, channel_count, aux| BusDescription {
+            name: name.to_string(),
+            channel_count,
+            aux,
+        };
+        let sidechain = |engine: &AudioEngine, id| unsafe {
+            let layouts = engine.bus_layouts.read().unwrap();
+            let data = &layouts[&id].process_data;
+            let inputs = std::slice::from_raw_parts(data.inputs, 2);
+            assert_eq!(inputs[1].silence_flags, 0);
+            std::slice::from_raw_parts(*inputs[1].channel_buffers_32, 64).to_vec()
+        };
+
+        let mut engine = AudioEngine::default();
+        let source = engine.insert_plugin(mock_gain_plugin(0.25, false)).unwrap();
+        let mut plugin = mock_gain_plugin(1.0, false);
+        plugin.input_buses = vec![bus("Input", 2, false), bus("Sidechain", 1, true)];
+        let id = engine.insert_plugin(plugin).unwrap();
+
+        // Only plugins with a sidechain bus take one, and never from themselves
+        assert!(engine
+            .set_sidechain_source(source, Some(SidechainSource::MainInput))
+            .is_err());
+        assert!(engine
+            .set_sidechain_source(id, Some(SidechainSource::Plugin(id)))
+            .is_err());
+        assert!(engine
+            .set_sidechain_source(id, Some(SidechainSource::Plugin(PluginId(999))))
+            .is_err());
+
+        // A mono sidechain gets the input channels averaged
+        engine
+            .set_sidechain_source(id, Some(SidechainSource::MainInput))
+            .unwrap();
+        let left = vec![1.0; 64];
+        let right = vec![0.5; 64];
+        engine.process_block(&[&left, &right]).unwrap();
+        assert_eq!(sidechain(&engine, id), vec![0.75; 64]);
+
+        engine
+            .set_sidechain_source(id, Some(SidechainSource::Plugin(source)))
+            .unwrap();
+        assert_eq!(
+            engine.sidechain_source(id),
+            Some(SidechainSource::Plugin(source))
+        );
+        engine.process_block(&[&left, &right]).unwrap();
+        assert_eq!(sidechain(&engine, id), vec![0.1875; 64]);
+
+        // Removing the source disconnects the sidechain
+        engine.remove_plugin(source).unwrap();
+        assert_eq!(engine.sidechain_source(id), None);
+    }
+
+    #[test]
+    fn test_gain_smoother_ramps_without_jumps() {
+        let mut smoother = GainSmoother::new(1.0, INPUT_GAIN_SMOOTHING, 48000.0);
+
+        let mut previous = smoother.current();
+        for _ in 0..48000 {
+            let gain = smoother.next(4.0);
+            assert!(gain >= previous && gain - previous < 0.01);
+            previous = gain;
+        }
+        assert!((smoother.current() - 4.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_soft_limit_is_linear_below_knee_and_capped() {
+        use crate::dsp::soft_limit;
+
+        assert_eq!(soft_limit(0.5, 1.0), 0.5);
+        assert_eq!(soft_limit(-0.8, 1.0), -0.8);
+
+        let mut previous = 0.8;
+        for step in 1..1000 {
+            let limited = soft_limit(0.8 + step as f32 * 0.01, 1.0);
+            assert!(limited >= previous && limited <= 1.0);
+            previous = limited;
+        }
+        assert_eq!(soft_limit(-100.0, 0.5), -0.5);
+    }
+
+    #[test]
+    fn test_output_limiter_caps_output_when_enabled() {
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(4.0, false)).unwrap();
+        assert_eq!(engine.output_limiter(), (false, DEFAULT_OUTPUT_CEILING_DB));
+
+        let input = vec![0.5; 64];
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[0], vec![2.0; 64]);
+
+        assert!(engine.set_output_limiter(true, 3.0).is_err());
+        assert!(engine.set_output_limiter(true, f32::NAN).is_err());
+        engine.set_output_limiter(true, -6.0).unwrap();
+        assert_eq!(engine.output_limiter(), (true, -6.0));
+
+        let ceiling = 10f32.powf(-6.0 / 20.0);
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert!(output[0]
+            .iter()
+            .all(|s| *s <= ceiling && *s > 0.9 * ceiling));
+
+        engine.set_output_limiter(false, -6.0).unwrap();
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[1], vec![2.0; 64]);
+    }
+
+    #[test]
+    fn test_quantized_full_scale_sine_does_not_wrap() {
+        use crate::dsp::Quantizer;
+
+        for (bits, dither) in [(32, false), (32, true), (24, true), (16, true)] {
+            let mut quantizer = Quantizer::new(bits);
+            for i in 0..4800 {
+                // Peaks slightly over full scale
+                let s = 1.05 * (i as f32 / 4800.0 * std::f32::consts::TAU * 10.0).sin();
+                let q = quantizer.quantize(s, dither);
+                if s > 0.01 {
+                    assert!(q > 0, "{} bits: {} became {}", bits, s, q);
+                } else if s < -0.01 {
+                    assert!(q < 0, "{} bits: {} became {}", bits, s, q);
+                }
+                // Lower resolutions leave the unused low bits clear
+                assert_eq!(q & ((1i64 << (32 - bits)) - 1) as i32, 0);
+            }
+
+            assert!(quantizer.quantize(1.0, dither) >= i32::MAX - (1 << (32 - bits)));
+            assert_eq!(
+                quantizer.quantize(4.0, dither),
+                i32::MAX & !((1i64 << (32 - bits)) - 1) as i32
+            );
+            assert_eq!(quantizer.quantize(-4.0, dither), i32::MIN);
+        }
+
+        let mut engine = AudioEngine {
+            output_sample_format: Some(SampleFormat::I16),
+            ..AudioEngine::default()
+        };
+        assert!(engine.dither());
+        engine.output_sample_format = Some(SampleFormat::I32);
+        assert!(!engine.dither());
+        engine.set_dither(true);
+        assert!(engine.dither());
+    }
+
+    #[test]
+    fn test_all_plugin_io_levels_in_chain_order() {
+        let mut engine = AudioEngine::default();
+        let first = engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        let second = engine.insert_plugin(mock_gain_plugin(4.0, false)).unwrap();
+        let bypassed = engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        engine.set_bypassed(bypassed, true).unwrap();
+
+        let left = vec![0.25; 64];
+        let right = vec![-0.5; 64];
+        engine.process_block(&[&left, &right]).unwrap();
+
+        assert_eq!(
+            engine.all_plugin_io_levels(),
+            vec![
+                (first, 0.5, 0.25),
+                (second, 0.25, 1.0),
+                (bypassed, 0.0, 0.0)
+            ]
+        );
+
+        // Reading resets the peaks, the next block's are picked up on their own
+        assert_eq!(engine.plugin_io_levels(first), Some((0.0, 0.0)));
+        let quiet = vec![0.125; 64];
+        engine.process_block(&[&quiet]).unwrap();
+        assert_eq!(engine.plugin_io_levels(second), Some((0.0625, 0.25)));
+        assert_eq!(engine.plugin_io_levels(PluginId(999)), None);
+    }
+
+    #[test]
+    fn test_loudness_match_gain_from_rms() {
+        use crate::dsp::{loudness_match_gain, LOUDNESS_MATCH_RANGE_DB};
+
+        assert_eq!(loudness_match_gain(0.5, 0.25), 2.0);
+        assert_eq!(loudness_match_gain(0.1, 0.4), 0.25);
+        assert_eq!(loudness_match_gain(0.3, 0.3), 1.0);
+
+        // Silence on either side leaves the level alone
+        assert_eq!(loudness_match_gain(0.0, 0.5), 1.0);
+        assert_eq!(loudness_match_gain(0.5, 0.0), 1.0);
+
+        let limit = 10f32.powf(LOUDNESS_MATCH_RANGE_DB / 20.0);
+        assert_eq!(loudness_match_gain(1.0, 0.001), limit);
+        assert_eq!(loudness_match_gain(0.001, 1.0), 1.0 / limit);
+
+        // A steady 6 dB boost is measured back to unity over the window
+        let matcher = LoudnessMatch::default();
+        matcher.set_enabled(true);
+        let (start, end) = matcher.next_block(0.25, 1.0, 64, 48000.0);
+        assert_eq!((start, end), (0.5, 0.5));
+        let (start, _) = matcher.next_block(0.25, 1.0, 64, 48000.0);
+        assert_eq!(start, 0.5);
+    }
+
+    #[test]
+    fn test_plugin_mix_blends_input_into_output() {
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        assert_eq!(engine.plugin_mix(id), Some(1.0));
+
+        let input = vec![0.4; 64];
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[0], vec![0.2; 64]);
+
+        // Ramps to the new mix over one block, then holds
+        engine.set_plugin_mix(id, 0.5).unwrap();
+        assert_eq!(engine.plugin_mix(id), Some(0.5));
+        engine.process_block(&[&input, &input]).unwrap();
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert!(output[0].iter().all(|s| (s - 0.3).abs() < 1e-6));
+
+        engine.set_plugin_mix(id, 0.0).unwrap();
+        engine.process_block(&[&input, &input]).unwrap();
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert!(output[1].iter().all(|s| (s - 0.4).abs() < 1e-6));
+
+        assert!(engine.set_plugin_mix(id, 1.5).is_err());
+        assert!(engine.set_plugin_mix(id, f32::NAN).is_err());
+        assert!(engine.set_plugin_mix(PluginId(0), 0.5).is_err());
+        assert_eq!(engine.plugin_mix(PluginId(0)), None);
+    }
+
+    #[test]
+    fn test_bypass_loudness_match_levels_plugin_output() {
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(mock_gain_plugin(2.0, false)).unwrap();
+        assert_eq!(engine.bypass_loudness_match(id), Some(false));
+
+        let input = vec![0.25; 64];
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        assert_eq!(output[0], vec![0.5; 64]);
+
+        engine.set_bypass_loudness_match(id, true).unwrap();
+        assert_eq!(engine.bypass_loudness_match(id), Some(true));
+        for _ in 0..4 {
+            let output = engine.process_block(&[&input, &input]).unwrap();
+            assert!(output[0].iter().all(|s| (s - 0.25).abs() < 1e-6));
+        }
+
+        assert!(engine.set_bypass_loudness_match(PluginId(0), true).is_err());
+        assert_eq!(engine.bypass_loudness_match(PluginId(0)), None);
+    }
+
+    #[test]
+    fn test_input_gain_validation() {
+        let mut engine = AudioEngine::default();
+        assert_eq!(engine.input_gain(), 1.0);
+
+        engine.set_input_gain(2.5).unwrap();
+        assert_eq!(engine.input_gain(), 2.5);
+
+        assert!(engine.set_input_gain(-1.0).is_err());
+        assert!(engine.set_input_gain(f32::NAN).is_err());
+        assert!(engine.set_input_gain(f32::INFINITY).is_err());
+        assert_eq!(engine.input_gain(), 2.5);
+    }
+
+    #[test]
+    fn test_latency_change_detection() {
+        let mut tracker = LatencyTracker::default();
+        let plugin = PluginId::new();
+
+        // The first poll of a plugin always counts as a change
+        assert!(tracker.update(plugin, 64));
+        assert!(!tracker.update(plugin, 64));
+        assert!(!tracker.update(plugin, 64));
+
+        assert!(tracker.update(plugin, 128));
+        assert_eq!(tracker.latency(plugin), Some(128));
+        assert!(!tracker.update(plugin, 128));
+
+        tracker.remove(plugin);
+        assert_eq!(tracker.latency(plugin), None);
+        assert!(tracker.update(plugin, 128));
+    }
+
+    #[test]
+    fn test_refresh_latencies_only_rebuilds_on_change() {
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        assert_eq!(engine.plugin_latency(id), Some(0));
+
+        assert!(!engine.refresh_latencies());
+
+        // Pretend the last poll saw a different value
+        engine.latency_tracker.update(id, 32);
+        assert!(engine.refresh_latencies());
+        assert_eq!(engine.plugin_latency(id), Some(0));
+        assert!(!engine.refresh_latencies());
+    }
+
+    #[test]
+    fn test_parallel_chains_are_latency_compensated() {
+        let mut engine = AudioEngine::default();
+        let slow = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        let chain = engine.add_chain().unwrap();
+        engine.latency_tracker.update(slow, 3);
+        engine.rebuild_latency_compensation();
+
+        // Only the empty chain is delayed, by the main chain's latency
+        assert_eq!(engine.chain_latency(), 3);
+        {
+            let delays = engine.chain_delays.lock().unwrap();
+            assert_eq!(delays.len(), 1);
+            assert_eq!(delays[&chain].delay(), 3);
+        }
+
+        engine.set_bypassed(slow, true).unwrap();
+        assert_eq!(engine.chain_latency(), 0);
+        assert!(engine.chain_delays.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_active_stream_config() {
+        let engine = AudioEngine::default();
+        assert!(engine.active_input_config().is_none());
+        assert!(engine.active_output_config().is_none());
+
+        let mut config = StreamConfig {
+            channels: 2,
+            sample_rate: SampleRate(44100),
+            buffer_size: cpal::BufferSize::Fixed(256),
+        };
+        let active = ActiveStreamConfig::new(&config, Some(SampleFormat::F32));
+        assert_eq!(active.sample_format, SampleFormat::I32);
+        assert_eq!(active.buffer_size(), Some(256));
+        let active = ActiveStreamConfig::new(&config, Some(SampleFormat::I24));
+        assert_eq!(active.sample_format, SampleFormat::I24);
+
+        config.buffer_size = cpal::BufferSize::Default;
+        assert_eq!(ActiveStreamConfig::new(&config, None).buffer_size(), None);
+    }
+
+    #[test]
+    fn test_channel_swap_and_mono_sum() {
+        let mut engine = AudioEngine::default();
+        let left = vec![0.5; 64];
+        let right = vec![-0.25; 64];
+
+        engine.set_channel_swap(true);
+        let output = engine.process_block(&[&left, &right]).unwrap();
+        assert_eq!(output, vec![right.clone(), left.clone()]);
+
+        // Summing happens after the swap and at half level
+        engine.set_mono_sum(true);
+        let output = engine.process_block(&[&left, &right]).unwrap();
+        assert_eq!(output, vec![vec![0.125; 64], vec![0.125; 64]]);
+
+        engine.set_channel_swap(false);
+        engine.set_mono_sum(false);
+        let output = engine.process_block(&[&left, &right]).unwrap();
+        assert_eq!(output, vec![left, right]);
+    }
+
+    #[test]
+    fn test_exclusive_mode_request() {
+        let mut engine = AudioEngine::default();
+        assert!(!engine.exclusive_mode());
+        assert!(!engine.exclusive_mode_granted());
+
+        for host in cpal::available_hosts() {
+            if engine.select_host(host.name()).is_err() {
+                continue;
+            }
+
+            // Only a host that's always exclusive takes the request, the rest refuse it
+            // and stay as they were
+            let supported = cfg!(target_os = "windows") && host.name() == "ASIO";
+            assert_eq!(is_exclusive_host(host), supported);
+            assert_eq!(engine.set_exclusive_mode(true).is_ok(), supported);
+            assert_eq!(engine.exclusive_mode(), supported);
+            assert!(!engine.exclusive_mode_granted());
+
+            engine.set_exclusive_mode(false).unwrap();
+            assert!(!engine.exclusive_mode());
         }
+    }
+
+    #[test]
+    fn test_pop_frames_keeps_ring_frames_apart() {
+        let mut quantizer = Quantizer::new(32);
+        let ring = |samples: &[f32]| {
+            let (mut producer, consumer) = HeapRb::<f32>::new(64).split();
+            producer.push_slice(samples);
+            consumer
+        };
+        let full_scale = |s: f32| (s * i32::MAX as f32) as i32;
+
+        let quantized = |s: f32| Quantizer::new(32).quantize(s, false);
+
+        // A mono chain on stereo outputs takes one ring sample per frame
+        let mut consumer = ring(&[0.5, 0.25]);
+        let mut data = [0; 4];
+        let identity = RoutingMatrix::identity();
+        let underrun = pop_frames(
+            &mut consumer,
+            &mut data,
+            1,
+            2,
+            Some(&identity),
+            &mut quantizer,
+            false,
+        );
+        assert!(!underrun);
+        assert_eq!(data, [quantized(0.5), 0, quantized(0.25), 0]);
+        assert!(consumer.is_empty());
+
+        // A stereo chain on four outputs leaves the extra channels silent
+        let mut consumer = ring(&[0.5, -0.5, 0.25, -0.25]);
+        let mut data = [1; 8];
+        pop_frames(&mut consumer, &mut data, 2, 4, None, &mut quantizer, false);
+        assert_eq!(
+            data,
+            [
+                full_scale(0.5),
+                full_scale(-0.5),
+                0,
+                0,
+                full_scale(0.25),
+                full_scale(-0.25),
+                0,
+                0
+            ]
+        );
+
+        // Running dry is reported and zero-filled
+        let mut consumer = ring(&[0.5, 0.5]);
+        let mut data = [1; 4];
+        assert!(pop_frames(
+            &mut consumer,
+            &mut data,
+            2,
+            2,
+            Some(&identity),
+            &mut quantizer,
+            false
+        ));
+        assert_eq!(&data[2..], [0, 0]);
+    }
+
+    #[test]
+    fn test_downmix_presets() {
+        let minus_3db = std::f32::consts::FRAC_1_SQRT_2;
+        let route = |preset: Downmix, input: &[f32], channels: usize| {
+            let mut output = vec![0.0; channels];
+            preset.matrix().route(input, &mut output);
+            output
+        };
 
-        #[cfg(target_os = "macos")]
-        if self.host.id() == cpal::HostId::CoreAudio {
-            self.output_device = self.input_device.clone();
-            self.output_config = self.input_config.clone();
+        assert_eq!(route(Downmix::Identity, &[0.25, -0.5], 2), [0.25, -0.5]);
+        let surround = route(Downmix::StereoTo51, &[0.4, 0.2], 6);
+        let expected = [0.4, 0.2, 0.3, 0.0, 0.4 * minus_3db, 0.2 * minus_3db];
+        for (sample, expected) in surround.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-6);
         }
 
-        self.update_current_settings();
-        self.update_process_data();
-        info!("Selected input device: {}", device_name);
-        Ok(())
+        // L, R, C, LFE, Ls, Rs
+        let frame = [0.5, 0.25, 0.2, 1.0, 0.1, -0.1];
+        let stereo = route(Downmix::FiveOneToStereo, &frame, 2);
+        assert!((stereo[0] - (0.5 + minus_3db * 0.2 + minus_3db * 0.1)).abs() < 1e-6);
+        assert!((stereo[1] - (0.25 + minus_3db * 0.2 - minus_3db * 0.1)).abs() < 1e-6);
+
+        let mut engine = AudioEngine::default();
+        engine.set_downmix_preset("5_1_to_stereo".parse().unwrap());
+        assert_eq!(engine.downmix_preset(), Downmix::FiveOneToStereo);
+        assert_eq!(
+            *engine.output_routing.lock().unwrap(),
+            Downmix::FiveOneToStereo.matrix()
+        );
+        assert!("quad".parse::<Downmix>().is_err());
     }
 
-    /// Select a specific output device
-    pub fn select_output(&mut self, device_name: &str) -> Result<()> {
-        self.stop_streams();
-
-        // Reset devices to None first
-        if self.host.id() == HostId::Asio {
-            self.input_device = None;
-            self.output_device = None;
-            self.input_config = None;
-            self.output_config = None;
+    #[test]
+    fn test_i24_round_trip() {
+        assert_eq!(sample_bits(SampleFormat::I24), 24);
 
-            // Store current host ID to recreate it
-            let current_host_id = self.host.id();
+        for sample in [-(1 << 23), (1 << 23) - 1, 0, 1, -1, 0x12_3456, -0x65_4321] {
+            let stream = i24_to_stream(I24::new(sample).unwrap());
+            assert_eq!(stream, sample << 8);
+            assert_eq!(stream_to_i24(stream).inner(), sample);
 
-            // Recreate the host to refresh its internal state
-            self.host = cpal::host_from_id(current_host_id)?;
+            // Through float and back, as the input and loopback conversions do
+            let float = stream as f32 / i32::MAX as f32;
+            assert_eq!(stream_to_i24(loopback_sample(float)).inner(), sample);
         }
 
-        trace!(
-            "Reset host and devices, now selecting output device: {}",
-            device_name
-        );
-
-        let device = self
-            .host
-            .output_devices()?
-            .find(|d| d.name().map_or(false, |name| name == device_name))
-            .ok_or_else(|| anyhow!("Output device '{}' not found", device_name))?;
+        // Full scale reads as full scale rather than 1/256 of it
+        let max = i24_to_stream(I24::new((1 << 23) - 1).unwrap()) as f32 / i32::MAX as f32;
+        assert!((max - 1.0).abs() < 1e-6);
+        let min = i24_to_stream(I24::new(-(1 << 23)).unwrap()) as f32 / i32::MAX as f32;
+        assert!((min + 1.0).abs() < 1e-6);
 
-        trace!(
-            "Supported output configs: {:?}",
-            device
-                .supported_output_configs()
-                .map(|m| m.collect::<Vec<_>>())
-                .unwrap_or_default()
-        );
+        // A driver that pads instead of sign extending
+        let padded = I24::new_unchecked(0x00FF_FFFF);
+        assert_eq!(i24_to_stream(padded), -1 << 8);
+    }
 
-        self.output_config = Some(
-            pick_best_format(
-                device.supported_output_configs().unwrap(),
-                Some(48000),             // No preferred sample rate
-                Some(256),               // No preferred buffer size
-                Some(SampleFormat::I32), // No preferred sample format
-                Some(2),                 // No preferred channels
-            )
-            .ok_or_else(|| {
-                anyhow!(
-                    "No supported input configurations for device '{}'",
-                    device_name
-                )
+    #[test]
+    fn test_loopback_test_mode_is_bit_exact() {
+        let mut engine = AudioEngine::default();
+        engine.insert_plugin(mock_gain_plugin(0.5, false)).unwrap();
+        engine.set_input_gain(2.0).unwrap();
+        engine.set_monitor_mix(0.5).unwrap();
+        engine.set_channel_swap(true);
+        engine.set_output_limiter(true, -6.0).unwrap();
+        engine.set_loopback_test_mode(true);
+
+        // 24 bit device samples in the i32 stream format, including both rails
+        let device: Vec<Vec<i32>> = [
+            [-(1 << 23), (1 << 23) - 1, 0, 1, -1, 0x12_3456, -0x65_4321],
+            [(1 << 23) - 1, -(1 << 23), 7, -7, 0x40_0000, -0x40_0000, 0],
+        ]
+        .iter()
+        .map(|channel| channel.iter().map(|s| s << 8).collect())
+        .collect();
+        let input: Vec<Vec<f32>> = device
+            .iter()
+            .map(|channel| {
+                channel
+                    .iter()
+                    .map(|&s| s as f32 / i32::MAX as f32)
+                    .collect()
             })
-            .unwrap()
-            .into(),
-        );
-        //device.default_output_config().ok().map(|c| c.into());
-        self.output_device = Some(device);
+            .collect();
 
-        // Handle ASIO/CoreAudio device exclusivity
-        #[cfg(target_os = "windows")]
-        if self.host.id() == cpal::HostId::Asio {
-            self.input_device = self.output_device.clone();
-            self.input_config = self.output_config.clone();
-        }
+        let output = engine.process_block(&[&input[0], &input[1]]).unwrap();
+        assert_eq!(output, input);
 
-        #[cfg(target_os = "macos")]
-        if self.host.id() == cpal::HostId::CoreAudio {
-            self.input_device = self.output_device.clone();
-            self.input_config = self.output_config.clone();
+        let round_trip: Vec<Vec<i32>> = output
+            .iter()
+            .map(|channel| channel.iter().map(|&s| loopback_sample(s)).collect())
+            .collect();
+        assert_eq!(round_trip, device);
+
+        // Leaving loopback runs the chain again
+        engine.set_loopback_test_mode(false);
+        let output = engine.process_block(&[&input[0], &input[1]]).unwrap();
+        assert_ne!(output, input);
+    }
+
+    #[test]
+    fn test_channel_utility_toggle_is_smoothed() {
+        let mut engine = AudioEngine::default();
+        let mut renderer = engine.chain_renderer();
+        for i in 0..64 {
+            renderer.output_data.write(0, i, 1.0);
+            renderer.output_data.write(1, i, 0.0);
         }
 
-        self.update_current_settings();
-        self.update_process_data();
-        info!("Selected output device: {}", device_name);
-        Ok(())
+        engine.set_channel_swap(true);
+        renderer.apply_channel_utilities(64, 2);
+        let output = renderer.output_data.as_mut_ref();
+        assert!(output[0][0] > 0.9 && output[1][0] < 0.1);
+        assert!(output[0][..64].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(output[1][..64].windows(2).all(|pair| pair[1] > pair[0]));
     }
 
-    /// Set the sample rate
-    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<()> {
-        self.current_sample_rate = sample_rate;
+    #[test]
+    fn test_output_levels_are_peaks_since_last_read() {
+        let mut engine = AudioEngine::default();
+        assert!(!engine.is_running());
+        assert_eq!(engine.output_levels(), [0.0, 0.0]);
+
+        let mut left = vec![0.1; 64];
+        left[10] = -0.75;
+        let right = vec![0.25; 64];
+        engine.process_block(&[&left, &right]).unwrap();
+        engine.process_block(&[&[0.5; 64], &[0.0; 64]]).unwrap();
+
+        assert_eq!(engine.output_levels(), [0.75, 0.25]);
+        assert_eq!(engine.output_levels(), [0.0, 0.0]);
+    }
 
-        // Update configs if devices are available
-        if let Some(ref mut config) = self.input_config {
-            config.sample_rate = cpal::SampleRate(sample_rate);
+    #[test]
+    fn test_meters_follow_the_engine() {
+        let mut engine = AudioEngine::default();
+        let meters = engine.meters();
+        assert!(!meters.is_running());
+        assert!(meters.active_output_config().is_none());
+
+        engine.process_block(&[&[0.5; 64], &[-0.25; 64]]).unwrap();
+        assert_eq!(meters.output_levels(), [0.5, 0.25]);
+        assert_eq!(engine.output_levels(), [0.0, 0.0]);
+        assert_eq!(meters.output_correlation(), engine.output_correlation());
+        assert_eq!(meters.headroom(), engine.headroom());
+
+        engine.overflow_count.fetch_add(2, Ordering::Relaxed);
+        assert_eq!(meters.xrun_count(), 2);
+
+        // Latency is published whenever the compensation is rebuilt
+        let plugin_id = engine.insert_plugin(mock_gain_plugin(1.0, false)).unwrap();
+        engine.latency_tracker.update(plugin_id, 3);
+        engine.rebuild_latency_compensation();
+        assert_eq!(meters.chain_latency(), engine.chain_latency());
+        assert_eq!(meters.chain_latency(), 3);
+
+        // The budget comes with the stream status, stopped streams clear it
+        engine.publish_stream_status();
+        assert_eq!(meters.chain_cpu(), engine.chain_cpu());
+        engine.stop_streams();
+        assert!(!meters.is_running());
+        assert!(!meters.is_overloaded());
+    }
+
+    #[test]
+    fn test_xrun_counters_follow_the_engine() {
+        let engine = AudioEngine::default();
+        let counters = engine.xrun_counters();
+        assert_eq!(counters.count(), 0);
+
+        engine.overflow_count.fetch_add(2, Ordering::Relaxed);
+        engine.underrun_count.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(counters.count(), 3);
+        assert_eq!(counters.count(), engine.xrun_count());
+
+        // Restarting the streams resets the same counters
+        engine.overflow_count.store(0, Ordering::Relaxed);
+        engine.underrun_count.store(0, Ordering::Relaxed);
+        assert_eq!(counters.count(), 0);
+    }
+
+    #[test]
+    fn test_sustained_overload_is_detected() {
+        let engine = AudioEngine::default();
+        assert_eq!(engine.headroom(), 100.0);
+        assert!(!engine.is_overloaded());
+
+        let budget = Duration::from_millis(10);
+        // A single slow block is no sustained overload
+        engine
+            .callback_load
+            .record(Duration::from_millis(12), budget);
+        assert!(!engine.is_overloaded());
+
+        // Enough blocks for the moving averages to settle
+        for _ in 0..500 {
+            engine
+                .callback_load
+                .record(Duration::from_millis(9), budget);
         }
-        if let Some(ref mut config) = self.output_config {
-            config.sample_rate = cpal::SampleRate(sample_rate);
+        assert!(engine.is_overloaded());
+        assert!((engine.headroom() - 10.0).abs() < 0.5);
+
+        for _ in 0..500 {
+            engine
+                .callback_load
+                .record(Duration::from_millis(5), budget);
         }
+        assert!(!engine.is_overloaded());
+        assert!((engine.headroom() - 50.0).abs() < 0.5);
 
-        // Update ProcessData to reflect any changes
-        self.update_process_data();
+        engine.callback_load.reset();
+        assert_eq!(engine.headroom(), 100.0);
+    }
 
-        info!("Set sample rate to: {}", sample_rate);
-        Ok(())
+    #[test]
+    fn test_output_correlation() {
+        let mut engine = AudioEngine::default();
+        assert_eq!(engine.output_correlation(), 0.0);
+
+        let tone = (0..512)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5)
+            .collect::<Vec<_>>();
+        let inverted = tone.iter().map(|s| -s).collect::<Vec<_>>();
+        let quadrature = (0..512)
+            .map(|i| (i as f32 * 0.05).cos() * 0.5)
+            .collect::<Vec<_>>();
+
+        engine.process_block(&[&tone, &tone]).unwrap();
+        assert!((engine.output_correlation() - 1.0).abs() < 1e-4);
+
+        engine.process_block(&[&tone, &inverted]).unwrap();
+        assert!((engine.output_correlation() + 1.0).abs() < 1e-4);
+
+        engine.process_block(&[&tone]).unwrap();
+        assert!((engine.output_correlation() - 1.0).abs() < 1e-4);
+
+        // A quarter period apart, the products average out over a few cycles
+        engine.process_block(&[&tone, &quadrature]).unwrap();
+        assert!(engine.output_correlation().abs() < 0.2);
     }
 
-    /// Set the buffer size
-    pub fn set_buffer_size(&mut self, buffer_size: u32) -> Result<()> {
-        self.current_buffer_size = buffer_size;
+    #[test]
+    fn test_dry_wet_blend() {
+        assert_eq!(dry_wet(0.2, 1.0, 0.0), 0.2);
+        assert_eq!(dry_wet(0.2, 1.0, 1.0), 1.0);
+        assert_eq!(dry_wet(0.2, 1.0, 0.5), 0.6);
+        assert_eq!(dry_wet(-1.0, 1.0, 0.25), -0.5);
+    }
 
-        // Update configs if devices are available
-        if let Some(ref mut config) = self.input_config {
-            config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
-        }
-        if let Some(ref mut config) = self.output_config {
-            config.buffer_size = cpal::BufferSize::Fixed(buffer_size);
-        }
+    #[test]
+    fn test_monitor_mix_delays_dry_input_by_chain_latency() {
+        let mut engine = AudioEngine::default();
+        let muted = engine.insert_plugin(mock_gain_plugin(0.0, false)).unwrap();
+        engine.latency_tracker.update(muted, 3);
+        engine.rebuild_latency_compensation();
+        assert_eq!(engine.monitor_delay.lock().unwrap().delay(), 3);
+
+        assert!(engine.set_monitor_mix(1.5).is_err());
+        assert!(engine.set_monitor_mix(f32::NAN).is_err());
+        engine.set_monitor_mix(0.5).unwrap();
+        assert_eq!(engine.monitor_mix(), 0.5);
+
+        // The chain is silent, so only the half-level dry impulse comes out, 3 frames late
+        let mut input = vec![0.0; 64];
+        input[0] = 1.0;
+        let output = engine.process_block(&[&input, &input]).unwrap();
+        let mut expected = vec![0.0; 64];
+        expected[3] = 0.5;
+        assert_eq!(output[0], expected);
+        assert_eq!(output[1], expected);
+
+        engine.set_bypassed(muted, true).unwrap();
+        assert_eq!(engine.monitor_delay.lock().unwrap().delay(), 0);
+    }
 
-        // Update ProcessData to reflect the new buffer size
-        self.update_process_data();
+    #[test]
+    fn test_monitor_mix_is_smoothed_and_delay_fed_while_wet() {
+        let mut engine = AudioEngine::default();
+        let muted = engine.insert_plugin(mock_gain_plugin(0.0, false)).unwrap();
+        engine.latency_tracker.update(muted, 3);
+        engine.rebuild_latency_compensation();
+        let mut renderer = engine.chain_renderer();
+        let render = |renderer: &mut ChainRenderer, input: &[f32]| {
+            for (i, sample) in input.iter().enumerate() {
+                for j in 0..2 {
+                    renderer.input_data.write(j, i, *sample);
+                }
+            }
+            unsafe { renderer.render(64, 2) };
+            renderer.output_data.as_ref()[0][..64].to_vec()
+        };
 
-        info!("Set buffer size to: {}", buffer_size);
-        Ok(())
+        // Fully wet the muted chain is all that's heard, the impulse still enters the delay
+        let mut impulse = vec![0.0; 64];
+        impulse[63] = 1.0;
+        assert!(render(&mut renderer, &impulse).iter().all(|&s| s == 0.0));
+
+        // Going dry fades in, starting with the impulse lined up 3 frames later
+        engine.set_monitor_mix(0.0).unwrap();
+        let block = render(&mut renderer, &[0.0; 64]);
+        assert!(block[2] > 0.0 && block[2] < 0.01);
+        assert!(block.iter().enumerate().all(|(i, &s)| i == 2 || s == 0.0));
+        let block = render(&mut renderer, &[1.0; 64]);
+        assert_eq!(block[..3], [0.0; 3]);
+        assert!(block[3] < 0.25);
+        assert!(block[3..].windows(2).all(|w| w[1] > w[0]));
+
+        // The mix holds while the delay is locked for resizing
+        let delay = engine.monitor_delay.lock().unwrap();
+        let held = render(&mut renderer, &[1.0; 64]);
+        drop(delay);
+        assert!(held.iter().all(|&s| s == held[0]));
+        assert!(render(&mut renderer, &[1.0; 64])[63] > held[0]);
     }
 
-    /// Internal helper to stop audio streams
-    fn stop_streams(&mut self) {
-        if let Some(stream) = self.input_stream.take() {
-            let _ = stream.pause();
-        }
-        if let Some(stream) = self.output_stream.take() {
-            let _ = stream.pause();
-        }
+    #[test]
+    fn test_delay_line() {
+        let mut delay = DelayLine::new(2);
+        assert_eq!(delay.process([1.0, -1.0]), [0.0, 0.0]);
+        assert_eq!(delay.process([2.0, -2.0]), [0.0, 0.0]);
+        assert_eq!(delay.process([3.0, -3.0]), [1.0, -1.0]);
+        assert_eq!(delay.process([4.0, -4.0]), [2.0, -2.0]);
+
+        assert_eq!(DelayLine::new(0).process([5.0, 6.0]), [5.0, 6.0]);
     }
 
-    /// Internal helper to update current settings from configs
-    fn update_current_settings(&mut self) {
-        if let Some(ref config) = self.input_config {
-            self.current_sample_rate = config.sample_rate.0;
-            if let cpal::BufferSize::Fixed(size) = config.buffer_size {
-                self.current_buffer_size = size;
-            }
-        } else if let Some(ref config) = self.output_config {
-            self.current_sample_rate = config.sample_rate.0;
-            if let cpal::BufferSize::Fixed(size) = config.buffer_size {
-                self.current_buffer_size = size;
-            }
-        }
+    #[test]
+    fn test_sync_array_try_write_bounds() {
+        let mut array = Sync2DArray::<f32, 2, 4>::new(0.0, 4);
+
+        assert!(array.try_write(1, 3, 1.0));
+        assert!(!array.try_write(2, 0, 1.0));
+        assert!(!array.try_write(0, 4, 1.0));
+        assert_eq!(array.read(), &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
     }
 
-    /// Internal helper to update ProcessData with current audio settings
-    fn update_process_data(&mut self) {
-        // Since ProcessData is Arc<ProcessData>, we can't modify it directly.
-        // We need to create a new ProcessData and replace the Arc.
-        let new_process_data = Arc::new(ProcessData {
+    #[test]
+    fn test_dyn_channel_buffer_beyond_fixed_limits() {
+        let channels = 6;
+        let frames = MAX_BLOCK_SIZE * 2;
+        let mut input = DynChannelBuffer::new(0.0f32, channels, frames);
+        let mut output = DynChannelBuffer::new(0.0f32, channels, frames);
+        for channel in 0..channels {
+            assert!(input.try_write(channel, frames - 1, channel as f32 + 1.0));
+        }
+        assert!(!input.try_write(channels, 0, 1.0));
+        assert!(!input.try_write(0, frames, 1.0));
+
+        let mut in_bus = bus_buffers(&mut input);
+        let mut out_bus = bus_buffers(&mut output);
+        assert_eq!(in_bus.num_channels, channels as i32);
+
+        let processor = mock::processor(0.5);
+        let mut data = ProcessData {
             process_mode: ProcessMode::Realtime,
             symbolic_sample_size: SymbolicSampleSize::Sample32,
-            num_samples: self.current_buffer_size as i32,
+            num_samples: frames as i32,
             num_inputs: 1,
             num_outputs: 1,
-            inputs: self.in_bus.get(),
-            outputs: self.out_bus.get(),
-            input_parameter_changes: self.input_params.get() as *mut _,
+            inputs: &mut in_bus,
+            outputs: &mut out_bus,
+            input_parameter_changes: std::ptr::null_mut(),
             output_parameter_changes: std::ptr::null_mut(),
             input_events: std::ptr::null_mut(),
             output_events: std::ptr::null_mut(),
             process_context: std::ptr::null_mut(),
-        });
+        };
+        unsafe {
+            processor.process(&mut data);
+        }
 
-        self.process_data = new_process_data;
+        for (channel, samples) in output.as_ref().iter().enumerate() {
+            assert_eq!(samples[frames - 1], (channel as f32 + 1.0) * 0.5);
+        }
     }
 
-    /// Start audio processing
-    pub fn run(&mut self) -> Result<()> {
-        let Some(ref input_device) = self.input_device else {
-            return Err(anyhow!("No input device selected"));
-        };
-        let Some(ref output_device) = self.output_device else {
-            return Err(anyhow!("No output device selected"));
-        };
-        let Some(ref input_config) = self.input_config else {
-            return Err(anyhow!("No input config set"));
-        };
-        let Some(ref output_config) = self.output_config else {
-            return Err(anyhow!("No output config set"));
-        };
+    #[test]
+    fn test_auto_tune_backs_off_from_smallest_stable_size() {
+        let curve = [(64, 1.2), (128, 0.8), (256, 0.5), (512, 0.3), (1024, 0.2)];
+        assert_eq!(pick_stable_buffer_size(&curve, 0.7), Some(512));
+
+        // Only what was measured before the load crossed the ceiling
+        assert_eq!(pick_stable_buffer_size(&curve[1..], 0.7), Some(512));
+        assert_eq!(pick_stable_buffer_size(&curve, 0.9), Some(256));
+        assert_eq!(pick_stable_buffer_size(&curve, 2.0), Some(128));
+
+        // The walk stops at the first size over the ceiling
+        let bumpy = [(64, 0.1), (128, 0.9), (256, 0.4), (512, 0.2)];
+        assert_eq!(pick_stable_buffer_size(&bumpy, 0.7), Some(512));
+
+        assert_eq!(
+            pick_stable_buffer_size(&[(256, 0.9), (512, 0.8)], 0.7),
+            Some(512)
+        );
+        assert_eq!(pick_stable_buffer_size(&[(256, 0.1)], 0.7), Some(256));
+        assert_eq!(pick_stable_buffer_size(&[], 0.7), None);
+
+        let mut engine = AudioEngine::default();
+        engine.input_device = None;
+        engine.output_device = None;
+        let previous_size = engine.buffer_size();
+        let size = engine.auto_tune_buffer_size().unwrap();
+        assert!((MIN_BLOCK_SIZE as u32..=MAX_DEVICE_BLOCK_SIZE as u32).contains(&size));
+        assert_eq!(engine.buffer_size(), previous_size);
+        assert!(!engine.is_running());
+    }
 
-        let channels = input_config.channels as usize;
-        let plugin_modules = self.plugin_modules.clone();
-        let buffer_size = self.current_buffer_size as usize;
+    #[test]
+    fn test_buffer_size_above_max_block_is_rejected() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(256).unwrap();
+
+        let err = engine
+            .set_buffer_size(MAX_DEVICE_BLOCK_SIZE as u32 * 2)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EngineError>(),
+            Some(&EngineError::BlockTooLarge {
+                frames: MAX_DEVICE_BLOCK_SIZE * 2,
+                max: MAX_DEVICE_BLOCK_SIZE,
+            })
+        );
+        assert_eq!(engine.buffer_size(), 256);
+        // Larger than the chain's blocks, the callbacks split it
+        assert!(engine.set_buffer_size(MAX_BLOCK_SIZE as u32 * 2).is_ok());
+        assert!(engine.set_buffer_size(MAX_DEVICE_BLOCK_SIZE as u32).is_ok());
+    }
 
-        let ring = HeapRb::<f32>::new(buffer_size * channels * 8);
-        let (mut producer, mut consumer) = ring.split();
+    #[test]
+    fn test_degenerate_buffer_size_is_rejected() {
+        let mut engine = AudioEngine::default();
+        engine.set_buffer_size(256).unwrap();
+
+        for frames in [0, MIN_BLOCK_SIZE - 1] {
+            let err = engine.set_buffer_size(frames as u32).unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<EngineError>(),
+                Some(&EngineError::BlockTooSmall {
+                    frames,
+                    min: MIN_BLOCK_SIZE,
+                })
+            );
+        }
+        assert_eq!(engine.buffer_size(), 256);
+        assert!(engine.set_buffer_size(MIN_BLOCK_SIZE as u32).is_ok());
+
+        // A zero size picked up from a device config doesn't get as far as the streams
+        engine.current_buffer_size = 0;
+        let err = engine.run().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EngineError>(),
+            Some(EngineError::BlockTooSmall { frames: 0, .. })
+        ));
+    }
 
-        let params = SincInterpolationParameters {
-            sinc_len: 256,
-            f_cutoff: 0.95,
-            interpolation: SincInterpolationType::Linear,
-            oversampling_factor: 256,
-            window: WindowFunction::BlackmanHarris2,
-        };
+    #[test]
+    fn test_highpass_coefficients_at_quarter_sample_rate() {
+        // At fs / 4, cos(w0) = 0 and sin(w0) = 1, so the cookbook terms reduce to constants
+        let c = BiquadCoefficients::highpass(12000.0, 48000.0);
+        let a0 = 1.0 + std::f32::consts::FRAC_1_SQRT_2;
+
+        assert!((c.b0 - 0.5 / a0).abs() < 1e-6);
+        assert!((c.b1 + 1.0 / a0).abs() < 1e-6);
+        assert!((c.b2 - 0.5 / a0).abs() < 1e-6);
+        assert!(c.a1.abs() < 1e-6);
+        assert!((c.a2 - (1.0 - std::f32::consts::FRAC_1_SQRT_2) / a0).abs() < 1e-6);
+    }
 
-        let mut resampler = SincFixedIn::<f32>::new(
-            output_config.sample_rate.0 as f64 / input_config.sample_rate.0 as f64,
-            2.0,
-            params,
-            buffer_size,
-            channels,
-        )?;
+    #[test]
+    fn test_highpass_removes_dc() {
+        let c = BiquadCoefficients::highpass(20.0, 48000.0);
+        let mut state = BiquadState::default();
 
-        let process_data = self.process_data.clone();
-        let mut input_data = self.input_data.clone();
-        let output_data = self.output_data.clone();
-        let mut resampled_data = self.resampled_data.clone();
+        let mut output = 0.0;
+        for _ in 0..48000 {
+            output = state.process(&c, 1.0);
+        }
+        assert!(output.abs() < 1e-3);
+    }
 
-        info!("Creating input stream with config: {:?}", input_config);
+    #[test]
+    fn test_denormal_protection() {
+        let engine = AudioEngine::default();
+        assert!(engine.denormal_protection());
+
+        let mut samples = [f32::MIN_POSITIVE / 2.0, f32::MIN_POSITIVE, -1e-40, 0.5];
+        dsp::flush_denormals(&mut samples);
+        assert_eq!(samples, [0.0, f32::MIN_POSITIVE, 0.0, 0.5]);
+
+        let halve = |x: f32| std::hint::black_box(x) / 2.0;
+        if HARDWARE_FTZ {
+            let guard = DenormalGuard::new();
+            assert_eq!(halve(f32::MIN_POSITIVE), 0.0);
+            drop(guard);
+        }
+        assert!(halve(f32::MIN_POSITIVE).is_subnormal());
+    }
 
-        let input_format = input_device.default_input_config().unwrap();
-        info!("Input format: {:?}", input_format);
+    #[test]
+    fn test_device_without_configs_is_an_error() {
+        let err = select_device_config(
+            "Empty Interface",
+            "input",
+            Ok(std::iter::empty()),
+            FormatStrategy::BestQuality,
+            (PREFERRED_SAMPLE_RATE, PREFERRED_BUFFER_SIZE),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Empty Interface"));
+
+        let err = select_device_config(
+            "Unplugged Interface",
+            "output",
+            Err::<std::iter::Empty<_>, _>(cpal::SupportedStreamConfigsError::DeviceNotAvailable),
+            FormatStrategy::BestQuality,
+            (PREFERRED_SAMPLE_RATE, PREFERRED_BUFFER_SIZE),
+        )
+        .unwrap_err();
+        assert!(format!("{:#}", err).contains("Unplugged Interface"));
+
+        let config = select_device_config(
+            "Interface",
+            "input",
+            Ok(vec![make_range(SampleFormat::I32)].into_iter()),
+            FormatStrategy::BestQuality,
+            (PREFERRED_SAMPLE_RATE, PREFERRED_BUFFER_SIZE),
+        )
+        .unwrap();
+        assert_eq!(config.sample_format(), SampleFormat::I32);
 
-        let input_stream = input_device.build_input_stream(
-            input_config,
-            move |data: &[i32], _: &cpal::InputCallbackInfo| {
-                let block_size = data.len() / channels;
+        let mut engine = AudioEngine::default();
+        assert!(engine.select_input("No Such Interface").is_err());
+        assert!(engine.select_output("No Such Interface").is_err());
+    }
 
-                // Copy input audio data to the input buffer
-                for (i, frame) in data.chunks(channels).enumerate() {
-                    for j in 0..channels {
-                        input_data.write(j, i, frame[j] as f32 / i32::MAX as f32);
-                    }
-                }
+    #[test]
+    fn test_distinct_sample_formats_drops_repeats() {
+        let configs = vec![
+            make_range(SampleFormat::I16),
+            make_range_with_config(
+                SampleFormat::F32,
+                8000,
+                192000,
+                SupportedBufferSize::Unknown,
+            ),
+            make_range(SampleFormat::I16),
+            make_range(SampleFormat::I32),
+            make_range_with_config(
+                SampleFormat::F32,
+                44100,
+                48000,
+                SupportedBufferSize::Unknown,
+            ),
+        ];
 
-                unsafe {
-                    if let Ok(plugins) = plugin_modules.try_read() {
-                        // Process plugins in a chain - each plugin's output becomes the next plugin's input
-                        for (index, (_plugin_id, plugin)) in plugins.iter().enumerate() {
-                            let data = process_data.clone();
-
-                            // For the first plugin, input comes from the audio input
-                            // For subsequent plugins, we need to copy the previous plugin's output to current input
-                            if index > 0 {
-                                // Copy output_data to input_data for chaining
-                                for i in 0..block_size {
-                                    for j in 0..channels {
-                                        let sample = (*output_data.data.get())[j][i];
-                                        input_data.write(j, i, sample);
-                                    }
-                                }
-                            }
+        assert_eq!(
+            distinct_sample_formats(&configs),
+            vec![SampleFormat::I16, SampleFormat::F32, SampleFormat::I32]
+        );
+        assert!(distinct_sample_formats(&[]).is_empty());
+    }
 
-                            // Clear the output buffer before processing
-                            for i in 0..block_size {
-                                for j in 0..channels {
-                                    (*output_data.data.get())[j][i] = 0.0;
-                                }
-                            }
+    #[test]
+    fn test_stream_formats_follow_selected_configs() {
+        let mut engine = AudioEngine::default();
 
-                            // Process the plugin
-                            plugin
-                                .processor
-                                .as_ref()
-                                .unwrap()
-                                .process(Arc::into_raw(data) as *mut _);
-                        }
-                    }
-                }
+        let input = pick_best_format(
+            vec![make_range(SampleFormat::I16), make_range(SampleFormat::F32)].into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        )
+        .unwrap();
+        let output = pick_best_format(
+            vec![make_range(SampleFormat::I32), make_range(SampleFormat::I16)].into_iter(),
+            None,
+            None,
+            None,
+            None,
+            FormatStrategy::BestQuality,
+        )
+        .unwrap();
 
-                let _ = resampler.process_partial_into_buffer(
-                    Some(output_data.as_ref()),
-                    resampled_data.as_mut_ref(),
-                    None,
-                );
+        engine.apply_input_config(input);
+        engine.apply_output_config(output);
 
-                for i in 0..block_size {
-                    resampled_data.as_ref().iter().for_each(|v| {
-                        let Some(sample) = v.get(i) else {
-                            return;
-                        };
+        assert_eq!(
+            engine.stream_formats(),
+            Some((SampleFormat::F32, SampleFormat::I32))
+        );
+    }
 
-                        let _ = producer.try_push(*sample);
-                    });
-                }
-            },
-            |err| {
-                error!("Input stream error: {:?}", err);
-            },
-            None,
-        )?;
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    fn test_audio_thread_affinity_is_applied_on_callback_thread() {
+        let mut engine = AudioEngine::default();
+        assert_eq!(engine.audio_thread_affinity(), None);
+        assert!(engine
+            .set_audio_thread_affinity(Some(usize::MAX - 1))
+            .is_err());
+
+        // Nothing requested, nothing pinned
+        let mut affinity = engine.affinity_pinner();
+        assert!(!affinity.apply());
+
+        engine.set_audio_thread_affinity(Some(0)).unwrap();
+        assert_eq!(engine.audio_thread_affinity(), Some(0));
+
+        // Pinned once, then left alone until the request changes
+        let callback = std::thread::spawn(move || (affinity.apply(), affinity.apply()));
+        assert_eq!(callback.join().unwrap(), (true, false));
+
+        engine.set_audio_thread_affinity(None).unwrap();
+        assert_eq!(engine.audio_thread_affinity(), None);
+    }
 
-        let output_stream = output_device.build_output_stream(
-            output_config,
-            move |data: &mut [i32], _: &cpal::OutputCallbackInfo| {
-                for sample in data {
-                    *sample = match consumer.try_pop() {
-                        Some(s) => {
-                            let scaled = s * i32::MAX as f32;
-                            scaled.round().clamp(i32::MIN as f32, i32::MAX as f32) as i32
-                        }
-                        None => 0i32,
-                    };
-                }
-            },
-            |err| {
-                error!("Output stream error: {:?}", err);
-            },
-            None,
-        )?;
+    #[test]
+    fn test_device_lost_flag_is_reported_once() {
+        let mut engine = AudioEngine::default();
+        engine.input_device.take();
+        engine.output_device.take();
+        assert!(!device_missing(None, &[]));
+        let scan = DeviceScan::scan(engine.host());
+        assert!(!engine.check_devices(&scan));
+
+        // A stream error flags the loss until the next check
+        engine.device_lost.store(true, Ordering::Relaxed);
+        assert!(engine.check_devices(&scan));
+        assert!(!engine.check_devices(&scan));
+
+        // Lists of a host the engine no longer uses are ignored
+        let Some(&other_host) = cpal::ALL_HOSTS.iter().find(|id| **id != scan.host) else {
+            return;
+        };
+        let stale = DeviceScan {
+            host: other_host,
+            inputs: Some(Vec::new()),
+            outputs: Some(Vec::new()),
+            ..scan
+        };
+        assert!(!engine.check_devices(&stale));
+        assert!(engine.recover_default_devices(&stale).is_err());
+    }
 
-        input_stream.play()?;
-        output_stream.play()?;
+    #[test]
+    fn test_stream_fade_ramps_in_and_out() {
+        let fading_out = Arc::new(AtomicBool::new(false));
+        let silent = Arc::new(AtomicBool::new(false));
+        let mut fade = StreamFade::new(fading_out.clone(), silent.clone(), 48000.0);
+        let mut output = Sync2DArray::<f32, 2, MAX_BLOCK_SIZE>::new(1.0, MAX_BLOCK_SIZE);
+
+        // 5 ms at 48 kHz is 240 samples
+        fade.apply(output.as_mut_ref(), 480);
+        let left = &output.as_ref()[0];
+        assert!(left[0] > 0.0 && left[0] < 0.01);
+        assert!(left[..240].windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(left[240..480].iter().all(|s| *s == 1.0));
+
+        fading_out.store(true, Ordering::Relaxed);
+        fade.apply(output.as_mut_ref(), 128);
+        assert!(!silent.load(Ordering::Relaxed));
+        fade.apply(output.as_mut_ref(), 128);
+        assert!(silent.load(Ordering::Relaxed));
+        assert_eq!(output.as_ref()[1][127], 0.0);
+    }
 
-        self.input_stream = Some(input_stream);
-        self.output_stream = Some(output_stream);
+    #[test]
+    #[ignore = "needs a working audio device"]
+    fn test_run_again_replaces_streams() {
+        let mut engine = AudioEngine::default();
+        engine.run().unwrap();
+
+        // Every running callback holds a renderer sharing the engine's state
+        let running = Arc::strong_count(&engine.processing_enabled);
+        engine.run().unwrap();
+        assert_eq!(Arc::strong_count(&engine.processing_enabled), running);
+
+        engine.stop_streams();
+        assert!(engine.active_output_config().is_none());
+        assert_eq!(Arc::strong_count(&engine.processing_enabled), 1);
+    }
 
-        info!("Audio streams started successfully");
-        Ok(())
+    #[test]
+    fn test_failed_run_keeps_the_running_streams() {
+        let mut engine = AudioEngine::default();
+        // Stands in for running streams, stopping them clears it
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(48000),
+            buffer_size: cpal::BufferSize::Fixed(256),
+        };
+        engine.active_output_config = Some(ActiveStreamConfig::new(&config, None));
+
+        engine.current_buffer_size = MAX_DEVICE_BLOCK_SIZE as u32 * 2;
+        assert!(engine.run().is_err());
+        assert!(engine.active_output_config().is_some());
+
+        engine.current_buffer_size = 256;
+        engine.output_device.take();
+        let err = engine.run().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EngineError>(),
+            Some(&EngineError::NoOutputDevice)
+        );
+        assert!(engine.active_output_config().is_some());
     }
 
-    /// Add a VST plugin to the processing chain
-    pub fn load_plugin(&mut self, path: &str) -> Result<PluginId> {
-        info!("Loading plugin: {:?}", path);
+    #[test]
+    fn test_run_without_output_device_is_typed_error() {
+        let mut engine = AudioEngine::default();
+        engine.output_device.take();
+        engine.output_config.take();
+
+        assert!(!engine.has_output_device());
+        let err = engine.run().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EngineError>(),
+            Some(&EngineError::NoOutputDevice)
+        );
+    }
 
-        let mut plugin = VSTHostContext::new(path)?;
+    #[test]
+    fn test_snapshot_lookup_in_fixture_bundle() {
+        use crate::vst::bundle::{bundle_root, find_snapshot, snapshot_paths};
 
-        unsafe {
-            plugin.processor.as_mut().unwrap().set_processing(true);
-        }
+        let fuid = "84E8DE5F92554F5396FAE4133C935A18";
+        let root = std::env::temp_dir().join(format!("sona-snapshot-{}", std::process::id()));
+        let bundle = root.join("Fixture.vst3");
+        let binary = bundle
+            .join("Contents")
+            .join("x86_64-win")
+            .join("Fixture.vst3");
+        let snapshots = bundle.join("Contents").join("Resources").join("Snapshots");
 
-        let id = plugin.id;
+        std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(&snapshots).unwrap();
+        std::fs::write(&binary, b"").unwrap();
 
-        self.plugin_modules.write().unwrap().insert(id, plugin);
-        info!("Successfully loaded plugin: {} with ID: {:?}", path, id);
-        Ok(id)
+        let [hidpi, standard] = snapshot_paths(&bundle, fuid);
+        assert_eq!(standard, snapshots.join(format!("{}_snapshot.png", fuid)));
+        assert_eq!(hidpi, snapshots.join(format!("{}_snapshot_2.0x.png", fuid)));
+
+        assert_eq!(bundle_root(&binary), Some(bundle.clone()));
+        assert_eq!(find_snapshot(&bundle, fuid), None);
+
+        std::fs::write(&standard, b"").unwrap();
+        assert_eq!(find_snapshot(&bundle, fuid), Some(standard));
+
+        std::fs::write(&hidpi, b"").unwrap();
+        assert_eq!(find_snapshot(&bundle, fuid), Some(hidpi));
+
+        std::fs::remove_dir_all(&root).unwrap();
     }
 
-    /// Remove a plugin from the processing chain, and thus invalidates its context
-    pub fn remove_plugin(&mut self, plugin_id: PluginId) -> Result<()> {
-        match self.plugin_modules.write().unwrap().remove(&plugin_id) {
-            Some(_) => {
-                info!("Removed plugin with ID: {:?}", plugin_id);
-                Ok(())
-            }
-            None => Err(anyhow!("Plugin with ID {:?} not found", plugin_id)),
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_bundle_resolves_real_binary() {
+        use crate::vst::bundle::{bundle_binary, module_path};
+
+        let root = std::env::temp_dir().join(format!("sona-symlink-{}", std::process::id()));
+        let bundle = root.join("central").join("Fixture.vst3");
+        std::fs::create_dir_all(&bundle).unwrap();
+        let linked = root.join("plugins").join("Linked.vst3");
+        std::fs::create_dir_all(linked.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(&bundle, &linked).unwrap();
+
+        // The binary is named after the real bundle, not the link
+        let binary = bundle_binary(&linked).unwrap();
+        std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        std::fs::write(&binary, b"").unwrap();
+        assert!(binary.starts_with(bundle.canonicalize().unwrap()));
+        assert!(binary.file_stem().is_some_and(|stem| stem == "Fixture"));
+
+        if cfg!(target_os = "macos") {
+            assert_eq!(module_path(&linked), bundle.canonicalize().unwrap());
+        } else {
+            assert_eq!(module_path(&linked), binary);
         }
-    }
 
-    /// Get reference to loaded plugin modules
-    pub fn plugin_modules(&self) -> RwLockReadGuard<'_, FxHashMap<PluginId, VSTHostContext>> {
-        self.plugin_modules.read().unwrap()
+        std::fs::remove_dir_all(&root).unwrap();
     }
 
-    pub fn plugin_modules_mut(
-        &mut self,
-    ) -> RwLockWriteGuard<'_, FxHashMap<PluginId, VSTHostContext>> {
-        self.plugin_modules.write().unwrap()
-    }
+    #[test]
+    fn test_plugin_format_from_extension() {
+        use crate::vst::host::PluginFormat;
+        use std::path::Path;
 
-    /// Check if a plugin is loaded
-    pub fn is_plugin_loaded(&self, plugin_id: PluginId) -> bool {
-        self.plugin_modules.read().unwrap().contains_key(&plugin_id)
+        assert_eq!(
+            PluginFormat::from_path(Path::new("Plugins/Reverb.VST3")),
+            Some(PluginFormat::Vst3)
+        );
+        assert_eq!(
+            PluginFormat::from_path(Path::new("Plugins/Delay.dll")),
+            Some(PluginFormat::Vst2)
+        );
+        assert_eq!(
+            PluginFormat::from_path(Path::new("Plugins/Delay.vst")),
+            Some(PluginFormat::Vst2)
+        );
+        assert_eq!(
+            PluginFormat::from_path(Path::new("Plugins/readme.txt")),
+            None
+        );
+        assert_eq!(PluginFormat::from_path(Path::new("Plugins/Delay")), None);
+
+        // Scan caches written before the format was recorded read back as VST3
+        let format: PluginFormat = serde_json::from_str("\"vst2\"").unwrap();
+        assert_eq!(format, PluginFormat::Vst2);
+        assert_eq!(PluginFormat::default(), PluginFormat::Vst3);
     }
 
-    /// Remove a plugin by ID
-    pub fn unload_plugin(&mut self, plugin_id: PluginId) -> Result<()> {
-        match self.plugin_modules.write().unwrap().remove(&plugin_id) {
-            Some(_) => {
-                info!("Removed plugin with ID: {:?}", plugin_id);
-                Ok(())
+    #[test]
+    fn test_vst2_effect_forwards_to_processor_and_editor() {
+        use vst::vst2::{opcode, Effect};
+        use vst3::base::funknown::IPlugView_Impl;
+        use vst3::gui::plug_view::{PlatformType, ViewRect};
+
+        mock_vst2::take_dispatched();
+        let effect = unsafe { Effect::from_entry(mock_vst2::entry, None) }.unwrap();
+        assert_eq!(
+            effect.uid(),
+            format!("{:08X}", i32::from_be_bytes(*b"Mock"))
+        );
+
+        let mut plugin = mock_plugin();
+        plugin.vst2 = Some(Arc::new(effect));
+        plugin.create_instances().unwrap();
+        assert_eq!(plugin.input_buses[0].channel_count, 2);
+        assert_eq!(plugin.output_buses[0].channel_count, 2);
+        for (i, &value) in mock_vst2::INITIAL_PARAMETERS.iter().enumerate() {
+            assert_eq!(plugin.parameters.value(i as u32), Some(value as f64));
+        }
+        assert_eq!(
+            mock_vst2::take_dispatched(),
+            vec![
+                (opcode::OPEN, 0, 0.0),
+                (opcode::SET_SAMPLE_RATE, 0, 48000.0),
+                (opcode::SET_BLOCK_SIZE, 2048, 0.0),
+            ]
+        );
+
+        // Starting processing resumes the effect, as loading a plugin does
+        let processor = plugin.processor.as_ref().unwrap();
+        unsafe {
+            processor.set_processing(true);
+            processor.set_processing(true);
+        }
+        assert_eq!(
+            mock_vst2::take_dispatched(),
+            vec![
+                (opcode::MAINS_CHANGED, 1, 0.0),
+                (opcode::START_PROCESS, 0, 0.0)
+            ]
+        );
+
+        // Parameter changes reach the effect before the block they're made for
+        let mut engine = AudioEngine::default();
+        let id = engine.insert_plugin(plugin).unwrap();
+        let output = engine.process_block(&[&[1.0; 16], &[-1.0; 16]]).unwrap();
+        assert_eq!(output, vec![vec![0.5; 16], vec![-0.5; 16]]);
+
+        engine.set_parameter(id, 0, 0.25).unwrap();
+        let output = engine.process_block(&[&[1.0; 16], &[-1.0; 16]]).unwrap();
+        assert_eq!(output, vec![vec![0.25; 16], vec![-0.25; 16]]);
+        assert!(engine.set_parameter(id, 2, 0.5).is_err());
+
+        // Automation from the plugin's editor is reported as an edit
+        mock_vst2::automate(1, 0.75);
+        let edits = engine.take_parameter_edits();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            (edits[0].plugin_id, edits[0].id, edits[0].value),
+            (id, 1, 0.75)
+        );
+        assert_eq!(engine.parameter_value(id, 1), Some(0.75));
+
+        {
+            let plugins = engine.plugin_modules();
+            let view = plugins[&id].view.as_ref().unwrap();
+            let mut rect = ViewRect::default();
+            unsafe {
+                assert_eq!(view.get_size(&mut rect), TResult::ResultOk);
+                assert_eq!(
+                    (rect.right, rect.bottom),
+                    (mock_vst2::EDITOR_WIDTH, mock_vst2::EDITOR_HEIGHT)
+                );
+                assert_eq!(view.can_resize(), TResult::ResultFalse);
+
+                // The mock never touches its parent window
+                let parent = std::ptr::dangling_mut();
+                assert_eq!(view.attached(parent, PlatformType::HWND), TResult::ResultOk);
+                assert_eq!(
+                    view.attached(parent, PlatformType::HWND),
+                    TResult::ResultFalse
+                );
+                assert_eq!(view.removed(), TResult::ResultOk);
             }
-            None => Err(anyhow!("Plugin with ID {:?} not found", plugin_id)),
         }
-    }
+        assert_eq!(
+            mock_vst2::take_dispatched(),
+            vec![
+                (opcode::EDIT_GET_RECT, 0, 0.0),
+                (opcode::EDIT_OPEN, 0, 0.0),
+                (opcode::EDIT_CLOSE, 0, 0.0),
+            ]
+        );
 
-    /// Get list of all loaded plugin IDs
-    pub fn get_loaded_plugin_ids(&self) -> Vec<PluginId> {
-        self.plugin_modules
-            .read()
-            .unwrap()
-            .keys()
-            .copied()
-            .collect()
+        // Removing the plugin stops and closes the effect
+        engine.remove_plugin(id).unwrap();
+        assert_eq!(
+            mock_vst2::take_dispatched(),
+            vec![
+                (opcode::STOP_PROCESS, 0, 0.0),
+                (opcode::MAINS_CHANGED, 0, 0.0),
+                (opcode::CLOSE, 0, 0.0),
+            ]
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+    #[test]
+    fn test_midi_clock_ticks_per_block() {
+        use crate::midi::ClockScheduler;
 
-    fn make_range(fmt: SampleFormat) -> SupportedStreamConfigRange {
-        SupportedStreamConfigRange::new(
-            2,
-            SampleRate(44100),
-            SampleRate(44100),
-            SupportedBufferSize::Unknown,
-            fmt,
-        )
-    }
+        // 120 BPM at 48 kHz is 2 beats per second, so a tick every 1000 samples
+        assert_eq!(ClockScheduler::samples_per_tick(120.0, 48000.0), 1000.0);
 
-    fn make_range_with_config(
-        fmt: SampleFormat,
-        min_rate: u32,
-        max_rate: u32,
-        buffer_size: SupportedBufferSize,
-    ) -> SupportedStreamConfigRange {
-        SupportedStreamConfigRange::new(
-            2,
-            SampleRate(min_rate),
-            SampleRate(max_rate),
-            buffer_size,
-            fmt,
-        )
+        let mut scheduler = ClockScheduler::default();
+        let mut ticks = Vec::new();
+        scheduler.advance(120.0, 48000.0, 512, |offset| ticks.push(offset));
+        assert_eq!(ticks, vec![0]);
+
+        ticks.clear();
+        scheduler.advance(120.0, 48000.0, 512, |offset| ticks.push(offset));
+        assert_eq!(ticks, vec![488]);
+
+        // One second in total is 48 ticks, whatever the block size
+        let mut count = 2;
+        for _ in 0..(48000 - 1024) / 512 {
+            scheduler.advance(120.0, 48000.0, 512, |_| count += 1);
+        }
+        scheduler.advance(120.0, 48000.0, (48000 - 1024) % 512, |_| count += 1);
+        assert_eq!(count, 48);
+
+        // Doubling the tempo halfway to the next tick keeps that half and halves the rest
+        let mut scheduler = ClockScheduler::default();
+        scheduler.advance(120.0, 48000.0, 1500, |_| {});
+        ticks.clear();
+        scheduler.advance(240.0, 48000.0, 1000, |offset| ticks.push(offset));
+        assert_eq!(ticks, vec![250, 750]);
     }
 
     #[test]
-    fn test_pick_best_format_prefers_f32() {
-        let configs = vec![
-            make_range(SampleFormat::I16),
-            make_range(SampleFormat::F32),
-            make_range(SampleFormat::U16),
-        ];
-        let result = pick_best_format(configs.into_iter(), None, None, None, None);
-        assert!(result.is_some());
-        assert_eq!(result.unwrap().sample_format(), SampleFormat::F32);
+    fn test_plugin_load_timeout() {
+        let slow = run_with_timeout(Duration::from_millis(20), || {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(1)
+        });
+        let err = slow.unwrap_err();
+        assert!(err.downcast_ref::<PluginLoadTimeout>().is_some());
+
+        let fast = run_with_timeout(Duration::from_secs(5), || Ok(2));
+        assert_eq!(fast.unwrap(), 2);
+
+        let failed = run_with_timeout(Duration::from_secs(5), || -> Result<()> {
+            Err(anyhow!("bad plugin"))
+        });
+        assert!(failed
+            .unwrap_err()
+            .downcast_ref::<PluginLoadTimeout>()
+            .is_none());
     }
 
     #[test]
-    fn test_pick_best_format_prefers_i32_over_u32() {
-        let configs = vec![make_range(SampleFormat::U32), make_range(SampleFormat::I32)];
-        let result = pick_best_format(configs.into_iter(), None, None, None, None);
-        assert!(result.is_some());
-        assert_eq!(result.unwrap().sample_format(), SampleFormat::I32);
+    fn test_resampler_quality_presets() {
+        for quality in [
+            ResamplerQuality::Fast,
+            ResamplerQuality::Balanced,
+            ResamplerQuality::HighQuality,
+        ] {
+            assert_eq!(
+                quality.as_str().parse::<ResamplerQuality>().unwrap(),
+                quality
+            );
+        }
+        assert!("ultra".parse::<ResamplerQuality>().is_err());
+
+        let fast = ResamplerQuality::Fast.sinc_parameters();
+        let high = ResamplerQuality::HighQuality.sinc_parameters();
+        assert!(fast.sinc_len < high.sinc_len);
+        assert_eq!(high.sinc_len, 256);
+        assert_eq!(high.oversampling_factor, 256);
     }
 
     #[test]
-    fn test_pick_best_format_returns_none_for_empty() {
-        let configs: Vec<SupportedStreamConfigRange> = vec![];
-        let result = pick_best_format(configs.into_iter(), None, None, None, None);
-        assert!(result.is_none());
+    fn test_ipc_process_round_trip() {
+        use crate::vst::ipc::{Request, Response};
+
+        let request = Request::Process {
+            num_samples: 256,
+            channels: 2,
+        };
+        assert_eq!(Request::decode(&request.encode()).unwrap(), request);
+
+        let response = Response::Processed { num_samples: 256 };
+        assert_eq!(Response::decode(&response.encode()).unwrap(), response);
+
+        // Every other message survives the trip too
+        for request in [
+            Request::Setup {
+                sample_rate: 44100.0,
+                max_samples_per_block: 2048,
+            },
+            Request::SetParameter { id: 7, value: 0.25 },
+            Request::SaveState,
+            Request::LoadState(vec![1, 2, 3]),
+            Request::Shutdown,
+        ] {
+            assert_eq!(Request::decode(&request.encode()).unwrap(), request);
+        }
+        for response in [
+            Response::Ok,
+            Response::State(vec![0; 5]),
+            Response::Error("failed".to_string()),
+            Response::Loaded {
+                name: "Gain".to_string(),
+            },
+        ] {
+            assert_eq!(Response::decode(&response.encode()).unwrap(), response);
+        }
     }
 
     #[test]
-    fn test_pick_best_format_picks_first_in_priority() {
-        let configs = vec![
-            make_range(SampleFormat::U8),
-            make_range(SampleFormat::I8),
-            make_range(SampleFormat::I16),
-        ];
-        let result = pick_best_format(configs.into_iter(), None, None, None, None);
-        assert!(result.is_some());
-        assert_eq!(result.unwrap().sample_format(), SampleFormat::I16);
+    fn test_ipc_encode_into_reuses_the_buffer() {
+        use crate::vst::ipc::Request;
+
+        let request = Request::Process {
+            num_samples: 256,
+            channels: 2,
+        };
+        let mut buf = Vec::with_capacity(64);
+        let capacity = buf.capacity();
+        let ptr = buf.as_ptr();
+
+        // Stale contents are replaced, not appended to
+        Request::LoadState(vec![9; 16]).encode_into(&mut buf);
+        for _ in 0..4 {
+            request.encode_into(&mut buf);
+            assert_eq!(buf, request.encode());
+        }
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(buf.as_ptr(), ptr);
     }
 
     #[test]
-    fn test_pick_best_format_prioritizes_sample_rate() {
-        let configs = vec![
-            make_range_with_config(
-                SampleFormat::F32,
-                22050,
-                22050,
-                SupportedBufferSize::Unknown,
-            ),
-            make_range_with_config(
-                SampleFormat::I16,
-                44100,
-                44100,
-                SupportedBufferSize::Unknown,
-            ),
-        ];
-        let result = pick_best_format(configs.into_iter(), Some(44100), None, None, None);
-        assert!(result.is_some());
-        let config = result.unwrap();
-        assert_eq!(config.sample_format(), SampleFormat::I16);
-        assert_eq!(config.sample_rate().0, 44100);
+    #[cfg(feature = "sandbox")]
+    fn test_sandbox_response_wait_times_out() {
+        use crate::vst::ipc::{IpcError, Response};
+        use crate::vst::sandbox::receive_response;
+        use std::sync::mpsc;
+
+        let (sender, responses) = mpsc::channel();
+        let timeout = Duration::from_millis(10);
+
+        // A host that never answers misses the deadline instead of blocking
+        let started = Instant::now();
+        assert!(matches!(
+            receive_response(&responses, Some(timeout)),
+            Err(IpcError::TimedOut(t)) if t == timeout
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        sender.send(Ok(Response::Ok)).unwrap();
+        assert_eq!(
+            receive_response(&responses, Some(timeout)).unwrap(),
+            Response::Ok
+        );
+
+        // A reader that stopped reads as a broken pipe
+        drop(sender);
+        assert!(matches!(
+            receive_response(&responses, None),
+            Err(IpcError::Io(_))
+        ));
     }
 
     #[test]
-    fn test_pick_best_format_prioritizes_buffer_size() {
-        use cpal::SupportedBufferSize;
-        let configs = vec![
-            make_range_with_config(
-                SampleFormat::F32,
-                44100,
-                44100,
-                SupportedBufferSize::Range { min: 128, max: 256 },
-            ),
-            make_range_with_config(
-                SampleFormat::I16,
-                44100,
-                44100,
-                SupportedBufferSize::Range {
-                    min: 512,
-                    max: 1024,
-                },
-            ),
-        ];
-        let result = pick_best_format(configs.into_iter(), Some(44100), Some(512), None, None);
-        assert!(result.is_some());
-        assert_eq!(result.unwrap().sample_format(), SampleFormat::I16);
+    fn test_ipc_rejects_malformed_messages() {
+        use crate::vst::ipc::{read_frame, write_frame, IpcError, Request};
+
+        let encoded = Request::Process {
+            num_samples: 64,
+            channels: 1,
+        }
+        .encode();
+        assert!(matches!(
+            Request::decode(&encoded[..encoded.len() - 1]),
+            Err(IpcError::Truncated)
+        ));
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Request::decode(&trailing),
+            Err(IpcError::TrailingBytes(1))
+        ));
+        assert!(matches!(
+            Request::decode(&[0xFF]),
+            Err(IpcError::UnknownTag(0xFF))
+        ));
+
+        let mut pipe = Vec::new();
+        write_frame(&mut pipe, &encoded).unwrap();
+        write_frame(&mut pipe, &Request::Shutdown.encode()).unwrap();
+
+        let mut reader = pipe.as_slice();
+        assert_eq!(read_frame(&mut reader).unwrap(), encoded);
+        assert_eq!(
+            Request::decode(&read_frame(&mut reader).unwrap()).unwrap(),
+            Request::Shutdown
+        );
+        assert!(matches!(read_frame(&mut reader), Err(IpcError::Io(_))));
     }
 
     #[test]
-    fn test_pick_best_format_exact_format_match() {
-        let configs = vec![make_range(SampleFormat::I16), make_range(SampleFormat::F32)];
-        let result = pick_best_format(
-            configs.into_iter(),
-            None,
-            None,
-            Some(SampleFormat::I16),
-            None,
+    fn test_with_config_rejects_mismatched_directions() {
+        let config = |sample_rate, buffer_size| AudioConfig {
+            device: Some("Unknown device".to_string()),
+            sample_rate,
+            buffer_size,
+            channels: 2,
+        };
+
+        assert!(
+            AudioEngine::with_config(Some(config(44100, 256)), Some(config(48000, 256))).is_err()
         );
-        assert!(result.is_some());
-        assert_eq!(result.unwrap().sample_format(), SampleFormat::I16);
+        assert!(
+            AudioEngine::with_config(Some(config(48000, 256)), Some(config(48000, 512))).is_err()
+        );
+        assert!(AudioEngine::with_config(Some(config(48000, 1)), None).is_err());
+        assert!(AudioEngine::with_config(Some(config(48000, 256)), None).is_err());
+
+        let engine = AudioEngine::with_config(None, None).unwrap();
+        assert_eq!(engine.input_device_name(), None);
+        assert_eq!(engine.output_device_name(), None);
     }
 
     #[test]
-    fn test_pick_best_format_all_preferences_match() {
-        use cpal::SupportedBufferSize;
-        let configs = vec![
-            make_range_with_config(
-                SampleFormat::I16,
-                22050,
-                22050,
-                SupportedBufferSize::Range { min: 128, max: 256 },
-            ),
-            make_range_with_config(
-                SampleFormat::F32,
-                44100,
-                48000,
-                SupportedBufferSize::Range {
-                    min: 512,
-                    max: 1024,
-                },
-            ),
-        ];
-        let result = pick_best_format(
-            configs.into_iter(),
-            Some(44100),
-            Some(512),
-            Some(SampleFormat::F32),
-            None,
-        );
-        assert!(result.is_some());
-        let config = result.unwrap();
-        assert_eq!(config.sample_format(), SampleFormat::F32);
-        assert_eq!(config.sample_rate().0, 44100);
+    fn test_roundtrip_latency() {
+        let mut engine = AudioEngine::default();
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(48000),
+            buffer_size: cpal::BufferSize::Fixed(240),
+        };
+        engine.set_resampler_quality(ResamplerQuality::Fast);
+        engine.set_ring_latency_blocks(2);
+        engine.current_sample_rate = 48000;
+        engine.current_buffer_size = 240;
+
+        engine.input_config = None;
+        engine.output_config = None;
+        assert_eq!(engine.roundtrip_latency_ms(), 0.0);
+
+        // Only the output buffer without a capture device
+        engine.output_config = Some(config.clone());
+        assert!((engine.roundtrip_latency_ms() - 5.0).abs() < 1e-3);
+
+        // Both buffers, half the two block ring and half the sinc filter
+        engine.input_config = Some(config);
+        let sinc_delay = (ResamplerQuality::Fast.sinc_parameters().sinc_len / 2) as f32;
+        let expected = (240.0 * 3.0 + sinc_delay) * 1000.0 / 48000.0;
+        assert!((engine.roundtrip_latency_ms() - expected).abs() < 1e-3);
+
+        engine.set_input_source(InputSource::Silence).unwrap();
+        assert!((engine.roundtrip_latency_ms() - 5.0).abs() < 1e-3);
     }
 
     #[test]
     fn test_audio_config_creation() {
         let config = AudioConfig {
+            device: None,
             sample_rate: 44100,
             buffer_size: 512,
             channels: 2,
@@ -0,0 +1,353 @@
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+
+/// MIDI clock resolution, in pulses per quarter note
+pub const CLOCK_PPQN: f64 = 24.0;
+
+pub const TIMING_CLOCK: u8 = 0xF8;
+pub const START: u8 = 0xFA;
+pub const CONTINUE: u8 = 0xFB;
+pub const STOP: u8 = 0xFC;
+
+pub const NOTE_OFF: u8 = 0x80;
+pub const NOTE_ON: u8 = 0x90;
+pub const CONTROL_CHANGE: u8 = 0xB0;
+pub const PITCH_BEND: u8 = 0xE0;
+
+/// Names of the available MIDI output ports
+pub fn output_port_names() -> Result<Vec<String>> {
+    let output = MidiOutput::new("sona")?;
+
+    Ok(output
+        .ports()
+        .iter()
+        .filter_map(|port| output.port_name(port).ok())
+        .collect())
+}
+
+/// Names of the available MIDI input ports
+pub fn input_port_names() -> Result<Vec<String>> {
+    let input = MidiInput::new("sona")?;
+
+    Ok(input
+        .ports()
+        .iter()
+        .filter_map(|port| input.port_name(port).ok())
+        .collect())
+}
+
+/// Channel voice messages taken from MIDI input, values normalized to 0-1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiMessage {
+    NoteOn {
+        channel: u8,
+        pitch: u8,
+        velocity: f32,
+    },
+    NoteOff {
+        channel: u8,
+        pitch: u8,
+        velocity: f32,
+    },
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        value: f32,
+    },
+    /// Centered at 8192/16383
+    PitchBend { channel: u8, value: f32 },
+}
+
+impl MidiMessage {
+    /// Parse a message from its raw bytes, `None` for anything but the messages above.
+    /// A note on with zero velocity is a note off.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = status & 0x0F;
+        let [first, second] = [*data.first()?, *data.get(1)?];
+        if first > 0x7F || second > 0x7F {
+            return None;
+        }
+
+        Some(match status & 0xF0 {
+            NOTE_ON if second > 0 => Self::NoteOn {
+                channel,
+                pitch: first,
+                velocity: second as f32 / 127.0,
+            },
+            NOTE_ON | NOTE_OFF => Self::NoteOff {
+                channel,
+                pitch: first,
+                velocity: second as f32 / 127.0,
+            },
+            CONTROL_CHANGE => Self::ControlChange {
+                channel,
+                controller: first,
+                value: second as f32 / 127.0,
+            },
+            PITCH_BEND => Self::PitchBend {
+                channel,
+                value: ((second as u16) << 7 | first as u16) as f32 / 16383.0,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Turns midir's timestamps, microseconds from a point that differs per backend, into
+/// instants. Anchored to the message delivered with the least delay so far
+#[derive(Debug, Default)]
+pub struct StampClock {
+    anchor: Option<(u64, Instant)>,
+}
+
+impl StampClock {
+    /// When a message stamped `stamp` arrived, given it was delivered at `now`
+    pub fn instant(&mut self, stamp: u64, now: Instant) -> Instant {
+        if let Some((anchor_stamp, anchor)) = self.anchor {
+            let at = anchor + Duration::from_micros(stamp.saturating_sub(anchor_stamp));
+            if stamp >= anchor_stamp && at <= now {
+                return at;
+            }
+        }
+
+        self.anchor = Some((stamp, now));
+        now
+    }
+}
+
+/// Start and length of the block the audio thread is on. MIDI input arriving during it
+/// is queued for the next block, at the offset it arrived at into this one, so the
+/// spacing of events survives at the cost of a block of latency
+#[derive(Debug)]
+pub struct BlockTiming {
+    epoch: Instant,
+    // Microseconds from `epoch`
+    start: AtomicU64,
+    block_size: AtomicU32,
+    sample_rate: AtomicU32,
+}
+
+impl Default for BlockTiming {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            start: AtomicU64::new(0),
+            block_size: AtomicU32::new(0),
+            sample_rate: AtomicU32::new(0),
+        }
+    }
+}
+
+impl BlockTiming {
+    /// Called from the audio thread as a block starts
+    pub fn start_block(&self, at: Instant, block_size: usize, sample_rate: f32) {
+        let start = at.saturating_duration_since(self.epoch).as_micros() as u64;
+        self.start.store(start, Ordering::Relaxed);
+        self.block_size.store(block_size as u32, Ordering::Relaxed);
+        self.sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Sample offset into the next block for an event that arrived `at`
+    pub fn sample_offset(&self, at: Instant) -> i32 {
+        let block_size = self.block_size.load(Ordering::Relaxed);
+        if block_size == 0 {
+            return 0;
+        }
+
+        let start = self.epoch + Duration::from_micros(self.start.load(Ordering::Relaxed));
+        let sample_rate = f32::from_bits(self.sample_rate.load(Ordering::Relaxed));
+        let offset =
+            (at.saturating_duration_since(start).as_secs_f64() * sample_rate as f64).round();
+        (offset as u32).min(block_size - 1) as i32
+    }
+}
+
+/// An open MIDI input port. Messages arrive on midir's thread and are parsed before
+/// they're handed on with the instant they arrived, the port closes when this is dropped
+pub struct MidiInputPort {
+    _connection: MidiInputConnection<()>,
+    port_name: String,
+}
+
+impl MidiInputPort {
+    pub fn connect(
+        port_name: &str,
+        mut on_message: impl FnMut(MidiMessage, Instant) + Send + 'static,
+    ) -> Result<Self> {
+        let input = MidiInput::new("sona")?;
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|port| input.port_name(port).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| anyhow!("MIDI input port not found: {}", port_name))?;
+
+        let mut clock = StampClock::default();
+        let connection = input
+            .connect(
+                &port,
+                "sona-input",
+                move |stamp, bytes, _| {
+                    if let Some(message) = MidiMessage::parse(bytes) {
+                        on_message(message, clock.instant(stamp, Instant::now()));
+                    }
+                },
+                (),
+            )
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", port_name, e))?;
+
+        info!("Listening to MIDI input {}", port_name);
+        Ok(Self {
+            _connection: connection,
+            port_name: port_name.to_string(),
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+}
+
+/// Places clock ticks on the samples of consecutive blocks.
+/// The position is kept as a fraction of a tick, so a tempo change only affects
+/// the spacing of the ticks that follow it.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockScheduler {
+    // Progress towards the next tick, 1.0 meaning it's due on the first sample
+    phase: f64,
+}
+
+impl Default for ClockScheduler {
+    fn default() -> Self {
+        Self { phase: 1.0 }
+    }
+}
+
+impl ClockScheduler {
+    pub fn samples_per_tick(tempo: f64, sample_rate: f64) -> f64 {
+        sample_rate * 60.0 / (tempo * CLOCK_PPQN)
+    }
+
+    /// Restart so the next tick lands on the first sample of the next block
+    pub fn reset(&mut self) {
+        self.phase = 1.0;
+    }
+
+    /// Advance by one block, calling `on_tick` with the sample offset of every tick in it
+    pub fn advance(
+        &mut self,
+        tempo: f64,
+        sample_rate: f64,
+        block_size: usize,
+        mut on_tick: impl FnMut(usize),
+    ) {
+        if tempo <= 0.0 || sample_rate <= 0.0 {
+            return;
+        }
+
+        let samples_per_tick = Self::samples_per_tick(tempo, sample_rate);
+        let block_size = block_size as f64;
+
+        let mut offset = (1.0 - self.phase) * samples_per_tick;
+        while offset < block_size {
+            on_tick(offset as usize);
+            offset += samples_per_tick;
+        }
+
+        self.phase = 1.0 - (offset - block_size) / samples_per_tick;
+    }
+}
+
+/// MIDI clock sent to an output port, following the engine transport
+pub struct MidiClockOut {
+    connection: MidiOutputConnection,
+    port_name: String,
+    scheduler: ClockScheduler,
+    playing: bool,
+    // Ticks sent since the transport was rewound, decides between start and continue
+    position: u64,
+}
+
+impl MidiClockOut {
+    pub fn connect(port_name: &str) -> Result<Self> {
+        let output = MidiOutput::new("sona")?;
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| output.port_name(port).ok().as_deref() == Some(port_name))
+            .ok_or_else(|| anyhow!("MIDI output port not found: {}", port_name))?;
+
+        let connection = output
+            .connect(&port, "sona-clock")
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", port_name, e))?;
+
+        info!("Sending MIDI clock to {}", port_name);
+        Ok(Self {
+            connection,
+            port_name: port_name.to_string(),
+            scheduler: ClockScheduler::default(),
+            playing: false,
+            position: 0,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Go back to the start, the next play sends start instead of continue
+    pub fn rewind(&mut self) {
+        self.position = 0;
+        self.scheduler.reset();
+    }
+
+    /// Send transport changes and the clock ticks falling in the next block.
+    /// Clock only runs while the transport is playing.
+    pub fn process(&mut self, tempo: f64, playing: bool, sample_rate: f64, block_size: usize) {
+        if playing != self.playing {
+            self.playing = playing;
+
+            let message = match (playing, self.position) {
+                (true, 0) => {
+                    self.scheduler.reset();
+                    START
+                }
+                (true, _) => CONTINUE,
+                (false, _) => STOP,
+            };
+            self.send(message);
+        }
+
+        if !playing {
+            return;
+        }
+
+        let mut ticks = 0;
+        self.scheduler
+            .advance(tempo, sample_rate, block_size, |_| ticks += 1);
+
+        for _ in 0..ticks {
+            self.send(TIMING_CLOCK);
+        }
+        self.position += ticks;
+    }
+
+    fn send(&mut self, message: u8) {
+        if let Err(err) = self.connection.send(&[message]) {
+            warn!("Failed to send MIDI message {:#04X}: {}", message, err);
+        }
+    }
+}
+
+impl Drop for MidiClockOut {
+    fn drop(&mut self) {
+        if self.playing {
+            self.send(STOP);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use ringbuf::traits::{Consumer, Producer, Split};
+use ringbuf::{HeapProd, HeapRb};
+
+/// Bars are counted in 4/4 from the start of the transport
+pub const BEATS_PER_BAR: f64 = 4.0;
+
+/// Recordings are always stereo, a mono chain is written to both channels
+const RECORDING_CHANNELS: usize = 2;
+/// Seconds of audio buffered between the audio thread and the file writer
+const RECORDING_BUFFER_SECS: usize = 2;
+/// How often the writer thread drains the buffer
+const WRITER_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A transport position to start or stop recording at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordPoint {
+    Sample(u64),
+    /// Start of a bar, 0 being the first
+    Bar(u32),
+}
+
+impl RecordPoint {
+    /// Transport sample of the point at the given tempo
+    pub fn to_sample(self, tempo: f64, sample_rate: u32) -> u64 {
+        match self {
+            RecordPoint::Sample(sample) => sample,
+            RecordPoint::Bar(bar) => {
+                let seconds = bar as f64 * BEATS_PER_BAR * 60.0 / tempo;
+                (seconds * sample_rate as f64).round() as u64
+            }
+        }
+    }
+}
+
+/// Span of the transport a recording covers, in samples. A recording without either point
+/// runs from when it's started until it's stopped, whether the transport plays or not.
+/// With one it only records while the transport plays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordWindow {
+    pub start: Option<u64>,
+    pub stop: Option<u64>,
+}
+
+impl RecordWindow {
+    fn follows_transport(&self) -> bool {
+        self.start.is_some() || self.stop.is_some()
+    }
+
+    /// Frames of a block to record. `position` is the transport sample of the block's
+    /// first frame, None while the transport is stopped.
+    pub fn frames_in_block(&self, position: Option<u64>, block_size: usize) -> Range<usize> {
+        let Some(position) = position else {
+            return if self.follows_transport() {
+                0..0
+            } else {
+                0..block_size
+            };
+        };
+
+        let end = position + block_size as u64;
+        let from = self
+            .start
+            .map_or(position, |start| start.clamp(position, end));
+        let to = self.stop.map_or(end, |stop| stop.clamp(from, end));
+        (from - position) as usize..(to - position) as usize
+    }
+
+    /// Whether the transport reaches the stop point by the end of the block
+    pub fn is_finished(&self, position: Option<u64>, block_size: usize) -> bool {
+        match (self.stop, position) {
+            (Some(stop), Some(position)) => position + block_size as u64 >= stop,
+            _ => false,
+        }
+    }
+}
+
+/// Audio thread side of a recording, queues the output for the writer thread
+pub struct RecordingTap {
+    window: RecordWindow,
+    producer: HeapProd<f32>,
+    finished: Arc<AtomicBool>,
+    overflowed: Arc<AtomicBool>,
+}
+
+impl RecordingTap {
+    /// Queue the frames of the chain's output block that fall in the window
+    pub fn capture<C: AsRef<[f32]>>(
+        &mut self,
+        buffer: &[C],
+        channels: usize,
+        position: Option<u64>,
+        block_size: usize,
+    ) {
+        if self.finished.load(Ordering::Relaxed) || channels == 0 {
+            return;
+        }
+
+        for i in self.window.frames_in_block(position, block_size) {
+            for j in 0..RECORDING_CHANNELS {
+                let sample = buffer[j.min(channels - 1)].as_ref()[i];
+                if self.producer.try_push(sample).is_err() {
+                    self.overflowed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if self.window.is_finished(position, block_size) {
+            self.finished.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A recording in progress. A writer thread drains the tap's queue into a 32 bit float WAV
+/// file, finalizing it once the stop point is reached or the recording is finished.
+pub struct Recording {
+    path: PathBuf,
+    finished: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    overflowed: Arc<AtomicBool>,
+    writer: Option<JoinHandle<Result<()>>>,
+}
+
+impl Recording {
+    /// Create the file and start the writer, returning the tap for the audio thread
+    pub fn start(
+        path: &Path,
+        sample_rate: u32,
+        window: RecordWindow,
+    ) -> Result<(Recording, RecordingTap)> {
+        let spec = hound::WavSpec {
+            channels: RECORDING_CHANNELS as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        let ring =
+            HeapRb::<f32>::new(sample_rate as usize * RECORDING_CHANNELS * RECORDING_BUFFER_SECS);
+        let (producer, mut consumer) = ring.split();
+        let finished = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let overflowed = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let finished = finished.clone();
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("recording-writer".to_string())
+                .spawn(move || -> Result<()> {
+                    loop {
+                        // Checked before draining, so everything queued before is written
+                        let done = stop.load(Ordering::Relaxed) || finished.load(Ordering::Relaxed);
+                        while let Some(sample) = consumer.try_pop() {
+                            writer.write_sample(sample)?;
+                        }
+                        if done {
+                            break;
+                        }
+                        std::thread::sleep(WRITER_POLL_INTERVAL);
+                    }
+                    writer.finalize()?;
+                    Ok(())
+                })?
+        };
+
+        let tap = RecordingTap {
+            window,
+            producer,
+            finished: finished.clone(),
+            overflowed: overflowed.clone(),
+        };
+        let recording = Recording {
+            path: path.to_path_buf(),
+            finished,
+            stop,
+            overflowed,
+            writer: Some(writer),
+        };
+        Ok((recording, tap))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the transport went past the stop point, the file is complete from then on
+    pub fn reached_stop(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    /// Write what's still queued and finalize the file. The tap must be dropped first.
+    pub fn finish(mut self) -> Result<PathBuf> {
+        self.stop.store(true, Ordering::Relaxed);
+        let writer = self
+            .writer
+            .take()
+            .expect("writer runs until the recording finishes");
+        writer
+            .join()
+            .map_err(|_| anyhow!("Recording writer panicked"))??;
+
+        if self.overflowed.load(Ordering::Relaxed) {
+            warn!(
+                "Recording {} dropped samples, the disk couldn't keep up",
+                self.path.display()
+            );
+        }
+        Ok(std::mem::take(&mut self.path))
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            let _ = writer.join();
+        }
+    }
+}
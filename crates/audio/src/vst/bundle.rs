@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+/// Find the `.vst3` bundle directory containing a plugin binary, or the path itself
+/// if it already is one. Returns `None` for single-file plugins.
+pub fn bundle_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|ancestor| {
+            ancestor.is_dir()
+                && ancestor
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("vst3"))
+        })
+        .map(Path::to_path_buf)
+}
+
+/// Architecture folder holding the binary inside `Contents`, as named by the VST3 spec
+fn binary_dir() -> String {
+    let arch = std::env::consts::ARCH;
+    if cfg!(target_os = "macos") {
+        "MacOS".to_string()
+    } else if cfg!(target_os = "windows") {
+        let arch = match arch {
+            "x86" => "x86",
+            "aarch64" => "arm64",
+            arch => arch,
+        };
+        format!("{}-win", arch)
+    } else {
+        let arch = if arch == "x86" { "i386" } else { arch };
+        format!("{}-linux", arch)
+    }
+}
+
+/// Binary of a bundle for the current platform. The bundle is resolved first, so a
+/// symlinked bundle gives the binary inside the real one, named after it.
+pub fn bundle_binary(bundle: &Path) -> Option<PathBuf> {
+    let bundle = bundle.canonicalize().ok()?;
+    let name = bundle.file_stem()?.to_string_lossy().into_owned();
+
+    let file = if cfg!(target_os = "macos") {
+        name
+    } else if cfg!(target_os = "windows") {
+        format!("{}.vst3", name)
+    } else {
+        format!("{}.so", name)
+    };
+    Some(bundle.join("Contents").join(binary_dir()).join(file))
+}
+
+/// Path to hand the platform loader for a plugin path, which may be a symlink. macOS
+/// loads the bundle itself, elsewhere a bundle is resolved to its binary.
+pub fn module_path(path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if path.is_dir() && !cfg!(target_os = "macos") {
+        if let Some(binary) = bundle_binary(&path) {
+            return binary;
+        }
+    }
+    path
+}
+
+/// Snapshot image paths for a class inside a bundle, HiDPI variant first.
+/// `fuid` is the 32 character form returned by `uid_to_fuid_string`.
+pub fn snapshot_paths(bundle: &Path, fuid: &str) -> [PathBuf; 2] {
+    let snapshots = bundle.join("Contents").join("Resources").join("Snapshots");
+
+    [
+        snapshots.join(format!("{}_snapshot_2.0x.png", fuid)),
+        snapshots.join(format!("{}_snapshot.png", fuid)),
+    ]
+}
+
+/// Locate the best available snapshot image for a class inside a bundle
+pub fn find_snapshot(bundle: &Path, fuid: &str) -> Option<PathBuf> {
+    snapshot_paths(bundle, fuid)
+        .into_iter()
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,261 @@
+use std::cell::UnsafeCell;
+use std::sync::Mutex;
+
+use vst3::base::funknown::FUnknown_Vtbl;
+use vst3::vst::audio_processor::{
+    Event, EventData, EventFlags, EventTypes, IEventList, IEventList_Vtbl, NoteOffEvent,
+    NoteOnEvent,
+};
+
+use super::parameters::HostParameterChanges;
+
+/// Most events one block holds. Allocated up front so the audio thread never allocates.
+pub const MAX_EVENTS: usize = 512;
+
+/// IEventList handed to a plugin's processor in ProcessData, kept sorted by sample offset
+#[repr(C)]
+pub struct HostEventList {
+    base: IEventList,
+    // Never resized, the processor may add events while it runs
+    events: Box<[Event]>,
+    count: usize,
+}
+
+impl Default for HostEventList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostEventList {
+    pub fn new() -> Self {
+        Self {
+            base: IEventList {
+                vtable: &EVENT_LIST_VTBL,
+            },
+            events: vec![Event::default(); MAX_EVENTS].into_boxed_slice(),
+            count: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.count = 0;
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events[..self.count]
+    }
+
+    /// Insert an event after any at the same or an earlier offset. False when full.
+    pub fn add(&mut self, event: Event) -> bool {
+        if self.count == self.events.len() {
+            return false;
+        }
+
+        let index = self.events[..self.count]
+            .partition_point(|existing| existing.sample_offset <= event.sample_offset);
+        self.events.copy_within(index..self.count, index + 1);
+        self.events[index] = event;
+        self.count += 1;
+        true
+    }
+}
+
+/// Events and parameter changes for one processing block
+#[derive(Default)]
+pub struct EventBlock {
+    pub events: HostEventList,
+    pub parameters: HostParameterChanges,
+}
+
+impl EventBlock {
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.parameters.clear();
+    }
+}
+
+/// Note and automation input for a plugin. The app fills one block while the audio thread
+/// hands the other to the processor, the two are swapped at the start of every block.
+pub struct EventList {
+    pending: Mutex<Box<EventBlock>>,
+    // Only touched by the audio thread
+    active: UnsafeCell<Box<EventBlock>>,
+}
+
+unsafe impl Sync for EventList {}
+unsafe impl Send for EventList {}
+
+impl Default for EventList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventList {
+    pub fn new() -> Self {
+        Self {
+            pending: Mutex::new(Box::default()),
+            active: UnsafeCell::new(Box::default()),
+        }
+    }
+
+    /// Queue a note on for the next block. False when the block is full.
+    pub fn add_note_on(&self, sample_offset: i32, channel: i16, pitch: i16, velocity: f32) -> bool {
+        let note_on = NoteOnEvent {
+            channel,
+            pitch,
+            velocity,
+            note_id: -1,
+            ..Default::default()
+        };
+        self.add_event(Event {
+            sample_offset,
+            flags: EventFlags::IsLive,
+            ty: EventTypes::NoteOnEvent,
+            data: EventData { note_on },
+            ..Default::default()
+        })
+    }
+
+    /// Queue a note off for the next block. False when the block is full.
+    pub fn add_note_off(
+        &self,
+        sample_offset: i32,
+        channel: i16,
+        pitch: i16,
+        velocity: f32,
+    ) -> bool {
+        let note_off = NoteOffEvent {
+            channel,
+            pitch,
+            velocity,
+            note_id: -1,
+            ..Default::default()
+        };
+        self.add_event(Event {
+            sample_offset,
+            flags: EventFlags::IsLive,
+            ty: EventTypes::NoteOffEvent,
+            data: EventData { note_off },
+            ..Default::default()
+        })
+    }
+
+    pub fn add_event(&self, event: Event) -> bool {
+        self.pending.lock().unwrap().events.add(event)
+    }
+
+    /// Queue a normalized parameter value at `sample_offset` into the next block. False
+    /// when the block has no room for it.
+    pub fn add_param_point(&self, id: u32, sample_offset: i32, value: f64) -> bool {
+        self.pending
+            .lock()
+            .unwrap()
+            .parameters
+            .add_change(id, sample_offset, value)
+    }
+
+    /// Swap the queued block in for the processor and start a fresh one for the app.
+    /// When the app is holding the queue the block starts empty and nothing is lost.
+    ///
+    /// # Safety
+    /// Audio thread only, the previous block must no longer be in use by the processor.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn next_block(&self) -> &mut EventBlock {
+        let active = &mut *self.active.get();
+        active.clear();
+
+        if let Ok(mut pending) = self.pending.try_lock() {
+            std::mem::swap(&mut *pending, active);
+        }
+        active
+    }
+}
+
+mod event_list {
+    use std::ffi::c_void;
+
+    use vst3::base::funknown::{FUnknown, Interface, TResult, FUID};
+    use vst3::vst::audio_processor::{Event, IEventList};
+
+    use super::HostEventList;
+
+    // FUID is passed by value to match the vtable signature
+    #[allow(improper_ctypes_definitions)]
+    pub unsafe extern "C" fn query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IEventList::iid || iid == FUnknown::iid {
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    pub unsafe extern "C" fn add_ref(_this: *mut FUnknown) -> u32 {
+        1
+    }
+
+    pub unsafe extern "C" fn release(_this: *mut FUnknown) -> u32 {
+        1
+    }
+
+    pub unsafe extern "C" fn get_event_count(this: *mut IEventList) -> i32 {
+        (*(this as *mut HostEventList)).count as i32
+    }
+
+    pub unsafe extern "C" fn get_event(
+        this: *mut IEventList,
+        index: i32,
+        e: *mut Event,
+    ) -> TResult {
+        let list = &*(this as *mut HostEventList);
+        let Some(event) = usize::try_from(index)
+            .ok()
+            .and_then(|index| list.events().get(index))
+        else {
+            return TResult::InvalidArgument;
+        };
+
+        if e.is_null() {
+            return TResult::InvalidArgument;
+        }
+        *e = *event;
+        TResult::ResultOk
+    }
+
+    pub unsafe extern "C" fn add_event(this: *mut IEventList, e: *mut Event) -> TResult {
+        let list = &mut *(this as *mut HostEventList);
+        if e.is_null() {
+            return TResult::InvalidArgument;
+        }
+
+        if list.add(*e) {
+            TResult::ResultOk
+        } else {
+            TResult::OutOfMemory
+        }
+    }
+}
+
+static EVENT_LIST_VTBL: IEventList_Vtbl = IEventList_Vtbl {
+    base: FUnknown_Vtbl {
+        query_interface: event_list::query_interface,
+        add_ref: event_list::add_ref,
+        release: event_list::release,
+    },
+    get_event_count: event_list::get_event_count,
+    get_event: event_list::get_event,
+    add_event: event_list::add_event,
+};
+
+impl std::fmt::Debug for HostEventList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.events()).finish()
+    }
+}
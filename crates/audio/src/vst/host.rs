@@ -2,13 +2,20 @@
 
 use std::{
     ffi::{c_char, c_void, CStr},
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::{info, trace, warn};
 use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 use vst3::{
+    ascii_to_uid,
     base::funknown::{
         FUnknown, FUnknown_HostImpl, FUnknown_Impl, IAudioProcessor, IAudioProcessor_Impl,
         IComponent, IComponent_Impl, IEditController, IEditController_Impl, IPlugView,
@@ -16,14 +23,16 @@ use vst3::{
         PFactoryInfo, TResult, ViewType, FUID,
     },
     gui::plug_view::{IPlugFrame, IPlugFrame_HostImpl, ViewRect},
-    uid_to_ascii,
+    uid_to_ascii, uid_to_fuid_string,
     vst::{
         audio_processor::{
-            BusDirection, BusInfo, IParameterChanges_HostImpl, IoMode, MediaType, ProcessMode,
-            ProcessSetup, SymbolicSampleSize,
+            BusDirection, BusInfo, BusType, ControllerNumbers, IMidiMapping, IMidiMapping_Impl,
+            IUnitInfo, IUnitInfo_Impl, IoMode, MediaType, ParameterFlags, ParameterInfo,
+            ProcessMode, ProcessSetup, ProgramListInfo, SymbolicSampleSize, UnitInfo,
+            NO_PROGRAM_LIST_ID,
         },
         host_application::{
-            IAttributeList, IAttributeList_HostImpl, IComponentHandler2,
+            IAttributeList, IAttributeList_HostImpl, IComponentHandler,
             IComponentHandler2_HostImpl, IComponentHandler_HostImpl, IConnectionPoint,
             IConnectionPoint_Impl, IHostApplication, IHostApplication_HostImpl, IMessage,
             IMessage_HostImpl, String128,
@@ -32,6 +41,13 @@ use vst3::{
     Module, VSTPtr,
 };
 
+use crate::dsp::{loudness_match_gain, DelayLine};
+
+use super::bundle::{bundle_root, find_snapshot, module_path};
+use super::events::EventList;
+use super::parameters::ParameterState;
+use super::stream::MemoryStream;
+
 /// Unique identifier for loaded plugins
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct PluginId(pub u64);
@@ -44,169 +60,1199 @@ impl PluginId {
     }
 }
 
+/// Identifier of a parallel processing chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChainId(pub u64);
+
+impl ChainId {
+    /// The chain every plugin starts in, it can't be removed
+    pub const MAIN: ChainId = ChainId(0);
+
+    pub fn new() -> Self {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        ChainId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// Signal fed to a plugin's sidechain input bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidechainSource {
+    /// The engine input, before any plugin
+    MainInput,
+    /// Another plugin's output
+    Plugin(PluginId),
+}
+
 impl From<PluginId> for String {
     fn from(id: PluginId) -> Self {
         id.0.to_string()
     }
 }
 
+/// Plugin API a plugin is written against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginFormat {
+    #[default]
+    Vst3,
+    Vst2,
+}
+
+impl PluginFormat {
+    /// Format of a plugin file or bundle, by its extension. `None` for anything else.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?;
+        if ext.eq_ignore_ascii_case("vst3") {
+            Some(Self::Vst3)
+        } else if ext.eq_ignore_ascii_case("dll") || ext.eq_ignore_ascii_case("vst") {
+            Some(Self::Vst2)
+        } else {
+            None
+        }
+    }
+}
+
+/// Metadata read from a plugin's factory without instantiating any class
+#[derive(Debug, Clone, Default)]
+pub struct PluginMetadata {
+    pub name: String,
+    pub vendor: String,
+    pub category: String,
+    pub uid: String,
+    /// Snapshot image for the class, if the bundle ships one
+    pub snapshot: Option<PathBuf>,
+    pub format: PluginFormat,
+}
+
+/// Category of the factory classes that process audio
+const AUDIO_MODULE_CLASS: &str = "Audio Module Class";
+
+/// A class a plugin factory can instantiate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClassInfo {
+    pub name: String,
+    /// Factory category, `Audio Module Class` for the ones `new_with_class` loads
+    pub category: String,
+    /// In the hyphenated form `new_with_class` takes
+    pub uid: String,
+}
+
+/// Internal helper to load a module just long enough to read its factory
+unsafe fn with_factory<T>(
+    path: &str,
+    f: impl FnOnce(&mut IPluginFactory) -> Result<T>,
+) -> Result<T> {
+    let mut module = Module::new(&module_path(Path::new(path)).to_string_lossy())?;
+    let mut factory = module.get_factory()?;
+
+    let result = f(&mut factory);
+
+    // The factory must be released before the module is unloaded
+    factory.release();
+    drop(module);
+
+    result
+}
+
+/// Every class the plugin's factory declares, audio or not, without instantiating any
+pub fn list_classes(path: &str) -> Result<Vec<ClassInfo>> {
+    if PluginFormat::from_path(Path::new(path)) == Some(PluginFormat::Vst2) {
+        let metadata = super::vst2::probe(Path::new(path))?;
+        return Ok(vec![ClassInfo {
+            name: metadata.name,
+            category: metadata.category,
+            uid: metadata.uid,
+        }]);
+    }
+
+    unsafe { with_factory(path, |factory| factory_classes(factory)) }
+}
+
+/// Internal helper for `list_classes`, reading every class of an open factory
+pub(crate) unsafe fn factory_classes(factory: &IPluginFactory) -> Result<Vec<ClassInfo>> {
+    (0..factory.count_classes())
+        .map(|i| -> Result<ClassInfo> {
+            let class_info = factory.get_class_info(i)?;
+            Ok(ClassInfo {
+                name: class_info.name(),
+                category: class_info.category(),
+                uid: uid_to_ascii(class_info.cid),
+            })
+        })
+        .collect()
+}
+
+/// Load a module just long enough to read its factory and first audio class info
+pub fn probe_plugin(path: &str) -> Result<PluginMetadata> {
+    probe_plugin_classes(path).map(|mut classes| classes.remove(0))
+}
+
+/// Metadata of every audio class in the plugin's factory, in factory order. Fails if
+/// there's none.
+pub fn probe_plugin_classes(path: &str) -> Result<Vec<PluginMetadata>> {
+    if PluginFormat::from_path(Path::new(path)) == Some(PluginFormat::Vst2) {
+        return super::vst2::probe(Path::new(path)).map(|metadata| vec![metadata]);
+    }
+
+    unsafe { with_factory(path, |factory| audio_classes(factory, path)) }
+}
+
+/// Internal helper for `probe_plugin_classes`, reading the audio classes of an open factory
+pub(crate) unsafe fn audio_classes(
+    factory: &IPluginFactory,
+    path: &str,
+) -> Result<Vec<PluginMetadata>> {
+    let vendor = factory_vendor(factory);
+
+    let mut classes = Vec::new();
+    for i in 0..factory.count_classes() {
+        let class_info = factory.get_class_info(i)?;
+
+        if class_info.category() != AUDIO_MODULE_CLASS {
+            continue;
+        }
+
+        let snapshot = bundle_root(Path::new(path))
+            .and_then(|bundle| find_snapshot(&bundle, &uid_to_fuid_string(class_info.cid)));
+
+        classes.push(PluginMetadata {
+            name: class_info.name(),
+            vendor: vendor.clone(),
+            category: class_info.category(),
+            uid: uid_to_ascii(class_info.cid),
+            snapshot,
+            format: PluginFormat::Vst3,
+        });
+    }
+
+    if classes.is_empty() {
+        return Err(anyhow!("No audio module class found in {}", path));
+    }
+    Ok(classes)
+}
+
+unsafe fn factory_vendor(factory: &IPluginFactory) -> String {
+    let mut factory_info = PFactoryInfo::default();
+    factory.get_factory_info(&mut factory_info);
+
+    CStr::from_ptr(factory_info.vendor.as_ptr())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// An audio bus as described by the plugin's component
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BusDescription {
+    pub name: String,
+    pub channel_count: i32,
+    /// Aux bus, e.g. a sidechain input or an additional output
+    pub aux: bool,
+}
+
+/// Read every audio bus on one side of a component
+unsafe fn describe_buses(component: &IComponent, direction: BusDirection) -> Vec<BusDescription> {
+    (0..component.get_bus_count(MediaType::Audio, direction))
+        .map(|i| {
+            let mut info = BusInfo::default();
+            component.get_bus_info(MediaType::Audio, direction, i, &mut info);
+            BusDescription {
+                name: info.name(),
+                channel_count: info.channel_count,
+                aux: info.bus_type == BusType::Aux,
+            }
+        })
+        .collect()
+}
+
+/// Titles of read-only parameters instruments commonly report their status through
+const STATUS_PARAMETER_TITLES: [&str; 4] = ["status", "voices", "active voices", "polyphony"];
+
+/// Whether a parameter is a read-only status readout rather than a control
+pub fn is_status_parameter(info: &ParameterInfo) -> bool {
+    info.flags & ParameterFlags::IsReadOnly != 0
+        && STATUS_PARAMETER_TITLES.contains(&info.title().trim().to_lowercase().as_str())
+}
+
+/// A parameter as the edit controller describes it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PluginParameter {
+    pub id: u32,
+    pub title: String,
+    pub units: String,
+    /// 0 for continuous parameters
+    pub step_count: i32,
+    pub default_value: f64,
+    /// The plugin accepts automation for it, flagged `kCanAutomate`
+    pub automatable: bool,
+    pub read_only: bool,
+    /// The plugin's own bypass switch, flagged `kIsBypass`
+    pub bypass: bool,
+}
+
+impl From<&ParameterInfo> for PluginParameter {
+    fn from(info: &ParameterInfo) -> Self {
+        Self {
+            id: info.id,
+            title: info.title(),
+            units: info.units(),
+            step_count: info.step_count,
+            default_value: info.default_normalized_value,
+            automatable: info.flags & ParameterFlags::CanAutomate != 0,
+            read_only: info.flags & ParameterFlags::IsReadOnly != 0,
+            bypass: info.flags & ParameterFlags::IsBypass != 0,
+        }
+    }
+}
+
+/// Moving average of how long a plugin's `process()` takes, updated from the audio thread
+#[derive(Debug, Default)]
+pub struct ProcessTimer {
+    // Average in microseconds, stored as f32 bits
+    average_us: AtomicU32,
+}
+
+impl ProcessTimer {
+    /// Weight of the newest block in the moving average
+    const SMOOTHING: f32 = 0.05;
+
+    pub fn record(&self, elapsed: Duration) {
+        let sample = elapsed.as_nanos() as f32 / 1000.0;
+        let average = self.average_micros();
+
+        let average = if average == 0.0 {
+            sample
+        } else {
+            average + Self::SMOOTHING * (sample - average)
+        };
+        self.average_us.store(average.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn average_micros(&self) -> f32 {
+        f32::from_bits(self.average_us.load(Ordering::Relaxed))
+    }
+}
+
+/// Fades a newly inserted plugin's output in over its first blocks, so loading it
+/// into a running chain doesn't pop
+#[derive(Debug, Default)]
+pub struct InsertRamp {
+    length: u32,
+    block: AtomicU32,
+}
+
+impl InsertRamp {
+    pub fn new(length: u32) -> Self {
+        Self {
+            length,
+            block: AtomicU32::new(0),
+        }
+    }
+
+    /// Gain at the start of `block`, rising from 0 to 1 over `length` blocks
+    pub fn gain(block: u32, length: u32) -> f32 {
+        if length == 0 {
+            return 1.0;
+        }
+        (block as f32 / length as f32).min(1.0)
+    }
+
+    /// Gain at the start and end of the next block, `None` once the ramp has finished
+    pub fn next_block(&self) -> Option<(f32, f32)> {
+        let block = self.block.load(Ordering::Relaxed);
+        if block >= self.length {
+            return None;
+        }
+
+        self.block.store(block + 1, Ordering::Relaxed);
+        Some((
+            Self::gain(block, self.length),
+            Self::gain(block + 1, self.length),
+        ))
+    }
+}
+
+/// Plugin-level dry/wet, blending the plugin's input back into its output before the next
+/// plugin. The dry signal is delayed by the plugin's latency so it lines up with the wet.
+#[derive(Debug, Default)]
+pub struct PluginMix {
+    // Dry share as f32 bits, zero being fully wet keeps the default a plain insert
+    dry: AtomicU32,
+    // Dry share at the end of the last block as f32 bits
+    last_dry: AtomicU32,
+    delay: Mutex<DelayLine>,
+}
+
+impl PluginMix {
+    pub fn wet(&self) -> f32 {
+        1.0 - f32::from_bits(self.dry.load(Ordering::Relaxed))
+    }
+
+    /// Set the wet share from 0 (dry) to 1 (wet), ramped to over the next block
+    pub fn set_wet(&self, wet: f32) {
+        let dry = 1.0 - wet.clamp(0.0, 1.0);
+        let previous = self.dry.swap(dry.to_bits(), Ordering::Relaxed);
+
+        // The delay line isn't fed while fully wet, don't blend in what it held from before
+        if previous == 0 && dry > 0.0 {
+            let mut delay = self.delay.lock().unwrap();
+            *delay = DelayLine::new(delay.delay());
+        }
+    }
+
+    /// Delay the dry signal by the plugin's latency, in samples
+    pub fn set_latency(&self, latency: usize) {
+        let mut delay = self.delay.lock().unwrap();
+        if delay.delay() != latency {
+            *delay = DelayLine::new(latency);
+        }
+    }
+
+    /// Wet share at the start and end of the next block, `None` while fully wet
+    pub fn next_block(&self) -> Option<(f32, f32)> {
+        let dry = self.dry.load(Ordering::Relaxed);
+        let last_dry = self.last_dry.swap(dry, Ordering::Relaxed);
+        if dry == 0 && last_dry == 0 {
+            return None;
+        }
+
+        Some((1.0 - f32::from_bits(last_dry), 1.0 - f32::from_bits(dry)))
+    }
+
+    /// Delay line for the dry signal, `None` if it's being resized. Audio thread only.
+    pub fn try_delay(&self) -> Option<std::sync::MutexGuard<'_, DelayLine>> {
+        self.delay.try_lock().ok()
+    }
+}
+
+/// Loudness-matched bypass. Scales a plugin's output to the level of its input, measured
+/// over a short window, so toggling bypass compares the sound rather than the level
+#[derive(Debug, Default)]
+pub struct LoudnessMatch {
+    enabled: AtomicBool,
+    // Windowed mean squares of the plugin's input and output, stored as f32 bits
+    input_ms: AtomicU32,
+    output_ms: AtomicU32,
+    // Gain at the end of the last block as f32 bits, 0 before the first block
+    gain: AtomicU32,
+}
+
+impl LoudnessMatch {
+    /// Time constant of the level measurement, in seconds
+    pub const WINDOW: f32 = 0.3;
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enable or disable matching, either way the measurement starts over
+    pub fn set_enabled(&self, enabled: bool) {
+        self.input_ms.store(0, Ordering::Relaxed);
+        self.output_ms.store(0, Ordering::Relaxed);
+        self.gain.store(0, Ordering::Relaxed);
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Feed the mean squares of one block's input and output. Returns the gain at the
+    /// start and end of the block, ramping from the previous block's gain
+    pub fn next_block(
+        &self,
+        input_ms: f32,
+        output_ms: f32,
+        frames: usize,
+        sample_rate: f32,
+    ) -> (f32, f32) {
+        let weight = 1.0 - (-(frames as f32) / (Self::WINDOW * sample_rate)).exp();
+        let average = |slot: &AtomicU32, value: f32| {
+            let previous = f32::from_bits(slot.load(Ordering::Relaxed));
+            let average = previous + weight * (value - previous);
+            slot.store(average.to_bits(), Ordering::Relaxed);
+            average
+        };
+
+        let input = average(&self.input_ms, input_ms);
+        let output = average(&self.output_ms, output_ms);
+        let target = loudness_match_gain(input.sqrt(), output.sqrt());
+
+        let previous = f32::from_bits(self.gain.swap(target.to_bits(), Ordering::Relaxed));
+        (if previous == 0.0 { target } else { previous }, target)
+    }
+}
+
+/// Input and output peaks of a plugin since the meters were last read. Peaks are stored
+/// as f32 bits, which order like the values since they're never negative
+#[derive(Debug, Default)]
+pub struct IoMeter {
+    input: AtomicU32,
+    output: AtomicU32,
+}
+
+impl IoMeter {
+    /// Fold in the peaks of one block, called from the audio thread
+    pub fn record(&self, input: f32, output: f32) {
+        self.input
+            .fetch_max(input.abs().to_bits(), Ordering::Relaxed);
+        self.output
+            .fetch_max(output.abs().to_bits(), Ordering::Relaxed);
+    }
+
+    /// (input, output) peaks since the last call
+    pub fn take(&self) -> (f32, f32) {
+        (
+            f32::from_bits(self.input.swap(0, Ordering::Relaxed)),
+            f32::from_bits(self.output.swap(0, Ordering::Relaxed)),
+        )
+    }
+}
+
 #[derive(Default)]
 pub struct VSTHostContext {
     pub id: PluginId,
     pub name: String,
+    /// Module path the plugin was loaded from
+    pub path: String,
+    pub format: PluginFormat,
+    pub vendor: String,
+    pub uid: String,
     pub module: Option<Module>,
     pub factory: Option<VSTPtr<IPluginFactory>>,
     pub component: Option<VSTPtr<IComponent>>,
     pub processor: Option<VSTPtr<IAudioProcessor>>,
     pub editor: Option<VSTPtr<IEditController>>,
     pub view: Option<VSTPtr<IPlugView>>,
+    /// Units and program lists, if the edit controller describes any
+    pub unit_info: Option<VSTPtr<IUnitInfo>>,
+    /// MIDI controllers the edit controller maps to parameters, if it maps any
+    pub midi_mapping: Option<VSTPtr<IMidiMapping>>,
+    /// Parameter of every mapped controller by channel and controller number, asked for
+    /// once at load so MIDI input never calls into the edit controller
+    pub midi_controllers: FxHashMap<(i16, i16), u32>,
 
     pub host: Option<Arc<VSTHostApplication>>,
     pub handler: Option<Arc<HostComponentHandler>>,
 
+    /// Parameter cache shared with the component handler, changes are handed to the
+    /// processor at the start of its next block
+    pub parameters: Arc<ParameterState>,
+    /// Notes and automation for the processor, filled by the app while the audio thread
+    /// hands the previous block to the plugin
+    pub events: Arc<EventList>,
+
     pub component_connection: Option<VSTPtr<IConnectionPoint>>,
     pub controller_connection: Option<VSTPtr<IConnectionPoint>>,
 
+    /// Parameter selecting the program, and the unit whose program list it indexes
+    pub program_parameter: Option<(u32, i32)>,
+
+    /// Read-only parameter the plugin reports its status through, and its title
+    pub status_parameter: Option<(u32, String)>,
+
+    /// Parameters the edit controller describes, empty for plugins without one
+    pub parameter_list: Vec<PluginParameter>,
+
+    /// Set when the edit controller is its own object rather than the component, it's
+    /// terminated separately then
+    pub separate_controller: bool,
+
     pub host_frame: Option<*mut HostPlugFrame>,
 
     pub bypass: bool,
+
+    /// Parallel chain the plugin processes in
+    pub chain: ChainId,
+
+    /// Audio buses the plugin exposes, the main bus first
+    pub input_buses: Vec<BusDescription>,
+    pub output_buses: Vec<BusDescription>,
+    /// Event input buses, instruments take notes through the first
+    pub event_inputs: i32,
+
+    /// Source of the sidechain input bus, silence when unset
+    pub sidechain: Option<SidechainSource>,
+
+    /// Index of the output bus routed to the engine output, 0 being the main bus
+    pub monitor_bus: usize,
+
+    /// Set when the plugin rejected setupProcessing/setActive, the chain skips it
+    pub setup_failed: bool,
+
+    pub process_time: ProcessTimer,
+
+    pub insert_ramp: InsertRamp,
+
+    pub loudness_match: LoudnessMatch,
+
+    /// Dry/wet of this plugin alone
+    pub mix: PluginMix,
+
+    pub io_meter: IoMeter,
+
+    /// The wrapped `AEffect` of a VST2 plugin, its processor and view forward to it
+    pub vst2: Option<Arc<super::vst2::Effect>>,
+
+    /// Plugin host process when the plugin runs sandboxed
+    #[cfg(feature = "sandbox")]
+    pub sandbox: Option<Arc<std::sync::Mutex<super::sandbox::SandboxClient>>>,
 }
 
 unsafe impl Sync for VSTHostContext {}
 unsafe impl Send for VSTHostContext {}
 
 impl VSTHostContext {
+    /// Load the first audio class of the plugin at `path`, VST2 plugins are recognized by
+    /// their extension
     pub fn new(path: &str) -> Result<Self> {
-        unsafe {
-            let plugin_id = PluginId::new();
+        let mut ctx = Self::open(path, None)?;
+        ctx.create_instances()?;
+        Ok(ctx)
+    }
 
-            let mut module = Module::new(path)?;
+    /// Load the audio class with the given UID, as `list_classes` reports it, from a
+    /// factory that may ship several. A VST2 plugin only has its own.
+    pub fn new_with_class(path: &str, class_uid: &str) -> Result<Self> {
+        let mut ctx = Self::open(path, Some(class_uid))?;
+        ctx.create_instances()?;
+        Ok(ctx)
+    }
+
+    /// Load the plugin's binary and pick its audio class with UID `class_uid`, or its first
+    /// one, without instantiating anything. Loading runs the plugin's static initializers,
+    /// which may hang, so this can run on another thread. `create_instances` must follow on
+    /// the UI thread, where VST3 expects the edit controller and its view to be created.
+    pub fn open(path: &str, class_uid: Option<&str>) -> Result<Self> {
+        if PluginFormat::from_path(Path::new(path)) == Some(PluginFormat::Vst2) {
+            let ctx = Self::open_vst2(path)?;
+            if class_uid.is_some_and(|uid| !ctx.uid.eq_ignore_ascii_case(uid)) {
+                return Err(anyhow!(
+                    "{} has no class {}",
+                    path,
+                    class_uid.unwrap_or_default()
+                ));
+            }
+            return Ok(ctx);
+        }
+        unsafe {
+            let mut module = Module::new(&module_path(Path::new(path)).to_string_lossy())?;
             let factory = module.get_factory()?;
 
-            let mut ctx = Self::default();
-            ctx.id = plugin_id;
+            let mut ctx = Self::from_factory(factory, path, class_uid)?;
+            ctx.module = Some(module);
+            Ok(ctx)
+        }
+    }
 
-            let mut factory_info = PFactoryInfo::default();
-            factory.get_factory_info(&mut factory_info);
+    /// Internal helper for `open`, picking the audio class with UID `class_uid`, or the
+    /// first one, from a loaded factory
+    pub(crate) unsafe fn from_factory(
+        factory: VSTPtr<IPluginFactory>,
+        path: &str,
+        class_uid: Option<&str>,
+    ) -> Result<Self> {
+        let class = class_uid.map(ascii_to_uid).transpose()?;
 
-            info!("Loaded plugin! {}", factory_info);
+        let mut ctx = Self::default();
+        ctx.id = PluginId::new();
+        ctx.path = path.to_string();
+        ctx.vendor = factory_vendor(&factory);
 
-            let host = Arc::new(VSTHostApplication::new());
-            let handler = Arc::new(HostComponentHandler::new());
+        info!("Loaded plugin! {} by {}", path, ctx.vendor);
 
-            let context = Arc::into_raw(host.clone()) as *mut FUnknown;
+        for i in 0..factory.count_classes() {
+            let class_info = factory.get_class_info(i)?;
+
+            if class_info.category() == AUDIO_MODULE_CLASS
+                && class.is_none_or(|cid| cid == class_info.cid)
+            {
+                ctx.name = class_info.name();
+                ctx.uid = uid_to_ascii(class_info.cid);
+                break;
+            }
+        }
+
+        ctx.factory = Some(factory);
+
+        if ctx.uid.is_empty() {
+            return Err(match class {
+                Some(cid) => anyhow!("{} has no audio class {}", path, uid_to_ascii(cid)),
+                None => anyhow!("No audio module class found in {}", path),
+            });
+        }
+        Ok(ctx)
+    }
+
+    /// Create the component, processor, edit controller and view of the class `open`
+    /// picked. Call on the UI thread.
+    pub fn create_instances(&mut self) -> Result<()> {
+        if self.vst2.is_some() {
+            return self.create_vst2_instances();
+        }
+
+        let factory = self
+            .factory
+            .take()
+            .ok_or_else(|| anyhow!("{} is not open", self.path))?;
+        let result = unsafe { self.create_class_instances(&factory) };
+        self.factory = Some(factory);
+        result
+    }
 
-            ctx.host = Some(host.clone());
-            ctx.handler = Some(handler.clone());
+    /// Internal helper for `create_instances` of a VST3 class
+    unsafe fn create_class_instances(&mut self, factory: &IPluginFactory) -> Result<()> {
+        let cid = ascii_to_uid(&self.uid)?;
+        let class_info = (0..factory.count_classes())
+            .filter_map(|i| factory.get_class_info(i).ok())
+            .find(|class_info| class_info.cid == cid)
+            .ok_or_else(|| anyhow!("{} has no audio class {}", self.path, self.uid))?;
 
-            for i in 0..factory.count_classes() {
-                let class_info = factory.get_class_info(i)?;
+        let host = Arc::new(VSTHostApplication::new());
+        let handler = Arc::new(HostComponentHandler::new(self.parameters.clone()));
 
-                if class_info.category() != "Audio Module Class" {
-                    continue;
+        let context = Arc::into_raw(host.clone()) as *mut FUnknown;
+
+        self.host = Some(host.clone());
+        self.handler = Some(handler.clone());
+
+        let comp = factory.create_instance::<IComponent>(class_info.cid)?;
+        comp.set_io_mode(IoMode::Advanced);
+
+        let edit = match comp.get_controller_class_id() {
+            Ok(edit_cid) => {
+                trace!("Initializing create_instance!");
+                self.separate_controller = true;
+                factory.create_instance::<IEditController>(edit_cid)?
+            }
+
+            Err(err) => {
+                trace!("Initializing query_interface! {:?}", err);
+                comp.query_interface::<IEditController>()?
+            }
+        };
+
+        let _ = comp.initialize(context);
+
+        let component_connection = comp.query_interface::<IConnectionPoint>()?;
+        let controller_connection = edit.query_interface::<IConnectionPoint>()?;
+
+        trace!("Component Connection: {:?}", component_connection);
+        trace!("Controller Connection: {:?}", controller_connection);
+
+        component_connection.connect(controller_connection);
+        controller_connection.connect(component_connection);
+
+        trace!("Setting up processor!");
+
+        let processor = comp.query_interface::<IAudioProcessor>()?;
+
+        trace!("Processor: {:?}", processor);
+
+        let mut data = ProcessSetup {
+            process_mode: ProcessMode::Realtime,
+            symbolic_sample_size: SymbolicSampleSize::Sample32,
+            max_samples_per_block: 2048,
+            sample_rate: 48000.0,
+        };
+        let res = processor.setup_processing(&mut data);
+
+        trace!(
+            "AudioProcessor: {:?}. Setup Processing: {:?}",
+            processor,
+            res
+        );
+
+        self.input_buses = describe_buses(comp, BusDirection::Input);
+        self.output_buses = describe_buses(comp, BusDirection::Output);
+        trace!("Input buses: {:?}", self.input_buses);
+        trace!("Output buses: {:?}", self.output_buses);
+
+        // Only the main buses carry audio. Extra buses such as a sidechain input
+        // are fed silence and their output is discarded, see `set_monitor_bus`
+        comp.activate_bus(MediaType::Audio, BusDirection::Input, 0, true);
+        comp.activate_bus(MediaType::Audio, BusDirection::Output, 0, true);
+        self.event_inputs = comp.get_bus_count(MediaType::Event, BusDirection::Input);
+        if self.event_inputs > 0 {
+            comp.activate_bus(MediaType::Event, BusDirection::Input, 0, true);
+        }
+        for i in 1..self.input_buses.len() as i32 {
+            comp.activate_bus(MediaType::Audio, BusDirection::Input, i, false);
+        }
+        for i in 1..self.output_buses.len() as i32 {
+            comp.activate_bus(MediaType::Audio, BusDirection::Output, i, false);
+        }
+
+        comp.set_active(true);
+
+        trace!("Parameter count: {}", edit.get_parameter_count());
+        for i in 0..edit.get_parameter_count() {
+            let mut info = ParameterInfo::default();
+            if edit.get_parameter_info(i, &mut info) == TResult::ResultOk {
+                trace!("Parameter {}: {} [{}]", info.id, info.title(), info.units());
+                if info.flags & ParameterFlags::IsProgramChange != 0
+                    && self.program_parameter.is_none()
+                {
+                    self.program_parameter = Some((info.id, info.unit_id));
+                }
+                if is_status_parameter(&info) && self.status_parameter.is_none() {
+                    self.status_parameter = Some((info.id, info.title()));
                 }
+                self.parameter_list.push(PluginParameter::from(&info));
+            }
+        }
 
-                ctx.name = class_info.name();
+        trace!("Initializing editor controller!");
+        let res = edit.initialize(context);
 
-                let comp = factory.create_instance::<IComponent>(class_info.cid)?;
-                comp.set_io_mode(IoMode::Advanced);
+        trace!("Setting command handler!");
+        let res = edit.set_component_handler(Arc::into_raw(handler.clone()) as *mut _);
 
-                let edit = match comp.get_controller_class_id() {
-                    Ok(edit_cid) => {
-                        trace!("Initializing create_instance!");
-                        factory.create_instance::<IEditController>(edit_cid)?
-                    }
+        // Null for processor-only plugins without a GUI of their own
+        let view = edit.create_view(ViewType::Editor);
+
+        if !view.is_null() {
+            // Create the frame on the heap for FFI safety
+            let host_frame = Box::into_raw(Box::new(HostPlugFrame::new()));
+            (*(view)).set_frame(host_frame as *mut _ as *mut IPlugFrame);
+
+            // Store the frame pointer for cleanup later
+            self.host_frame = Some(host_frame);
+        }
+
+        warn!(
+            "{} {:?} {:?} {:?} {:?}",
+            class_info, comp as *mut _, edit as *mut _, context as *mut _, view
+        );
+
+        self.controller_connection = Some(VSTPtr::new(controller_connection));
+        self.component_connection = Some(VSTPtr::new(component_connection));
+        self.component = Some(VSTPtr::new(comp));
+        self.processor = Some(VSTPtr::new(processor));
+        self.unit_info = edit
+            .query_interface::<IUnitInfo>()
+            .ok()
+            .map(|unit_info| VSTPtr::new(unit_info as *mut _));
+        self.midi_mapping = edit
+            .query_interface::<IMidiMapping>()
+            .ok()
+            .map(|midi_mapping| VSTPtr::new(midi_mapping as *mut _));
+        self.midi_controllers = self.query_midi_controllers();
+        self.editor = Some(VSTPtr::new(edit));
+        self.view = (!view.is_null()).then(|| VSTPtr::new(view));
+        Ok(())
+    }
+
+    /// Re-run setupProcessing and reactivate the plugin for new stream settings.
+    /// If the plugin rejects either call it is flagged as `setup_failed` and left inactive.
+    pub fn reconfigure(
+        &mut self,
+        sample_rate: f64,
+        max_samples_per_block: i32,
+        process_mode: ProcessMode,
+    ) -> Result<()> {
+        // Sandboxed plugins only have a processor, activation happens in the child
+        let Some(processor) = self.processor.as_ref() else {
+            return Ok(());
+        };
+        let component = self.component.as_ref();
+
+        let result = unsafe {
+            processor.set_processing(false);
+            if let Some(component) = component {
+                component.set_active(false);
+            }
 
-                    Err(err) => {
-                        trace!("Initializing query_interface! {:?}", err);
-                        comp.query_interface::<IEditController>()?
+            let mut setup = ProcessSetup {
+                process_mode,
+                symbolic_sample_size: SymbolicSampleSize::Sample32,
+                max_samples_per_block,
+                sample_rate,
+            };
+
+            match processor.setup_processing(&mut setup) {
+                TResult::ResultOk => {
+                    match component.map_or(TResult::ResultOk, |c| c.set_active(true)) {
+                        TResult::ResultOk => {
+                            processor.set_processing(true);
+                            Ok(())
+                        }
+                        err => Err(anyhow!("setActive failed for {}: {}", self.name, err)),
                     }
-                };
+                }
+                err => Err(anyhow!("setupProcessing failed for {}: {}", self.name, err)),
+            }
+        };
 
-                let _ = comp.initialize(context);
+        self.setup_failed = result.is_err();
+        result
+    }
 
-                let component_connection = comp.query_interface::<IConnectionPoint>()?;
-                let controller_connection = edit.query_interface::<IConnectionPoint>()?;
+    /// Name of the active program, resolved through the program list of the unit the
+    /// program change parameter belongs to. `None` for plugins without program lists.
+    pub fn current_program_name(&self) -> Option<String> {
+        let (param_id, unit_id) = self.program_parameter?;
+        let unit_info = self.unit_info.as_ref()?;
 
-                trace!("Component Connection: {:?}", component_connection);
-                trace!("Controller Connection: {:?}", controller_connection);
+        unsafe {
+            let list_id = (0..unit_info.get_unit_count()).find_map(|i| {
+                let mut info = UnitInfo::default();
+                (unit_info.get_unit_info(i, &mut info) == TResult::ResultOk && info.id == unit_id)
+                    .then_some(info.program_list_id)
+            })?;
+            if list_id == NO_PROGRAM_LIST_ID {
+                return None;
+            }
 
-                component_connection.connect(controller_connection);
-                controller_connection.connect(component_connection);
+            let program_count = (0..unit_info.get_program_list_count()).find_map(|i| {
+                let mut info = ProgramListInfo::default();
+                (unit_info.get_program_list_info(i, &mut info) == TResult::ResultOk
+                    && info.id == list_id)
+                    .then_some(info.program_count)
+            })?;
+            if program_count <= 0 {
+                return None;
+            }
 
-                trace!("Setting up processor!");
+            // The parameter steps through the list, as a list parameter with
+            // `program_count - 1` steps
+            let value = match self.editor.as_ref() {
+                Some(editor) => editor.get_param_normalized(param_id),
+                None => self.parameters.value(param_id)?,
+            };
+            let index = ((value * program_count as f64) as i32).clamp(0, program_count - 1);
+
+            let mut name: String128 = [0; 128];
+            (unit_info.get_program_name(list_id, index, &mut name) == TResult::ResultOk)
+                .then(|| vst3::utf16_to_string(&name))
+        }
+    }
+
+    /// Status the plugin reports through its status parameter, e.g. "Voices: 12", as the
+    /// plugin formats the parameter's current value. `None` for plugins without one.
+    pub fn status_string(&self) -> Option<String> {
+        let (param_id, title) = self.status_parameter.as_ref()?;
+        let editor = self.editor.as_ref()?;
+
+        unsafe {
+            let value = editor.get_param_normalized(*param_id);
+            let mut text: String128 = [0; 128];
+            (editor.get_param_string_by_value(*param_id, value, text.as_mut_ptr() as *mut c_void)
+                == TResult::ResultOk)
+                .then(|| format!("{}: {}", title, vst3::utf16_to_string(&text)))
+        }
+    }
+
+    /// Parameter the plugin bypasses itself with, if it has one
+    pub fn bypass_param_id(&self) -> Option<u32> {
+        self.parameter_list
+            .iter()
+            .find(|parameter| parameter.bypass)
+            .map(|parameter| parameter.id)
+    }
+
+    /// Bypass or re-enable the plugin. Plugins with a bypass parameter are switched through
+    /// it and keep processing, so they handle their latency and tails themselves. Others
+    /// are skipped by the host and the signal passes through dry.
+    pub fn set_bypassed(&mut self, bypassed: bool) {
+        self.bypass = bypassed;
+
+        let Some(id) = self.bypass_param_id() else {
+            return;
+        };
+        let value = if bypassed { 1.0 } else { 0.0 };
+        self.parameters.set(id, value);
+        // Keep the editor's switch in step
+        if let Some(editor) = &self.editor {
+            unsafe {
+                editor.set_param_normalized(id, value);
+            }
+        }
+    }
 
-                let processor = comp.query_interface::<IAudioProcessor>()?;
+    /// Whether the host skips the plugin, rather than the plugin bypassing itself
+    pub fn host_bypassed(&self) -> bool {
+        self.bypass && self.bypass_param_id().is_none()
+    }
 
-                trace!("Processor: {:?}", processor);
+    /// Whether the plugin takes notes, through an event input bus
+    pub fn accepts_notes(&self) -> bool {
+        self.event_inputs > 0
+    }
 
-                let mut data = ProcessSetup {
-                    process_mode: ProcessMode::Realtime,
-                    symbolic_sample_size: SymbolicSampleSize::Sample32,
-                    max_samples_per_block: 2048,
-                    sample_rate: 48000.0,
+    /// Parameter the plugin maps a MIDI controller on `channel` to, for the first event
+    /// bus. `controller` is a CC number or one of `ControllerNumbers`
+    pub fn midi_controller_param(&self, channel: i16, controller: i16) -> Option<u32> {
+        self.midi_controllers.get(&(channel, controller)).copied()
+    }
+
+    /// Internal helper asking the edit controller for the parameter of every controller
+    /// on every channel of the first event bus
+    fn query_midi_controllers(&self) -> FxHashMap<(i16, i16), u32> {
+        let Some(midi_mapping) = self.midi_mapping.as_ref() else {
+            return FxHashMap::default();
+        };
+
+        let mut controllers = FxHashMap::default();
+        for channel in 0..16 {
+            for controller in 0..=ControllerNumbers::PitchBend {
+                let mut id = 0;
+                let assigned = unsafe {
+                    midi_mapping.get_midi_controller_assignment(0, channel, controller, &mut id)
                 };
-                let res = processor.setup_processing(&mut data);
+                if assigned == TResult::ResultOk {
+                    controllers.insert((channel, controller), id);
+                }
+            }
+        }
+        controllers
+    }
 
-                trace!(
-                    "AudioProcessor: {:?}. Setup Processing: {:?}",
-                    processor,
-                    res
-                );
+    /// Queue automation for a parameter at `sample_offset` into the processor's next block.
+    /// Parameters the plugin doesn't mark automatable are rejected, `set_parameter` still
+    /// sets them. Plugins that don't describe their parameters accept any.
+    pub fn automate_parameter(&self, id: u32, sample_offset: i32, value: f64) -> Result<()> {
+        if !self.parameter_list.is_empty() {
+            let parameter = self
+                .parameter_list
+                .iter()
+                .find(|parameter| parameter.id == id)
+                .ok_or_else(|| anyhow!("{} has no parameter {}", self.name, id))?;
+            if !parameter.automatable {
+                return Err(anyhow!(
+                    "{} doesn't allow automating {}",
+                    self.name,
+                    parameter.title
+                ));
+            }
+        }
+
+        if !self
+            .events
+            .add_param_point(id, sample_offset, value.clamp(0.0, 1.0))
+        {
+            return Err(anyhow!("Automation queue of {} is full", self.name));
+        }
+        Ok(())
+    }
 
-                for i in 0..comp.get_bus_count(MediaType::Audio, BusDirection::Input) {
-                    let mut bus_info = BusInfo::default();
-                    comp.get_bus_info(MediaType::Audio, BusDirection::Input, i, &mut bus_info);
+    /// Activate an output bus for monitoring, deactivating the previously monitored one.
+    /// The main bus always stays active.
+    pub fn set_monitor_bus(&mut self, index: usize) -> Result<()> {
+        if index == self.monitor_bus {
+            return Ok(());
+        }
 
-                    trace!("Bus Input Info: {:?}", bus_info);
+        if let (Some(component), Some(processor)) =
+            (self.component.as_ref(), self.processor.as_ref())
+        {
+            unsafe {
+                let bus_count = component.get_bus_count(MediaType::Audio, BusDirection::Output);
+                if index as i32 >= bus_count {
+                    return Err(anyhow!(
+                        "Output bus {} out of range, plugin has {} output buses",
+                        index,
+                        bus_count
+                    ));
+                }
+
+                // Bus activation is only allowed while the component is inactive
+                processor.set_processing(false);
+                component.set_active(false);
+
+                if self.monitor_bus != 0 {
+                    component.activate_bus(
+                        MediaType::Audio,
+                        BusDirection::Output,
+                        self.monitor_bus as i32,
+                        false,
+                    );
                 }
+                component.activate_bus(MediaType::Audio, BusDirection::Output, index as i32, true);
 
-                for i in 0..comp.get_bus_count(MediaType::Audio, BusDirection::Output) {
-                    let mut bus_info = BusInfo::default();
-                    comp.get_bus_info(MediaType::Audio, BusDirection::Output, i, &mut bus_info);
+                component.set_active(true);
+                processor.set_processing(true);
+            }
+        }
 
-                    trace!("Bus Output Info: {:?}", bus_info);
+        self.monitor_bus = index;
+        Ok(())
+    }
+
+    /// Connect the sidechain input bus (the second input bus) to a source, or disconnect
+    /// it. The bus is only active while connected.
+    pub fn set_sidechain(&mut self, source: Option<SidechainSource>) -> Result<()> {
+        if self.input_buses.len() < 2 {
+            return Err(anyhow!("{} has no sidechain input bus", self.name));
+        }
+
+        if source.is_some() != self.sidechain.is_some() {
+            if let (Some(component), Some(processor)) =
+                (self.component.as_ref(), self.processor.as_ref())
+            {
+                unsafe {
+                    // Bus activation is only allowed while the component is inactive
+                    processor.set_processing(false);
+                    component.set_active(false);
+                    component.activate_bus(
+                        MediaType::Audio,
+                        BusDirection::Input,
+                        1,
+                        source.is_some(),
+                    );
+                    component.set_active(true);
+                    processor.set_processing(true);
                 }
+            }
+        }
 
-                comp.activate_bus(MediaType::Audio, BusDirection::Input, 0, true);
-                comp.activate_bus(MediaType::Audio, BusDirection::Output, 0, true);
+        self.sidechain = source;
+        Ok(())
+    }
 
-                comp.set_active(true);
+    /// Save the component and controller state. The component state is prefixed with
+    /// its length as a little-endian u32, the controller state follows it.
+    pub fn save_state(&self) -> Result<Vec<u8>> {
+        #[cfg(feature = "sandbox")]
+        if let Some(client) = &self.sandbox {
+            return client.lock().unwrap().save_state();
+        }
 
-                trace!("Parameter count: {}", edit.get_parameter_count());
+        if let Some(effect) = &self.vst2 {
+            return Ok(effect.save_state());
+        }
 
-                trace!("Initializing editor controller!");
-                let res = edit.initialize(context);
+        let component = self
+            .component
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} has no component", self.name))?;
 
-                trace!("Setting command handler!");
-                let res = edit.set_component_handler(Arc::into_raw(handler.clone()) as *mut _);
+        let mut component_state = MemoryStream::new(Vec::new());
+        let mut controller_state = MemoryStream::new(Vec::new());
 
-                let view = edit.create_view(ViewType::Editor);
+        unsafe {
+            match component.get_state(component_state.as_ptr()) {
+                TResult::ResultOk => {}
+                err => return Err(anyhow!("getState failed for {}: {}", self.name, err)),
+            }
 
-                // Create the frame on the heap for FFI safety
-                let host_frame = Box::into_raw(Box::new(HostPlugFrame::new()));
-                (*(view)).set_frame(host_frame as *mut _ as *mut IPlugFrame);
+            // A controller without state of its own is fine
+            if let Some(editor) = self.editor.as_ref() {
+                if editor.get_state(controller_state.as_ptr()) != TResult::ResultOk {
+                    controller_state = MemoryStream::new(Vec::new());
+                }
+            }
+        }
 
-                // Store the frame pointer for cleanup later
-                ctx.host_frame = Some(host_frame);
+        let component_state = component_state.into_inner();
+        let mut state = Vec::with_capacity(4 + component_state.len());
+        state.extend_from_slice(&(component_state.len() as u32).to_le_bytes());
+        state.extend_from_slice(&component_state);
+        state.extend_from_slice(&controller_state.into_inner());
+        Ok(state)
+    }
 
-                warn!(
-                    "{} {:?} {:?} {:?} {:?}",
-                    class_info, comp as *mut _, edit as *mut _, context as *mut _, view
-                );
+    /// Restore state written by `save_state`, syncing the controller with the component
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        #[cfg(feature = "sandbox")]
+        if let Some(client) = &self.sandbox {
+            return client.lock().unwrap().load_state(state);
+        }
 
-                ctx.controller_connection = Some(VSTPtr::new(controller_connection));
-                ctx.component_connection = Some(VSTPtr::new(component_connection));
-                ctx.component = Some(VSTPtr::new(comp));
-                ctx.processor = Some(VSTPtr::new(processor));
-                ctx.editor = Some(VSTPtr::new(edit));
-                ctx.view = Some(VSTPtr::new(view));
+        if let Some(effect) = &self.vst2 {
+            return effect.load_state(state);
+        }
+
+        let component = self
+            .component
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} has no component", self.name))?;
+
+        let (length, rest) = state
+            .split_first_chunk::<4>()
+            .ok_or_else(|| anyhow!("Plugin state is truncated"))?;
+        let length = u32::from_le_bytes(*length) as usize;
+        if length > rest.len() {
+            return Err(anyhow!("Plugin state is truncated"));
+        }
+        let (component_state, controller_state) = rest.split_at(length);
+
+        unsafe {
+            let mut stream = MemoryStream::new(component_state.to_vec());
+            match component.set_state(stream.as_ptr()) {
+                TResult::ResultOk => {}
+                err => return Err(anyhow!("setState failed for {}: {}", self.name, err)),
             }
 
-            ctx.factory = Some(factory);
-            ctx.module = Some(module);
+            if let Some(editor) = self.editor.as_ref() {
+                let mut stream = MemoryStream::new(component_state.to_vec());
+                editor.set_component_state(stream.as_ptr());
 
-            Ok(ctx)
+                if !controller_state.is_empty() {
+                    let mut stream = MemoryStream::new(controller_state.to_vec());
+                    editor.set_state(stream.as_ptr());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set a normalized parameter value on the edit controller, like its editor would
+    pub fn set_parameter(&mut self, id: u32, value: f64) -> Result<()> {
+        let value = value.clamp(0.0, 1.0);
+
+        #[cfg(feature = "sandbox")]
+        if let Some(client) = &self.sandbox {
+            client.lock().unwrap().set_parameter(id, value)?;
+            self.parameters.set(id, value);
+            return Ok(());
+        }
+
+        // VST2 parameters are indices, the processor applies the change
+        if let Some(effect) = &self.vst2 {
+            if id >= effect.parameter_count() {
+                return Err(anyhow!("{} has no parameter {}", self.name, id));
+            }
+            self.parameters.set(id, value);
+            return Ok(());
+        }
+
+        let editor = self
+            .editor
+            .as_ref()
+            .ok_or_else(|| anyhow!("{} has no edit controller", self.name))?;
+
+        match unsafe { editor.set_param_normalized(id, value) } {
+            TResult::ResultOk => {
+                self.parameters.set(id, value);
+                Ok(())
+            }
+            err => Err(anyhow!(
+                "setParamNormalized({}) failed for {}: {}",
+                id,
+                self.name,
+                err
+            )),
+        }
+    }
+
+    /// Whether the plugin has a GUI of its own. Without one the app shows the generic
+    /// parameter UI instead.
+    pub fn has_editor(&self) -> bool {
+        self.view.is_some()
+    }
+
+    /// Size the editor view for a region of `width` by `height` pixels it's embedded in,
+    /// see `embedded_view_rect`. Returns the size the view took, `None` without a view.
+    pub fn fit_view_to_region(&mut self, width: i32, height: i32) -> Option<ViewRect> {
+        let view = self.view.as_ref()?;
+        unsafe {
+            let mut preferred = ViewRect::default();
+            view.get_size(&mut preferred);
+            let resizable = view.can_resize() == TResult::ResultOk;
+
+            let mut rect = embedded_view_rect(width, height, preferred, resizable, |rect| {
+                view.check_size_constraint(rect);
+            });
+            view.on_size(&mut rect);
+            Some(rect)
         }
     }
 
@@ -242,20 +1288,65 @@ impl Drop for VSTHostContext {
         unsafe {
             warn!("Dropping VSTHostContext!");
 
-            // Clean up the frame first if it exists
+            // Tear down in the reverse order of `new`: stop processing and deactivate
+            // before anything is released, some plugins free their DSP state there.
+            // Any of these may be missing if instantiation failed part way through
+            if let Some(processor) = self.processor.as_ref() {
+                processor.set_processing(false);
+            }
+            if let Some(component) = self.component.as_ref() {
+                component.set_active(false);
+            }
+
+            // The view may still call into the frame until it's removed
+            if let Some(mut view) = self.view.take() {
+                view.removed();
+                view.release();
+            }
             if let Some(frame_ptr) = self.host_frame.take() {
                 let _frame = Box::from_raw(frame_ptr);
                 // Box automatically drops and deallocates
             }
 
-            self.controller_connection.take().unwrap().release();
-            self.component_connection.take().unwrap().release();
-            self.view.unwrap().removed();
-            self.view.take().unwrap().release();
-            self.editor.take().unwrap().release();
-            self.processor.take().unwrap().release();
-            self.component.take().unwrap().release();
-            self.factory.take().unwrap().release();
+            if let (Some(component), Some(controller)) = (
+                self.component_connection.as_ref(),
+                self.controller_connection.as_ref(),
+            ) {
+                let component_ptr = &**component as *const IConnectionPoint as *mut _;
+                let controller_ptr = &**controller as *const IConnectionPoint as *mut _;
+                component.disconnect(controller_ptr);
+                controller.disconnect(component_ptr);
+            }
+            if let Some(mut connection) = self.controller_connection.take() {
+                connection.release();
+            }
+            if let Some(mut connection) = self.component_connection.take() {
+                connection.release();
+            }
+
+            if let Some(mut unit_info) = self.unit_info.take() {
+                unit_info.release();
+            }
+            if let Some(mut midi_mapping) = self.midi_mapping.take() {
+                midi_mapping.release();
+            }
+            if let Some(mut editor) = self.editor.take() {
+                editor.set_component_handler(std::ptr::null_mut());
+                if self.separate_controller {
+                    editor.terminate();
+                }
+                editor.release();
+            }
+            if let Some(mut processor) = self.processor.take() {
+                processor.release();
+            }
+            if let Some(mut component) = self.component.take() {
+                component.terminate();
+                component.release();
+            }
+            if let Some(mut factory) = self.factory.take() {
+                factory.release();
+            }
 
             drop(self.module.take());
         }
@@ -301,7 +1392,7 @@ impl FUnknown_HostImpl for VSTHostApplication {
         if iid == IHostApplication::iid {
             *obj = self as *mut _ as *mut c_void;
         } else {
-            let host = Box::new(HostComponentHandler::new());
+            let host = Box::new(HostComponentHandler::new(Arc::default()));
             *obj = Box::into_raw(host) as *mut c_void;
         }
 
@@ -334,13 +1425,16 @@ impl IHostApplication_HostImpl for VSTHostApplication {
     }
 }
 
+/// Receives parameter edits from the plugin's edit controller, e.g. when its editor
+/// moves a knob
 #[repr(C)]
 pub struct HostComponentHandler {
     vtable: &'static [*const (); 11],
+    parameters: Arc<ParameterState>,
 }
 
 impl HostComponentHandler {
-    pub fn new() -> Self {
+    pub fn new(parameters: Arc<ParameterState>) -> Self {
         Self {
             vtable: &[
                 <Self as FUnknown_HostImpl>::query_interface as *const _,
@@ -355,6 +1449,7 @@ impl HostComponentHandler {
                 <Self as IComponentHandler2_HostImpl>::start_group_edit as *const _,
                 <Self as IComponentHandler2_HostImpl>::finish_group_edit as *const _,
             ],
+            parameters,
         }
     }
 }
@@ -379,8 +1474,12 @@ impl FUnknown_HostImpl for HostComponentHandler {
         if iid == IHostApplication::iid {
             let host = Box::new(VSTHostApplication::new());
             *obj = Box::into_raw(host) as *mut c_void;
-        } else if iid == IComponentHandler2::iid {
+        } else if iid == IComponentHandler::iid || iid == FUnknown::iid {
             *obj = self as *mut _ as *mut c_void;
+        } else {
+            // The vtable doesn't follow IComponentHandler2's layout
+            *obj = std::ptr::null_mut();
+            return TResult::NoInterface;
         }
 
         TResult::ResultOk
@@ -393,8 +1492,9 @@ impl IComponentHandler_HostImpl for HostComponentHandler {
         TResult::ResultOk
     }
 
-    unsafe fn perform_edit(&mut self, id: u32, value: u32) -> TResult {
-        warn!("perform_edit: {:?}", id);
+    unsafe fn perform_edit(&mut self, id: u32, value: f64) -> TResult {
+        trace!("perform_edit: {} = {}", id, value);
+        self.parameters.edit(id, value);
         TResult::ResultOk
     }
 
@@ -431,6 +1531,39 @@ impl IComponentHandler2_HostImpl for HostComponentHandler {
     }
 }
 
+/// Size of an editor embedded in a region of `width` by `height` pixels. A resizable view
+/// is offered the whole region and adjusted to its size constraints through `constrain`,
+/// a fixed one keeps its preferred size. The view may end up larger than the region, the
+/// surface it's attached to clips it.
+pub fn embedded_view_rect(
+    width: i32,
+    height: i32,
+    preferred: ViewRect,
+    resizable: bool,
+    constrain: impl FnOnce(&mut ViewRect),
+) -> ViewRect {
+    let mut rect = if resizable {
+        ViewRect {
+            left: 0,
+            top: 0,
+            right: width.max(0),
+            bottom: height.max(0),
+        }
+    } else {
+        preferred
+    };
+    if resizable {
+        constrain(&mut rect);
+    }
+
+    ViewRect {
+        left: 0,
+        top: 0,
+        right: (rect.right - rect.left).max(0),
+        bottom: (rect.bottom - rect.top).max(0),
+    }
+}
+
 #[repr(C)]
 pub struct HostPlugFrame {
     vtable: &'static [*const (); 4],
@@ -655,51 +1788,3 @@ impl IAttributeList_HostImpl for HostApplicationList {
         TResult::ResultOk
     }
 }
-
-#[repr(C)]
-pub struct HostParameterChanges {
-    vtable: &'static [*const (); 6],
-}
-
-impl HostParameterChanges {
-    pub fn new() -> Self {
-        Self {
-            vtable: &[
-                <Self as FUnknown_HostImpl>::query_interface as *const (),
-                <Self as FUnknown_HostImpl>::add_ref as *const (),
-                <Self as FUnknown_HostImpl>::release as *const (),
-                <Self as IParameterChanges_HostImpl>::get_parameter_count as *const (),
-                <Self as IParameterChanges_HostImpl>::get_parameter_data as *const (),
-                <Self as IParameterChanges_HostImpl>::add_parameter_data as *const (),
-            ],
-        }
-    }
-}
-
-impl Interface for HostParameterChanges {
-    type VTable = [*const (); 6];
-    fn vtable(&self) -> &'static Self::VTable {
-        self.vtable
-    }
-
-    const iid: FUID = [6; 16];
-}
-
-impl FUnknown_HostImpl for HostParameterChanges {}
-
-impl IParameterChanges_HostImpl for HostParameterChanges {
-    unsafe fn get_parameter_count(&mut self) -> i32 {
-        // warn!("get_parameter_count");
-        0
-    }
-
-    unsafe fn get_parameter_data(&mut self) -> *mut c_void {
-        warn!("get_parameter_data");
-        std::ptr::null_mut()
-    }
-
-    unsafe fn add_parameter_data(&mut self, id: *const c_char, index: *mut i32) -> *mut c_void {
-        warn!("add_parameter_data");
-        std::ptr::null_mut()
-    }
-}
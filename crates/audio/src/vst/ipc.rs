@@ -0,0 +1,246 @@
+use std::io::{self, Read, Write};
+
+/// Largest frame either side accepts, guards against reading garbage as a length
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+/// Control messages sent from the app to a sandboxed plugin host. Audio doesn't travel
+/// through the pipe, `Process` refers to samples already in the shared buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Process `num_samples` frames of `channels` channels from the shared input buffer
+    Process {
+        num_samples: u32,
+        channels: u32,
+    },
+    Setup {
+        sample_rate: f64,
+        max_samples_per_block: u32,
+    },
+    SetParameter {
+        id: u32,
+        value: f64,
+    },
+    SaveState,
+    LoadState(Vec<u8>),
+    Shutdown,
+}
+
+/// Replies from the plugin host, one for every request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// The shared output buffer holds `num_samples` processed frames
+    Processed {
+        num_samples: u32,
+    },
+    Ok,
+    State(Vec<u8>),
+    Error(String),
+    /// Sent once at startup when the plugin finished loading
+    Loaded {
+        name: String,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IpcError {
+    #[error("Message ended unexpectedly")]
+    Truncated,
+    #[error("Unknown message tag {0}")]
+    UnknownTag(u8),
+    #[error("{0} unread bytes after the message")]
+    TrailingBytes(usize),
+    #[error("Message string is not valid UTF-8")]
+    InvalidString,
+    #[error("Frame of {0} bytes exceeds the maximum size")]
+    FrameTooLarge(usize),
+    #[error("No response within {0:?}")]
+    TimedOut(std::time::Duration),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Request {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    /// Encode into `buf`, replacing its contents. A buffer with room for the message is
+    /// reused as is, so the audio thread can send `Process` without allocating.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.clear();
+        match self {
+            Self::Process {
+                num_samples,
+                channels,
+            } => {
+                buf.push(0);
+                buf.extend_from_slice(&num_samples.to_le_bytes());
+                buf.extend_from_slice(&channels.to_le_bytes());
+            }
+            Self::Setup {
+                sample_rate,
+                max_samples_per_block,
+            } => {
+                buf.push(1);
+                buf.extend_from_slice(&sample_rate.to_le_bytes());
+                buf.extend_from_slice(&max_samples_per_block.to_le_bytes());
+            }
+            Self::SetParameter { id, value } => {
+                buf.push(2);
+                buf.extend_from_slice(&id.to_le_bytes());
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+            Self::SaveState => buf.push(3),
+            Self::LoadState(state) => {
+                buf.push(4);
+                put_bytes(buf, state);
+            }
+            Self::Shutdown => buf.push(5),
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, IpcError> {
+        let mut reader = Reader(bytes);
+        let request = match reader.u8()? {
+            0 => Self::Process {
+                num_samples: reader.u32()?,
+                channels: reader.u32()?,
+            },
+            1 => Self::Setup {
+                sample_rate: reader.f64()?,
+                max_samples_per_block: reader.u32()?,
+            },
+            2 => Self::SetParameter {
+                id: reader.u32()?,
+                value: reader.f64()?,
+            },
+            3 => Self::SaveState,
+            4 => Self::LoadState(reader.bytes()?.to_vec()),
+            5 => Self::Shutdown,
+            tag => return Err(IpcError::UnknownTag(tag)),
+        };
+        reader.finish()?;
+        Ok(request)
+    }
+}
+
+impl Response {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::Processed { num_samples } => {
+                buf.push(0);
+                buf.extend_from_slice(&num_samples.to_le_bytes());
+            }
+            Self::Ok => buf.push(1),
+            Self::State(state) => {
+                buf.push(2);
+                put_bytes(&mut buf, state);
+            }
+            Self::Error(message) => {
+                buf.push(3);
+                put_bytes(&mut buf, message.as_bytes());
+            }
+            Self::Loaded { name } => {
+                buf.push(4);
+                put_bytes(&mut buf, name.as_bytes());
+            }
+        }
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, IpcError> {
+        let mut reader = Reader(bytes);
+        let response = match reader.u8()? {
+            0 => Self::Processed {
+                num_samples: reader.u32()?,
+            },
+            1 => Self::Ok,
+            2 => Self::State(reader.bytes()?.to_vec()),
+            3 => Self::Error(reader.string()?),
+            4 => Self::Loaded {
+                name: reader.string()?,
+            },
+            tag => return Err(IpcError::UnknownTag(tag)),
+        };
+        reader.finish()?;
+        Ok(response)
+    }
+}
+
+/// Write one message as a little-endian u32 length followed by its bytes
+pub fn write_frame(writer: &mut impl Write, message: &[u8]) -> Result<(), IpcError> {
+    if message.len() > MAX_FRAME_SIZE {
+        return Err(IpcError::FrameTooLarge(message.len()));
+    }
+
+    writer.write_all(&(message.len() as u32).to_le_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read one message written by `write_frame`
+pub fn read_frame(reader: &mut impl Read) -> Result<Vec<u8>, IpcError> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(IpcError::FrameTooLarge(length));
+    }
+
+    let mut message = vec![0u8; length];
+    reader.read_exact(&mut message)?;
+    Ok(message)
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], IpcError> {
+        let (head, rest) = self.0.split_first_chunk::<N>().ok_or(IpcError::Truncated)?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, IpcError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, IpcError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, IpcError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], IpcError> {
+        let length = self.u32()? as usize;
+        if length > self.0.len() {
+            return Err(IpcError::Truncated);
+        }
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, IpcError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| IpcError::InvalidString)
+    }
+
+    fn finish(&self) -> Result<(), IpcError> {
+        match self.0.len() {
+            0 => Ok(()),
+            trailing => Err(IpcError::TrailingBytes(trailing)),
+        }
+    }
+}
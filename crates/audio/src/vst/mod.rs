@@ -1 +1,9 @@
+pub mod bundle;
+pub mod events;
 pub mod host;
+pub mod ipc;
+pub mod parameters;
+#[cfg(feature = "sandbox")]
+pub mod sandbox;
+pub mod stream;
+pub mod vst2;
@@ -0,0 +1,451 @@
+use std::cell::UnsafeCell;
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+use ringbuf::traits::{Consumer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use rustc_hash::FxHashMap;
+use vst3::base::funknown::FUnknown_Vtbl;
+use vst3::vst::audio_processor::{
+    IParamValueQueue, IParamValueQueue_Vtbl, IParameterChanges, IParameterChanges_Vtbl,
+};
+
+use super::host::PluginId;
+
+/// Most parameters that can change within one block. Queues are allocated up front so
+/// the audio thread never allocates.
+pub const MAX_PARAMETER_CHANGES: usize = 128;
+
+/// Most points one parameter's queue holds within one block
+const MAX_QUEUE_POINTS: usize = 16;
+
+/// Most changes waiting for the processor, past it new changes are dropped
+pub const PARAMETER_QUEUE_CAPACITY: usize = 1024;
+
+/// Parameter edit a plugin reported through its component handler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParameterEdit {
+    pub plugin_id: PluginId,
+    pub id: u32,
+    pub value: f64,
+}
+
+/// Editor edits the app hasn't been told about yet, from every plugin of an engine.
+/// Shared with the plugins' `ParameterState`, so the app can take them without
+/// holding on to the engine.
+#[derive(Debug, Default)]
+pub struct EditQueue {
+    edits: Mutex<Vec<ParameterEdit>>,
+}
+
+impl EditQueue {
+    fn push(&self, edit: ParameterEdit) {
+        self.edits.lock().unwrap().push(edit);
+    }
+
+    /// Take the edits queued since the last call
+    pub fn take(&self) -> Vec<ParameterEdit> {
+        std::mem::take(&mut *self.edits.lock().unwrap())
+    }
+}
+
+/// Parameter changes on their way to the audio thread.
+///
+/// Any thread may push: the app's commands, the plugin's editor through the component
+/// handler. Pushes are serialized by the producer's mutex, which the audio thread never
+/// takes. Only the audio thread pops, from the consumer side of a lock-free SPSC ring,
+/// so a change is always read whole and the audio thread never waits on a writer. Both
+/// sides are fixed size, nothing allocates once the queue is built.
+pub struct ParameterQueue {
+    producer: Mutex<HeapProd<(u32, f64)>>,
+    // Only touched by the audio thread
+    consumer: UnsafeCell<HeapCons<(u32, f64)>>,
+}
+
+// SAFETY: The producer is behind a mutex, the consumer is only used by the audio thread,
+// see `drain_into`
+unsafe impl Sync for ParameterQueue {}
+unsafe impl Send for ParameterQueue {}
+
+impl Default for ParameterQueue {
+    fn default() -> Self {
+        Self::new(PARAMETER_QUEUE_CAPACITY)
+    }
+}
+
+impl std::fmt::Debug for ParameterQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParameterQueue").finish_non_exhaustive()
+    }
+}
+
+impl ParameterQueue {
+    pub fn new(capacity: usize) -> Self {
+        let (producer, consumer) = HeapRb::new(capacity).split();
+        Self {
+            producer: Mutex::new(producer),
+            consumer: UnsafeCell::new(consumer),
+        }
+    }
+
+    /// Queue a change for the processor's next block. False when the queue is full.
+    pub fn push(&self, id: u32, value: f64) -> bool {
+        self.producer.lock().unwrap().try_push((id, value)).is_ok()
+    }
+
+    /// Move queued changes into the parameter changes of the block about to be processed.
+    /// Changes that don't fit the block stay queued for the next one.
+    ///
+    /// # Safety
+    /// Audio thread only, never called for the same queue from two threads at once.
+    pub unsafe fn drain_into(&self, changes: &mut HostParameterChanges) {
+        let consumer = &mut *self.consumer.get();
+        let accepted = consumer
+            .iter()
+            .take_while(|(id, value)| changes.add_change(*id, 0, *value))
+            .count();
+        consumer.skip(accepted);
+    }
+}
+
+/// Parameter values shared between a plugin's edit controller, the audio thread and
+/// the app. Every change is cached and queued for the processor, edits made in the
+/// plugin's own editor are also queued for the app.
+#[derive(Debug, Default)]
+pub struct ParameterState {
+    values: Mutex<FxHashMap<u32, f64>>,
+    // Changes the processor hasn't seen yet, handed over at the start of its next block
+    pending: ParameterQueue,
+    // Where editor edits are reported, set once the plugin joins an engine
+    edits: Mutex<Option<(PluginId, Arc<EditQueue>)>>,
+}
+
+impl ParameterState {
+    /// Cache a new normalized value and queue it for the processor
+    pub fn set(&self, id: u32, value: f64) {
+        self.values.lock().unwrap().insert(id, value);
+        if !self.pending.push(id, value) {
+            warn!(
+                "Parameter queue full, dropped change of {} to {}",
+                id, value
+            );
+        }
+    }
+
+    /// Like `set`, for an edit made in the plugin's editor the app should mirror
+    pub fn edit(&self, id: u32, value: f64) {
+        self.set(id, value);
+        if let Some((plugin_id, queue)) = &*self.edits.lock().unwrap() {
+            queue.push(ParameterEdit {
+                plugin_id: *plugin_id,
+                id,
+                value,
+            });
+        }
+    }
+
+    /// Report this plugin's editor edits to `queue` as edits of `plugin_id`
+    pub fn report_edits_to(&self, plugin_id: PluginId, queue: Arc<EditQueue>) {
+        *self.edits.lock().unwrap() = Some((plugin_id, queue));
+    }
+
+    /// Last known normalized value of a parameter
+    pub fn value(&self, id: u32) -> Option<f64> {
+        self.values.lock().unwrap().get(&id).copied()
+    }
+
+    /// Move pending changes into the processor's parameter changes for the next block.
+    /// Changes that don't fit this block stay queued.
+    ///
+    /// # Safety
+    /// Audio thread only, see `ParameterQueue::drain_into`.
+    pub unsafe fn drain_into(&self, changes: &mut HostParameterChanges) {
+        self.pending.drain_into(changes);
+    }
+}
+
+/// One parameter's value changes within a block
+#[repr(C)]
+pub struct ParamValueQueue {
+    base: IParamValueQueue,
+    id: u32,
+    points: Vec<(i32, f64)>,
+}
+
+impl ParamValueQueue {
+    fn new() -> Self {
+        Self {
+            base: IParamValueQueue {
+                vtable: &PARAM_VALUE_QUEUE_VTBL,
+            },
+            id: 0,
+            points: Vec::with_capacity(MAX_QUEUE_POINTS),
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// (sample offset, normalized value) pairs in the order they were added
+    pub fn points(&self) -> &[(i32, f64)] {
+        &self.points
+    }
+
+    /// Add a point, replacing one at the same offset. False when the queue is full.
+    fn add_point(&mut self, sample_offset: i32, value: f64) -> Option<usize> {
+        if let Some(index) = self.points.iter().position(|(o, _)| *o == sample_offset) {
+            self.points[index].1 = value;
+            return Some(index);
+        }
+        if self.points.len() == MAX_QUEUE_POINTS {
+            return None;
+        }
+        self.points.push((sample_offset, value));
+        Some(self.points.len() - 1)
+    }
+}
+
+/// IParameterChanges handed to a plugin's processor in ProcessData, refilled every block
+#[repr(C)]
+pub struct HostParameterChanges {
+    base: IParameterChanges,
+    // Never resized, plugins hold queue pointers for the duration of process()
+    queues: Box<[ParamValueQueue]>,
+    count: usize,
+}
+
+impl Default for HostParameterChanges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostParameterChanges {
+    pub fn new() -> Self {
+        Self {
+            base: IParameterChanges {
+                vtable: &PARAMETER_CHANGES_VTBL,
+            },
+            queues: (0..MAX_PARAMETER_CHANGES)
+                .map(|_| ParamValueQueue::new())
+                .collect(),
+            count: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for queue in &mut self.queues[..self.count] {
+            queue.points.clear();
+        }
+        self.count = 0;
+    }
+
+    /// Rescale every point's sample offset by `ratio`, for a plugin running at another
+    /// rate than the block was made for. Offsets stay within `frames`, points landing on
+    /// the same offset keep the later value.
+    pub fn scale_offsets(&mut self, ratio: f64, frames: i32) {
+        let last = (frames - 1).max(0);
+        for queue in &mut self.queues[..self.count] {
+            for (offset, _) in &mut queue.points {
+                *offset = ((*offset as f64 * ratio) as i32).clamp(0, last);
+            }
+            queue.points.dedup_by(|later, earlier| {
+                let same = later.0 == earlier.0;
+                if same {
+                    earlier.1 = later.1;
+                }
+                same
+            });
+        }
+    }
+
+    /// Queues of the parameters changed this block
+    pub fn queues(&self) -> &[ParamValueQueue] {
+        &self.queues[..self.count]
+    }
+
+    /// Add a change at `sample_offset` into the block. False when there's no room left.
+    pub fn add_change(&mut self, id: u32, sample_offset: i32, value: f64) -> bool {
+        match self.queue_index(id) {
+            Some(index) => self.queues[index].add_point(sample_offset, value).is_some(),
+            None => false,
+        }
+    }
+
+    /// Index of the queue for a parameter, taking a free one if it has none yet
+    fn queue_index(&mut self, id: u32) -> Option<usize> {
+        if let Some(index) = self.queues().iter().position(|queue| queue.id == id) {
+            return Some(index);
+        }
+        if self.count == self.queues.len() {
+            return None;
+        }
+
+        self.queues[self.count].id = id;
+        self.count += 1;
+        Some(self.count - 1)
+    }
+}
+
+mod parameter_changes {
+    use std::ffi::c_void;
+
+    use vst3::base::funknown::{FUnknown, Interface, TResult, FUID};
+    use vst3::vst::audio_processor::{IParamValueQueue, IParameterChanges};
+
+    use super::{HostParameterChanges, ParamValueQueue};
+
+    // FUID is passed by value to match the vtable signature
+    #[allow(improper_ctypes_definitions)]
+    pub unsafe extern "C" fn query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IParameterChanges::iid || iid == FUnknown::iid {
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    #[allow(improper_ctypes_definitions)]
+    pub unsafe extern "C" fn queue_query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IParamValueQueue::iid || iid == FUnknown::iid {
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    pub unsafe extern "C" fn add_ref(_this: *mut FUnknown) -> u32 {
+        1
+    }
+
+    pub unsafe extern "C" fn release(_this: *mut FUnknown) -> u32 {
+        1
+    }
+
+    pub unsafe extern "C" fn get_parameter_count(this: *mut IParameterChanges) -> i32 {
+        (*(this as *mut HostParameterChanges)).count as i32
+    }
+
+    pub unsafe extern "C" fn get_parameter_data(
+        this: *mut IParameterChanges,
+        index: i32,
+    ) -> *mut IParamValueQueue {
+        let changes = &mut *(this as *mut HostParameterChanges);
+        if index < 0 || index as usize >= changes.count {
+            return std::ptr::null_mut();
+        }
+        &mut changes.queues[index as usize] as *mut ParamValueQueue as *mut IParamValueQueue
+    }
+
+    pub unsafe extern "C" fn add_parameter_data(
+        this: *mut IParameterChanges,
+        id: *const u32,
+        index: *mut i32,
+    ) -> *mut IParamValueQueue {
+        let changes = &mut *(this as *mut HostParameterChanges);
+        if id.is_null() {
+            return std::ptr::null_mut();
+        }
+        let Some(queue) = changes.queue_index(*id) else {
+            return std::ptr::null_mut();
+        };
+
+        if !index.is_null() {
+            *index = queue as i32;
+        }
+        &mut changes.queues[queue] as *mut ParamValueQueue as *mut IParamValueQueue
+    }
+
+    pub unsafe extern "C" fn get_parameter_id(this: *mut IParamValueQueue) -> u32 {
+        (*(this as *mut ParamValueQueue)).id
+    }
+
+    pub unsafe extern "C" fn get_point_count(this: *mut IParamValueQueue) -> i32 {
+        (*(this as *mut ParamValueQueue)).points.len() as i32
+    }
+
+    pub unsafe extern "C" fn get_point(
+        this: *mut IParamValueQueue,
+        index: i32,
+        sample_offset: *mut i32,
+        value: *mut f64,
+    ) -> TResult {
+        let queue = &*(this as *mut ParamValueQueue);
+        let Some((offset, point)) = usize::try_from(index)
+            .ok()
+            .and_then(|index| queue.points.get(index))
+        else {
+            return TResult::InvalidArgument;
+        };
+
+        if !sample_offset.is_null() {
+            *sample_offset = *offset;
+        }
+        if !value.is_null() {
+            *value = *point;
+        }
+        TResult::ResultOk
+    }
+
+    pub unsafe extern "C" fn add_point(
+        this: *mut IParamValueQueue,
+        sample_offset: i32,
+        value: f64,
+        index: *mut i32,
+    ) -> TResult {
+        let queue = &mut *(this as *mut ParamValueQueue);
+        match queue.add_point(sample_offset, value) {
+            Some(added) => {
+                if !index.is_null() {
+                    *index = added as i32;
+                }
+                TResult::ResultOk
+            }
+            None => TResult::ResultFalse,
+        }
+    }
+}
+
+static PARAMETER_CHANGES_VTBL: IParameterChanges_Vtbl = IParameterChanges_Vtbl {
+    base: FUnknown_Vtbl {
+        query_interface: parameter_changes::query_interface,
+        add_ref: parameter_changes::add_ref,
+        release: parameter_changes::release,
+    },
+    get_parameter_count: parameter_changes::get_parameter_count,
+    get_parameter_data: parameter_changes::get_parameter_data,
+    add_parameter_data: parameter_changes::add_parameter_data,
+};
+
+static PARAM_VALUE_QUEUE_VTBL: IParamValueQueue_Vtbl = IParamValueQueue_Vtbl {
+    base: FUnknown_Vtbl {
+        query_interface: parameter_changes::queue_query_interface,
+        add_ref: parameter_changes::add_ref,
+        release: parameter_changes::release,
+    },
+    get_parameter_id: parameter_changes::get_parameter_id,
+    get_point_count: parameter_changes::get_point_count,
+    get_point: parameter_changes::get_point,
+    add_point: parameter_changes::add_point,
+};
+
+impl std::fmt::Debug for HostParameterChanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.queues().iter().map(|queue| (queue.id, &queue.points)))
+            .finish()
+    }
+}
@@ -0,0 +1,659 @@
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info, warn};
+use memmap2::MmapMut;
+use vst3::base::funknown::{
+    FUnknown, FUnknown_Vtbl, IAudioProcessor, IAudioProcessor_Impl, IAudioProcessor_Vtbl,
+    Interface, TResult, FUID,
+};
+use vst3::vst::audio_processor::speaker_arr::SpeakerArrangement;
+use vst3::vst::audio_processor::{
+    AudioBusBuffers, BusDirection, ProcessData, ProcessMode, ProcessSetup, SymbolicSampleSize,
+};
+use vst3::VSTPtr;
+
+use super::host::{PluginId, VSTHostContext};
+use super::ipc::{read_frame, write_frame, IpcError, Request, Response};
+use crate::MAX_BLOCK_SIZE;
+
+/// Hidden command line flag that makes the app host a single plugin for a sandboxed slot
+pub const HOST_PLUGIN_FLAG: &str = "--host-plugin";
+
+/// Channels per direction the shared buffer has room for
+const SANDBOX_CHANNELS: usize = 2;
+
+/// How long a host gets to exit after `Shutdown` before it's killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the audio thread waits for a processed block. Well past any device block's
+/// budget, a host that misses it is treated as hung and passed through from then on.
+pub const PROCESS_TIMEOUT: Duration = Duration::from_millis(100);
+
+static NEXT_BUFFER: AtomicU64 = AtomicU64::new(0);
+
+/// Audio exchanged with a plugin host, mapped from a temp file by both processes.
+/// Holds the input channels followed by the output channels, `MAX_BLOCK_SIZE` samples each.
+pub struct SharedBuffer {
+    map: MmapMut,
+    path: PathBuf,
+    // Only the app side removes the file
+    owner: bool,
+    _file: File,
+}
+
+impl SharedBuffer {
+    const LEN: usize = 2 * SANDBOX_CHANNELS * MAX_BLOCK_SIZE;
+
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        file.set_len((Self::LEN * size_of::<f32>()) as u64)?;
+
+        Self::map(file, path, true)
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        Self::map(file, path, false)
+    }
+
+    fn map(file: File, path: &Path, owner: bool) -> Result<Self> {
+        // SAFETY: The file is private to the app and its host, neither resizes it
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        if map.len() < Self::LEN * size_of::<f32>() {
+            return Err(anyhow!("Shared buffer {} is too small", path.display()));
+        }
+
+        Ok(Self {
+            map,
+            path: path.to_path_buf(),
+            owner,
+            _file: file,
+        })
+    }
+
+    /// Pointers to the input channels followed by the output channels
+    pub fn channels(&mut self) -> [*mut f32; 2 * SANDBOX_CHANNELS] {
+        let base = self.map.as_mut_ptr() as *mut f32;
+        std::array::from_fn(|i| unsafe { base.add(i * MAX_BLOCK_SIZE) })
+    }
+}
+
+impl Drop for SharedBuffer {
+    fn drop(&mut self) {
+        if self.owner {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// App side of a plugin running in a `--host-plugin` child process.
+/// Once the host dies every request fails and the plugin is passed through.
+pub struct SandboxClient {
+    child: Child,
+    requests: ChildStdin,
+    // Read on a thread of their own, so waiting for one can time out
+    responses: Receiver<Result<Response, IpcError>>,
+    // Reused for every request, `Process` never outgrows it
+    request_buffer: Vec<u8>,
+    buffer: SharedBuffer,
+    crashed: bool,
+}
+
+impl SandboxClient {
+    /// Start a host for the plugin at `path` and wait until it has loaded it.
+    /// Returns the client and the plugin name reported by the host.
+    pub fn spawn(path: &str) -> Result<(Self, String)> {
+        let buffer_path = std::env::temp_dir().join(format!(
+            "sona-sandbox-{}-{}.buf",
+            std::process::id(),
+            NEXT_BUFFER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let buffer = SharedBuffer::create(&buffer_path)?;
+
+        let mut child = Command::new(std::env::current_exe()?)
+            .arg(HOST_PLUGIN_FLAG)
+            .arg(path)
+            .arg(&buffer_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .context("Failed to start the plugin host")?;
+
+        let requests = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+
+        // Ends with the first pipe error, once the host exits or the client is dropped
+        let (sender, responses) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("sandbox-responses".to_string())
+            .spawn(move || loop {
+                let response = read_frame(&mut stdout).and_then(|frame| Response::decode(&frame));
+                let failed = response.is_err();
+                if sender.send(response).is_err() || failed {
+                    break;
+                }
+            })?;
+
+        let mut client = Self {
+            child,
+            requests,
+            responses,
+            request_buffer: Vec::with_capacity(64),
+            buffer,
+            crashed: false,
+        };
+
+        match client.receive(None)? {
+            Response::Loaded { name } => {
+                info!("Plugin host {} loaded {}", client.child.id(), name);
+                Ok((client, name))
+            }
+            Response::Error(err) => Err(anyhow!("Plugin host failed to load {}: {}", path, err)),
+            other => Err(anyhow!("Unexpected response from plugin host: {:?}", other)),
+        }
+    }
+
+    pub fn is_crashed(&self) -> bool {
+        self.crashed
+    }
+
+    /// Send a request and wait for its response. Any pipe error marks the host as crashed.
+    pub fn request(&mut self, request: &Request) -> Result<Response> {
+        self.request_within(request, None)
+    }
+
+    /// Send a request and wait at most `timeout` for its response, a host that misses it
+    /// is marked as crashed like one whose pipe broke
+    fn request_within(&mut self, request: &Request, timeout: Option<Duration>) -> Result<Response> {
+        if self.crashed {
+            return Err(anyhow!("Plugin host has exited"));
+        }
+
+        request.encode_into(&mut self.request_buffer);
+        write_frame(&mut self.requests, &self.request_buffer).map_err(|e| self.crash(e))?;
+        self.receive(timeout)
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Response> {
+        receive_response(&self.responses, timeout).map_err(|e| self.crash(e))
+    }
+
+    fn crash(&mut self, err: IpcError) -> anyhow::Error {
+        if !self.crashed {
+            self.crashed = true;
+            let status = self.child.try_wait().ok().flatten();
+            error!(
+                "Plugin host {} stopped responding ({}), exit status: {:?}",
+                self.child.id(),
+                err,
+                status
+            );
+        }
+        anyhow!("Plugin host has exited: {}", err)
+    }
+
+    pub fn set_parameter(&mut self, id: u32, value: f64) -> Result<()> {
+        expect_ok(self.request(&Request::SetParameter { id, value })?)
+    }
+
+    pub fn save_state(&mut self) -> Result<Vec<u8>> {
+        match self.request(&Request::SaveState)? {
+            Response::State(state) => Ok(state),
+            other => expect_ok(other).and(Err(anyhow!("Plugin host sent no state"))),
+        }
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        expect_ok(self.request(&Request::LoadState(state.to_vec()))?)
+    }
+
+    /// Run one block through the host. `inputs` and `outputs` hold a pointer per channel.
+    unsafe fn process(
+        &mut self,
+        inputs: &[*mut f32],
+        outputs: &[*mut f32],
+        num_samples: usize,
+    ) -> Result<()> {
+        let channels = self.buffer.channels();
+        let (shared_inputs, shared_outputs) = channels.split_at(SANDBOX_CHANNELS);
+
+        for (j, shared) in shared_inputs.iter().enumerate().take(outputs.len()) {
+            match inputs.get(j) {
+                Some(input) => std::ptr::copy_nonoverlapping(*input, *shared, num_samples),
+                None => std::ptr::write_bytes(*shared, 0, num_samples),
+            }
+        }
+
+        let request = Request::Process {
+            num_samples: num_samples as u32,
+            channels: outputs.len() as u32,
+        };
+        match self.request_within(&request, Some(PROCESS_TIMEOUT))? {
+            Response::Processed { .. } => {
+                for (output, shared) in outputs.iter().zip(shared_outputs) {
+                    std::ptr::copy_nonoverlapping(*shared, *output, num_samples);
+                }
+                Ok(())
+            }
+            Response::Error(err) => Err(anyhow!(err)),
+            other => Err(anyhow!("Unexpected response from plugin host: {:?}", other)),
+        }
+    }
+}
+
+/// Wait for the next response read from the host, at most `timeout` if given
+pub fn receive_response(
+    responses: &Receiver<Result<Response, IpcError>>,
+    timeout: Option<Duration>,
+) -> Result<Response, IpcError> {
+    let disconnected = || IpcError::Io(io::ErrorKind::UnexpectedEof.into());
+    match timeout {
+        Some(timeout) => match responses.recv_timeout(timeout) {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => Err(IpcError::TimedOut(timeout)),
+            Err(RecvTimeoutError::Disconnected) => Err(disconnected()),
+        },
+        None => responses.recv().unwrap_or_else(|_| Err(disconnected())),
+    }
+}
+
+fn expect_ok(response: Response) -> Result<()> {
+    match response {
+        Response::Ok => Ok(()),
+        Response::Error(err) => Err(anyhow!(err)),
+        other => Err(anyhow!("Unexpected response from plugin host: {:?}", other)),
+    }
+}
+
+impl Drop for SandboxClient {
+    fn drop(&mut self) {
+        if !self.crashed {
+            Request::Shutdown.encode_into(&mut self.request_buffer);
+            let _ = write_frame(&mut self.requests, &self.request_buffer);
+        }
+
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        warn!("Plugin host {} didn't exit, killing it", self.child.id());
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl VSTHostContext {
+    /// Load a plugin in its own `--host-plugin` process, so a crash only silences that
+    /// plugin's processing. Only the audio processor is forwarded, there is no editor.
+    pub fn sandboxed(path: &str) -> Result<Self> {
+        let (client, name) = SandboxClient::spawn(path)?;
+        let client = Arc::new(Mutex::new(client));
+
+        let mut ctx = Self::default();
+        ctx.id = PluginId::new();
+        ctx.name = name;
+        ctx.path = path.to_string();
+        ctx.processor = Some(proxy::processor(client.clone()));
+        ctx.sandbox = Some(client);
+        Ok(ctx)
+    }
+}
+
+/// Entry point of the `--host-plugin <path> <buffer>` child process. Loads the plugin and
+/// serves requests from stdin until told to shut down or the app goes away.
+pub fn run_plugin_host(path: &str, buffer_path: &str) -> i32 {
+    let mut requests = io::stdin().lock();
+    let mut responses = match protocol_output() {
+        Ok(output) => output,
+        Err(err) => {
+            error!("Failed to set up the plugin host output: {}", err);
+            return 1;
+        }
+    };
+
+    let mut respond = |response: Response| write_frame(&mut responses, &response.encode()).is_ok();
+
+    let mut buffer = match SharedBuffer::open(Path::new(buffer_path)) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            respond(Response::Error(format!("{:#}", err)));
+            return 1;
+        }
+    };
+
+    let mut plugin = match VSTHostContext::new(path) {
+        Ok(plugin) => plugin,
+        Err(err) => {
+            respond(Response::Error(format!("{:#}", err)));
+            return 1;
+        }
+    };
+    match plugin.processor.as_mut() {
+        Some(processor) => unsafe {
+            processor.set_processing(true);
+        },
+        None => {
+            respond(Response::Error("Plugin has no audio processor".to_string()));
+            return 1;
+        }
+    }
+
+    if !respond(Response::Loaded {
+        name: plugin.name.clone(),
+    }) {
+        return 0;
+    }
+
+    let channels = buffer.channels();
+    let (inputs, outputs) = channels.split_at(SANDBOX_CHANNELS);
+    let mut inputs = inputs.to_vec();
+    let mut outputs = outputs.to_vec();
+
+    loop {
+        let request = match read_frame(&mut requests).and_then(|frame| Request::decode(&frame)) {
+            Ok(request) => request,
+            // The app closed the pipe, nothing left to serve
+            Err(IpcError::Io(_)) => return 0,
+            Err(err) => {
+                error!("Invalid request: {}", err);
+                return 1;
+            }
+        };
+
+        let response = match request {
+            Request::Process {
+                num_samples,
+                channels,
+            } => {
+                let num_samples = (num_samples as usize).min(MAX_BLOCK_SIZE);
+                let channels = (channels as i32).clamp(1, SANDBOX_CHANNELS as i32);
+
+                let mut in_bus = AudioBusBuffers {
+                    num_channels: channels,
+                    silence_flags: 0,
+                    channel_buffers_32: inputs.as_mut_ptr(),
+                };
+                let mut out_bus = AudioBusBuffers {
+                    num_channels: channels,
+                    silence_flags: 0,
+                    channel_buffers_32: outputs.as_mut_ptr(),
+                };
+                let mut data = ProcessData {
+                    process_mode: ProcessMode::Realtime,
+                    symbolic_sample_size: SymbolicSampleSize::Sample32,
+                    num_samples: num_samples as i32,
+                    num_inputs: 1,
+                    num_outputs: 1,
+                    inputs: &mut in_bus,
+                    outputs: &mut out_bus,
+                    input_parameter_changes: std::ptr::null_mut(),
+                    output_parameter_changes: std::ptr::null_mut(),
+                    input_events: std::ptr::null_mut(),
+                    output_events: std::ptr::null_mut(),
+                    process_context: std::ptr::null_mut(),
+                };
+
+                let processor = plugin.processor.as_mut().unwrap();
+                match unsafe { processor.process(&mut data) } {
+                    TResult::ResultOk => Response::Processed {
+                        num_samples: num_samples as u32,
+                    },
+                    err => Response::Error(format!("process failed: {}", err)),
+                }
+            }
+            Request::Setup {
+                sample_rate,
+                max_samples_per_block,
+            } => match plugin.reconfigure(
+                sample_rate,
+                max_samples_per_block as i32,
+                ProcessMode::Realtime,
+            ) {
+                Ok(()) => Response::Ok,
+                Err(err) => Response::Error(err.to_string()),
+            },
+            Request::SetParameter { id, value } => match plugin.set_parameter(id, value) {
+                Ok(()) => Response::Ok,
+                Err(err) => Response::Error(err.to_string()),
+            },
+            Request::SaveState => match plugin.save_state() {
+                Ok(state) => Response::State(state),
+                Err(err) => Response::Error(err.to_string()),
+            },
+            Request::LoadState(state) => match plugin.load_state(&state) {
+                Ok(()) => Response::Ok,
+                Err(err) => Response::Error(err.to_string()),
+            },
+            Request::Shutdown => {
+                respond(Response::Ok);
+                return 0;
+            }
+        };
+
+        if !respond(response) {
+            return 0;
+        }
+    }
+}
+
+/// Stream the host writes responses to. Plugins printing to stdout would corrupt the
+/// protocol, so on unix the real stdout is moved to a new descriptor and stdout is
+/// pointed at stderr.
+#[cfg(unix)]
+fn protocol_output() -> io::Result<Box<dyn Write>> {
+    use std::os::fd::FromRawFd;
+
+    unsafe {
+        let fd = libc::dup(libc::STDOUT_FILENO);
+        if fd < 0 || libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Box::new(File::from_raw_fd(fd)))
+    }
+}
+
+#[cfg(not(unix))]
+fn protocol_output() -> io::Result<Box<dyn Write>> {
+    Ok(Box::new(io::stdout()))
+}
+
+/// IAudioProcessor forwarding to a `SandboxClient`
+mod proxy {
+    use super::*;
+
+    #[repr(C)]
+    struct ProcessorProxy {
+        base: IAudioProcessor,
+        references: AtomicU32,
+        client: Arc<Mutex<SandboxClient>>,
+    }
+
+    pub fn processor(client: Arc<Mutex<SandboxClient>>) -> VSTPtr<IAudioProcessor> {
+        let proxy = Box::new(ProcessorProxy {
+            base: IAudioProcessor {
+                vtable: &PROCESSOR_VTBL,
+            },
+            references: AtomicU32::new(1),
+            client,
+        });
+        VSTPtr::new(Box::into_raw(proxy) as *mut IAudioProcessor)
+    }
+
+    // FUID is passed by value to match the vtable signature
+    #[allow(improper_ctypes_definitions)]
+    unsafe extern "C" fn query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IAudioProcessor::iid || iid == FUnknown::iid {
+            add_ref(this);
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    unsafe extern "C" fn add_ref(this: *mut FUnknown) -> u32 {
+        (*(this as *mut ProcessorProxy))
+            .references
+            .fetch_add(1, Ordering::Relaxed)
+            + 1
+    }
+
+    unsafe extern "C" fn release(this: *mut FUnknown) -> u32 {
+        let remaining = (*(this as *mut ProcessorProxy))
+            .references
+            .fetch_sub(1, Ordering::AcqRel)
+            - 1;
+        if remaining == 0 {
+            drop(Box::from_raw(this as *mut ProcessorProxy));
+        }
+        remaining
+    }
+
+    unsafe extern "C" fn set_bus_arrangements(
+        _this: *mut IAudioProcessor,
+        _inputs: *mut SpeakerArrangement,
+        _num_inputs: i32,
+        _outputs: *mut SpeakerArrangement,
+        _num_outputs: i32,
+    ) -> TResult {
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn get_bus_arrangements(
+        _this: *mut IAudioProcessor,
+        _dir: BusDirection,
+        _index: i32,
+        _arr: *mut SpeakerArrangement,
+    ) -> TResult {
+        TResult::NotImplemented
+    }
+
+    unsafe extern "C" fn can_process_sample_size(
+        _this: *mut IAudioProcessor,
+        symbolic_sample_size: SymbolicSampleSize,
+    ) -> TResult {
+        match symbolic_sample_size {
+            SymbolicSampleSize::Sample32 => TResult::ResultOk,
+            _ => TResult::ResultFalse,
+        }
+    }
+
+    unsafe extern "C" fn get_latency_samples(_this: *mut IAudioProcessor) -> u32 {
+        0
+    }
+
+    unsafe extern "C" fn setup_processing(
+        this: *mut IAudioProcessor,
+        setup: *mut ProcessSetup,
+    ) -> TResult {
+        let proxy = &*(this as *mut ProcessorProxy);
+        let setup = &*setup;
+        let mut client = proxy.client.lock().unwrap();
+
+        match client.request(&Request::Setup {
+            sample_rate: setup.sample_rate,
+            max_samples_per_block: setup.max_samples_per_block.max(0) as u32,
+        }) {
+            Ok(Response::Ok) => TResult::ResultOk,
+            Ok(response) => {
+                warn!("Plugin host rejected setup: {:?}", response);
+                TResult::InternalError
+            }
+            Err(_) => TResult::InternalError,
+        }
+    }
+
+    unsafe extern "C" fn set_processing(_this: *mut IAudioProcessor, _state: bool) -> TResult {
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn process(this: *mut IAudioProcessor, data: *mut ProcessData) -> TResult {
+        let proxy = &*(this as *mut ProcessorProxy);
+        let data = &*data;
+        if data.num_outputs < 1 || data.outputs.is_null() {
+            return TResult::InvalidArgument;
+        }
+
+        let num_samples = (data.num_samples.max(0) as usize).min(MAX_BLOCK_SIZE);
+        let output = &*data.outputs;
+        let outputs = std::slice::from_raw_parts(
+            output.channel_buffers_32,
+            (output.num_channels.max(0) as usize).min(SANDBOX_CHANNELS),
+        );
+        let inputs = if data.num_inputs > 0 && !data.inputs.is_null() {
+            let input = &*data.inputs;
+            std::slice::from_raw_parts(
+                input.channel_buffers_32,
+                (input.num_channels.max(0) as usize).min(SANDBOX_CHANNELS),
+            )
+        } else {
+            &[]
+        };
+
+        // The main thread may be in a slow request, such as saving state
+        let processed = proxy.client.try_lock().is_ok_and(|mut client| {
+            !client.is_crashed() && client.process(inputs, outputs, num_samples).is_ok()
+        });
+        if !processed {
+            // Pass the input through, a dead plugin shouldn't silence the chain
+            for (j, output) in outputs.iter().enumerate() {
+                match inputs.get(j) {
+                    Some(input) => std::ptr::copy(*input, *output, num_samples),
+                    None => std::ptr::write_bytes(*output, 0, num_samples),
+                }
+            }
+        }
+
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn get_tail_samples(_this: *mut IAudioProcessor) -> u32 {
+        0
+    }
+
+    static PROCESSOR_VTBL: IAudioProcessor_Vtbl = IAudioProcessor_Vtbl {
+        base: FUnknown_Vtbl {
+            query_interface,
+            add_ref,
+            release,
+        },
+        set_bus_arrangements,
+        get_bus_arrangements,
+        can_process_sample_size,
+        get_latency_samples,
+        setup_processing,
+        set_processing,
+        process,
+        get_tail_samples,
+    };
+}
@@ -0,0 +1,165 @@
+use std::ffi::c_void;
+
+use vst3::base::funknown::{FUnknown_Vtbl, IBStream, IBStream_Vtbl};
+
+/// Growable in-memory IBStream for saving and restoring plugin state.
+/// The stream is owned by the host, plugins must not keep it past the call.
+#[repr(C)]
+pub struct MemoryStream {
+    base: IBStream,
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl MemoryStream {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            base: IBStream {
+                vtable: &MEMORY_STREAM_VTBL,
+            },
+            data,
+            position: 0,
+        }
+    }
+
+    /// Pointer to pass as the `state` argument of getState/setState
+    pub fn as_ptr(&mut self) -> *mut c_void {
+        self as *mut Self as *mut c_void
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+mod memory_stream {
+    use std::ffi::c_void;
+
+    use vst3::base::funknown::{FUnknown, IBStream, Interface, SeekMode, TResult, FUID};
+
+    use super::MemoryStream;
+
+    // FUID is passed by value to match the vtable signature
+    #[allow(improper_ctypes_definitions)]
+    pub unsafe extern "C" fn query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IBStream::iid || iid == FUnknown::iid {
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    pub unsafe extern "C" fn add_ref(_this: *mut FUnknown) -> u32 {
+        1
+    }
+
+    pub unsafe extern "C" fn release(_this: *mut FUnknown) -> u32 {
+        1
+    }
+
+    pub unsafe extern "C" fn read(
+        this: *mut IBStream,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_read: *mut i32,
+    ) -> TResult {
+        let stream = &mut *(this as *mut MemoryStream);
+        if num_bytes < 0 || (buffer.is_null() && num_bytes > 0) {
+            return TResult::InvalidArgument;
+        }
+
+        let available = stream.data.len().saturating_sub(stream.position);
+        let count = available.min(num_bytes as usize);
+        if count > 0 {
+            std::ptr::copy_nonoverlapping(
+                stream.data.as_ptr().add(stream.position),
+                buffer as *mut u8,
+                count,
+            );
+        }
+        stream.position += count;
+
+        if !num_bytes_read.is_null() {
+            *num_bytes_read = count as i32;
+        }
+        TResult::ResultOk
+    }
+
+    pub unsafe extern "C" fn write(
+        this: *mut IBStream,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_written: *mut i32,
+    ) -> TResult {
+        let stream = &mut *(this as *mut MemoryStream);
+        if num_bytes < 0 || (buffer.is_null() && num_bytes > 0) {
+            return TResult::InvalidArgument;
+        }
+
+        let count = num_bytes as usize;
+        let end = stream.position + count;
+        if end > stream.data.len() {
+            stream.data.resize(end, 0);
+        }
+        if count > 0 {
+            std::ptr::copy_nonoverlapping(
+                buffer as *const u8,
+                stream.data.as_mut_ptr().add(stream.position),
+                count,
+            );
+        }
+        stream.position = end;
+
+        if !num_bytes_written.is_null() {
+            *num_bytes_written = num_bytes;
+        }
+        TResult::ResultOk
+    }
+
+    pub unsafe extern "C" fn seek(
+        this: *mut IBStream,
+        pos: i64,
+        mode: i32,
+        result: *mut i64,
+    ) -> TResult {
+        let stream = &mut *(this as *mut MemoryStream);
+        let base = match mode {
+            SeekMode::Set => 0,
+            SeekMode::Cur => stream.position as i64,
+            SeekMode::End => stream.data.len() as i64,
+            _ => return TResult::InvalidArgument,
+        };
+
+        stream.position = (base + pos).max(0) as usize;
+        if !result.is_null() {
+            *result = stream.position as i64;
+        }
+        TResult::ResultOk
+    }
+
+    pub unsafe extern "C" fn tell(this: *mut IBStream, pos: *mut i64) -> TResult {
+        if pos.is_null() {
+            return TResult::InvalidArgument;
+        }
+        *pos = (*(this as *mut MemoryStream)).position as i64;
+        TResult::ResultOk
+    }
+}
+
+static MEMORY_STREAM_VTBL: IBStream_Vtbl = IBStream_Vtbl {
+    base: FUnknown_Vtbl {
+        query_interface: memory_stream::query_interface,
+        add_ref: memory_stream::add_ref,
+        release: memory_stream::release,
+    },
+    read: memory_stream::read,
+    write: memory_stream::write,
+    seek: memory_stream::seek,
+    tell: memory_stream::tell,
+};
@@ -0,0 +1,941 @@
+use std::ffi::{c_char, c_void, CStr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use libloading::{Library, Symbol};
+use log::{info, trace};
+use vst3::base::funknown::IAudioProcessor_Impl;
+use vst3::vst::audio_processor::{ProcessMode, ProcessSetup, SymbolicSampleSize};
+
+use super::host::{BusDescription, PluginFormat, PluginId, PluginMetadata, VSTHostContext};
+use super::parameters::ParameterState;
+
+/// `'VstP'`, the magic every `AEffect` starts with
+pub(crate) const EFFECT_MAGIC: i32 = 0x5673_7450;
+
+/// Version reported for `audioMasterVersion`, VST 2.4
+const HOST_VERSION: isize = 2400;
+
+/// Class category VST3 factories use for effects, reused so both formats list alike
+const AUDIO_MODULE_CATEGORY: &str = "Audio Module Class";
+
+/// Longest string any of the `effGet*` string opcodes writes, with room to spare for
+/// plugins that ignore the documented limits
+const STRING_CAPACITY: usize = 256;
+
+/// Dispatcher opcodes the host sends to the plugin
+pub(crate) mod opcode {
+    pub const OPEN: i32 = 0;
+    pub const CLOSE: i32 = 1;
+    pub const GET_PARAM_NAME: i32 = 8;
+    pub const SET_SAMPLE_RATE: i32 = 10;
+    pub const SET_BLOCK_SIZE: i32 = 11;
+    pub const MAINS_CHANGED: i32 = 12;
+    pub const EDIT_GET_RECT: i32 = 13;
+    pub const EDIT_OPEN: i32 = 14;
+    pub const EDIT_CLOSE: i32 = 15;
+    pub const GET_CHUNK: i32 = 23;
+    pub const SET_CHUNK: i32 = 24;
+    pub const GET_EFFECT_NAME: i32 = 45;
+    pub const GET_VENDOR_STRING: i32 = 47;
+    pub const GET_TAIL_SIZE: i32 = 52;
+    pub const START_PROCESS: i32 = 71;
+    pub const STOP_PROCESS: i32 = 72;
+}
+
+/// `audioMaster` opcodes the plugin sends to the host
+pub(crate) mod host_opcode {
+    pub const AUTOMATE: i32 = 0;
+    pub const VERSION: i32 = 1;
+    pub const SIZE_WINDOW: i32 = 15;
+    pub const GET_SAMPLE_RATE: i32 = 16;
+    pub const GET_BLOCK_SIZE: i32 = 17;
+    pub const GET_VENDOR_STRING: i32 = 32;
+    pub const GET_PRODUCT_STRING: i32 = 33;
+    pub const GET_VENDOR_VERSION: i32 = 34;
+}
+
+pub(crate) mod flags {
+    pub const HAS_EDITOR: i32 = 1;
+    pub const CAN_REPLACING: i32 = 1 << 4;
+    pub const PROGRAM_CHUNKS: i32 = 1 << 5;
+}
+
+pub(crate) type HostCallback =
+    unsafe extern "C" fn(*mut AEffect, i32, i32, isize, *mut c_void, f32) -> isize;
+pub(crate) type EntryProc = unsafe extern "C" fn(HostCallback) -> *mut AEffect;
+type DispatcherProc =
+    unsafe extern "C" fn(*mut AEffect, i32, i32, isize, *mut c_void, f32) -> isize;
+type ProcessProc = unsafe extern "C" fn(*mut AEffect, *mut *mut f32, *mut *mut f32, i32);
+type SetParameterProc = unsafe extern "C" fn(*mut AEffect, i32, f32);
+type GetParameterProc = unsafe extern "C" fn(*mut AEffect, i32) -> f32;
+
+/// The classic VST 2.4 plugin instance, as laid out by `aeffect.h`
+#[repr(C)]
+pub struct AEffect {
+    pub(crate) magic: i32,
+    pub(crate) dispatcher: DispatcherProc,
+    pub(crate) process: Option<ProcessProc>,
+    pub(crate) set_parameter: SetParameterProc,
+    pub(crate) get_parameter: GetParameterProc,
+    pub(crate) num_programs: i32,
+    pub(crate) num_params: i32,
+    pub(crate) num_inputs: i32,
+    pub(crate) num_outputs: i32,
+    pub(crate) flags: i32,
+    pub(crate) reserved1: isize,
+    pub(crate) reserved2: isize,
+    pub(crate) initial_delay: i32,
+    pub(crate) real_qualities: i32,
+    pub(crate) off_qualities: i32,
+    pub(crate) io_ratio: f32,
+    pub(crate) object: *mut c_void,
+    pub(crate) user: *mut c_void,
+    pub(crate) unique_id: i32,
+    pub(crate) version: i32,
+    pub(crate) process_replacing: Option<ProcessProc>,
+    pub(crate) process_double_replacing: Option<unsafe extern "C" fn()>,
+    pub(crate) future: [c_char; 56],
+}
+
+/// Editor rectangle returned by `effEditGetRect`
+#[repr(C)]
+pub(crate) struct ERect {
+    pub(crate) top: i16,
+    pub(crate) left: i16,
+    pub(crate) bottom: i16,
+    pub(crate) right: i16,
+}
+
+/// What the host callback needs to answer a plugin, reached through `AEffect::user`
+#[derive(Default)]
+struct HostData {
+    parameters: Arc<ParameterState>,
+    sample_rate: AtomicU32,
+    block_size: AtomicU32,
+    /// Editor view while it's open, told about `audioMasterSizeWindow`
+    view: AtomicPtr<c_void>,
+}
+
+/// A loaded VST2 plugin. The library stays loaded as long as the effect exists.
+pub struct Effect {
+    effect: *mut AEffect,
+    host: Box<HostData>,
+    // Dropped last, the plugin's code lives in it
+    _library: Option<Library>,
+}
+
+unsafe impl Send for Effect {}
+unsafe impl Sync for Effect {}
+
+impl Effect {
+    pub fn load(path: &Path) -> Result<Self> {
+        unsafe {
+            let library = Library::new(binary_path(path))?;
+
+            let entry: Symbol<EntryProc> = library
+                .get(b"VSTPluginMain\0")
+                .or_else(|_| library.get(b"main\0"))?;
+            let entry = *entry;
+            Self::from_entry(entry, Some(library))
+                .ok_or_else(|| anyhow!("{} is not a VST2 plugin", path.display()))
+        }
+    }
+
+    /// Internal helper for `load`, create the effect through its entry point and open it.
+    /// In-process plugins, like the ones tests use, have no library.
+    pub(crate) unsafe fn from_entry(entry: EntryProc, library: Option<Library>) -> Option<Self> {
+        let effect = entry(host_callback);
+        if effect.is_null() || (*effect).magic != EFFECT_MAGIC {
+            return None;
+        }
+
+        let host = Box::<HostData>::default();
+        host.sample_rate
+            .store(48000.0f32.to_bits(), Ordering::Relaxed);
+        host.block_size.store(2048, Ordering::Relaxed);
+        (*effect).user = &*host as *const HostData as *mut c_void;
+
+        let effect = Self {
+            effect,
+            host,
+            _library: library,
+        };
+        effect.dispatch(opcode::OPEN, 0, 0, std::ptr::null_mut(), 0.0);
+
+        Some(effect)
+    }
+
+    fn dispatch(&self, opcode: i32, index: i32, value: isize, ptr: *mut c_void, opt: f32) -> isize {
+        unsafe { ((*self.effect).dispatcher)(self.effect, opcode, index, value, ptr, opt) }
+    }
+
+    fn string(&self, opcode: i32, index: i32) -> String {
+        let mut buffer = [0 as c_char; STRING_CAPACITY];
+        self.dispatch(opcode, index, 0, buffer.as_mut_ptr() as *mut c_void, 0.0);
+        buffer[STRING_CAPACITY - 1] = 0;
+        unsafe { CStr::from_ptr(buffer.as_ptr()) }
+            .to_string_lossy()
+            .trim()
+            .to_string()
+    }
+
+    pub fn name(&self) -> String {
+        self.string(opcode::GET_EFFECT_NAME, 0)
+    }
+
+    pub fn vendor(&self) -> String {
+        self.string(opcode::GET_VENDOR_STRING, 0)
+    }
+
+    /// The plugin's four character ID, as eight hex digits
+    pub fn uid(&self) -> String {
+        format!("{:08X}", unsafe { (*self.effect).unique_id })
+    }
+
+    pub fn parameter_count(&self) -> u32 {
+        unsafe { (*self.effect).num_params.max(0) as u32 }
+    }
+
+    pub fn parameter_name(&self, index: u32) -> String {
+        self.string(opcode::GET_PARAM_NAME, index as i32)
+    }
+
+    pub fn parameter(&self, index: u32) -> f32 {
+        unsafe { ((*self.effect).get_parameter)(self.effect, index as i32) }
+    }
+
+    pub fn set_parameter(&self, index: u32, value: f32) {
+        unsafe { ((*self.effect).set_parameter)(self.effect, index as i32, value) }
+    }
+
+    /// Whether the plugin implements `processReplacing`, the only processing call used
+    pub fn can_replace(&self) -> bool {
+        unsafe {
+            (*self.effect).flags & flags::CAN_REPLACING != 0
+                && (*self.effect).process_replacing.is_some()
+        }
+    }
+
+    pub fn has_editor(&self) -> bool {
+        unsafe { (*self.effect).flags & flags::HAS_EDITOR != 0 }
+    }
+
+    /// Opaque state from `effGetChunk` for plugins that support it, all parameter values
+    /// as little-endian f32s otherwise
+    pub fn save_state(&self) -> Vec<u8> {
+        if self.program_chunks() {
+            let mut data: *mut c_void = std::ptr::null_mut();
+            let size = self.dispatch(
+                opcode::GET_CHUNK,
+                0,
+                0,
+                &mut data as *mut _ as *mut c_void,
+                0.0,
+            );
+            if size > 0 && !data.is_null() {
+                return unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }
+                    .to_vec();
+            }
+        }
+
+        (0..self.parameter_count())
+            .flat_map(|i| self.parameter(i).to_le_bytes())
+            .collect()
+    }
+
+    /// Restore state written by `save_state`
+    pub fn load_state(&self, state: &[u8]) -> Result<()> {
+        if self.program_chunks() {
+            let mut chunk = state.to_vec();
+            self.dispatch(
+                opcode::SET_CHUNK,
+                0,
+                chunk.len() as isize,
+                chunk.as_mut_ptr() as *mut c_void,
+                0.0,
+            );
+            return Ok(());
+        }
+
+        if state.len() != self.parameter_count() as usize * size_of::<f32>() {
+            return Err(anyhow!("Plugin state doesn't match the parameter count"));
+        }
+        for (i, value) in state.chunks_exact(size_of::<f32>()).enumerate() {
+            let value = f32::from_le_bytes(value.try_into().unwrap());
+            self.set_parameter(i as u32, value);
+            self.host.parameters.set(i as u32, value as f64);
+        }
+        Ok(())
+    }
+
+    fn program_chunks(&self) -> bool {
+        unsafe { (*self.effect).flags & flags::PROGRAM_CHUNKS != 0 }
+    }
+}
+
+impl Drop for Effect {
+    fn drop(&mut self) {
+        self.dispatch(opcode::CLOSE, 0, 0, std::ptr::null_mut(), 0.0);
+    }
+}
+
+/// Binary to load for a plugin path. macOS `.vst` plugins are bundles.
+fn binary_path(path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match path.file_stem() {
+        Some(stem) if path.is_dir() => path.join("Contents").join("MacOS").join(stem),
+        _ => path,
+    }
+}
+
+unsafe extern "C" fn host_callback(
+    effect: *mut AEffect,
+    opcode: i32,
+    index: i32,
+    value: isize,
+    ptr: *mut c_void,
+    opt: f32,
+) -> isize {
+    // The plugin may call before `user` is set, e.g. from its entry point
+    let host = if effect.is_null() || (*effect).user.is_null() {
+        None
+    } else {
+        Some(&*((*effect).user as *const HostData))
+    };
+
+    match opcode {
+        host_opcode::VERSION => HOST_VERSION,
+        host_opcode::AUTOMATE => {
+            if let Some(host) = host {
+                host.parameters.edit(index as u32, opt as f64);
+            }
+            0
+        }
+        host_opcode::GET_SAMPLE_RATE => host
+            .map(|host| f32::from_bits(host.sample_rate.load(Ordering::Relaxed)) as isize)
+            .unwrap_or(0),
+        host_opcode::GET_BLOCK_SIZE => host
+            .map(|host| host.block_size.load(Ordering::Relaxed) as isize)
+            .unwrap_or(0),
+        host_opcode::SIZE_WINDOW => {
+            let Some(view) = host.map(|host| host.view.load(Ordering::Acquire)) else {
+                return 0;
+            };
+            if view.is_null() {
+                return 0;
+            }
+            editor::resize(view, index, value as i32) as isize
+        }
+        host_opcode::GET_VENDOR_STRING | host_opcode::GET_PRODUCT_STRING => {
+            if !ptr.is_null() {
+                let name = c"Sona".to_bytes_with_nul();
+                std::ptr::copy_nonoverlapping(name.as_ptr(), ptr as *mut u8, name.len());
+            }
+            1
+        }
+        host_opcode::GET_VENDOR_VERSION => 1,
+        _ => {
+            trace!("Unhandled VST2 host opcode {}", opcode);
+            0
+        }
+    }
+}
+
+/// Read a VST2 plugin's name, vendor and ID
+pub fn probe(path: &Path) -> Result<PluginMetadata> {
+    let effect = Effect::load(path)?;
+
+    Ok(PluginMetadata {
+        name: display_name(&effect, path),
+        vendor: effect.vendor(),
+        category: AUDIO_MODULE_CATEGORY.to_string(),
+        uid: effect.uid(),
+        snapshot: None,
+        format: PluginFormat::Vst2,
+    })
+}
+
+/// The plugin's own name, the file name for plugins that don't report one
+fn display_name(effect: &Effect, path: &Path) -> String {
+    let name = effect.name();
+    if !name.is_empty() {
+        return name;
+    }
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl VSTHostContext {
+    /// Load a VST2 plugin's library and open its effect, see `VSTHostContext::open`. Its
+    /// `AEffect` is later wrapped in an audio processor and, if it has one, a plug view, so
+    /// the chain and editor treat it like a VST3 plugin.
+    pub(super) fn open_vst2(path: &str) -> Result<Self> {
+        let effect = Effect::load(Path::new(path))?;
+        if !effect.can_replace() {
+            return Err(anyhow!("{} doesn't support processReplacing", path));
+        }
+        info!("Loaded VST2 plugin {} ({})", effect.name(), effect.uid());
+
+        let mut ctx = Self::default();
+        ctx.id = PluginId::new();
+        ctx.path = path.to_string();
+        ctx.format = PluginFormat::Vst2;
+        ctx.name = display_name(&effect, Path::new(path));
+        ctx.vendor = effect.vendor();
+        ctx.uid = effect.uid();
+        ctx.vst2 = Some(Arc::new(effect));
+        Ok(ctx)
+    }
+
+    /// Wrap an opened effect in a processor and view, see `VSTHostContext::create_instances`
+    pub(super) fn create_vst2_instances(&mut self) -> Result<()> {
+        let effect = self
+            .vst2
+            .clone()
+            .ok_or_else(|| anyhow!("{} is not open", self.path))?;
+
+        let (inputs, outputs) =
+            unsafe { ((*effect.effect).num_inputs, (*effect.effect).num_outputs) };
+        self.input_buses = vec![BusDescription {
+            name: "Input".to_string(),
+            channel_count: inputs,
+            aux: false,
+        }];
+        self.output_buses = vec![BusDescription {
+            name: "Output".to_string(),
+            channel_count: outputs,
+            aux: false,
+        }];
+
+        // Share the parameter cache, so automation from the plugin's editor reaches the app
+        self.parameters = effect.host.parameters.clone();
+        for i in 0..effect.parameter_count() {
+            trace!("Parameter {}: {}", i, effect.parameter_name(i));
+            self.parameters.set(i, effect.parameter(i) as f64);
+        }
+
+        if effect.has_editor() {
+            self.view = Some(editor::view(effect.clone()));
+        }
+        let processor = processor::processor(effect);
+
+        let mut setup = ProcessSetup {
+            process_mode: ProcessMode::Realtime,
+            symbolic_sample_size: SymbolicSampleSize::Sample32,
+            max_samples_per_block: 2048,
+            sample_rate: 48000.0,
+        };
+        unsafe { processor.setup_processing(&mut setup) };
+
+        self.processor = Some(processor);
+        Ok(())
+    }
+}
+
+/// IAudioProcessor forwarding to an `Effect`
+mod processor {
+    use vst3::base::funknown::{
+        FUnknown, FUnknown_Vtbl, IAudioProcessor, IAudioProcessor_Vtbl, Interface, TResult, FUID,
+    };
+    use vst3::vst::audio_processor::speaker_arr::SpeakerArrangement;
+    use vst3::vst::audio_processor::{
+        BusDirection, IParamValueQueue_Impl, IParameterChanges, IParameterChanges_Impl, ProcessData,
+    };
+    use vst3::VSTPtr;
+
+    use super::*;
+    use crate::MAX_BLOCK_SIZE;
+
+    #[repr(C)]
+    struct ProcessorProxy {
+        base: IAudioProcessor,
+        references: AtomicU32,
+        effect: Arc<Effect>,
+        resumed: bool,
+        // Channel pointers handed to processReplacing, sized for the plugin up front
+        inputs: Vec<*mut f32>,
+        outputs: Vec<*mut f32>,
+        // Stand-ins for channels the engine doesn't provide
+        silence: Vec<Vec<f32>>,
+        discard: Vec<Vec<f32>>,
+    }
+
+    pub fn processor(effect: Arc<Effect>) -> VSTPtr<IAudioProcessor> {
+        let (inputs, outputs) = unsafe {
+            (
+                (*effect.effect).num_inputs.max(0) as usize,
+                (*effect.effect).num_outputs.max(0) as usize,
+            )
+        };
+
+        let proxy = Box::new(ProcessorProxy {
+            base: IAudioProcessor {
+                vtable: &PROCESSOR_VTBL,
+            },
+            references: AtomicU32::new(1),
+            effect,
+            resumed: false,
+            inputs: vec![std::ptr::null_mut(); inputs],
+            outputs: vec![std::ptr::null_mut(); outputs],
+            silence: vec![vec![0.0; MAX_BLOCK_SIZE]; inputs],
+            discard: vec![vec![0.0; MAX_BLOCK_SIZE]; outputs],
+        });
+        VSTPtr::new(Box::into_raw(proxy) as *mut IAudioProcessor)
+    }
+
+    // FUID is passed by value to match the vtable signature
+    #[allow(improper_ctypes_definitions)]
+    unsafe extern "C" fn query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IAudioProcessor::iid || iid == FUnknown::iid {
+            add_ref(this);
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    unsafe extern "C" fn add_ref(this: *mut FUnknown) -> u32 {
+        (*(this as *mut ProcessorProxy))
+            .references
+            .fetch_add(1, Ordering::Relaxed)
+            + 1
+    }
+
+    unsafe extern "C" fn release(this: *mut FUnknown) -> u32 {
+        let remaining = (*(this as *mut ProcessorProxy))
+            .references
+            .fetch_sub(1, Ordering::AcqRel)
+            - 1;
+        if remaining == 0 {
+            drop(Box::from_raw(this as *mut ProcessorProxy));
+        }
+        remaining
+    }
+
+    unsafe extern "C" fn set_bus_arrangements(
+        _this: *mut IAudioProcessor,
+        _inputs: *mut SpeakerArrangement,
+        _num_inputs: i32,
+        _outputs: *mut SpeakerArrangement,
+        _num_outputs: i32,
+    ) -> TResult {
+        TResult::ResultFalse
+    }
+
+    unsafe extern "C" fn get_bus_arrangements(
+        _this: *mut IAudioProcessor,
+        _dir: BusDirection,
+        _index: i32,
+        _arr: *mut SpeakerArrangement,
+    ) -> TResult {
+        TResult::NotImplemented
+    }
+
+    unsafe extern "C" fn can_process_sample_size(
+        _this: *mut IAudioProcessor,
+        symbolic_sample_size: SymbolicSampleSize,
+    ) -> TResult {
+        match symbolic_sample_size {
+            SymbolicSampleSize::Sample32 => TResult::ResultOk,
+            _ => TResult::ResultFalse,
+        }
+    }
+
+    unsafe extern "C" fn get_latency_samples(this: *mut IAudioProcessor) -> u32 {
+        let proxy = &*(this as *mut ProcessorProxy);
+        (*proxy.effect.effect).initial_delay.max(0) as u32
+    }
+
+    unsafe extern "C" fn setup_processing(
+        this: *mut IAudioProcessor,
+        setup: *mut ProcessSetup,
+    ) -> TResult {
+        let proxy = &*(this as *mut ProcessorProxy);
+        let setup = &*setup;
+        let effect = &proxy.effect;
+
+        let sample_rate = setup.sample_rate as f32;
+        let block_size = setup.max_samples_per_block.max(0);
+        effect
+            .host
+            .sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+        effect
+            .host
+            .block_size
+            .store(block_size as u32, Ordering::Relaxed);
+
+        // Both are only allowed while suspended, which `set_processing(false)` ensures
+        effect.dispatch(
+            opcode::SET_SAMPLE_RATE,
+            0,
+            0,
+            std::ptr::null_mut(),
+            sample_rate,
+        );
+        effect.dispatch(
+            opcode::SET_BLOCK_SIZE,
+            0,
+            block_size as isize,
+            std::ptr::null_mut(),
+            0.0,
+        );
+        TResult::ResultOk
+    }
+
+    /// VST2 has no separate activation, resuming and starting processing go together
+    unsafe extern "C" fn set_processing(this: *mut IAudioProcessor, state: bool) -> TResult {
+        let proxy = &mut *(this as *mut ProcessorProxy);
+        if state == proxy.resumed {
+            return TResult::ResultOk;
+        }
+
+        let effect = &proxy.effect;
+        if state {
+            effect.dispatch(opcode::MAINS_CHANGED, 0, 1, std::ptr::null_mut(), 0.0);
+            effect.dispatch(opcode::START_PROCESS, 0, 0, std::ptr::null_mut(), 0.0);
+        } else {
+            effect.dispatch(opcode::STOP_PROCESS, 0, 0, std::ptr::null_mut(), 0.0);
+            effect.dispatch(opcode::MAINS_CHANGED, 0, 0, std::ptr::null_mut(), 0.0);
+        }
+        proxy.resumed = state;
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn process(this: *mut IAudioProcessor, data: *mut ProcessData) -> TResult {
+        let proxy = &mut *(this as *mut ProcessorProxy);
+        let data = &*data;
+        if data.num_outputs < 1 || data.outputs.is_null() {
+            return TResult::InvalidArgument;
+        }
+        let effect = proxy.effect.effect;
+
+        // VST2 parameters take effect right away, only the last point of a block counts
+        let changes = data.input_parameter_changes as *mut IParameterChanges;
+        if !changes.is_null() {
+            for index in 0..(*changes).get_parameter_count() {
+                let queue = &*(*changes).get_parameter_data(index);
+                let points = queue.get_point_count();
+                if points == 0 {
+                    continue;
+                }
+
+                let mut offset = 0;
+                let mut value = 0.0;
+                queue.get_point(points - 1, &mut offset, &mut value);
+                ((*effect).set_parameter)(effect, queue.get_parameter_id() as i32, value as f32);
+            }
+        }
+
+        let num_samples = (data.num_samples.max(0) as usize).min(MAX_BLOCK_SIZE);
+        let output = &*data.outputs;
+        let (input_channels, input_buffers) = if data.num_inputs > 0 && !data.inputs.is_null() {
+            let input = &*data.inputs;
+            (input.num_channels.max(0) as usize, input.channel_buffers_32)
+        } else {
+            (0, std::ptr::null_mut())
+        };
+
+        for (i, channel) in proxy.inputs.iter_mut().enumerate() {
+            *channel = if i < input_channels {
+                *input_buffers.add(i)
+            } else {
+                let silence = &mut proxy.silence[i];
+                silence[..num_samples].fill(0.0);
+                silence.as_mut_ptr()
+            };
+        }
+        for (i, channel) in proxy.outputs.iter_mut().enumerate() {
+            *channel = if i < output.num_channels.max(0) as usize {
+                *output.channel_buffers_32.add(i)
+            } else {
+                proxy.discard[i].as_mut_ptr()
+            };
+        }
+
+        if let Some(process_replacing) = (*effect).process_replacing {
+            process_replacing(
+                effect,
+                proxy.inputs.as_mut_ptr(),
+                proxy.outputs.as_mut_ptr(),
+                num_samples as i32,
+            );
+        }
+
+        // Channels the plugin doesn't have stay silent
+        for i in proxy.outputs.len()..output.num_channels.max(0) as usize {
+            std::ptr::write_bytes(*output.channel_buffers_32.add(i), 0, num_samples);
+        }
+
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn get_tail_samples(this: *mut IAudioProcessor) -> u32 {
+        let proxy = &*(this as *mut ProcessorProxy);
+        proxy
+            .effect
+            .dispatch(opcode::GET_TAIL_SIZE, 0, 0, std::ptr::null_mut(), 0.0)
+            .max(0) as u32
+    }
+
+    static PROCESSOR_VTBL: IAudioProcessor_Vtbl = IAudioProcessor_Vtbl {
+        base: FUnknown_Vtbl {
+            query_interface,
+            add_ref,
+            release,
+        },
+        set_bus_arrangements,
+        get_bus_arrangements,
+        can_process_sample_size,
+        get_latency_samples,
+        setup_processing,
+        set_processing,
+        process,
+        get_tail_samples,
+    };
+}
+
+/// IPlugView forwarding to an `Effect`'s editor
+mod editor {
+    use vst3::base::funknown::{
+        FUnknown, FUnknown_Vtbl, IPlugView, IPlugView_Vtbl, Interface, TResult, FUID,
+    };
+    use vst3::gui::plug_view::{IPlugFrame, IPlugFrame_Impl, ViewRect};
+    use vst3::VSTPtr;
+
+    use super::*;
+
+    #[repr(C)]
+    struct ViewProxy {
+        base: IPlugView,
+        references: AtomicU32,
+        effect: Arc<Effect>,
+        frame: *mut IPlugFrame,
+        open: bool,
+    }
+
+    pub fn view(effect: Arc<Effect>) -> VSTPtr<IPlugView> {
+        let proxy = Box::new(ViewProxy {
+            base: IPlugView { vtable: &VIEW_VTBL },
+            references: AtomicU32::new(1),
+            effect,
+            frame: std::ptr::null_mut(),
+            open: false,
+        });
+        VSTPtr::new(Box::into_raw(proxy) as *mut IPlugView)
+    }
+
+    /// Handle `audioMasterSizeWindow` for an open view by asking its frame to resize
+    pub unsafe fn resize(view: *mut c_void, width: i32, height: i32) -> bool {
+        let proxy = &*(view as *mut ViewProxy);
+        if proxy.frame.is_null() {
+            return false;
+        }
+
+        let mut rect = ViewRect {
+            left: 0,
+            top: 0,
+            right: width,
+            bottom: height,
+        };
+        (*proxy.frame).resize_view(view as *mut IPlugView, &mut rect) == TResult::ResultOk
+    }
+
+    /// The editor's size as reported by `effEditGetRect`
+    unsafe fn editor_rect(effect: &Effect) -> Option<ViewRect> {
+        let mut rect: *mut ERect = std::ptr::null_mut();
+        effect.dispatch(
+            opcode::EDIT_GET_RECT,
+            0,
+            0,
+            &mut rect as *mut _ as *mut c_void,
+            0.0,
+        );
+        if rect.is_null() {
+            return None;
+        }
+
+        let rect = &*rect;
+        Some(ViewRect {
+            left: 0,
+            top: 0,
+            right: (rect.right - rect.left) as i32,
+            bottom: (rect.bottom - rect.top) as i32,
+        })
+    }
+
+    // FUID is passed by value to match the vtable signature
+    #[allow(improper_ctypes_definitions)]
+    unsafe extern "C" fn query_interface(
+        this: *mut FUnknown,
+        iid: FUID,
+        obj: *mut *mut c_void,
+    ) -> TResult {
+        if iid == IPlugView::iid || iid == FUnknown::iid {
+            add_ref(this);
+            *obj = this as *mut c_void;
+            TResult::ResultOk
+        } else {
+            *obj = std::ptr::null_mut();
+            TResult::NoInterface
+        }
+    }
+
+    unsafe extern "C" fn add_ref(this: *mut FUnknown) -> u32 {
+        (*(this as *mut ViewProxy))
+            .references
+            .fetch_add(1, Ordering::Relaxed)
+            + 1
+    }
+
+    unsafe extern "C" fn release(this: *mut FUnknown) -> u32 {
+        let remaining = (*(this as *mut ViewProxy))
+            .references
+            .fetch_sub(1, Ordering::AcqRel)
+            - 1;
+        if remaining == 0 {
+            drop(Box::from_raw(this as *mut ViewProxy));
+        }
+        remaining
+    }
+
+    unsafe extern "C" fn is_platform_type_supported(
+        _this: *mut IPlugView,
+        ty: *const c_char,
+    ) -> TResult {
+        let ty = CStr::from_ptr(ty).to_bytes();
+        if (cfg!(target_os = "windows") && ty == b"HWND")
+            || (cfg!(target_os = "macos") && ty == b"NSView")
+        {
+            TResult::ResultOk
+        } else {
+            TResult::ResultFalse
+        }
+    }
+
+    unsafe extern "C" fn attached(
+        this: *mut IPlugView,
+        parent: *mut c_void,
+        _ty: *const c_char,
+    ) -> TResult {
+        let proxy = &mut *(this as *mut ViewProxy);
+        if proxy.open {
+            return TResult::ResultFalse;
+        }
+
+        proxy.effect.dispatch(opcode::EDIT_OPEN, 0, 0, parent, 0.0);
+        proxy
+            .effect
+            .host
+            .view
+            .store(this as *mut c_void, Ordering::Release);
+        proxy.open = true;
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn removed(this: *mut IPlugView) -> TResult {
+        let proxy = &mut *(this as *mut ViewProxy);
+        if !proxy.open {
+            return TResult::ResultFalse;
+        }
+
+        proxy
+            .effect
+            .host
+            .view
+            .store(std::ptr::null_mut(), Ordering::Release);
+        proxy
+            .effect
+            .dispatch(opcode::EDIT_CLOSE, 0, 0, std::ptr::null_mut(), 0.0);
+        proxy.open = false;
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn on_wheel(_this: *mut IPlugView, _distance: f32) -> TResult {
+        TResult::ResultFalse
+    }
+
+    unsafe extern "C" fn on_key_down(
+        _this: *mut IPlugView,
+        _key: u16,
+        _key_code: i16,
+        _modifiers: i16,
+    ) -> TResult {
+        TResult::ResultFalse
+    }
+
+    unsafe extern "C" fn on_key_up(
+        _this: *mut IPlugView,
+        _key: u16,
+        _key_code: i16,
+        _modifiers: i16,
+    ) -> TResult {
+        TResult::ResultFalse
+    }
+
+    unsafe extern "C" fn get_size(this: *mut IPlugView, size: *mut ViewRect) -> TResult {
+        let proxy = &*(this as *mut ViewProxy);
+        match editor_rect(&proxy.effect) {
+            Some(rect) => {
+                *size = rect;
+                TResult::ResultOk
+            }
+            None => TResult::ResultFalse,
+        }
+    }
+
+    unsafe extern "C" fn on_size(_this: *mut IPlugView, _new_size: *mut ViewRect) -> TResult {
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn on_focus(_this: *mut IPlugView, _state: bool) -> TResult {
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn set_frame(this: *mut IPlugView, frame: *mut IPlugFrame) -> TResult {
+        (*(this as *mut ViewProxy)).frame = frame;
+        TResult::ResultOk
+    }
+
+    unsafe extern "C" fn can_resize(_this: *mut IPlugView) -> TResult {
+        TResult::ResultFalse
+    }
+
+    /// VST2 editors have a fixed size, every rect is constrained to it
+    unsafe extern "C" fn check_size_constraint(
+        this: *mut IPlugView,
+        rect: *mut ViewRect,
+    ) -> TResult {
+        get_size(this, rect)
+    }
+
+    static VIEW_VTBL: IPlugView_Vtbl = IPlugView_Vtbl {
+        base: FUnknown_Vtbl {
+            query_interface,
+            add_ref,
+            release,
+        },
+        is_platform_type_supported,
+        attached,
+        removed,
+        on_wheel,
+        on_key_down,
+        on_key_up,
+        get_size,
+        on_size,
+        on_focus,
+        set_frame,
+        can_resize,
+        check_size_constraint,
+    };
+}
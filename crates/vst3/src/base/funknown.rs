@@ -13,8 +13,8 @@ use crate::gui::plug_view::{IPlugFrame, ViewRect};
 use crate::uid_to_ascii;
 use crate::vst::audio_processor::speaker_arr::SpeakerArrangement;
 use crate::vst::audio_processor::{
-    BusDirection, BusInfo, IoMode, MediaType, ProcessData, ProcessSetup, RoutingInfo,
-    SymbolicSampleSize,
+    BusDirection, BusInfo, IoMode, MediaType, ParameterInfo, ProcessData, ProcessSetup,
+    RoutingInfo, SymbolicSampleSize,
 };
 
 pub type FUID = [c_char; 16];
@@ -66,16 +66,14 @@ pub struct PClassInfo {
 impl PClassInfo {
     pub unsafe fn category(&self) -> String {
         CStr::from_ptr(self.category.as_ptr())
-            .to_str()
-            .unwrap()
-            .to_string()
+            .to_string_lossy()
+            .into_owned()
     }
 
     pub unsafe fn name(&self) -> String {
         CStr::from_ptr(self.name.as_ptr())
-            .to_str()
-            .unwrap()
-            .to_string()
+            .to_string_lossy()
+            .into_owned()
     }
 }
 
@@ -353,6 +351,22 @@ pub trait IAudioProcessor: FUnknown {
     fn get_tail_samples(&mut self) -> u32;
 }
 
+/// Seek modes of `IBStream::seek`
+pub mod SeekMode {
+    pub const Set: i32 = 0;
+    pub const Cur: i32 = 1;
+    pub const End: i32 = 2;
+}
+
+#[interface(0xC3BF6EA2, 0x30994752, 0x9B6BF990, 0x1EE33E9B)]
+pub trait IBStream: FUnknown {
+    fn read(&mut self, buffer: *mut c_void, num_bytes: i32, num_bytes_read: *mut i32) -> TResult;
+    fn write(&mut self, buffer: *mut c_void, num_bytes: i32, num_bytes_written: *mut i32)
+        -> TResult;
+    fn seek(&mut self, pos: i64, mode: i32, result: *mut i64) -> TResult;
+    fn tell(&mut self, pos: *mut i64) -> TResult;
+}
+
 #[interface(0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802)]
 pub trait IComponent: IPluginBase {
     #[private]
@@ -416,12 +430,12 @@ pub trait IEditController: IPluginBase {
 
     fn get_parameter_count(&mut self) -> i32;
 
-    fn get_parameter_info(&mut self, param_index: i32, info: *mut c_void) -> TResult;
+    fn get_parameter_info(&mut self, param_index: i32, info: *mut ParameterInfo) -> TResult;
 
     fn get_param_string_by_value(
         &mut self,
         id: u32,
-        value_normalized: u32,
+        value_normalized: f64,
         string: *mut c_void,
     ) -> TResult;
 
@@ -432,13 +446,13 @@ pub trait IEditController: IPluginBase {
         value_normalized: *mut c_void,
     ) -> TResult;
 
-    fn normalized_param_to_plain(&mut self, id: u32, value_normalized: u32) -> u32;
+    fn normalized_param_to_plain(&mut self, id: u32, value_normalized: f64) -> f64;
 
-    fn plain_param_to_normalized(&mut self, id: u32, plain_value: u32) -> u32;
+    fn plain_param_to_normalized(&mut self, id: u32, plain_value: f64) -> f64;
 
-    fn get_param_normalized(&mut self, id: u32) -> u32;
+    fn get_param_normalized(&mut self, id: u32) -> f64;
 
-    fn set_param_normalized(&mut self, id: u32, value: u32) -> TResult;
+    fn set_param_normalized(&mut self, id: u32, value: f64) -> TResult;
 
     fn set_component_handler(&mut self, handler: *mut c_void) -> TResult;
 
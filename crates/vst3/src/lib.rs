@@ -64,6 +64,26 @@ impl<T: FUnknown_Impl> DerefMut for VSTPtr<T> {
 unsafe impl<T: FUnknown_Impl> Sync for VSTPtr<T> {}
 unsafe impl<T: FUnknown_Impl> Send for VSTPtr<T> {}
 
+/// Format a UID as the VST3 SDK's `FUID::toString` does, undoing the COM compatible byte
+/// order of the first two longs. This is the form used in bundle resource names.
+pub fn uid_to_fuid_string(uid: [c_char; 16]) -> String {
+    let b = uid.map(|byte| byte as u8);
+
+    let l1 = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    let l2 = u32::from_be_bytes([b[5], b[4], b[7], b[6]]);
+    let l3 = u32::from_be_bytes([b[8], b[9], b[10], b[11]]);
+    let l4 = u32::from_be_bytes([b[12], b[13], b[14], b[15]]);
+
+    format!("{:08X}{:08X}{:08X}{:08X}", l1, l2, l3, l4)
+}
+
+/// Decode a fixed-size UTF-16 string such as `String128`, up to its first null.
+/// Unpaired surrogates become U+FFFD instead of failing the whole string.
+pub fn utf16_to_string(buffer: &[u16]) -> String {
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
+
 pub fn uid_to_ascii(uid: [c_char; 16]) -> String {
     // Convert [u8; 16] to a hex string (32 characters long)
     let hex_string = uid
@@ -86,3 +106,86 @@ pub fn uid_to_ascii(uid: [c_char; 16]) -> String {
 
     formatted_uid
 }
+
+/// Parse a UID in the hyphenated form `uid_to_ascii` produces, e.g.
+/// `565354AB-434F-4D70-7265-737369766F6E`. Hex digits may be upper or lower case.
+pub fn ascii_to_uid(s: &str) -> Result<[c_char; 16]> {
+    let groups = s.split('-').collect::<Vec<_>>();
+    if groups.iter().map(|group| group.len()).ne([8, 4, 4, 4, 12]) {
+        return Err(anyhow::anyhow!(
+            "Invalid UID '{}', expected the form XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX",
+            s
+        ));
+    }
+
+    let hex = groups.concat();
+    if let Some(c) = hex.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(anyhow::anyhow!("Invalid UID '{}', '{}' is not a hex digit", s, c));
+    }
+
+    let mut uid = [0 as c_char; 16];
+    for (byte, pair) in uid.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        // Both characters were checked to be ASCII hex digits
+        let pair = std::str::from_utf8(pair).unwrap();
+        *byte = u8::from_str_radix(pair, 16).unwrap() as c_char;
+    }
+    Ok(uid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_to_uid_round_trip() {
+        let uids = [
+            [0; 16],
+            [0xFFu8 as c_char; 16],
+            std::array::from_fn(|i| (i as u8 * 17) as c_char),
+            std::array::from_fn(|i| (0x80 + i as u8) as c_char),
+        ];
+        for uid in uids {
+            assert_eq!(ascii_to_uid(&uid_to_ascii(uid)).unwrap(), uid);
+        }
+
+        let uid = ascii_to_uid("565354ab-434f-4d70-7265-737369766f6e").unwrap();
+        assert_eq!(uid_to_ascii(uid), "565354AB-434F-4D70-7265-737369766F6E");
+
+        for invalid in [
+            "",
+            "565354AB434F4D707265737369766F6E",
+            "565354AB-434F-4D70-7265-737369766F6",
+            "565354AB-434F-4D70-7265-737369766F6E0",
+            "565354A-B434F-4D70-7265-737369766F6E",
+            "565354AB-434F-4D70-7265-737369766F6G",
+            "+65354AB-434F-4D70-7265-737369766F6E",
+        ] {
+            assert!(ascii_to_uid(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_utf16_to_string_stops_at_null() {
+        let mut buffer = [0u16; 128];
+        for (slot, c) in buffer.iter_mut().zip("Größe 混響 🎸".encode_utf16()) {
+            *slot = c;
+        }
+        // Leftovers past the terminator must not leak into the name
+        buffer[20] = 'x' as u16;
+
+        assert_eq!(utf16_to_string(&buffer), "Größe 混響 🎸");
+        assert_eq!(utf16_to_string(&[0x0041, 0xD800, 0x0042]), "A\u{FFFD}B");
+        assert_eq!(utf16_to_string(&['a' as u16, 'b' as u16]), "ab");
+    }
+
+    #[test]
+    fn test_uid_to_fuid_string_undoes_com_layout() {
+        use crate::base::funknown::IComponent;
+
+        // Declared as interface(0xE831FF31, 0xF2D54301, 0x928EBBEE, 0x25697802)
+        assert_eq!(
+            uid_to_fuid_string(IComponent::iid),
+            "E831FF31F2D54301928EBBEE25697802"
+        );
+    }
+}
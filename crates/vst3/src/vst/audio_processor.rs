@@ -8,6 +8,7 @@ use crate::base::funknown::{
 };
 
 use super::host_application::String128;
+use crate::utf16_to_string;
 use vst3_macro::{implement, interface};
 
 #[repr(C)]
@@ -32,10 +33,63 @@ impl ProcessData {}
 unsafe impl Send for ProcessData {}
 unsafe impl Sync for ProcessData {}
 
+/// Transport state handed to the processor with every block
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct ProcessContext {
-    pub padding: [u8; 200],
+    /// Combination of `ProcessContextFlags`, saying which of the fields below are valid
+    pub state: u32,
+    pub sample_rate: f64,
+    /// Project time of the first sample of the block
+    pub project_time_samples: i64,
+    pub system_time: i64,
+    /// Samples processed since the processor was activated, whether playing or not
+    pub continous_time_samples: i64,
+    /// Musical position of the first sample of the block, in quarter notes
+    pub project_time_music: f64,
+    /// Start of the last bar before the block, in quarter notes
+    pub bar_position_music: f64,
+    pub cycle_start_music: f64,
+    pub cycle_end_music: f64,
+    /// Beats per minute
+    pub tempo: f64,
+    pub time_sig_numerator: i32,
+    pub time_sig_denominator: i32,
+    pub chord: Chord,
+    pub smpte_offset_subframes: i32,
+    pub frame_rate: FrameRate,
+    pub samples_to_next_clock: i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Chord {
+    pub key_note: u8,
+    pub root_note: u8,
+    pub chord_mask: i16,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameRate {
+    pub frames_per_second: u32,
+    pub flags: u32,
+}
+
+pub mod ProcessContextFlags {
+    pub const Playing: u32 = 1 << 1;
+    pub const CycleActive: u32 = 1 << 2;
+    pub const Recording: u32 = 1 << 3;
+    pub const SystemTimeValid: u32 = 1 << 8;
+    pub const ProjectTimeMusicValid: u32 = 1 << 9;
+    pub const TempoValid: u32 = 1 << 10;
+    pub const BarPositionValid: u32 = 1 << 11;
+    pub const CycleValid: u32 = 1 << 12;
+    pub const TimeSigValid: u32 = 1 << 13;
+    pub const SmpteValid: u32 = 1 << 14;
+    pub const ClockValid: u32 = 1 << 15;
+    pub const ContTimeValid: u32 = 1 << 17;
+    pub const ChordValid: u32 = 1 << 18;
 }
 
 #[repr(C)]
@@ -47,7 +101,7 @@ pub struct AudioBusBuffers {
 }
 
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessMode {
     #[default]
     Realtime = 0,
@@ -82,7 +136,7 @@ pub enum MediaType {
 }
 
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum BusDirection {
     #[default]
     Input = 0,
@@ -90,7 +144,7 @@ pub enum BusDirection {
 }
 
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum BusType {
     #[default]
     Main = 0,
@@ -138,6 +192,120 @@ impl Default for BusInfo {
     }
 }
 
+impl BusInfo {
+    pub fn name(&self) -> String {
+        utf16_to_string(&self.name)
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ParameterInfo {
+    pub id: u32,
+    pub title: String128,
+    pub short_title: String128,
+    pub units: String128,
+    pub step_count: i32,
+    pub default_normalized_value: f64,
+    pub unit_id: i32,
+    pub flags: i32,
+}
+
+impl ParameterInfo {
+    pub fn title(&self) -> String {
+        utf16_to_string(&self.title)
+    }
+
+    pub fn short_title(&self) -> String {
+        utf16_to_string(&self.short_title)
+    }
+
+    pub fn units(&self) -> String {
+        utf16_to_string(&self.units)
+    }
+}
+
+impl Default for ParameterInfo {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            title: [0u16; 128],
+            short_title: [0u16; 128],
+            units: [0u16; 128],
+            step_count: 0,
+            default_normalized_value: 0.0,
+            unit_id: 0,
+            flags: 0,
+        }
+    }
+}
+
+pub mod ParameterFlags {
+    pub const NoFlags: i32 = 0;
+    pub const CanAutomate: i32 = 1 << 0;
+    pub const IsReadOnly: i32 = 1 << 1;
+    pub const IsWrapAround: i32 = 1 << 2;
+    pub const IsList: i32 = 1 << 3;
+    pub const IsHidden: i32 = 1 << 4;
+    pub const IsProgramChange: i32 = 1 << 15;
+    pub const IsBypass: i32 = 1 << 16;
+}
+
+/// Unit every parameter belongs to unless the plugin declares its own
+pub const ROOT_UNIT_ID: i32 = 0;
+/// Program list id of a unit without programs
+pub const NO_PROGRAM_LIST_ID: i32 = -1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UnitInfo {
+    pub id: i32,
+    pub parent_unit_id: i32,
+    pub name: String128,
+    pub program_list_id: i32,
+}
+
+impl UnitInfo {
+    pub fn name(&self) -> String {
+        utf16_to_string(&self.name)
+    }
+}
+
+impl Default for UnitInfo {
+    fn default() -> Self {
+        Self {
+            id: ROOT_UNIT_ID,
+            parent_unit_id: -1,
+            name: [0u16; 128],
+            program_list_id: NO_PROGRAM_LIST_ID,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ProgramListInfo {
+    pub id: i32,
+    pub name: String128,
+    pub program_count: i32,
+}
+
+impl ProgramListInfo {
+    pub fn name(&self) -> String {
+        utf16_to_string(&self.name)
+    }
+}
+
+impl Default for ProgramListInfo {
+    fn default() -> Self {
+        Self {
+            id: NO_PROGRAM_LIST_ID,
+            name: [0u16; 128],
+            program_count: 0,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct RoutingInfo {
@@ -1101,6 +1269,175 @@ pub mod speaker_arr {
 #[interface(0xA4779663, 0x0BB64A56, 0xB44384A8, 0x466FEB9D)]
 pub trait IParameterChanges: FUnknown {
     fn get_parameter_count(&mut self) -> i32;
-    fn get_parameter_data(&mut self) -> *mut c_void;
-    fn add_parameter_data(&mut self, id: *const c_char, index: *mut i32) -> *mut c_void;
+    fn get_parameter_data(&mut self, index: i32) -> *mut IParamValueQueue;
+    fn add_parameter_data(&mut self, id: *const u32, index: *mut i32) -> *mut IParamValueQueue;
+}
+
+#[interface(0x01263A18, 0xED074F6F, 0x98C9D356, 0x4686F9BA)]
+pub trait IParamValueQueue: FUnknown {
+    fn get_parameter_id(&mut self) -> u32;
+    fn get_point_count(&mut self) -> i32;
+    fn get_point(&mut self, index: i32, sample_offset: *mut i32, value: *mut f64) -> TResult;
+    fn add_point(&mut self, sample_offset: i32, value: f64, index: *mut i32) -> TResult;
+}
+
+pub mod EventTypes {
+    pub const NoteOnEvent: u16 = 0;
+    pub const NoteOffEvent: u16 = 1;
+    pub const DataEvent: u16 = 2;
+    pub const PolyPressureEvent: u16 = 3;
+    pub const NoteExpressionValueEvent: u16 = 4;
+    pub const NoteExpressionTextEvent: u16 = 5;
+    pub const ChordEvent: u16 = 6;
+    pub const ScaleEvent: u16 = 7;
+    pub const LegacyMIDICCOutEvent: u16 = 65535;
+}
+
+pub mod EventFlags {
+    pub const IsLive: u16 = 1 << 0;
+    pub const UserReserved1: u16 = 1 << 14;
+    pub const UserReserved2: u16 = 1 << 15;
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NoteOnEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub tuning: f32,
+    pub velocity: f32,
+    pub length: i32,
+    pub note_id: i32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NoteOffEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub velocity: f32,
+    pub note_id: i32,
+    pub tuning: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct DataEvent {
+    pub size: u32,
+    pub ty: u32,
+    pub bytes: *const u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PolyPressureEvent {
+    pub channel: i16,
+    pub pitch: i16,
+    pub pressure: f32,
+    pub note_id: i32,
+}
+
+/// Payload of an `Event`, selected by its `ty`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union EventData {
+    pub note_on: NoteOnEvent,
+    pub note_off: NoteOffEvent,
+    pub data: DataEvent,
+    pub poly_pressure: PolyPressureEvent,
+    // Size of the largest member, the note expression text event
+    _reserved: [u64; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Event {
+    pub bus_index: i32,
+    pub sample_offset: i32,
+    pub ppq_position: f64,
+    pub flags: u16,
+    pub ty: u16,
+    pub data: EventData,
+}
+
+impl Default for Event {
+    fn default() -> Self {
+        Self {
+            bus_index: 0,
+            sample_offset: 0,
+            ppq_position: 0.0,
+            flags: 0,
+            ty: EventTypes::NoteOnEvent,
+            data: EventData { _reserved: [0; 3] },
+        }
+    }
+}
+
+impl std::fmt::Debug for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Event")
+            .field("bus_index", &self.bus_index)
+            .field("sample_offset", &self.sample_offset)
+            .field("ty", &self.ty)
+            .finish()
+    }
+}
+
+#[interface(0x3A2C4214, 0x346349FE, 0xB2C4F397, 0xB9695A44)]
+pub trait IEventList: FUnknown {
+    fn get_event_count(&mut self) -> i32;
+    fn get_event(&mut self, index: i32, e: *mut Event) -> TResult;
+    fn add_event(&mut self, e: *mut Event) -> TResult;
+}
+
+/// Controller numbers past the 0-127 MIDI CCs, for `IMidiMapping`
+pub mod ControllerNumbers {
+    pub const AfterTouch: i16 = 128;
+    pub const PitchBend: i16 = 129;
+}
+
+#[interface(0xDF0FF9F7, 0x49B74669, 0xB63AB732, 0x7ADBF5E5)]
+pub trait IMidiMapping: FUnknown {
+    fn get_midi_controller_assignment(
+        &mut self,
+        bus_index: i32,
+        channel: i16,
+        midi_controller_number: i16,
+        id: *mut u32,
+    ) -> TResult;
+}
+
+#[interface(0x3D4BD6B5, 0x913A4FD2, 0xA886E768, 0xA5EB92C1)]
+pub trait IUnitInfo: FUnknown {
+    fn get_unit_count(&mut self) -> i32;
+    fn get_unit_info(&mut self, unit_index: i32, info: *mut UnitInfo) -> TResult;
+    fn get_program_list_count(&mut self) -> i32;
+    fn get_program_list_info(&mut self, list_index: i32, info: *mut ProgramListInfo) -> TResult;
+    fn get_program_name(&mut self, list_id: i32, program_index: i32, name: *mut String128) -> TResult;
+    fn get_program_info(
+        &mut self,
+        list_id: i32,
+        program_index: i32,
+        attribute_id: *const c_char,
+        attribute_value: *mut String128,
+    ) -> TResult;
+    fn has_program_pitch_names(&mut self, list_id: i32, program_index: i32) -> TResult;
+    fn get_program_pitch_name(
+        &mut self,
+        list_id: i32,
+        program_index: i32,
+        midi_pitch: i16,
+        name: *mut String128,
+    ) -> TResult;
+    fn get_selected_unit(&mut self) -> i32;
+    fn select_unit(&mut self, unit_id: i32) -> TResult;
+    fn get_unit_by_bus(
+        &mut self,
+        media_type: MediaType,
+        dir: BusDirection,
+        bus_index: i32,
+        channel: i32,
+        unit_id: *mut i32,
+    ) -> TResult;
+    fn set_unit_program_data(&mut self, list_or_unit_id: i32, program_index: i32, data: *mut c_void) -> TResult;
 }
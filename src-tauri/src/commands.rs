@@ -1,27 +1,61 @@
 #[cfg(target_os = "windows")]
 use std::ffi::c_void;
-use std::{error::Error, fmt, sync::Mutex};
-
-use audio::{vst::host::PluginId, AudioEngine};
-use log::trace;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use audio::{
+    diagnostics::Diagnostics,
+    recording::RecordPoint,
+    vst::host::{
+        list_classes, BusDescription, ChainId, ClassInfo, PluginFormat, PluginId, PluginParameter,
+        SidechainSource,
+    },
+    AbSlot, ActiveStreamConfig, AudioEngine, Downmix, EngineError, EngineMeters, FormatStrategy,
+    InputSource, PluginLoadTimeout, ResamplerQuality,
+};
+use log::{trace, warn};
 use serde::{ser::SerializeStruct, Serialize};
-use tauri::{ipc::InvokeError, Manager, PhysicalSize};
-use vst3::gui::plug_view::ViewRect;
+use tauri::{ipc::InvokeError, Emitter, Manager, PhysicalPosition, PhysicalSize};
 #[cfg(target_os = "windows")]
 use vst3::{base::funknown::IPlugView_Impl, gui::plug_view::PlatformType};
+use vst3::{
+    base::funknown::{IPlugView, TResult},
+    gui::plug_view::ViewRect,
+};
 
-use crate::plugins::PluginRegistry;
+use crate::plugins::{DiscoveredPlugin, PluginRegistry};
 
 type GlobalAudio = Mutex<AudioEngine>;
 type GlobalPluginRegistry = Mutex<PluginRegistry>;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AudioError {
     HostError,
     InputDeviceError,
     OutputDeviceError,
     PluginLoadError,
     PluginEditorError,
+    PluginStateError,
+    MidiError,
+    PluginLoadTimeout,
+    NoOutputDevice,
+    /// One of the errors above with the underlying cause, already formatted
+    Detailed(String),
+}
+
+impl AudioError {
+    /// Attach the underlying error so the frontend sees why the operation failed
+    pub fn detail(self, error: impl fmt::Display) -> AudioError {
+        AudioError::Detailed(format!("{}: {:#}", self, error))
+    }
 }
 
 impl Error for AudioError {}
@@ -34,6 +68,11 @@ impl fmt::Display for AudioError {
             AudioError::OutputDeviceError => write!(f, "Output device error"),
             AudioError::PluginLoadError => write!(f, "Plugin load error"),
             AudioError::PluginEditorError => write!(f, "Plugin editor error"),
+            AudioError::PluginStateError => write!(f, "Plugin state error"),
+            AudioError::MidiError => write!(f, "MIDI error"),
+            AudioError::PluginLoadTimeout => write!(f, "Plugin load timed out"),
+            AudioError::NoOutputDevice => write!(f, "No output device available"),
+            AudioError::Detailed(message) => write!(f, "{}", message),
         }
     }
 }
@@ -74,6 +113,52 @@ pub fn get_output_devices(app_handle: tauri::AppHandle) -> Result<Vec<String>, A
     Ok(output_devices.to_vec())
 }
 
+/// Input devices of any available host, so they can be previewed before switching to it
+#[tauri::command]
+pub fn get_input_devices_for_host(
+    app_handle: tauri::AppHandle,
+    host: String,
+) -> Result<Vec<String>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    let host_id = engine
+        .available_host_by_name(&host)
+        .map_err(|e| AudioError::HostError.detail(e))?;
+    let Some(input_devices) = engine.cached_input_device_names(&host_id) else {
+        return Err(AudioError::InputDeviceError);
+    };
+    Ok(input_devices.to_vec())
+}
+
+/// Output devices of any available host, so they can be previewed before switching to it
+#[tauri::command]
+pub fn get_output_devices_for_host(
+    app_handle: tauri::AppHandle,
+    host: String,
+) -> Result<Vec<String>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    let host_id = engine
+        .available_host_by_name(&host)
+        .map_err(|e| AudioError::HostError.detail(e))?;
+    let Some(output_devices) = engine.cached_output_device_names(&host_id) else {
+        return Err(AudioError::OutputDeviceError);
+    };
+    Ok(output_devices.to_vec())
+}
+
+/// Rescan hosts and devices, for devices plugged in since startup
+#[tauri::command]
+pub fn refresh_devices(app_handle: tauri::AppHandle) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.refresh_devices();
+    Ok(())
+}
+
 /// Get current audio state
 #[tauri::command]
 pub fn get_host(app_handle: tauri::AppHandle) -> Result<String, AudioError> {
@@ -113,16 +198,58 @@ pub fn get_buffer_size(app_handle: tauri::AppHandle) -> Result<u32, AudioError>
     Ok(engine.buffer_size())
 }
 
+/// Map a missing device reported by the engine to its specific error, anything else to `fallback`
+fn engine_error(error: anyhow::Error, fallback: AudioError) -> AudioError {
+    match error.downcast_ref::<EngineError>() {
+        Some(EngineError::NoOutputDevice) => AudioError::NoOutputDevice,
+        Some(EngineError::BlockTooLarge { .. } | EngineError::BlockTooSmall { .. }) | None => {
+            fallback.detail(error)
+        }
+    }
+}
+
+#[tauri::command]
+pub fn has_input_device(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.has_input_device())
+}
+
+#[tauri::command]
+pub fn has_output_device(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.has_output_device())
+}
+
+/// Notify the frontend about plugins that rejected re-setup after a settings change
+fn emit_setup_failures(app_handle: &tauri::AppHandle, engine: &mut AudioEngine) {
+    let failed: Vec<u64> = engine
+        .take_setup_failures()
+        .into_iter()
+        .map(|id| id.0)
+        .collect();
+
+    if !failed.is_empty() {
+        let _ = app_handle.emit("plugin-setup-failed", failed);
+    }
+}
+
 /// Set current audio states
 #[tauri::command]
 pub fn select_host(app_handle: tauri::AppHandle, host: String) -> Result<(), AudioError> {
     let audio_state = app_handle.state::<GlobalAudio>();
     let mut engine = audio_state.lock().unwrap();
 
-    engine
+    let result = engine
         .select_host(&host)
         .and_then(|_| engine.run())
-        .map_err(|_| AudioError::HostError)
+        .map_err(|e| engine_error(e, AudioError::HostError));
+
+    emit_setup_failures(&app_handle, &mut engine);
+    result
 }
 
 #[tauri::command]
@@ -130,10 +257,13 @@ pub fn select_input(app_handle: tauri::AppHandle, input_device: String) -> Resul
     let audio_state = app_handle.state::<GlobalAudio>();
     let mut engine = audio_state.lock().unwrap();
 
-    engine
+    let result = engine
         .select_input(&input_device)
         .and_then(|_| engine.run())
-        .map_err(|_| AudioError::InputDeviceError)
+        .map_err(|e| engine_error(e, AudioError::InputDeviceError));
+
+    emit_setup_failures(&app_handle, &mut engine);
+    result
 }
 
 #[tauri::command]
@@ -144,225 +274,2052 @@ pub fn select_output(
     let audio_state = app_handle.state::<GlobalAudio>();
     let mut engine = audio_state.lock().unwrap();
 
-    engine
+    let result = engine
         .select_output(&output_device)
         .and_then(|_| engine.run())
-        .map_err(|_| AudioError::OutputDeviceError)
+        .map_err(|e| engine_error(e, AudioError::OutputDeviceError));
+
+    emit_setup_failures(&app_handle, &mut engine);
+    result
 }
 
 #[tauri::command]
-pub fn set_buffer_size(app_handle: tauri::AppHandle, size: u32) -> Result<(), AudioError> {
+pub fn get_monitor_output(app_handle: tauri::AppHandle) -> Result<Option<String>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.monitor_output())
+}
+
+/// Select the device playing the monitor tap, `None` turns it off
+#[tauri::command]
+pub fn select_monitor_output(
+    app_handle: tauri::AppHandle,
+    device_name: Option<String>,
+) -> Result<(), AudioError> {
     let audio_state = app_handle.state::<GlobalAudio>();
     let mut engine = audio_state.lock().unwrap();
 
-    engine
-        .set_buffer_size(size)
+    let result = engine
+        .select_monitor_output(device_name.as_deref())
         .and_then(|_| engine.run())
-        .map_err(|_| AudioError::HostError)
+        .map_err(|e| engine_error(e, AudioError::OutputDeviceError));
+
+    emit_setup_failures(&app_handle, &mut engine);
+    result
 }
 
 #[tauri::command]
-pub fn get_plugin_paths(app_handle: tauri::AppHandle) -> Result<Vec<String>, AudioError> {
-    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
-    let registry = plugin_registry.lock().unwrap();
+pub fn get_supported_sample_rates(
+    app_handle: tauri::AppHandle,
+    device_name: String,
+) -> Result<Vec<u32>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-    Ok(registry.get_plugin_paths().to_vec())
+    Ok(engine.supported_sample_rates(&device_name))
 }
 
 #[tauri::command]
-pub fn set_plugin_paths(app_handle: tauri::AppHandle, paths: Vec<String>) -> Result<(), String> {
-    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
-    let mut registry = plugin_registry.lock().unwrap();
+pub fn get_supported_buffer_sizes(
+    app_handle: tauri::AppHandle,
+    device_name: String,
+) -> Result<Vec<u32>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-    registry.set_plugin_paths(paths)?;
-    Ok(())
+    Ok(engine.supported_buffer_sizes(&device_name))
 }
 
 #[tauri::command]
-pub fn browse_directory(app_handle: tauri::AppHandle) -> Result<(), String> {
-    use tauri::Emitter;
-    use tauri_plugin_dialog::DialogExt;
+pub fn get_sample_rate(app_handle: tauri::AppHandle) -> Result<u32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-    // Use the dialog with a callback instead of await
-    app_handle
-        .dialog()
-        .file()
-        .set_title("Select VST Plugin Directory")
-        .pick_folder(move |result| match result {
-            Some(path) => {
-                let path_str = path
-                    .as_path()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "Unknown path".to_string());
-                let _ = app_handle.emit("directory-selected", path_str);
-            }
-            None => {
-                let _ = app_handle.emit("directory-cancelled", ());
-            }
-        });
+    Ok(engine.sample_rate())
+}
 
-    Ok(())
+#[tauri::command]
+pub fn set_sample_rate(app_handle: tauri::AppHandle, rate: u32) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    // The detail carries the supported ranges so the frontend can show them
+    engine
+        .check_sample_rate(rate)
+        .map_err(|e| AudioError::HostError.detail(e))?;
+
+    let result = engine
+        .set_sample_rate(rate)
+        .and_then(|_| engine.run())
+        .map_err(|e| engine_error(e, AudioError::HostError));
+
+    emit_setup_failures(&app_handle, &mut engine);
+    result
 }
 
 #[tauri::command]
-pub fn get_discovered_plugins(app_handle: tauri::AppHandle) -> Result<Vec<String>, AudioError> {
-    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
-    let registry = plugin_registry.lock().unwrap();
+pub fn set_buffer_size(app_handle: tauri::AppHandle, size: u32) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
 
-    Ok(registry.get_discovered_plugins().to_vec())
+    engine
+        .set_buffer_size(size)
+        .and_then(|_| engine.run())
+        .map_err(|e| engine_error(e, AudioError::HostError))
 }
 
+/// Time the chain to find the smallest buffer size it runs at reliably, then apply it
+/// and restart the streams. Returns the chosen size.
 #[tauri::command]
-pub fn scan_plugins(app_handle: tauri::AppHandle) -> Result<Vec<String>, String> {
-    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
-    let mut registry = plugin_registry.lock().unwrap();
+pub fn auto_tune_buffer_size(app_handle: tauri::AppHandle) -> Result<u32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
 
-    registry.scan_plugins()
+    engine
+        .auto_tune_buffer_size()
+        .and_then(|size| {
+            engine.set_buffer_size(size)?;
+            engine.run()?;
+            Ok(size)
+        })
+        .map_err(|e| engine_error(e, AudioError::HostError))
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamFormats {
+    pub input: String,
+    pub output: String,
 }
 
 #[tauri::command]
-pub fn get_cpu_usage() -> Result<f32, String> {
-    use sysinfo::System;
+pub fn get_stream_formats(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<StreamFormats>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-    let mut system = System::new_all();
-    system.refresh_cpu(); // Refresh CPU information.
+    Ok(engine
+        .stream_formats()
+        .map(|(input, output)| StreamFormats {
+            input: format!("{:?}", input),
+            output: format!("{:?}", output),
+        }))
+}
 
-    // Wait a bit because CPU usage is calculated over time
-    std::thread::sleep(std::time::Duration::from_millis(200));
-    system.refresh_cpu();
+#[derive(Debug, Serialize)]
+pub struct ActiveStream {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub buffer_size: Option<u32>,
+    pub sample_format: String,
+}
 
-    // Calculate average CPU usage across all cores
-    let cpus = system.cpus();
-    if cpus.is_empty() {
-        return Err("No CPU information available".to_string());
+impl From<&ActiveStreamConfig> for ActiveStream {
+    fn from(active: &ActiveStreamConfig) -> Self {
+        Self {
+            channels: active.config.channels,
+            sample_rate: active.config.sample_rate.0,
+            buffer_size: active.buffer_size(),
+            sample_format: format!("{:?}", active.sample_format),
+        }
     }
+}
 
-    let total_usage: f32 = cpus.iter().map(|cpu| cpu.cpu_usage()).sum();
-    let average_usage = total_usage / cpus.len() as f32;
+#[derive(Debug, Serialize)]
+pub struct ActiveConfig {
+    pub input: Option<ActiveStream>,
+    pub output: Option<ActiveStream>,
+}
 
-    Ok(average_usage)
+/// Configs the running streams were built with, both None while stopped
+#[tauri::command]
+pub fn get_active_config(app_handle: tauri::AppHandle) -> Result<ActiveConfig, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(ActiveConfig {
+        input: engine.active_input_config().map(ActiveStream::from),
+        output: engine.active_output_config().map(ActiveStream::from),
+    })
 }
 
-#[derive(Debug)]
-pub struct PluginInfo {
-    pub id: PluginId,
-    pub name: String,
+/// Everything the `engine-state` event bundles, so the UI doesn't poll each separately
+#[derive(Debug, Serialize)]
+pub struct EngineState {
+    /// Share of the block budget the chain spends processing, in percent
+    pub cpu: f32,
+    /// Share of the block budget left after processing, in percent
+    pub headroom: f32,
+    /// Output peak per channel since the last event
+    pub meters: [f32; 2],
+    /// Phase correlation of the output, +1 mono to -1 out of phase
+    pub correlation: f32,
+    pub xruns: u64,
+    /// Chain latency in samples
+    pub latency: u32,
+    pub active_config: ActiveConfig,
 }
 
-impl Serialize for PluginInfo {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let mut state = serializer.serialize_struct("PluginInfo", 2)?;
-        state.serialize_field("id", &self.id.0)?;
-        state.serialize_field("name", &self.name)?;
-        state.end()
+impl EngineState {
+    pub fn read(meters: &EngineMeters) -> Self {
+        Self {
+            cpu: meters.chain_cpu(),
+            headroom: meters.headroom(),
+            meters: meters.output_levels(),
+            correlation: meters.output_correlation(),
+            xruns: meters.xrun_count(),
+            latency: meters.chain_latency(),
+            active_config: ActiveConfig {
+                input: meters
+                    .active_input_config()
+                    .as_ref()
+                    .map(ActiveStream::from),
+                output: meters
+                    .active_output_config()
+                    .as_ref()
+                    .map(ActiveStream::from),
+            },
+        }
+    }
+}
+
+/// Shortest and longest allowed time between two `engine-state` events
+pub const ENGINE_STATE_INTERVAL_RANGE_MS: (u64, u64) = (10, 1000);
+
+/// Time between two `engine-state` events in milliseconds
+pub struct EngineStateInterval(AtomicU64);
+
+impl EngineStateInterval {
+    pub fn new(interval: Duration) -> Self {
+        Self(AtomicU64::new(interval.as_millis() as u64))
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_millis(self.0.load(Ordering::Relaxed))
     }
 }
 
 #[tauri::command]
-pub fn get_loaded_plugins(app_handle: tauri::AppHandle) -> Result<Vec<PluginInfo>, AudioError> {
-    trace!("Getting loaded plugins");
+pub fn get_engine_state_interval(app_handle: tauri::AppHandle) -> Result<u64, AudioError> {
+    Ok(app_handle.state::<EngineStateInterval>().get().as_millis() as u64)
+}
+
+/// Change how often `engine-state` is emitted, takes effect after the next event
+#[tauri::command]
+pub fn set_engine_state_interval(
+    app_handle: tauri::AppHandle,
+    interval_ms: u64,
+) -> Result<(), AudioError> {
+    let (min, max) = ENGINE_STATE_INTERVAL_RANGE_MS;
+    if !(min..=max).contains(&interval_ms) {
+        return Err(AudioError::HostError.detail(format!(
+            "Engine state interval must be between {} and {} ms, got {}",
+            min, max, interval_ms
+        )));
+    }
+
+    app_handle
+        .state::<EngineStateInterval>()
+        .0
+        .store(interval_ms, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Sample formats the selected input device supports
+#[tauri::command]
+pub fn get_supported_input_formats(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<String>, AudioError> {
     let audio_state = app_handle.state::<GlobalAudio>();
     let engine = audio_state.lock().unwrap();
 
-    let plugins = engine.plugin_modules();
+    Ok(engine
+        .supported_input_formats()
+        .iter()
+        .map(|format| format!("{:?}", format))
+        .collect())
+}
 
-    Ok(plugins
-        .values()
-        .map(|plugin| PluginInfo {
-            id: plugin.id,
-            name: plugin.name.clone(),
-        })
+/// Sample formats the selected output device supports
+#[tauri::command]
+pub fn get_supported_output_formats(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<String>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine
+        .supported_output_formats()
+        .iter()
+        .map(|format| format!("{:?}", format))
         .collect())
 }
 
 #[tauri::command]
-pub fn load_plugin(app_handle: tauri::AppHandle, path: &str) -> Result<(), AudioError> {
+pub fn get_ring_latency_blocks(app_handle: tauri::AppHandle) -> Result<usize, AudioError> {
     let audio_state = app_handle.state::<GlobalAudio>();
-    let mut engine = audio_state.lock().unwrap();
+    let engine = audio_state.lock().unwrap();
 
-    engine
-        .load_plugin(path)
-        .map(|_| ())
-        .map_err(|_| AudioError::PluginLoadError)
+    Ok(engine.ring_latency_blocks())
 }
 
 #[tauri::command]
-pub fn remove_plugin(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<(), AudioError> {
+pub fn set_ring_latency_blocks(
+    app_handle: tauri::AppHandle,
+    blocks: usize,
+) -> Result<(), AudioError> {
     let audio_state = app_handle.state::<GlobalAudio>();
     let mut engine = audio_state.lock().unwrap();
 
+    engine.set_ring_latency_blocks(blocks);
     engine
-        .remove_plugin(PluginId(plugin_id))
-        .map(|_| ())
-        .map_err(|_| AudioError::PluginLoadError)
+        .run()
+        .map_err(|e| engine_error(e, AudioError::HostError))
 }
 
 #[tauri::command]
-pub fn open_plugin_editor(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<(), AudioError> {
+pub fn get_resampler_quality(app_handle: tauri::AppHandle) -> Result<String, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.resampler_quality().as_str().to_string())
+}
+
+#[tauri::command]
+pub fn set_resampler_quality(
+    app_handle: tauri::AppHandle,
+    quality: String,
+) -> Result<(), AudioError> {
     let audio_state = app_handle.state::<GlobalAudio>();
     let mut engine = audio_state.lock().unwrap();
-    let plugin_id = PluginId(plugin_id);
 
-    unsafe {
-        let mut modules = engine.plugin_modules_mut();
+    quality
+        .parse::<ResamplerQuality>()
+        .and_then(|quality| {
+            engine.set_resampler_quality(quality);
+            engine.run()
+        })
+        .map_err(|e| engine_error(e, AudioError::HostError))
+}
 
-        // Get the first plugin (any plugin from the map)
-        let plugin = modules.get_mut(&plugin_id).unwrap();
+#[derive(Debug, Serialize)]
+pub struct ExclusiveMode {
+    pub requested: bool,
+    pub granted: bool,
+}
 
-        let window = tauri::WindowBuilder::new(&app_handle, plugin_id)
-            .build()
-            .map_err(|_| AudioError::PluginEditorError)?;
-        let _ = window.set_title(&plugin.name);
-        let _ = window.set_resizable(false);
+#[tauri::command]
+pub fn get_exclusive_mode(app_handle: tauri::AppHandle) -> Result<ExclusiveMode, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-        #[cfg(target_os = "windows")]
-        let hwnd = window.hwnd().map_err(|_| AudioError::PluginEditorError)?.0;
-        #[cfg(target_os = "macos")]
-        let hwnd = window
-            .ns_view()
-            .map_err(|_| AudioError::PluginEditorError)?
-            .0;
+    Ok(ExclusiveMode {
+        requested: engine.exclusive_mode(),
+        granted: engine.exclusive_mode_granted(),
+    })
+}
 
-        // plugin.component.unwrap().set_active(false);
+/// Request exclusive access to the device, refused on hosts cpal only opens shared
+#[tauri::command]
+pub fn set_exclusive_mode(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
 
-        let view = plugin.view.unwrap();
+    engine
+        .set_exclusive_mode(enabled)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
 
-        #[cfg(target_os = "windows")]
-        view.attached(hwnd as *mut c_void, PlatformType::HWND);
+#[tauri::command]
+pub fn get_format_strategy(app_handle: tauri::AppHandle) -> Result<String, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-        #[cfg(target_os = "macos")]
-        view.attached(hwnd as *mut c_void, PlatformType::NSView);
+    Ok(engine.format_strategy().as_str().to_string())
+}
 
-        let mut rect = ViewRect::default();
-        view.check_size_constraint(&mut rect);
+/// Applies to the next device selection, the current devices keep their configs
+#[tauri::command]
+pub fn set_format_strategy(
+    app_handle: tauri::AppHandle,
+    strategy: String,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
 
-        let scale_factor = window.scale_factor().unwrap();
+    let strategy = strategy
+        .parse::<FormatStrategy>()
+        .map_err(|e| AudioError::HostError.detail(e))?;
+    engine.set_format_strategy(strategy);
+    Ok(())
+}
 
-        view.on_size(&mut rect);
-        let new_size = PhysicalSize::new(rect.right, rect.bottom).to_logical::<i32>(scale_factor);
-        let _ = window.set_size(new_size);
+#[derive(Debug, Serialize)]
+pub struct ResampleRatios {
+    pub nominal: Option<f64>,
+    pub effective: Option<f64>,
+}
 
-        let cloned_window = window.clone();
+/// Configured and in-use resampling ratios, they differ while adaptive resampling
+/// follows clock drift
+#[tauri::command]
+pub fn get_resample_ratios(app_handle: tauri::AppHandle) -> Result<ResampleRatios, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
 
-        plugin.set_window_resize_callback(move |view, new_size| {
-            view.on_size(&mut *new_size);
-            let _ = cloned_window
-                .set_size(
-                    PhysicalSize::new(new_size.right, new_size.bottom)
-                        .to_logical::<i32>(scale_factor),
-                )
-                .unwrap();
-        });
+    Ok(ResampleRatios {
+        nominal: engine.nominal_resample_ratio(),
+        effective: engine.effective_resample_ratio(),
+    })
+}
 
-        window.on_window_event(move |event| {
-            if let tauri::WindowEvent::CloseRequested { .. } = event {
-                view.removed();
-            }
+#[tauri::command]
+pub fn get_adaptive_resampling(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.adaptive_resampling())
+}
+
+#[tauri::command]
+pub fn set_adaptive_resampling(
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_adaptive_resampling(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_latency_polling(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.latency_polling())
+}
+
+#[tauri::command]
+pub fn set_latency_polling(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_latency_polling(enabled);
+    Ok(())
+}
+
+/// Latency of the slowest plugin chain, in samples
+#[tauri::command]
+pub fn get_chain_latency(app_handle: tauri::AppHandle) -> Result<u32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.chain_latency())
+}
+
+/// Round-trip latency from input to output device in milliseconds, including the
+/// device buffers, the ring and the resampler on top of the chain latency
+#[tauri::command]
+pub fn get_roundtrip_latency(app_handle: tauri::AppHandle) -> Result<f32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.roundtrip_latency_ms())
+}
+
+/// Share of the block budget left after processing, in percent
+#[tauri::command]
+pub fn get_headroom(app_handle: tauri::AppHandle) -> Result<f32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.headroom())
+}
+
+/// Everything support needs to troubleshoot the audio setup, for the UI to save to a file
+#[tauri::command]
+pub fn get_diagnostics(app_handle: tauri::AppHandle) -> Result<Diagnostics, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.diagnostics())
+}
+
+#[tauri::command]
+pub fn get_input_gain(app_handle: tauri::AppHandle) -> Result<f32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.input_gain())
+}
+
+#[tauri::command]
+pub fn set_input_gain(app_handle: tauri::AppHandle, linear: f32) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_input_gain(linear)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_monitor_mix(app_handle: tauri::AppHandle) -> Result<f32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.monitor_mix())
+}
+
+#[tauri::command]
+pub fn set_monitor_mix(app_handle: tauri::AppHandle, wet: f32) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_monitor_mix(wet)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+/// Switch between the processed output and the bypassed chain, returns whether the
+/// bypassed chain is heard now
+#[tauri::command]
+pub fn global_ab(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    Ok(engine.toggle_global_ab())
+}
+
+#[tauri::command]
+pub fn get_global_ab(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.global_ab())
+}
+
+#[tauri::command]
+pub fn get_global_ab_loudness_match(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.global_ab_loudness_match())
+}
+
+#[tauri::command]
+pub fn set_global_ab_loudness_match(
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_global_ab_loudness_match(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_channel_swap(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.channel_swap())
+}
+
+#[tauri::command]
+pub fn set_channel_swap(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_channel_swap(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_mono_sum(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.mono_sum())
+}
+
+#[tauri::command]
+pub fn set_mono_sum(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_mono_sum(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_downmix_preset(app_handle: tauri::AppHandle) -> Result<String, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.downmix_preset().as_str().to_string())
+}
+
+/// Route the output through "identity", "stereo_to_5_1" or "5_1_to_stereo"
+#[tauri::command]
+pub fn set_downmix_preset(app_handle: tauri::AppHandle, preset: String) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    let preset = preset
+        .parse::<Downmix>()
+        .map_err(|e| AudioError::HostError.detail(e))?;
+    engine.set_downmix_preset(preset);
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutputLimiter {
+    pub enabled: bool,
+    pub ceiling_db: f32,
+}
+
+#[tauri::command]
+pub fn get_output_limiter(app_handle: tauri::AppHandle) -> Result<OutputLimiter, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    let (enabled, ceiling_db) = engine.output_limiter();
+    Ok(OutputLimiter {
+        enabled,
+        ceiling_db,
+    })
+}
+
+/// Soft clip the output at `ceiling_db` dBFS to protect ears and speakers
+#[tauri::command]
+pub fn set_output_limiter(
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+    ceiling_db: f32,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_output_limiter(enabled, ceiling_db)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_input_source(app_handle: tauri::AppHandle) -> Result<InputSource, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.input_source())
+}
+
+/// Feed the chain from the input device or a test signal, e.g.
+/// `{ "type": "sine_wave", "freq": 440, "amp": 0.5 }`
+#[tauri::command]
+pub fn set_input_source(
+    app_handle: tauri::AppHandle,
+    source: InputSource,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_input_source(source)
+        .map_err(|e| engine_error(e, AudioError::InputDeviceError))
+}
+
+#[tauri::command]
+pub fn get_input_highpass(app_handle: tauri::AppHandle) -> Result<Option<f32>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.input_highpass())
+}
+
+#[tauri::command]
+pub fn set_input_highpass(
+    app_handle: tauri::AppHandle,
+    cutoff: Option<f32>,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_input_highpass(cutoff)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_plugin_paths(app_handle: tauri::AppHandle) -> Result<Vec<String>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.get_plugin_paths().to_vec())
+}
+
+#[tauri::command]
+pub fn set_plugin_paths(app_handle: tauri::AppHandle, paths: Vec<String>) -> Result<(), String> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry.set_plugin_paths(paths)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn browse_directory(app_handle: tauri::AppHandle) -> Result<(), String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    // Use the dialog with a callback instead of await
+    app_handle
+        .dialog()
+        .file()
+        .set_title("Select VST Plugin Directory")
+        .pick_folder(move |result| match result {
+            Some(path) => {
+                let path_str = path
+                    .as_path()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "Unknown path".to_string());
+                let _ = app_handle.emit("directory-selected", path_str);
+            }
+            None => {
+                let _ = app_handle.emit("directory-cancelled", ());
+            }
         });
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_discovered_plugins(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<DiscoveredPlugin>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.get_discovered_plugins().to_vec())
+}
+
+#[tauri::command]
+pub fn get_discovered_plugin_paths(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<String>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.get_discovered_plugin_paths())
+}
+
+#[tauri::command]
+pub fn get_plugin_icon_path(
+    app_handle: tauri::AppHandle,
+    uid: String,
+) -> Result<Option<String>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry
+        .plugin_icon_path(&uid)
+        .map(|icon| icon.to_string_lossy().into_owned()))
+}
+
+#[tauri::command]
+pub fn get_blacklisted_plugins(app_handle: tauri::AppHandle) -> Result<Vec<String>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.get_blacklisted_plugins())
+}
+
+#[tauri::command]
+pub fn clear_plugin_blacklist(app_handle: tauri::AppHandle) -> Result<(), AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry.clear_blacklist();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_plugin_probe_timeout(app_handle: tauri::AppHandle) -> Result<u64, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.probe_timeout().as_millis() as u64)
+}
+
+#[tauri::command]
+pub fn set_plugin_probe_timeout(
+    app_handle: tauri::AppHandle,
+    timeout_ms: u64,
+) -> Result<(), AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry.set_probe_timeout(std::time::Duration::from_millis(timeout_ms));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_plugin_follow_symlinks(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.follow_symlinks())
+}
+
+/// Whether scans follow symlinks, e.g. to bundles linked from a central location
+#[tauri::command]
+pub fn set_plugin_follow_symlinks(
+    app_handle: tauri::AppHandle,
+    follow: bool,
+) -> Result<(), AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry.set_follow_symlinks(follow);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn scan_plugins(app_handle: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry.scan_plugins()
+}
+
+#[tauri::command]
+pub fn rescan_plugins(app_handle: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry.invalidate_cache();
+    registry.scan_plugins()
+}
+
+#[tauri::command]
+pub fn get_cpu_usage() -> Result<f32, String> {
+    use sysinfo::System;
+
+    let mut system = System::new_all();
+    system.refresh_cpu(); // Refresh CPU information.
+
+    // Wait a bit because CPU usage is calculated over time
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    system.refresh_cpu();
+
+    // Calculate average CPU usage across all cores
+    let cpus = system.cpus();
+    if cpus.is_empty() {
+        return Err("No CPU information available".to_string());
+    }
+
+    let total_usage: f32 = cpus.iter().map(|cpu| cpu.cpu_usage()).sum();
+    let average_usage = total_usage / cpus.len() as f32;
+
+    Ok(average_usage)
+}
+
+/// Share of the block budget each loaded plugin spends processing, in percent
+#[tauri::command]
+pub fn get_plugin_cpu_usage(app_handle: tauri::AppHandle) -> Result<HashMap<u64, f32>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine
+        .get_loaded_plugin_ids()
+        .into_iter()
+        .map(|id| (id.0, engine.plugin_cpu(id)))
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct PluginIoLevels {
+    pub plugin_id: u64,
+    pub input: f32,
+    pub output: f32,
+}
+
+/// Input and output peaks of every loaded plugin since the last call, in chain order
+#[tauri::command]
+pub fn get_all_plugin_io_levels(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<PluginIoLevels>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine
+        .all_plugin_io_levels()
+        .into_iter()
+        .map(|(id, input, output)| PluginIoLevels {
+            plugin_id: id.0,
+            input,
+            output,
+        })
+        .collect())
+}
+
+#[derive(Debug)]
+pub struct PluginInfo {
+    pub id: PluginId,
+    pub name: String,
+    pub vendor: String,
+    pub format: PluginFormat,
+    pub bypassed: bool,
+    /// Last latency the plugin reported, in its own samples
+    pub latency_samples: u32,
+    pub has_editor: bool,
+    pub chain: ChainId,
+    /// Position in the processing order
+    pub index: usize,
+    /// Active preset, for plugins with program lists
+    pub program: Option<String>,
+    /// Status the plugin reports, e.g. its active voices
+    pub status: Option<String>,
+}
+
+impl Serialize for PluginInfo {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("PluginInfo", 11)?;
+        state.serialize_field("id", &self.id.0)?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field("vendor", &self.vendor)?;
+        state.serialize_field("format", &self.format)?;
+        state.serialize_field("bypassed", &self.bypassed)?;
+        state.serialize_field("latency_samples", &self.latency_samples)?;
+        state.serialize_field("has_editor", &self.has_editor)?;
+        state.serialize_field("chain", &self.chain.0)?;
+        state.serialize_field("index", &self.index)?;
+        state.serialize_field("program", &self.program)?;
+        state.serialize_field("status", &self.status)?;
+        state.end()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParallelChainInfo {
+    pub id: u64,
+    pub gain: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainInfo {
+    pub locked: bool,
+    pub plugins: Vec<PluginInfo>,
+    pub chains: Vec<ParallelChainInfo>,
+}
+
+/// Collect the loaded plugins in chain order
+fn loaded_plugin_infos(engine: &AudioEngine) -> Vec<PluginInfo> {
+    let plugins = engine.plugin_modules();
+
+    engine
+        .plugin_order()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, id)| Some((index, plugins.get(id)?)))
+        .map(|(index, plugin)| PluginInfo {
+            id: plugin.id,
+            name: plugin.name.clone(),
+            vendor: plugin.vendor.clone(),
+            format: plugin.format,
+            bypassed: plugin.bypass,
+            latency_samples: engine.plugin_latency(plugin.id).unwrap_or(0),
+            has_editor: plugin.has_editor(),
+            chain: plugin.chain,
+            index,
+            program: plugin.current_program_name(),
+            status: plugin.status_string(),
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn get_loaded_plugins(app_handle: tauri::AppHandle) -> Result<Vec<PluginInfo>, AudioError> {
+    trace!("Getting loaded plugins");
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(loaded_plugin_infos(&engine))
+}
+
+#[tauri::command]
+pub fn get_chain_info(app_handle: tauri::AppHandle) -> Result<ChainInfo, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(ChainInfo {
+        locked: engine.is_chain_locked(),
+        plugins: loaded_plugin_infos(&engine),
+        chains: engine
+            .chains()
+            .into_iter()
+            .map(|(id, gain)| ParallelChainInfo { id: id.0, gain })
+            .collect(),
+    })
+}
+
+#[tauri::command]
+pub fn set_chain_locked(app_handle: tauri::AppHandle, locked: bool) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_chain_locked(locked);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn add_chain(app_handle: tauri::AppHandle) -> Result<u64, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .add_chain()
+        .map(|id| id.0)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn remove_chain(app_handle: tauri::AppHandle, chain_id: u64) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .remove_chain(ChainId(chain_id))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn set_chain_gain(
+    app_handle: tauri::AppHandle,
+    chain_id: u64,
+    gain: f32,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_chain_gain(ChainId(chain_id), gain)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn set_plugin_chain(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    chain_id: u64,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_plugin_chain(PluginId(plugin_id), ChainId(chain_id))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_plugin_sample_rate(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+) -> Result<u32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.plugin_sample_rate(PluginId(plugin_id)))
+}
+
+/// Run a plugin at its own sample rate, `None` follows the engine rate again
+#[tauri::command]
+pub fn set_plugin_sample_rate(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    sample_rate: Option<u32>,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_plugin_sample_rate(PluginId(plugin_id), sample_rate)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Last known normalized value of a plugin parameter
+#[tauri::command]
+pub fn get_plugin_parameter(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    param_id: u32,
+) -> Result<Option<f64>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.parameter_value(PluginId(plugin_id), param_id))
+}
+
+/// Set a normalized plugin parameter, e.g. from a generic parameter UI
+#[tauri::command]
+pub fn set_plugin_parameter(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    param_id: u32,
+    value: f64,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_parameter(PluginId(plugin_id), param_id, value)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Parameters a plugin describes, with whether each can be automated
+#[tauri::command]
+pub fn get_plugin_parameters(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+) -> Result<Vec<PluginParameter>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    engine
+        .plugin_parameters(PluginId(plugin_id))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Queue automation for a plugin parameter at `sample_offset` into its next block.
+/// Fails for parameters the plugin doesn't mark automatable.
+#[tauri::command]
+pub fn automate_plugin_parameter(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    param_id: u32,
+    sample_offset: i32,
+    value: f64,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    engine
+        .automate_parameter(PluginId(plugin_id), param_id, sample_offset, value)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_plugin_mix(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<f32, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    engine
+        .plugin_mix(PluginId(plugin_id))
+        .ok_or_else(|| AudioError::PluginLoadError.detail("plugin not found"))
+}
+
+/// Blend a plugin's output with its input, from 0 (dry) to 1 (wet)
+#[tauri::command]
+pub fn set_plugin_mix(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    wet: f32,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_plugin_mix(PluginId(plugin_id), wet)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_midi_output_ports() -> Result<Vec<String>, AudioError> {
+    audio::midi::output_port_names().map_err(|e| AudioError::MidiError.detail(e))
+}
+
+#[tauri::command]
+pub fn list_midi_inputs(app_handle: tauri::AppHandle) -> Result<Vec<String>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.list_midi_inputs())
+}
+
+/// Play the instrument plugins from a MIDI input port
+#[tauri::command]
+pub fn select_midi_input(app_handle: tauri::AppHandle, port: &str) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .select_midi_input(port)
+        .map_err(|e| AudioError::MidiError.detail(e))
+}
+
+#[tauri::command]
+pub fn enable_midi_clock_out(app_handle: tauri::AppHandle, port: &str) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .enable_midi_clock_out(port)
+        .map_err(|e| AudioError::MidiError.detail(e))
+}
+
+#[tauri::command]
+pub fn disable_midi_clock_out(app_handle: tauri::AppHandle) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.disable_midi_clock_out();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_tempo(app_handle: tauri::AppHandle) -> Result<f64, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.tempo())
+}
+
+#[tauri::command]
+pub fn set_tempo(app_handle: tauri::AppHandle, bpm: f64) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_tempo(bpm)
+        .map_err(|e| AudioError::MidiError.detail(e))
+}
+
+#[tauri::command]
+pub fn set_transport_playing(
+    app_handle: tauri::AppHandle,
+    playing: bool,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_transport_playing(playing);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn rewind_transport(app_handle: tauri::AppHandle) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.rewind_transport();
+    Ok(())
+}
+
+/// A transport position given either as a sample or as a bar, not both
+fn record_point(sample: Option<u64>, bar: Option<u32>) -> Result<Option<RecordPoint>, AudioError> {
+    match (sample, bar) {
+        (Some(_), Some(_)) => {
+            Err(AudioError::HostError
+                .detail("a recording point is either a sample or a bar, not both"))
+        }
+        (Some(sample), None) => Ok(Some(RecordPoint::Sample(sample))),
+        (None, Some(bar)) => Ok(Some(RecordPoint::Bar(bar))),
+        (None, None) => Ok(None),
+    }
+}
+
+#[tauri::command]
+pub fn start_recording(
+    app_handle: tauri::AppHandle,
+    path: String,
+    start_at_sample: Option<u64>,
+    start_at_bar: Option<u32>,
+    stop_at_sample: Option<u64>,
+    stop_at_bar: Option<u32>,
+) -> Result<(), AudioError> {
+    let start_at = record_point(start_at_sample, start_at_bar)?;
+    let stop_at = record_point(stop_at_sample, stop_at_bar)?;
+
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .start_recording(path, start_at, stop_at)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+/// Returns the path of the finished recording
+#[tauri::command]
+pub fn stop_recording(app_handle: tauri::AppHandle) -> Result<String, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .stop_recording()
+        .map(|path| path.to_string_lossy().into_owned())
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+#[tauri::command]
+pub fn is_recording(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.is_recording())
+}
+
+#[tauri::command]
+pub fn get_processing_enabled(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.is_processing_enabled())
+}
+
+#[tauri::command]
+pub fn set_processing_enabled(
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_processing_enabled(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_denormal_protection(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.denormal_protection())
+}
+
+#[tauri::command]
+pub fn set_denormal_protection(
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_denormal_protection(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_parallel_processing(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.parallel_processing())
+}
+
+/// Process independent parallel chains on worker threads
+#[tauri::command]
+pub fn set_parallel_processing(
+    app_handle: tauri::AppHandle,
+    enabled: bool,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_parallel_processing(enabled)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_dither(app_handle: tauri::AppHandle) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.dither())
+}
+
+#[tauri::command]
+pub fn set_dither(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine.set_dither(enabled);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_audio_thread_affinity(
+    app_handle: tauri::AppHandle,
+) -> Result<Option<usize>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.audio_thread_affinity())
+}
+
+/// Pin the audio thread to a CPU core, `None` unpins it
+#[tauri::command]
+pub fn set_audio_thread_affinity(
+    app_handle: tauri::AppHandle,
+    core: Option<usize>,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_audio_thread_affinity(core)
+        .map_err(|e| AudioError::HostError.detail(e))
+}
+
+/// Load a plugin, `class_uid` picks one of the classes of a factory that ships several
+#[tauri::command]
+pub fn load_plugin(
+    app_handle: tauri::AppHandle,
+    path: &str,
+    class_uid: Option<String>,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    let result = registry
+        .run_guarded(path, || match class_uid.as_deref() {
+            Some(class_uid) => engine.load_plugin_class(path, class_uid),
+            None => engine.load_plugin(path),
+        })
+        .ok_or(AudioError::PluginLoadError)?;
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if err.is::<PluginLoadTimeout>() => {
+            registry.mark_suspect(path);
+            Err(AudioError::PluginLoadTimeout)
+        }
+        Err(err) => Err(AudioError::PluginLoadError.detail(err)),
+    }
+}
+
+/// Classes the plugin's factory declares, the audio ones can be passed to `load_plugin`
+#[tauri::command]
+pub fn list_plugin_classes(
+    app_handle: tauri::AppHandle,
+    path: &str,
+) -> Result<Vec<ClassInfo>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    registry
+        .run_guarded(path, || list_classes(path))
+        .ok_or(AudioError::PluginLoadError)?
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnresolvedChainEntry {
+    pub index: usize,
+    pub uid_or_name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainImportReport {
+    /// IDs of the plugins added, in chain order
+    pub plugins: Vec<u64>,
+    pub unresolved: Vec<UnresolvedChainEntry>,
+    /// Settings of added plugins that couldn't be applied
+    pub warnings: Vec<String>,
+}
+
+/// Append a chain described in JSON by another tool, resolving plugins against the
+/// scanned ones. Entries that couldn't be added are reported rather than failing the import.
+#[tauri::command]
+pub fn import_chain(
+    app_handle: tauri::AppHandle,
+    json: String,
+) -> Result<ChainImportReport, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    let import = engine
+        .import_chain_from_json(&json, |uid_or_name| registry.resolve_plugin(uid_or_name))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))?;
+
+    Ok(ChainImportReport {
+        plugins: import.plugins.iter().map(|id| id.0).collect(),
+        unresolved: import
+            .unresolved
+            .into_iter()
+            .map(|entry| UnresolvedChainEntry {
+                index: entry.index,
+                uid_or_name: entry.uid_or_name,
+                reason: entry.reason,
+            })
+            .collect(),
+        warnings: import.warnings,
+    })
+}
+
+/// Load a plugin in its own host process. Needs the `sandbox` feature.
+#[tauri::command]
+pub fn load_plugin_sandboxed(app_handle: tauri::AppHandle, path: &str) -> Result<(), AudioError> {
+    #[cfg(feature = "sandbox")]
+    {
+        let audio_state = app_handle.state::<GlobalAudio>();
+        let mut engine = audio_state.lock().unwrap();
+
+        match engine.load_plugin_sandboxed(path) {
+            Ok(_) => Ok(()),
+            Err(err) if err.is::<PluginLoadTimeout>() => Err(AudioError::PluginLoadTimeout),
+            Err(err) => Err(AudioError::PluginLoadError.detail(err)),
+        }
+    }
+
+    #[cfg(not(feature = "sandbox"))]
+    {
+        let _ = (app_handle, path);
+        Err(AudioError::PluginLoadError.detail("built without sandbox support"))
+    }
+}
+
+#[tauri::command]
+pub fn get_suspect_plugins(app_handle: tauri::AppHandle) -> Result<Vec<String>, AudioError> {
+    let plugin_registry = app_handle.state::<GlobalPluginRegistry>();
+    let registry = plugin_registry.lock().unwrap();
+
+    Ok(registry.get_suspect_plugins())
+}
+
+#[tauri::command]
+pub fn remove_plugin(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .remove_plugin(PluginId(plugin_id))
+        .map(|_| ())
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn move_plugin_to_front(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .move_plugin_to_front(PluginId(plugin_id))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn move_plugin_to_back(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .move_plugin_to_back(PluginId(plugin_id))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Reload a plugin from disk keeping its place in the chain. Returns whether its
+/// state could be restored.
+#[tauri::command]
+pub fn reload_plugin(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<bool, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+    let plugin_id = PluginId(plugin_id);
+
+    // The editor's view goes away with the old instance
+    if let Some(window) = app_handle.get_window(&String::from(plugin_id)) {
+        let _ = window.destroy();
+    }
+    remove_editor_window(&app_handle, plugin_id.0);
+    if let Some(window) = app_handle.get_window(&embedded_editor_label(plugin_id.0)) {
+        let _ = window.destroy();
+    }
+    remove_embedded_editor(&app_handle, plugin_id.0);
+
+    engine
+        .reload_plugin(plugin_id)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Store the whole chain in A/B slot "a" or "b"
+#[tauri::command]
+pub fn store_ab(app_handle: tauri::AppHandle, slot: String) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    slot.parse::<AbSlot>()
+        .map(|slot| engine.store_snapshot(slot))
+        .map_err(|e| AudioError::PluginStateError.detail(e))
+}
+
+/// Restore the chain stored in A/B slot "a" or "b"
+#[tauri::command]
+pub fn recall_ab(app_handle: tauri::AppHandle, slot: String) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    slot.parse::<AbSlot>()
+        .and_then(|slot| engine.recall_snapshot(slot))
+        .map_err(|e| AudioError::PluginStateError.detail(e))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PluginBuses {
+    pub inputs: Vec<BusDescription>,
+    pub outputs: Vec<BusDescription>,
+}
+
+/// Audio buses a plugin exposes, e.g. to pick the output bus to monitor
+#[tauri::command]
+pub fn get_plugin_buses(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+) -> Result<PluginBuses, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+    let plugins = engine.plugin_modules();
+
+    let plugin = plugins
+        .get(&PluginId(plugin_id))
+        .ok_or_else(|| AudioError::PluginLoadError.detail("plugin not found"))?;
+    Ok(PluginBuses {
+        inputs: plugin.input_buses.clone(),
+        outputs: plugin.output_buses.clone(),
+    })
+}
+
+#[tauri::command]
+pub fn set_monitor_bus(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    bus_index: usize,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_monitor_bus(PluginId(plugin_id), bus_index)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Feed a plugin's sidechain from the main input or another plugin, neither disconnects it
+#[tauri::command]
+pub fn set_sidechain_source(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    main_input: bool,
+    source_plugin: Option<u64>,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    let source = if main_input {
+        Some(SidechainSource::MainInput)
+    } else {
+        source_plugin.map(|id| SidechainSource::Plugin(PluginId(id)))
+    };
+    engine
+        .set_sidechain_source(PluginId(plugin_id), source)
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+#[tauri::command]
+pub fn get_monitor_tap(app_handle: tauri::AppHandle) -> Result<Option<u64>, AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let engine = audio_state.lock().unwrap();
+
+    Ok(engine.monitor_tap().map(|id| id.0))
+}
+
+/// Route a plugin's wet output to the monitor output, `None` silences it
+#[tauri::command]
+pub fn set_monitor_tap(
+    app_handle: tauri::AppHandle,
+    plugin_id: Option<u64>,
+) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    engine
+        .set_monitor_tap(plugin_id.map(PluginId))
+        .map_err(|e| AudioError::PluginLoadError.detail(e))
+}
+
+/// Attach a plugin's view to a window's native surface
+unsafe fn attach_view(window: &tauri::Window, view: &IPlugView) -> Result<(), AudioError> {
+    #[cfg(target_os = "windows")]
+    let hwnd = window
+        .hwnd()
+        .map_err(|e| AudioError::PluginEditorError.detail(e))?
+        .0;
+    #[cfg(target_os = "macos")]
+    let hwnd = window
+        .ns_view()
+        .map_err(|e| AudioError::PluginEditorError.detail(e))?
+        .0;
+
+    #[cfg(target_os = "windows")]
+    view.attached(hwnd as *mut c_void, PlatformType::HWND);
+
+    #[cfg(target_os = "macos")]
+    view.attached(hwnd as *mut c_void, PlatformType::NSView);
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let _ = (window, view);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn open_plugin_editor(app_handle: tauri::AppHandle, plugin_id: u64) -> Result<(), AudioError> {
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+    let plugin_id = PluginId(plugin_id);
+
+    // A second window attaching the same view crashes some plugins, a docked editor
+    // already has it attached
+    if let Some(window) = embedded_editor_window(&app_handle, plugin_id.0) {
+        window
+            .set_focus()
+            .map_err(|e| AudioError::PluginEditorError.detail(e))?;
+        return Ok(());
+    }
+    if !app_handle
+        .state::<EditorWindows>()
+        .0
+        .lock()
+        .unwrap()
+        .insert(plugin_id.0)
+    {
+        if let Some(window) = app_handle.get_window(&String::from(plugin_id)) {
+            window
+                .set_focus()
+                .map_err(|e| AudioError::PluginEditorError.detail(e))?;
+        }
+        return Ok(());
+    }
+
+    let result = unsafe { open_editor_window(&app_handle, &mut engine, plugin_id) };
+    if result.is_err() {
+        remove_editor_window(&app_handle, plugin_id.0);
+    }
+    result
+}
+
+/// Internal helper to build the editor window and attach the plugin's view to it
+unsafe fn open_editor_window(
+    app_handle: &tauri::AppHandle,
+    engine: &mut AudioEngine,
+    plugin_id: PluginId,
+) -> Result<(), AudioError> {
+    let mut modules = engine.plugin_modules_mut();
+
+    let plugin = modules
+        .get_mut(&plugin_id)
+        .ok_or_else(|| AudioError::PluginEditorError.detail("plugin not found"))?;
+
+    // Processor-only plugins are edited through the generic parameter UI
+    if !plugin.has_editor() {
+        return Err(AudioError::PluginEditorError.detail("Plugin has no editor"));
+    }
+
+    let window = tauri::WindowBuilder::new(app_handle, plugin_id)
+        .build()
+        .map_err(|e| AudioError::PluginEditorError.detail(e))?;
+    let _ = window.set_title(&plugin.name);
+    let _ = window.set_resizable(false);
+
+    // plugin.component.unwrap().set_active(false);
+
+    let view = plugin.view.unwrap();
+    attach_view(&window, &view)?;
+
+    let mut rect = ViewRect::default();
+    view.check_size_constraint(&mut rect);
+
+    view.on_size(&mut rect);
+    size_editor_window(&window, &rect, window.scale_factor().unwrap_or(1.0));
+
+    let cloned_window = window.clone();
+
+    // The window may have moved to a monitor with another scale factor since it opened
+    plugin.set_window_resize_callback(move |view, new_size| {
+        view.on_size(&mut *new_size);
+        size_editor_window(
+            &cloned_window,
+            new_size,
+            cloned_window.scale_factor().unwrap_or(1.0),
+        );
+    });
+
+    let handle = app_handle.clone();
+    let resized = window.clone();
+    window.on_window_event(move |event| match event {
+        tauri::WindowEvent::CloseRequested { .. } => {
+            // Only the first close request detaches the view
+            if remove_editor_window(&handle, plugin_id.0) {
+                view.removed();
+            }
+        }
+        tauri::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+            // The view keeps its size in pixels, the window's logical size follows it
+            let mut rect = ViewRect::default();
+            if view.get_size(&mut rect) == TResult::ResultOk {
+                size_editor_window(&resized, &rect, *scale_factor);
+            }
+        }
+        _ => {}
+    });
+
+    Ok(())
+}
+
+/// Size an editor window to its view, whose rect is in physical pixels
+fn size_editor_window(window: &tauri::Window, rect: &ViewRect, scale_factor: f64) {
+    let size = PhysicalSize::new(rect.right, rect.bottom).to_logical::<i32>(scale_factor);
+    if let Err(e) = window.set_size(size) {
+        warn!("Failed to resize editor window {}: {}", window.label(), e);
+    }
+}
+
+/// Plugins with an editor window open, see `open_plugin_editor`
+#[derive(Default)]
+pub struct EditorWindows(Mutex<HashSet<u64>>);
+
+/// Stop tracking a plugin's editor window, returns whether it was open
+fn remove_editor_window(app_handle: &tauri::AppHandle, plugin_id: u64) -> bool {
+    app_handle
+        .state::<EditorWindows>()
+        .0
+        .lock()
+        .unwrap()
+        .remove(&plugin_id)
+}
+
+/// Region of the main window an embedded editor is docked to, in logical pixels
+#[derive(Debug, Clone, Copy)]
+struct EditorRegion {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+/// Editors docked inside the main window by plugin, see `embed_plugin_editor`
+#[derive(Default)]
+pub struct EmbeddedEditors(Mutex<HashMap<u64, EditorRegion>>);
+
+/// Size an embedded editor's view took, in logical pixels
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct EmbeddedEditorSize {
+    width: f64,
+    height: f64,
+}
+
+fn embedded_editor_label(plugin_id: u64) -> String {
+    format!("embedded-editor-{}", plugin_id)
+}
+
+fn remove_embedded_editor(app_handle: &tauri::AppHandle, plugin_id: u64) {
+    app_handle
+        .state::<EmbeddedEditors>()
+        .0
+        .lock()
+        .unwrap()
+        .remove(&plugin_id);
+}
+
+/// Window a plugin's editor is docked to, if `embed_plugin_editor` attached its view
+fn embedded_editor_window(app_handle: &tauri::AppHandle, plugin_id: u64) -> Option<tauri::Window> {
+    if !app_handle
+        .state::<EmbeddedEditors>()
+        .0
+        .lock()
+        .unwrap()
+        .contains_key(&plugin_id)
+    {
+        return None;
     }
+    app_handle.get_window(&embedded_editor_label(plugin_id))
+}
+
+/// Move an embedded editor's window over its region of the main window
+fn position_embedded_editor(main: &tauri::Window, window: &tauri::Window, region: EditorRegion) {
+    let (Ok(origin), Ok(scale_factor)) = (main.inner_position(), main.scale_factor()) else {
+        return;
+    };
+    let _ = window.set_position(PhysicalPosition::new(
+        origin.x + (region.x * scale_factor) as i32,
+        origin.y + (region.y * scale_factor) as i32,
+    ));
+}
+
+/// Size an embedded editor's window to its view, clipped to its region
+fn size_embedded_editor(window: &tauri::Window, region: EditorRegion, rect: &ViewRect) {
+    let scale_factor = window.scale_factor().unwrap_or(1.0);
+    let width = rect.right.min((region.width * scale_factor) as i32).max(0);
+    let height = rect
+        .bottom
+        .min((region.height * scale_factor) as i32)
+        .max(0);
+    let _ = window.set_size(PhysicalSize::new(width as u32, height as u32));
+}
+
+/// Keep the embedded editors over their regions as the main window moves
+pub fn follow_main_window(app_handle: &tauri::AppHandle) {
+    let Some(main) = app_handle.get_window("main") else {
+        return;
+    };
+
+    let editors = app_handle.state::<EmbeddedEditors>();
+    for (plugin_id, region) in editors.0.lock().unwrap().iter() {
+        if let Some(window) = app_handle.get_window(&embedded_editor_label(*plugin_id)) {
+            position_embedded_editor(&main, &window, *region);
+        }
+    }
+}
+
+/// Dock a plugin's editor inside the main window instead of a window of its own, over
+/// the region at `x`, `y` of `width` by `height` logical pixels. The view is attached to a
+/// borderless child window that follows the main window. Call again with the new region
+/// when the layout changes. Returns the size the view took, a view with a minimum size
+/// larger than the region is clipped to it. Fails while the editor is open in a window of
+/// its own, close that first.
+#[tauri::command]
+pub fn embed_plugin_editor(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> Result<EmbeddedEditorSize, AudioError> {
+    let main = app_handle
+        .get_window("main")
+        .ok_or_else(|| AudioError::PluginEditorError.detail("main window not found"))?;
+    let scale_factor = main
+        .scale_factor()
+        .map_err(|e| AudioError::PluginEditorError.detail(e))?;
+
+    let audio_state = app_handle.state::<GlobalAudio>();
+    let mut engine = audio_state.lock().unwrap();
+
+    // The view can only be attached to one window at a time
+    if app_handle
+        .state::<EditorWindows>()
+        .0
+        .lock()
+        .unwrap()
+        .contains(&plugin_id)
+    {
+        return Err(AudioError::PluginEditorError.detail("editor is open in its own window"));
+    }
+
+    let mut modules = engine.plugin_modules_mut();
+    let plugin = modules
+        .get_mut(&PluginId(plugin_id))
+        .ok_or_else(|| AudioError::PluginEditorError.detail("plugin not found"))?;
+    let view = plugin
+        .view
+        .ok_or_else(|| AudioError::PluginEditorError.detail("plugin has no editor"))?;
+
+    let region = EditorRegion {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    let label = embedded_editor_label(plugin_id);
+    let window = match app_handle.get_window(&label) {
+        Some(window) => window,
+        None => {
+            let window = tauri::WindowBuilder::new(&app_handle, &label)
+                .parent(&main)
+                .and_then(|builder| {
+                    builder
+                        .decorations(false)
+                        .resizable(false)
+                        .skip_taskbar(true)
+                        .build()
+                })
+                .map_err(|e| AudioError::PluginEditorError.detail(e))?;
+            unsafe { attach_view(&window, &view)? };
+
+            // The view resizing itself is still clipped to the region it's docked to
+            let resized = window.clone();
+            let handle = app_handle.clone();
+            plugin.set_window_resize_callback(move |view, new_size| {
+                let region = handle
+                    .state::<EmbeddedEditors>()
+                    .0
+                    .lock()
+                    .unwrap()
+                    .get(&plugin_id)
+                    .copied();
+                unsafe { view.on_size(&mut *new_size) };
+                if let Some(region) = region {
+                    size_embedded_editor(&resized, region, new_size);
+                }
+            });
+
+            let handle = app_handle.clone();
+            window.on_window_event(move |event| {
+                if let tauri::WindowEvent::CloseRequested { .. } = event {
+                    unsafe { view.removed() };
+                    remove_embedded_editor(&handle, plugin_id);
+                }
+            });
+            window
+        }
+    };
+    // Only tracked once the view is attached, `open_plugin_editor` checks this
+    app_handle
+        .state::<EmbeddedEditors>()
+        .0
+        .lock()
+        .unwrap()
+        .insert(plugin_id, region);
+
+    let rect = plugin
+        .fit_view_to_region(
+            (width * scale_factor) as i32,
+            (height * scale_factor) as i32,
+        )
+        .ok_or_else(|| AudioError::PluginEditorError.detail("plugin has no editor"))?;
+    position_embedded_editor(&main, &window, region);
+    size_embedded_editor(&window, region, &rect);
+
+    Ok(EmbeddedEditorSize {
+        width: rect.right as f64 / scale_factor,
+        height: rect.bottom as f64 / scale_factor,
+    })
+}
 
+/// Take a docked editor out of the main window
+#[tauri::command]
+pub fn close_embedded_editor(
+    app_handle: tauri::AppHandle,
+    plugin_id: u64,
+) -> Result<(), AudioError> {
+    if let Some(window) = app_handle.get_window(&embedded_editor_label(plugin_id)) {
+        window
+            .close()
+            .map_err(|e| AudioError::PluginEditorError.detail(e))?;
+    }
     Ok(())
 }
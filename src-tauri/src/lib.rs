@@ -1,12 +1,14 @@
-use audio::AudioEngine;
-use log::info;
+use audio::{AudioEngine, DeviceScan};
+use log::{info, trace, warn};
 use serde_json::json;
 use std::sync::Mutex;
-use tauri::{Manager, RunEvent};
+use std::time::Duration;
+use tauri::{Emitter, Manager, RunEvent};
 use tauri_plugin_store::StoreExt;
 use tracing_subscriber::fmt::time::LocalTime;
 use tracing_subscriber::EnvFilter;
 
+use crate::commands::{EditorWindows, EmbeddedEditors, EngineState, EngineStateInterval};
 use crate::plugins::PluginRegistry;
 
 mod commands;
@@ -16,6 +18,239 @@ mod settings;
 type GlobalAudio = Mutex<AudioEngine>;
 type GlobalPluginRegistry = Mutex<PluginRegistry>;
 
+/// Minimum time between two `xrun` events sent to the frontend
+const XRUN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls the engine's xrun counters and emits a throttled `xrun` event with the
+/// running total whenever it grows
+fn spawn_xrun_monitor(app: tauri::AppHandle) {
+    // Polled without the engine lock, which commands may hold for a while
+    let counters = app.state::<GlobalAudio>().lock().unwrap().xrun_counters();
+
+    std::thread::spawn(move || {
+        let mut last_count = 0;
+
+        loop {
+            std::thread::sleep(XRUN_POLL_INTERVAL);
+
+            let count = counters.count();
+
+            // The counters are reset every time the streams are restarted
+            if count < last_count {
+                last_count = 0;
+            }
+
+            if count > last_count {
+                let _ = app.emit("xrun", count);
+                last_count = count;
+            }
+        }
+    });
+}
+
+/// How often the engine is checked for sustained overload
+const OVERLOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Emits `overload-warning` with the headroom left once processing keeps running over
+/// 80% of the block budget, again only after the overload went away in between
+fn spawn_overload_monitor(app: tauri::AppHandle) {
+    // The engine lock is only taken here to share the meters, polling them runs without it
+    let meters = app.state::<GlobalAudio>().lock().unwrap().meters();
+
+    std::thread::spawn(move || {
+        let mut overloaded = false;
+
+        loop {
+            std::thread::sleep(OVERLOAD_POLL_INTERVAL);
+
+            let is_overloaded = meters.is_running() && meters.is_overloaded();
+            let headroom = meters.headroom();
+
+            if is_overloaded && !overloaded {
+                warn!(
+                    "Processing is close to the block budget, {:.0}% headroom left",
+                    headroom
+                );
+                let _ = app.emit("overload-warning", json!({ "headroom": headroom }));
+            }
+            overloaded = is_overloaded;
+        }
+    });
+}
+
+/// How often plugin latencies are polled when latency polling is enabled
+const LATENCY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls plugin latencies while the engine has latency polling enabled and emits
+/// `latency-changed` with the new chain latency whenever a plugin's latency changed
+fn spawn_latency_monitor(app: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(LATENCY_POLL_INTERVAL);
+
+        let audio_state = app.state::<GlobalAudio>();
+        let mut engine = audio_state.lock().unwrap();
+
+        if engine.latency_polling() && engine.refresh_latencies() {
+            let _ = app.emit("latency-changed", engine.chain_latency());
+        }
+    });
+}
+
+/// How often parameter edits made in plugin editors are forwarded to the frontend
+const PARAMETER_POLL_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Emits `parameter-changed` for every parameter edit made in a plugin's own editor,
+/// so a generic parameter UI can follow along
+fn spawn_parameter_monitor(app: tauri::AppHandle) {
+    let edits = app
+        .state::<GlobalAudio>()
+        .lock()
+        .unwrap()
+        .parameter_edit_queue();
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(PARAMETER_POLL_INTERVAL);
+
+        for edit in edits.take() {
+            let _ = app.emit(
+                "parameter-changed",
+                json!({
+                    "plugin_id": edit.plugin_id.0,
+                    "param_id": edit.id,
+                    "value": edit.value,
+                }),
+            );
+        }
+    });
+}
+
+/// Default time between two `engine-state` events
+const ENGINE_STATE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Emits `engine-state` with the CPU load, output meters, xruns, latency and active
+/// config every `EngineStateInterval` while the streams are running
+fn spawn_engine_state_monitor(app: tauri::AppHandle) {
+    // Read without the engine lock, which commands may hold for a while
+    let meters = app.state::<GlobalAudio>().lock().unwrap().meters();
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(app.state::<EngineStateInterval>().get());
+
+        if !meters.is_running() {
+            continue;
+        }
+        let _ = app.emit("engine-state", EngineState::read(&meters));
+    });
+}
+
+/// How often the selected devices are checked for removal
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Watches for the selected audio devices disappearing. Emits `device-lost` once, then
+/// retries the default devices every poll until that works and emits `device-recovered`.
+fn spawn_device_monitor(app: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        let mut lost = false;
+
+        loop {
+            std::thread::sleep(DEVICE_POLL_INTERVAL);
+
+            // Listing devices can take a while, the engine is only locked to apply it
+            let host = app.state::<GlobalAudio>().lock().unwrap().shared_host();
+            let scan = DeviceScan::scan(&host);
+
+            let audio_state = app.state::<GlobalAudio>();
+            let mut engine = audio_state.lock().unwrap();
+
+            let (lost_inputs, restored_inputs) = engine.check_midi_inputs(&scan);
+            for port in lost_inputs {
+                let _ = app.emit("midi-input-lost", json!({ "port": port }));
+            }
+            for port in restored_inputs {
+                info!("Reopened MIDI input {}", port);
+                let _ = app.emit("midi-input-recovered", json!({ "port": port }));
+            }
+
+            if !lost && engine.check_devices(&scan) {
+                warn!("Audio device lost");
+                lost = true;
+                let _ = app.emit(
+                    "device-lost",
+                    json!({
+                        "input": engine.input_device_name(),
+                        "output": engine.output_device_name(),
+                    }),
+                );
+            }
+
+            if !lost {
+                continue;
+            }
+
+            match engine.recover_default_devices(&scan) {
+                Ok(()) => {
+                    info!("Recovered audio on the default devices");
+                    lost = false;
+                    let _ = app.emit(
+                        "device-recovered",
+                        json!({
+                            "input": engine.input_device_name(),
+                            "output": engine.output_device_name(),
+                        }),
+                    );
+                }
+                Err(err) => trace!("Audio device recovery failed: {}", err),
+            }
+        }
+    });
+}
+
+/// Handles the hidden `--probe-plugin <path>` mode used by plugin scanning. Returns the
+/// exit code if the app was started in that mode
+pub fn run_plugin_probe() -> Option<i32> {
+    let mut args = std::env::args().skip(1);
+
+    if args.next().as_deref() != Some(plugins::PROBE_PLUGIN_FLAG) {
+        return None;
+    }
+
+    Some(match args.next() {
+        Some(path) => plugins::run_probe(&path),
+        None => 2,
+    })
+}
+
+/// Handles the hidden `--host-plugin <path> <buffer>` mode that runs a sandboxed plugin.
+/// Returns the exit code if the app was started in that mode
+#[cfg(feature = "sandbox")]
+pub fn run_plugin_host() -> Option<i32> {
+    let mut args = std::env::args().skip(1);
+
+    if args.next().as_deref() != Some(audio::vst::sandbox::HOST_PLUGIN_FLAG) {
+        return None;
+    }
+
+    Some(match (args.next(), args.next()) {
+        (Some(path), Some(buffer)) => audio::vst::sandbox::run_plugin_host(&path, &buffer),
+        _ => 2,
+    })
+}
+
+/// Rescan after the plugin path watcher saw plugin files come or go, and let the
+/// frontend know with a `plugins-changed` event
+fn on_plugin_paths_changed(app: &tauri::AppHandle) {
+    let plugin_registry = app.state::<GlobalPluginRegistry>();
+    let mut registry = plugin_registry.lock().unwrap();
+
+    info!("Plugin paths changed, rescanning");
+    if let Err(err) = registry.scan_plugins() {
+        warn!("Failed to rescan plugins: {}", err);
+        return;
+    }
+
+    let _ = app.emit("plugins-changed", registry.get_discovered_plugins());
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tracing_subscriber::fmt()
@@ -30,8 +265,13 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             commands::get_hosts,
+            commands::has_input_device,
+            commands::has_output_device,
             commands::get_input_devices,
             commands::get_output_devices,
+            commands::get_input_devices_for_host,
+            commands::get_output_devices_for_host,
+            commands::refresh_devices,
             commands::get_host,
             commands::get_input_device,
             commands::get_output_device,
@@ -39,17 +279,130 @@ pub fn run() {
             commands::select_host,
             commands::select_input,
             commands::select_output,
+            commands::get_monitor_output,
+            commands::select_monitor_output,
             commands::set_buffer_size,
+            commands::auto_tune_buffer_size,
+            commands::get_sample_rate,
+            commands::get_supported_sample_rates,
+            commands::get_supported_buffer_sizes,
+            commands::set_sample_rate,
+            commands::get_stream_formats,
+            commands::get_active_config,
+            commands::get_engine_state_interval,
+            commands::set_engine_state_interval,
+            commands::get_supported_input_formats,
+            commands::get_supported_output_formats,
+            commands::get_ring_latency_blocks,
+            commands::set_ring_latency_blocks,
+            commands::get_resampler_quality,
+            commands::set_resampler_quality,
+            commands::get_resample_ratios,
+            commands::get_exclusive_mode,
+            commands::set_exclusive_mode,
+            commands::get_format_strategy,
+            commands::set_format_strategy,
+            commands::get_adaptive_resampling,
+            commands::set_adaptive_resampling,
+            commands::get_latency_polling,
+            commands::set_latency_polling,
+            commands::get_chain_latency,
+            commands::get_roundtrip_latency,
+            commands::get_headroom,
+            commands::get_diagnostics,
+            commands::get_input_gain,
+            commands::set_input_gain,
+            commands::get_monitor_mix,
+            commands::set_monitor_mix,
+            commands::global_ab,
+            commands::get_global_ab,
+            commands::get_global_ab_loudness_match,
+            commands::set_global_ab_loudness_match,
+            commands::get_channel_swap,
+            commands::set_channel_swap,
+            commands::get_mono_sum,
+            commands::set_mono_sum,
+            commands::get_downmix_preset,
+            commands::set_downmix_preset,
+            commands::get_output_limiter,
+            commands::set_output_limiter,
+            commands::get_input_source,
+            commands::set_input_source,
+            commands::get_input_highpass,
+            commands::set_input_highpass,
             commands::get_plugin_paths,
             commands::set_plugin_paths,
             commands::get_discovered_plugins,
+            commands::get_discovered_plugin_paths,
+            commands::get_plugin_icon_path,
+            commands::get_blacklisted_plugins,
+            commands::clear_plugin_blacklist,
+            commands::get_plugin_probe_timeout,
+            commands::set_plugin_probe_timeout,
+            commands::get_plugin_follow_symlinks,
+            commands::set_plugin_follow_symlinks,
             commands::browse_directory,
             commands::scan_plugins,
+            commands::rescan_plugins,
             commands::get_cpu_usage,
+            commands::get_plugin_cpu_usage,
+            commands::get_all_plugin_io_levels,
             commands::get_loaded_plugins,
+            commands::get_chain_info,
+            commands::set_chain_locked,
+            commands::add_chain,
+            commands::remove_chain,
+            commands::set_chain_gain,
+            commands::set_plugin_chain,
+            commands::get_plugin_sample_rate,
+            commands::set_plugin_sample_rate,
+            commands::get_plugin_parameter,
+            commands::set_plugin_parameter,
+            commands::get_plugin_parameters,
+            commands::automate_plugin_parameter,
+            commands::get_plugin_mix,
+            commands::set_plugin_mix,
+            commands::get_processing_enabled,
+            commands::get_midi_output_ports,
+            commands::list_midi_inputs,
+            commands::select_midi_input,
+            commands::enable_midi_clock_out,
+            commands::disable_midi_clock_out,
+            commands::get_tempo,
+            commands::set_tempo,
+            commands::set_transport_playing,
+            commands::rewind_transport,
+            commands::start_recording,
+            commands::stop_recording,
+            commands::is_recording,
+            commands::set_processing_enabled,
+            commands::get_denormal_protection,
+            commands::set_denormal_protection,
+            commands::get_parallel_processing,
+            commands::set_parallel_processing,
+            commands::get_dither,
+            commands::set_dither,
+            commands::get_audio_thread_affinity,
+            commands::set_audio_thread_affinity,
             commands::load_plugin,
+            commands::list_plugin_classes,
+            commands::import_chain,
+            commands::load_plugin_sandboxed,
+            commands::get_suspect_plugins,
             commands::remove_plugin,
+            commands::move_plugin_to_front,
+            commands::move_plugin_to_back,
+            commands::reload_plugin,
+            commands::store_ab,
+            commands::recall_ab,
+            commands::get_plugin_buses,
+            commands::set_monitor_bus,
+            commands::set_sidechain_source,
+            commands::get_monitor_tap,
+            commands::set_monitor_tap,
             commands::open_plugin_editor,
+            commands::embed_plugin_editor,
+            commands::close_embedded_editor,
         ])
         .setup(|app| {
             app.manage(Mutex::new(settings::create_audio_engine_from_settings(
@@ -59,6 +412,36 @@ pub fn run() {
                 app.app_handle(),
             )));
 
+            spawn_xrun_monitor(app.app_handle().clone());
+            spawn_overload_monitor(app.app_handle().clone());
+            spawn_device_monitor(app.app_handle().clone());
+            spawn_latency_monitor(app.app_handle().clone());
+            spawn_parameter_monitor(app.app_handle().clone());
+
+            app.manage(EditorWindows::default());
+            app.manage(EmbeddedEditors::default());
+            if let Some(main) = app.get_window("main") {
+                let app_handle = app.app_handle().clone();
+                main.on_window_event(move |event| {
+                    if let tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) = event {
+                        commands::follow_main_window(&app_handle);
+                    }
+                });
+            }
+
+            app.manage(EngineStateInterval::new(ENGINE_STATE_INTERVAL));
+            spawn_engine_state_monitor(app.app_handle().clone());
+
+            let app_handle = app.app_handle().clone();
+            if let Err(err) = app
+                .state::<GlobalPluginRegistry>()
+                .lock()
+                .unwrap()
+                .start_watching(move || on_plugin_paths_changed(&app_handle))
+            {
+                warn!("{}", err);
+            }
+
             Ok(())
         })
         .build(tauri::generate_context!())
@@ -78,14 +461,25 @@ pub fn run() {
                             "host": engine.host_name(),
                             "input": engine.input_device_name(),
                             "output": engine.output_device_name(),
-                            "buffer_size": engine.buffer_size()
+                            "buffer_size": engine.buffer_size(),
+                            "resampler_quality": engine.resampler_quality().as_str(),
+                            "format_strategy": engine.format_strategy().as_str()
                         }),
                     );
+                    store.set(
+                        "midi-ports",
+                        json!(settings::MidiPortSettings::from_engine(&engine)),
+                    );
 
+                    let mut registry = plugin_registry.lock().unwrap();
+                    registry.stop_watching();
+                    store.set("plugin-paths", registry.get_plugin_paths());
+                    store.set("plugin-blacklist", registry.get_blacklisted_plugins());
                     store.set(
-                        "plugin-paths",
-                        plugin_registry.lock().unwrap().get_plugin_paths(),
+                        "plugin-probe-timeout-ms",
+                        registry.probe_timeout().as_millis() as u64,
                     );
+                    store.set("plugin-follow-symlinks", registry.follow_symlinks());
 
                     store.save().unwrap();
                     store.close_resource();
@@ -2,5 +2,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    if let Some(code) = sona_lib::run_plugin_probe() {
+        std::process::exit(code);
+    }
+
+    #[cfg(feature = "sandbox")]
+    if let Some(code) = sona_lib::run_plugin_host() {
+        std::process::exit(code);
+    }
+
     sona_lib::run()
 }
@@ -1,8 +1,166 @@
-use log::info;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use audio::vst::host::{probe_plugin_classes, PluginFormat, PluginMetadata};
+use log::{info, warn};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Hidden command line flag that makes the app probe a single plugin and exit
+pub const PROBE_PLUGIN_FLAG: &str = "--probe-plugin";
+
+/// How long a probe subprocess may run before it's killed and the plugin blacklisted
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a running probe subprocess is checked for completion
+const PROBE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Installers write many files in a burst, wait for them to settle before rescanning
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
+
+type ChangeHandler = Arc<dyn Fn() + Send + Sync>;
+
+/// Entry point of the probe subprocess, prints the metadata of each of the plugin's
+/// classes as a JSON list on stdout
+pub fn run_probe(path: &str) -> i32 {
+    let plugins = DiscoveredPlugin::probe(path.to_string());
+
+    match serde_json::to_string(&plugins) {
+        Ok(json) => {
+            println!("{}", json);
+            0
+        }
+        Err(_) => 1,
+    }
+}
+
+/// A plugin found during scanning, with whatever metadata could be read from its factory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredPlugin {
+    pub path: String,
+    pub name: String,
+    pub vendor: Option<String>,
+    pub category: Option<String>,
+    pub uid: Option<String>,
+    /// Snapshot image shipped in the bundle, preferring the HiDPI variant
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// VST3 for entries cached before the format was recorded
+    #[serde(default)]
+    pub format: PluginFormat,
+}
+
+impl DiscoveredPlugin {
+    /// Filename-only entry for plugins that couldn't be probed
+    fn from_path(path: String) -> Self {
+        let name = Path::new(&path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.clone());
+        let format = PluginFormat::from_path(Path::new(&path)).unwrap_or_default();
+
+        Self {
+            path,
+            name,
+            vendor: None,
+            category: None,
+            uid: None,
+            icon: None,
+            format,
+        }
+    }
+
+    /// Briefly load the module to read its factory metadata, falling back to the filename.
+    /// A factory that ships several audio classes gives an entry for each.
+    fn probe(path: String) -> Vec<Self> {
+        match probe_plugin_classes(&path) {
+            Ok(classes) => Self::from_classes(&path, classes),
+            Err(err) => {
+                warn!("Failed to probe plugin {}: {}", path, err);
+                vec![Self::from_path(path)]
+            }
+        }
+    }
+
+    /// An entry for each audio class probed from the plugin at `path`
+    fn from_classes(path: &str, classes: Vec<PluginMetadata>) -> Vec<Self> {
+        classes
+            .into_iter()
+            .map(|metadata| Self {
+                path: path.to_string(),
+                name: metadata.name,
+                vendor: Some(metadata.vendor),
+                category: Some(metadata.category),
+                uid: Some(metadata.uid),
+                icon: metadata
+                    .snapshot
+                    .map(|snapshot| snapshot.to_string_lossy().into_owned()),
+                format: metadata.format,
+            })
+            .collect()
+    }
+}
+
+/// Scan results for a single plugin path
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CachedScan {
+    /// Modification time (ns since epoch) of every directory walked under the path
+    directories: HashMap<String, u64>,
+    plugins: Vec<DiscoveredPlugin>,
+    /// Set by scans that also looked for VST2 plugins, older caches are rescanned
+    #[serde(default)]
+    includes_vst2: bool,
+}
+
+impl CachedScan {
+    /// A scan is still valid if none of its directories gained or lost entries
+    fn is_fresh(&self) -> bool {
+        self.includes_vst2
+            && !self.directories.is_empty()
+            && self
+                .directories
+                .iter()
+                .all(|(dir, mtime)| dir_mtime(Path::new(dir)) == Some(*mtime))
+    }
+}
+
+fn dir_mtime(path: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+/// Plugins are `.vst3` bundle directories on macOS and Linux, module files on Windows
+const BUNDLE_PLUGINS: bool = cfg!(any(target_os = "macos", target_os = "linux"));
+
+/// Whether a changed path is, or is inside, a VST3 or VST2 module or bundle
+fn is_plugin_path(path: &Path) -> bool {
+    path.components()
+        .any(|component| PluginFormat::from_path(Path::new(component.as_os_str())).is_some())
+}
 
 pub struct PluginRegistry {
     plugin_paths: Vec<String>,
-    plugins: Vec<String>,
+    plugins: Vec<DiscoveredPlugin>,
+    cache_path: Option<PathBuf>,
+    scan_cache: HashMap<String, CachedScan>,
+    blacklist: BTreeSet<String>,
+    // Plugins that timed out while loading this session, they may just be slow
+    suspect: BTreeSet<String>,
+    probe_timeout: Duration,
+    /// Follow symlinks while walking plugin paths, e.g. bundles linked from a central
+    /// location. Link cycles are detected and skipped
+    follow_symlinks: bool,
+    /// File naming the plugin currently being probed or loaded, left behind if it crashes
+    in_flight_path: Option<PathBuf>,
+    watcher: Option<Debouncer<RecommendedWatcher>>,
+    on_change: Option<ChangeHandler>,
 }
 
 impl PluginRegistry {
@@ -10,6 +168,201 @@ impl PluginRegistry {
         Self {
             plugin_paths: Vec::new(),
             plugins: Vec::new(),
+            cache_path: None,
+            scan_cache: HashMap::new(),
+            blacklist: BTreeSet::new(),
+            suspect: BTreeSet::new(),
+            probe_timeout: DEFAULT_PROBE_TIMEOUT,
+            follow_symlinks: true,
+            in_flight_path: None,
+            watcher: None,
+            on_change: None,
+        }
+    }
+
+    /// Set the file used to persist scan results across launches, loading it if present
+    pub fn set_cache_path(&mut self, path: PathBuf) {
+        self.scan_cache = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        self.cache_path = Some(path);
+    }
+
+    /// Drop all cached scan results so the next scan walks every path again
+    pub fn invalidate_cache(&mut self) {
+        self.scan_cache.clear();
+
+        if let Some(path) = &self.cache_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Set the file used to track the plugin in flight. If a previous run crashed while
+    /// touching a plugin the marker is still there, so that plugin gets blacklisted
+    pub fn set_in_flight_path(&mut self, path: PathBuf) {
+        if let Ok(crashed) = std::fs::read_to_string(&path) {
+            if !crashed.is_empty() {
+                warn!("Plugin crashed during the previous run: {}", crashed);
+                self.blacklist(&crashed);
+            }
+            let _ = std::fs::remove_file(&path);
+        }
+        self.in_flight_path = Some(path);
+    }
+
+    fn mark_in_flight(&self, plugin: Option<&str>) {
+        let Some(path) = &self.in_flight_path else {
+            return;
+        };
+
+        match plugin {
+            Some(plugin) => {
+                if let Some(parent) = path.parent() {
+                    let _ = std::fs::create_dir_all(parent);
+                }
+                if let Err(err) = std::fs::write(path, plugin) {
+                    info!("Failed to write plugin marker: {}", err);
+                }
+            }
+            None => {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    pub fn probe_timeout(&self) -> Duration {
+        self.probe_timeout
+    }
+
+    pub fn set_probe_timeout(&mut self, timeout: Duration) {
+        self.probe_timeout = timeout;
+    }
+
+    pub fn follow_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    /// Cached scans were walked with the old setting, so they're dropped when it changes
+    pub fn set_follow_symlinks(&mut self, follow: bool) {
+        if follow != self.follow_symlinks {
+            self.follow_symlinks = follow;
+            self.scan_cache.clear();
+        }
+    }
+
+    /// Probe a plugin in a child process so a crashing plugin can't take the app down.
+    /// Returns `Ok(None)` if the child crashed, timed out or printed garbage, and an error
+    /// only if the child couldn't be spawned at all
+    fn probe_in_subprocess(&self, path: &str) -> std::io::Result<Option<Vec<DiscoveredPlugin>>> {
+        let mut child = Command::new(std::env::current_exe()?)
+            .arg(PROBE_PLUGIN_FLAG)
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let deadline = Instant::now() + self.probe_timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            if Instant::now() >= deadline {
+                warn!("Timed out probing plugin: {}", path);
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+
+            std::thread::sleep(PROBE_POLL_INTERVAL);
+        };
+
+        if !status.success() {
+            warn!("Probe of {} exited with {}", path, status);
+            return Ok(None);
+        }
+
+        let mut output = String::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_string(&mut output)?;
+        }
+
+        Ok(serde_json::from_str(output.trim()).ok())
+    }
+
+    /// Run `f` against a plugin unless it's blacklisted, blacklisting it if `f` panics
+    pub fn run_guarded<T>(&mut self, plugin: &str, f: impl FnOnce() -> T) -> Option<T> {
+        if self.is_blacklisted(plugin) {
+            warn!("Skipping blacklisted plugin: {}", plugin);
+            return None;
+        }
+
+        self.mark_in_flight(Some(plugin));
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        self.mark_in_flight(None);
+
+        match result {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("Plugin panicked: {}", plugin);
+                self.blacklist(plugin);
+                None
+            }
+        }
+    }
+
+    /// Skip this plugin on every future scan and load until the blacklist is cleared
+    pub fn blacklist(&mut self, path: &str) {
+        self.blacklist.insert(path.to_string());
+        self.remove_plugin(path);
+    }
+
+    pub fn clear_blacklist(&mut self) {
+        self.blacklist.clear();
+        // Cached scans left blacklisted plugins out, walk again so they're picked back up
+        self.invalidate_cache();
+    }
+
+    pub fn set_blacklist(&mut self, paths: Vec<String>) {
+        self.blacklist = paths.into_iter().collect();
+    }
+
+    pub fn is_blacklisted(&self, path: &str) -> bool {
+        self.blacklist.contains(path)
+    }
+
+    pub fn get_blacklisted_plugins(&self) -> Vec<String> {
+        self.blacklist.iter().cloned().collect()
+    }
+
+    /// Flag a plugin that took too long to load. Unlike the blacklist it's still loadable.
+    pub fn mark_suspect(&mut self, path: &str) {
+        warn!("Plugin timed out while loading: {}", path);
+        self.suspect.insert(path.to_string());
+    }
+
+    pub fn get_suspect_plugins(&self) -> Vec<String> {
+        self.suspect.iter().cloned().collect()
+    }
+
+    fn save_cache(&self) {
+        let Some(path) = &self.cache_path else {
+            return;
+        };
+
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        match serde_json::to_string(&self.scan_cache) {
+            Ok(contents) => {
+                if let Err(err) = std::fs::write(path, contents) {
+                    info!("Failed to write plugin scan cache: {}", err);
+                }
+            }
+            Err(err) => info!("Failed to serialize plugin scan cache: {}", err),
         }
     }
 
@@ -45,15 +398,19 @@ impl PluginRegistry {
 
         let clean_path = Self::clean_path(canonical_path);
         self.plugin_paths.push(clean_path);
+        self.restart_watching();
         Ok(())
     }
 
     pub fn remove_plugin_path(&mut self, path: &str) {
         self.plugin_paths.retain(|p| p != path);
+        self.restart_watching();
     }
 
     pub fn set_plugin_paths(&mut self, paths: Vec<String>) -> Result<(), String> {
         self.plugin_paths.clear();
+        // Stop watching the old paths right away, each added path restarts the watcher
+        self.restart_watching();
 
         for path in paths {
             self.add_plugin_path(path)?;
@@ -62,6 +419,64 @@ impl PluginRegistry {
         Ok(())
     }
 
+    /// Watch every plugin path and call `on_change` once plugin files stop being
+    /// added or removed for a second
+    pub fn start_watching(
+        &mut self,
+        on_change: impl Fn() + Send + Sync + 'static,
+    ) -> Result<(), String> {
+        self.on_change = Some(Arc::new(on_change));
+        self.watch_plugin_paths()
+    }
+
+    pub fn stop_watching(&mut self) {
+        self.watcher = None;
+        self.on_change = None;
+    }
+
+    /// Tear down the current watcher and watch the current paths, if watching at all
+    fn restart_watching(&mut self) {
+        self.watcher = None;
+
+        if self.on_change.is_some() {
+            if let Err(err) = self.watch_plugin_paths() {
+                warn!("Failed to watch plugin paths: {}", err);
+            }
+        }
+    }
+
+    fn watch_plugin_paths(&mut self) -> Result<(), String> {
+        let Some(on_change) = self.on_change.clone() else {
+            return Ok(());
+        };
+
+        let mut debouncer =
+            new_debouncer(
+                WATCH_DEBOUNCE,
+                move |result: DebounceEventResult| match result {
+                    Ok(events) => {
+                        if events.iter().any(|event| is_plugin_path(&event.path)) {
+                            on_change();
+                        }
+                    }
+                    Err(err) => warn!("Plugin path watcher error: {}", err),
+                },
+            )
+            .map_err(|e| format!("Failed to create plugin path watcher: {}", e))?;
+
+        for path in &self.plugin_paths {
+            if let Err(err) = debouncer
+                .watcher()
+                .watch(Path::new(path), RecursiveMode::Recursive)
+            {
+                warn!("Failed to watch plugin path {}: {}", path, err);
+            }
+        }
+
+        self.watcher = Some(debouncer);
+        Ok(())
+    }
+
     pub fn get_plugin_paths(&self) -> &[String] {
         &self.plugin_paths
     }
@@ -70,51 +485,284 @@ impl PluginRegistry {
         // Clear existing plugins before scanning
         self.plugins.clear();
 
-        for path in self.plugin_paths.clone() {
-            // Check if the path exists before scanning
-            if !std::path::Path::new(&path).exists() {
-                info!("Skipping non-existent path: {}", path);
-                continue;
+        // Walk every plugin path in parallel, reusing cached results for unchanged paths
+        let mut scans: Vec<(String, CachedScan, bool)> = self
+            .plugin_paths
+            .par_iter()
+            .map(|path| match self.scan_cache.get(path) {
+                Some(cached) if cached.is_fresh() => {
+                    info!("Using cached scan for: {}", path);
+                    (path.clone(), cached.clone(), false)
+                }
+                _ => (
+                    path.clone(),
+                    Self::collect_plugins(path, self.follow_symlinks),
+                    true,
+                ),
+            })
+            .collect();
+
+        // Probe newly walked plugins one at a time, each in its own subprocess
+        for (_, scan, walked) in scans.iter_mut() {
+            if *walked {
+                for plugin in std::mem::take(&mut scan.plugins) {
+                    let probed = self.probe(plugin.path);
+                    scan.plugins.extend(probed);
+                }
             }
+        }
 
-            // Use walkdir for recursive directory traversal
-            let walker = walkdir::WalkDir::new(&path)
-                .follow_links(false) // Don't follow symlinks to avoid infinite loops
-                .into_iter()
-                .filter_map(|e| match e {
-                    Ok(entry) => Some(entry),
-                    Err(err) => {
-                        info!("Error accessing path during scan: {}", err);
-                        None
-                    }
-                })
-                .filter(|e| e.file_type().is_file()) // Only process files, not directories
-                .filter(|e| {
-                    // Check if file has .vst3 extension
-                    e.path()
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .map_or(false, |ext| ext.eq_ignore_ascii_case("vst3"))
-                });
-
-            for entry in walker {
-                let plugin_path = entry.path().to_string_lossy().to_string();
-                self.add_plugin(plugin_path);
+        // Sort so the result doesn't depend on scheduling
+        let mut plugins: Vec<DiscoveredPlugin> = scans
+            .iter()
+            .flat_map(|(_, scan, _)| scan.plugins.iter().cloned())
+            .filter(|plugin| !self.is_blacklisted(&plugin.path))
+            .collect();
+        plugins.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for plugin in plugins {
+            self.add_plugin(plugin);
+        }
+
+        self.scan_cache = scans
+            .into_iter()
+            .map(|(path, scan, _)| (path, scan))
+            .collect();
+        self.save_cache();
+
+        Ok(self.get_discovered_plugin_paths())
+    }
+
+    /// Probe out of process, blacklisting plugins that crash or hang. Falls back to probing
+    /// in process if the subprocess can't be spawned. Gives an entry per audio class.
+    fn probe(&mut self, path: String) -> Vec<DiscoveredPlugin> {
+        if self.is_blacklisted(&path) {
+            return Vec::new();
+        }
+
+        match self.probe_in_subprocess(&path) {
+            Ok(Some(plugins)) => plugins,
+            Ok(None) => {
+                self.blacklist(&path);
+                Vec::new()
+            }
+            Err(err) => {
+                warn!("Failed to spawn plugin probe, probing in process: {}", err);
+                let fallback = path.clone();
+                self.run_guarded(&fallback, || DiscoveredPlugin::probe(path))
+                    .unwrap_or_default()
             }
         }
+    }
+
+    /// Recursively collect every `.vst3` and `.dll`/`.vst` plugin under a single plugin
+    /// path. For VST3 on Windows that's the module file, on macOS and Linux the bundle
+    /// directory, which isn't descended into. A plugin reachable through several symlinks is listed once.
+    fn collect_plugins(path: &str, follow_symlinks: bool) -> CachedScan {
+        let mut scan = CachedScan {
+            includes_vst2: true,
+            ..Default::default()
+        };
+        let mut seen = HashSet::new();
+
+        // Check if the path exists before scanning
+        if !Path::new(path).exists() {
+            info!("Skipping non-existent path: {}", path);
+            return scan;
+        }
+
+        // Use walkdir for recursive directory traversal. When following symlinks it reports
+        // a link back to an ancestor as an error instead of looping
+        let mut walker = walkdir::WalkDir::new(path)
+            .follow_links(follow_symlinks)
+            .into_iter();
+
+        while let Some(entry) = walker.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    info!("Error accessing path during scan: {}", err);
+                    continue;
+                }
+            };
+
+            let is_dir = entry.file_type().is_dir();
+            if is_dir {
+                // Remember directory mtimes so unchanged paths can be skipped next time
+                if let Some(mtime) = dir_mtime(entry.path()) {
+                    scan.directories
+                        .insert(entry.path().to_string_lossy().to_string(), mtime);
+                }
+            }
 
-        Ok(self.get_discovered_plugins().to_vec())
+            let is_plugin = match PluginFormat::from_path(entry.path()) {
+                Some(PluginFormat::Vst3) if BUNDLE_PLUGINS => is_dir,
+                // VST2 plugins are `.vst` bundles on macOS, `.dll` files on Windows
+                Some(PluginFormat::Vst2) if is_dir => cfg!(target_os = "macos"),
+                Some(_) => entry.file_type().is_file(),
+                None => false,
+            };
+
+            if is_plugin {
+                let real_path = std::fs::canonicalize(entry.path())
+                    .unwrap_or_else(|_| entry.path().to_path_buf());
+                if seen.insert(real_path) {
+                    scan.plugins.push(DiscoveredPlugin::from_path(
+                        entry.path().to_string_lossy().to_string(),
+                    ));
+                }
+
+                if is_dir {
+                    walker.skip_current_dir();
+                }
+            }
+        }
+
+        scan
     }
 
-    pub fn add_plugin(&mut self, plugin: String) {
+    pub fn add_plugin(&mut self, plugin: DiscoveredPlugin) {
         self.plugins.push(plugin);
     }
 
     pub fn remove_plugin(&mut self, plugin: &str) {
-        self.plugins.retain(|p| p != plugin);
+        self.plugins.retain(|p| p.path != plugin);
     }
 
-    pub fn get_discovered_plugins(&self) -> &[String] {
+    pub fn get_discovered_plugins(&self) -> &[DiscoveredPlugin] {
         &self.plugins
     }
+
+    pub fn get_discovered_plugin_paths(&self) -> Vec<String> {
+        self.plugins.iter().map(|p| p.path.clone()).collect()
+    }
+
+    /// Path and class UID of the scanned plugin with the given class UID, or failing that
+    /// the given name, both compared case-insensitively. The UID is unset for plugins that
+    /// couldn't be probed, their first class is loaded then.
+    pub fn resolve_plugin(&self, uid_or_name: &str) -> Option<(String, Option<String>)> {
+        self.plugins
+            .iter()
+            .find(|p| {
+                p.uid
+                    .as_deref()
+                    .is_some_and(|uid| uid.eq_ignore_ascii_case(uid_or_name))
+            })
+            .or_else(|| {
+                self.plugins
+                    .iter()
+                    .find(|p| p.name.eq_ignore_ascii_case(uid_or_name))
+            })
+            .map(|p| (p.path.clone(), p.uid.clone()))
+    }
+
+    /// Snapshot image of the plugin class with the given uid, if it's still on disk
+    pub fn plugin_icon_path(&self, uid: &str) -> Option<PathBuf> {
+        self.plugins
+            .iter()
+            .find(|p| p.uid.as_deref() == Some(uid))
+            .and_then(|p| p.icon.as_ref())
+            .map(PathBuf::from)
+            .filter(|icon| icon.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(name: &str, uid: &str) -> PluginMetadata {
+        PluginMetadata {
+            name: name.to_string(),
+            vendor: "Mock Audio".to_string(),
+            category: "Audio Module Class".to_string(),
+            uid: uid.to_string(),
+            snapshot: None,
+            format: PluginFormat::Vst3,
+        }
+    }
+
+    #[test]
+    fn test_scan_lists_each_class_and_resolves_to_it() {
+        let path = "/plugins/Multi.vst3";
+        let mono = "5D1E5A3F-0000-4000-8000-000000000001";
+        let stereo = "5D1E5A3F-0000-4000-8000-000000000003";
+
+        let plugins = DiscoveredPlugin::from_classes(
+            path,
+            vec![metadata("Mono", mono), metadata("Stereo", stereo)],
+        );
+        let entries = plugins
+            .iter()
+            .map(|p| (p.path.as_str(), p.name.as_str(), p.uid.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![(path, "Mono", Some(mono)), (path, "Stereo", Some(stereo))]
+        );
+
+        // Imports load the class that matched, by UID or by name
+        let mut registry = PluginRegistry::new();
+        registry.plugins = plugins;
+        registry.plugins.push(DiscoveredPlugin::from_path(
+            "/plugins/Unprobed.vst3".to_string(),
+        ));
+        assert_eq!(
+            registry.resolve_plugin(&stereo.to_lowercase()),
+            Some((path.to_string(), Some(stereo.to_string())))
+        );
+        assert_eq!(
+            registry.resolve_plugin("mono"),
+            Some((path.to_string(), Some(mono.to_string())))
+        );
+        assert_eq!(
+            registry.resolve_plugin("Unprobed"),
+            Some(("/plugins/Unprobed.vst3".to_string(), None))
+        );
+        assert_eq!(registry.resolve_plugin("Missing"), None);
+    }
+
+    /// Names of the plugins a scan of `path` finds, sorted
+    fn scanned_names(path: &Path, follow_symlinks: bool) -> Vec<String> {
+        let mut names = PluginRegistry::collect_plugins(path.to_str().unwrap(), follow_symlinks)
+            .plugins
+            .into_iter()
+            .map(|p| p.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_descends_symlinked_directories_only_when_following() {
+        let root = std::env::temp_dir().join(format!("sona-scan-links-{}", std::process::id()));
+        let plugins = root.join("plugins");
+        let shared = root.join("shared");
+        std::fs::create_dir_all(plugins.join("Own.vst3")).unwrap();
+        std::fs::create_dir_all(shared.join("Shared.vst3")).unwrap();
+        std::os::unix::fs::symlink(&shared, plugins.join("Shared")).unwrap();
+
+        assert_eq!(scanned_names(&plugins, true), vec!["Own", "Shared"]);
+        assert_eq!(scanned_names(&plugins, false), vec!["Own"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scan_survives_symlink_cycles() {
+        let root = std::env::temp_dir().join(format!("sona-scan-cycle-{}", std::process::id()));
+        let plugins = root.join("plugins");
+        std::fs::create_dir_all(plugins.join("Nested").join("Own.vst3")).unwrap();
+
+        // A link back to the scanned path, and a second way into the same directory
+        std::os::unix::fs::symlink(&plugins, plugins.join("Nested").join("Loop")).unwrap();
+        std::os::unix::fs::symlink(plugins.join("Nested"), plugins.join("Again")).unwrap();
+
+        assert_eq!(scanned_names(&plugins, true), vec!["Own"]);
+        assert_eq!(scanned_names(&plugins, false), vec!["Own"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
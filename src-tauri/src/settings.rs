@@ -1,4 +1,8 @@
-use audio::AudioEngine;
+use std::time::Duration;
+
+use audio::{midi, AudioEngine, FormatStrategy, ResamplerQuality};
+use log::warn;
+use serde::{Deserialize, Serialize};
 use tauri::Manager;
 use tauri_plugin_store::StoreExt;
 
@@ -10,6 +14,14 @@ pub fn create_audio_engine_from_settings(app: &tauri::AppHandle) -> AudioEngine
 
     let _ = store.get("audio-settings").and_then(|v| {
         v.as_object().map(|obj| {
+            // Before the devices below are selected, it decides their configs
+            if let Some(strategy) = obj
+                .get("format_strategy")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<FormatStrategy>().ok())
+            {
+                engine.set_format_strategy(strategy);
+            }
             obj.get("host")
                 .and_then(|v| v.as_str())
                 .map(|s| engine.select_host(s).ok());
@@ -22,12 +34,73 @@ pub fn create_audio_engine_from_settings(app: &tauri::AppHandle) -> AudioEngine
             obj.get("buffer_size")
                 .and_then(|v| v.as_u64())
                 .map(|v| engine.set_buffer_size(v as u32).ok());
+            if let Some(quality) = obj
+                .get("resampler_quality")
+                .and_then(|v| v.as_str())
+                .and_then(|s| s.parse::<ResamplerQuality>().ok())
+            {
+                engine.set_resampler_quality(quality);
+            }
         })
     });
 
+    // After the devices, so a port that fails to open doesn't hold up audio
+    if let Some(ports) = store
+        .get("midi-ports")
+        .and_then(|v| serde_json::from_value::<MidiPortSettings>(v).ok())
+    {
+        ports.restore(&mut engine);
+    }
+
     engine
 }
 
+/// Selected MIDI ports, saved under `midi-ports`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MidiPortSettings {
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub output: Option<String>,
+}
+
+impl MidiPortSettings {
+    pub fn from_engine(engine: &AudioEngine) -> Self {
+        Self {
+            inputs: engine.midi_inputs().to_vec(),
+            output: engine.midi_clock_out_port(),
+        }
+    }
+
+    /// Drop the ports that aren't present anymore
+    fn retain_available(&mut self, inputs: &[String], outputs: &[String]) {
+        self.inputs.retain(|port| {
+            let present = inputs.contains(port);
+            if !present {
+                warn!("Saved MIDI input {} is no longer present", port);
+            }
+            present
+        });
+
+        if let Some(port) = self.output.take_if(|port| !outputs.contains(port)) {
+            warn!("Saved MIDI output {} is no longer present", port);
+        }
+    }
+
+    pub fn restore(mut self, engine: &mut AudioEngine) {
+        let inputs = midi::input_port_names().unwrap_or_default();
+        let outputs = midi::output_port_names().unwrap_or_default();
+        self.retain_available(&inputs, &outputs);
+
+        engine.set_midi_inputs(self.inputs);
+        if let Some(port) = self.output {
+            if let Err(err) = engine.enable_midi_clock_out(&port) {
+                warn!("{}", err);
+            }
+        }
+    }
+}
+
 pub fn create_plugin_registry_from_settings(app: &tauri::AppHandle) -> PluginRegistry {
     let store = app.store(".settings.json").unwrap();
     let mut registry = PluginRegistry::new();
@@ -63,7 +136,68 @@ pub fn create_plugin_registry_from_settings(app: &tauri::AppHandle) -> PluginReg
     };
 
     registry.set_plugin_paths(paths);
+
+    if let Some(blacklist) = store.get("plugin-blacklist").and_then(|val| {
+        val.as_array().map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_owned()))
+                .collect()
+        })
+    }) {
+        registry.set_blacklist(blacklist);
+    }
+
+    if let Some(timeout) = store
+        .get("plugin-probe-timeout-ms")
+        .and_then(|v| v.as_u64())
+    {
+        registry.set_probe_timeout(Duration::from_millis(timeout));
+    }
+
+    if let Some(follow) = store
+        .get("plugin-follow-symlinks")
+        .and_then(|v| v.as_bool())
+    {
+        registry.set_follow_symlinks(follow);
+    }
+
+    if let Ok(cache_dir) = app.path().app_cache_dir() {
+        registry.set_cache_path(cache_dir.join("plugin-scan-cache.json"));
+        registry.set_in_flight_path(cache_dir.join("plugin-in-flight"));
+    }
     let _ = registry.scan_plugins();
 
     registry
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midi_ports_round_trip_and_missing_ports() {
+        let ports = MidiPortSettings {
+            inputs: vec!["Keys".to_string(), "Pads".to_string()],
+            output: Some("Clock".to_string()),
+        };
+
+        let value = serde_json::to_value(&ports).unwrap();
+        assert_eq!(
+            serde_json::from_value::<MidiPortSettings>(value).unwrap(),
+            ports
+        );
+        assert_eq!(
+            serde_json::from_value::<MidiPortSettings>(serde_json::json!({})).unwrap(),
+            MidiPortSettings::default()
+        );
+
+        let mut restored = ports.clone();
+        restored.retain_available(&["Pads".to_string()], &[]);
+        assert_eq!(restored.inputs, vec!["Pads".to_string()]);
+        assert_eq!(restored.output, None);
+
+        let mut restored = ports.clone();
+        restored.retain_available(&ports.inputs, &["Clock".to_string()]);
+        assert_eq!(restored, ports);
+    }
+}
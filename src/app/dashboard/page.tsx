@@ -19,6 +19,7 @@ import { SettingsModal } from "@/components/settings-modal"
 import { Play, FileMusic, Info, BarChart3, Settings, AudioWaveform, Sun, Moon, Plus } from "lucide-react"
 import { Titlebar } from "@/components/title-bar"
 import { invoke } from "@tauri-apps/api/core"
+import { listen } from "@tauri-apps/api/event"
 
 // Sample data structure for playlists
 const initialPlaylists = [
@@ -107,9 +108,27 @@ export default function MusicPracticeApp() {
   type PluginInfo = {
     id: number
     name: string
+    vendor: string
+    format: "vst3" | "vst2"
+    bypassed: boolean
+    latency_samples: number
+    has_editor: boolean
+    chain: number
+    index: number
+    program: string | null
+    status: string | null
   }
 
-  const [discoveredPlugins, setDiscoveredPlugins] = useState<string[]>([])
+  type DiscoveredPlugin = {
+    path: string
+    name: string
+    vendor: string | null
+    category: string | null
+    uid: string | null
+    icon: string | null
+  }
+
+  const [discoveredPlugins, setDiscoveredPlugins] = useState<DiscoveredPlugin[]>([])
   const [showPluginDialog, setShowPluginDialog] = useState(false)
 
   useEffect(() => {
@@ -129,11 +148,20 @@ export default function MusicPracticeApp() {
       setPlugins(plugins);
 
       // Also load discovered plugins
-      const discovered: string[] = await invoke("get_discovered_plugins");
+      const discovered: DiscoveredPlugin[] = await invoke("get_discovered_plugins");
       setDiscoveredPlugins(discovered);
     }
 
     run();
+
+    // Rescans triggered by the plugin directory watcher
+    const unlisten = listen<DiscoveredPlugin[]>('plugins-changed', (event) => {
+      setDiscoveredPlugins(event.payload);
+    });
+
+    return () => {
+      unlisten.then(f => f());
+    }
   }, [])
 
   const toggleVisualizer = () => setShowVisualizer(!showVisualizer)
@@ -146,9 +174,10 @@ export default function MusicPracticeApp() {
     setShowPluginDialog(true)
   }
 
-  const loadPlugin = async (pluginPath: string) => {
+  // A factory shipping several classes is listed once per class, the UID picks which
+  const loadPlugin = async (pluginPath: string, classUid: string | null) => {
     try {
-      await invoke("load_plugin", { path: pluginPath });
+      await invoke("load_plugin", { path: pluginPath, classUid });
       // Refresh the loaded plugins list
       const response: PluginInfo[] = await invoke("get_loaded_plugins");
       let plugins = [];
@@ -267,14 +296,15 @@ export default function MusicPracticeApp() {
             </p>
             <ScrollArea className="h-[300px] w-full rounded-md border p-4">
               <div className="space-y-2">
-                {discoveredPlugins.map((pluginPath, index) => {
-                  const pluginName = pluginPath.split(/[/\\]/).pop()?.replace(/\.(dll|vst3)$/i, '') || pluginPath;
+                {discoveredPlugins.map((plugin, index) => {
+                  const pluginPath = plugin.path;
+                  const pluginName = plugin.vendor ? `${plugin.name} (${plugin.vendor})` : plugin.name;
                   return (
                     <Button
                       key={index}
                       variant="ghost"
                       className="w-full justify-start h-auto p-4 border border-border/50 hover:border-border hover:bg-accent/50"
-                      onClick={() => loadPlugin(pluginPath)}
+                      onClick={() => loadPlugin(pluginPath, plugin.uid)}
                     >
                       <div className="flex items-center gap-3">
                         <div className="h-8 w-8 rounded bg-primary/10 flex items-center justify-center">
@@ -39,6 +39,8 @@ export function SettingsModal({ open, onOpenChange }: SettingsModalProps) {
 
   const [sampleRate, setSampleRate] = useState("44100")
   const [bufferSize, setBufferSize] = useState("256")
+  const [supportedSampleRates, setSupportedSampleRates] = useState<number[]>([44100, 48000, 88200, 96000])
+  const [supportedBufferSizes, setSupportedBufferSizes] = useState<number[]>([64, 128, 256, 512, 1024])
   const [inputGain, setInputGain] = useState(75)
   const [outputGain, setOutputGain] = useState(80)
   const [enableLowLatency, setEnableLowLatency] = useState(true)
@@ -51,7 +53,7 @@ export function SettingsModal({ open, onOpenChange }: SettingsModalProps) {
         const paths: string[] = await invoke('get_plugin_paths')
         setPluginDirectories(paths)
         
-        const plugins: string[] = await invoke('get_discovered_plugins')
+        const plugins: string[] = await invoke('get_discovered_plugin_paths')
         setDiscoveredPlugins(plugins)
       } catch (error) {
         console.error('Failed to load plugin data:', error)
@@ -214,6 +216,12 @@ export function SettingsModal({ open, onOpenChange }: SettingsModalProps) {
     async function run() {
       const response = await invoke('select_input', { inputDevice: inputDevice });
       console.log("Selected input device:", response);
+
+      const rates: number[] = await invoke('get_supported_sample_rates', { deviceName: inputDevice });
+      if (rates.length > 0) setSupportedSampleRates(rates);
+
+      const sizes: number[] = await invoke('get_supported_buffer_sizes', { deviceName: inputDevice });
+      if (sizes.length > 0) setSupportedBufferSizes(sizes);
     }
 
     run();
@@ -373,10 +381,9 @@ export function SettingsModal({ open, onOpenChange }: SettingsModalProps) {
                             <SelectValue />
                           </SelectTrigger>
                           <SelectContent>
-                            <SelectItem value="44100">44.1 kHz</SelectItem>
-                            <SelectItem value="48000">48 kHz</SelectItem>
-                            <SelectItem value="88200">88.2 kHz</SelectItem>
-                            <SelectItem value="96000">96 kHz</SelectItem>
+                            {supportedSampleRates.map((rate) => (
+                              <SelectItem key={rate} value={rate.toString()}>{rate / 1000} kHz</SelectItem>
+                            ))}
                           </SelectContent>
                         </Select>
                       </div>
@@ -388,12 +395,9 @@ export function SettingsModal({ open, onOpenChange }: SettingsModalProps) {
                             <SelectValue />
                           </SelectTrigger>
                           <SelectContent>
-                            <SelectItem value="64">64 samples</SelectItem>
-                            <SelectItem value="128">128 samples</SelectItem>
-                            <SelectItem value="192">192 samples</SelectItem>
-                            <SelectItem value="256">256 samples</SelectItem>
-                            <SelectItem value="512">512 samples</SelectItem>
-                            <SelectItem value="1024">1024 samples</SelectItem>
+                            {supportedBufferSizes.map((size) => (
+                              <SelectItem key={size} value={size.toString()}>{size} samples</SelectItem>
+                            ))}
                           </SelectContent>
                         </Select>
                       </div>